@@ -0,0 +1,80 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::ChannelData;
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+use eventbuilder::evb::compass_data::{generate_board_channel_uuid, CompassData};
+
+const EVENTS: usize = 100_000;
+
+// Focal plane events with every SPS channel and a rotating CeBrA detector, spaced 1 us apart (times in ps)
+fn make_events() -> Vec<Vec<CompassData>> {
+    let sps = Board::sps(0);
+    (0..EVENTS)
+        .map(|i| {
+            let start = i as i64 * 1_000_000;
+            let mut event: Vec<CompassData> = sps
+                .channels
+                .iter()
+                .enumerate()
+                .filter(|(_, channel)| **channel != ChannelType::None)
+                .map(|(channel, _)| CompassData {
+                    uuid: generate_board_channel_uuid(&0, &(channel as u32)),
+                    energy: 1000.0 + channel as f64,
+                    energy_short: Some(500.0),
+                    timestamp: start + channel as i64 * 1000,
+                    waveform: None,
+                })
+                .collect();
+            event.push(CompassData {
+                uuid: generate_board_channel_uuid(&1, &((i % 9) as u32)),
+                energy: 2000.0,
+                energy_short: Some(1000.0),
+                timestamp: start + 20_000,
+                waveform: None,
+            });
+            event
+        })
+        .collect()
+}
+
+fn append_events(c: &mut Criterion) {
+    let map = ChannelMap::new(&[Board::sps(0), Board::cebra(1)]).unwrap();
+    let params = BuildParams::default();
+    let events = make_events();
+    let weights = Some((0.5, 0.5));
+
+    let mut group = c.benchmark_group("append_events");
+    group.sample_size(10);
+
+    group.bench_function("append_event", |b| {
+        b.iter_batched(
+            || events.clone(),
+            |events| {
+                let mut data = ChannelData::new(&map, &params);
+                for event in events {
+                    data.append_event(event, &map, weights);
+                }
+                data
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("append_events_batch", |b| {
+        b.iter_batched(
+            || events.clone(),
+            |events| {
+                let mut data = ChannelData::new(&map, &params);
+                data.append_events_batch(events, &map, weights);
+                data
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, append_events);
+criterion_main!(benches);
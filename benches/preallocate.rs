@@ -0,0 +1,115 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::ChannelData;
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+use eventbuilder::evb::compass_data::{generate_board_channel_uuid, CompassData};
+
+const EVENTS: usize = 5_000_000;
+
+// Counts allocations and reallocations so the two paths can be compared directly
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// A single scintillator, so that a 5M event run stays small in memory
+fn channel_map() -> ChannelMap {
+    let mut board = Board::default();
+    board.channels[0] = ChannelType::ScintLeft;
+    ChannelMap::new(&[board]).unwrap()
+}
+
+fn make_events() -> Vec<Vec<CompassData>> {
+    (0..EVENTS)
+        .map(|i| {
+            vec![CompassData {
+                uuid: generate_board_channel_uuid(&0, &0),
+                energy: 1000.0,
+                energy_short: Some(500.0),
+                timestamp: i as i64 * 1_000_000,
+                waveform: None,
+            }]
+        })
+        .collect()
+}
+
+fn fill(mut data: ChannelData, events: Vec<Vec<CompassData>>, map: &ChannelMap) -> ChannelData {
+    for event in events {
+        data.append_event(event, map, None);
+    }
+    data
+}
+
+// Allocations made while filling, the event vectors themselves are built beforehand
+fn count_allocations(data: ChannelData, map: &ChannelMap) -> usize {
+    let events = make_events();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let data = fill(data, events, map);
+    let count = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    drop(data);
+    count
+}
+
+fn preallocate(c: &mut Criterion) {
+    let map = channel_map();
+    let params = BuildParams::default();
+
+    println!(
+        "Allocations for {} events: new = {}, with_capacity = {}",
+        EVENTS,
+        count_allocations(ChannelData::new(&map, &params), &map),
+        count_allocations(ChannelData::with_capacity(&map, &params, EVENTS), &map)
+    );
+
+    let mut group = c.benchmark_group("preallocate");
+    group.sample_size(10);
+
+    group.bench_function("new", |b| {
+        b.iter_batched(
+            make_events,
+            |events| fill(ChannelData::new(&map, &params), events, &map),
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.bench_function("with_capacity", |b| {
+        b.iter_batched(
+            make_events,
+            |events| {
+                fill(
+                    ChannelData::with_capacity(&map, &params, EVENTS),
+                    events,
+                    &map,
+                )
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, preallocate);
+criterion_main!(benches);
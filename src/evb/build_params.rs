@@ -0,0 +1,840 @@
+use std::borrow::Cow;
+use std::ops::RangeInclusive;
+
+use polars::prelude::{polars_err, GzipLevel, ParquetCompression, PolarsResult, ZstdLevel};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::{AsRefStr, EnumIter};
+
+use super::channel_data::{ChannelDataField, INVALID_VALUE};
+use super::channel_map::ChannelType;
+use super::column_alias::{resolve_column_names, ColumnAlias, ColumnPrefix};
+use super::compass_data::CompassData;
+use super::event_builder::TimeSlice;
+use super::expression::DerivedFieldEntry;
+use super::position::{FocalPlaneConfig, FocalPlaneOverride};
+
+// Which hit fills a detector's fields when the detector fires more than once in an event
+#[derive(Debug, Clone, Copy, Default, PartialEq, AsRefStr, EnumIter, Serialize, Deserialize)]
+pub enum MultiHitPolicy {
+    #[default]
+    Last,
+    First,
+    MaxEnergy, // uncalibrated energy
+    MinTime,
+}
+
+impl MultiHitPolicy {
+    // Whether a later hit replaces the hit currently chosen for its detector, ties keep the current hit
+    pub fn prefers(&self, candidate: &CompassData, current: &CompassData) -> bool {
+        match self {
+            MultiHitPolicy::Last => true,
+            MultiHitPolicy::First => false,
+            MultiHitPolicy::MaxEnergy => candidate.energy > current.energy,
+            MultiHitPolicy::MinTime => candidate.timestamp < current.timestamp,
+        }
+    }
+}
+
+// Codec of the Parquet output files. Zstd is what polars writes by default, Snappy is the one older readers
+// are most likely to handle
+#[derive(Debug, Clone, Copy, Default, PartialEq, AsRefStr, EnumIter, Serialize, Deserialize)]
+pub enum ParquetCodec {
+    #[default]
+    Zstd,
+    Snappy,
+    Gzip,
+    Lz4,
+    Uncompressed,
+}
+
+impl ParquetCodec {
+    // Levels the codec accepts, None for codecs without a level
+    pub fn levels(&self) -> Option<RangeInclusive<i32>> {
+        match self {
+            ParquetCodec::Zstd => Some(1..=22),
+            ParquetCodec::Gzip => Some(0..=9),
+            _ => None,
+        }
+    }
+
+    // Compression for the Parquet writer. A level of None is the codec's default, and the level is ignored
+    // by codecs without one
+    pub fn compression(&self, level: Option<i32>) -> PolarsResult<ParquetCompression> {
+        // Polars takes gzip level 10 as well, keep to the range offered in the UI
+        if let (Some(level), Some(levels)) = (level, self.levels()) {
+            if !levels.contains(&level) {
+                return Err(
+                    polars_err!(InvalidOperation: "invalid {} level {}", self.as_ref(), level),
+                );
+            }
+        }
+        Ok(match self {
+            ParquetCodec::Zstd => {
+                ParquetCompression::Zstd(level.map(ZstdLevel::try_new).transpose()?)
+            }
+            ParquetCodec::Gzip => ParquetCompression::Gzip(
+                level
+                    .map(|level| {
+                        u8::try_from(level)
+                            .map_err(
+                                |_| polars_err!(InvalidOperation: "invalid gzip level {}", level),
+                            )
+                            .and_then(GzipLevel::try_new)
+                    })
+                    .transpose()?,
+            ),
+            ParquetCodec::Snappy => ParquetCompression::Snappy,
+            ParquetCodec::Lz4 => ParquetCompression::Lz4Raw,
+            ParquetCodec::Uncompressed => ParquetCompression::Uncompressed,
+        })
+    }
+}
+
+// What happens to a hit whose energy is outside of its detector's EnergyLimit
+#[derive(Debug, Clone, Copy, Default, PartialEq, AsRefStr, EnumIter, Serialize, Deserialize)]
+pub enum EnergyLimitPolicy {
+    #[default]
+    Drop,
+    Clamp,
+}
+
+// Valid range of uncalibrated energies for one detector type, e.g. to catch CoMPASS overflow codes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnergyLimit {
+    pub channel_type: ChannelType,
+    pub min: f64,
+    pub max: f64,
+    pub policy: EnergyLimitPolicy,
+}
+
+impl Default for EnergyLimit {
+    fn default() -> Self {
+        EnergyLimit {
+            channel_type: ChannelType::None,
+            min: 0.0,
+            max: 65535.0,
+            policy: EnergyLimitPolicy::default(),
+        }
+    }
+}
+
+impl EnergyLimit {
+    // The hit to use in its place, None if it should be dropped
+    pub fn apply<'a>(&self, hit: &'a CompassData) -> Option<Cow<'a, CompassData>> {
+        if hit.energy >= self.min && hit.energy <= self.max {
+            return Some(Cow::Borrowed(hit));
+        }
+        match self.policy {
+            EnergyLimitPolicy::Drop => None,
+            EnergyLimitPolicy::Clamp => Some(Cow::Owned(CompassData {
+                energy: hit.energy.max(self.min).min(self.max), // no panic on min > max like clamp
+                ..hit.clone()
+            })),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, index: usize, on_remove: impl FnOnce()) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt(format!("energy_limit_type_{}", index))
+                .selected_text(self.channel_type.as_ref())
+                .show_ui(ui, |ui| {
+                    for variant in ChannelType::iter() {
+                        ui.selectable_value(&mut self.channel_type, variant, variant.as_ref());
+                    }
+                });
+            ui.add(
+                egui::DragValue::new(&mut self.min)
+                    .speed(1.0)
+                    .prefix("Min: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.max)
+                    .speed(1.0)
+                    .prefix("Max: "),
+            );
+            egui::ComboBox::from_id_salt(format!("energy_limit_policy_{}", index))
+                .selected_text(self.policy.as_ref())
+                .show_ui(ui, |ui| {
+                    for policy in EnergyLimitPolicy::iter() {
+                        ui.selectable_value(&mut self.policy, policy, policy.as_ref());
+                    }
+                });
+
+            if ui.button("❌").clicked() {
+                on_remove();
+            }
+        });
+    }
+}
+
+// Options which control the optional output fields and the physics calculated for each event
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildParams {
+    #[serde(alias = "detector_bitmask")] // saved configs from before the column was renamed
+    pub hit_pattern: bool,
+    pub multiplicity: bool,
+    pub multi_hit_policy: MultiHitPolicy,
+    pub normalize_xavg_weights: bool,
+    pub xavg_weight_slopes: Option<(f64, f64)>, // change of (w1, w2) per mm of X1, constant weights if None
+    pub theta_degrees: bool,
+    pub delay_line_time_diff: bool,
+    pub delay_line_check: Option<f64>, // warn when more than this fraction of a plane's differences is negative, off if None
+    pub time_spread: bool, // report the mean and approximate FWHM of each relative time at the end of a run
+    pub keep_raw_energy: bool,
+    pub clamp_pedestals: bool, // energies below their channel's pedestal become zero instead of negative
+    pub no_short_gate: bool, // set for firmware without PSD, whose files have no short gate energy
+    pub psd_fields: bool, // (energy - short) / energy of the scintillators and CeBrA as <Detector>PSD
+    pub time_unit: Option<u64>, // whole ps per timestamp tick of the files, 1 (ps) if None, 1000 for firmware writing ns
+    pub copied_short_tolerance: Option<f64>, // short energies this close to the energy are left unset, off if None
+    pub energy_step: Option<f64>, // energies are written rounded to a multiple of this, unrounded if None
+    pub provenance_columns: bool,
+    pub anchor: Option<ChannelType>, // opens the events and is the reference of the relative times, None is ScintLeft
+    pub scint_right_fallback: bool,
+    pub scint_right_offset: f64, // ns, ScintRight time - ScintLeft time for the same event
+    pub cebra_rel_time_right: bool,
+    pub cebra_reference: Option<ChannelType>, // None is the anchor, with the ScintRight fallback if enabled
+    pub prune_empty_columns: bool,
+    pub keep_invalid_sentinel: bool,
+    pub sentinel_value: Option<f64>, // written for unset values with keep_invalid_sentinel, -1e6 if None
+    pub parallel: bool,
+    pub parallel_batch_hits: Option<usize>, // hits gathered before each parallel build, 1e6 if None
+    pub slice_start: Option<f64>, // s, events opened earlier are left out, from the start of the run if None
+    pub slice_stop: Option<f64>, // s, events opened at or after it are left out, to the end of the run if None
+    pub check_time_order: bool,
+    pub channel_counts: bool,
+    pub strict_channel_map: bool, // fail a run with hits from unmapped channels instead of dropping them
+    pub f32_storage: bool,
+    pub integer_timestamps: bool,
+    pub stream_output: bool,
+    pub resume_output: bool, // continue an existing streamed output file instead of overwriting it
+    pub parquet_codec: ParquetCodec,
+    pub compression_level: Option<i32>, // None is the codec's default level
+    pub csv_preview: bool,
+    pub root_output: bool, // only used when built with the root feature
+    pub focal_plane: FocalPlaneConfig,
+    pub focal_plane_overrides: Vec<FocalPlaneOverride>, // the first one containing a run replaces focal_plane for it
+    pub derived_fields: Vec<DerivedFieldEntry>,
+    pub energy_limits: Vec<EnergyLimit>, // detector types without a limit accept any energy
+    pub column_aliases: Vec<ColumnAlias>,
+    pub column_prefix: String, // prepended to the name of every built-in column which is not aliased
+    pub column_prefixes: Vec<ColumnPrefix>, // detector types with their own prefix instead of column_prefix
+    pub waveform_channels: Vec<ChannelType>, // detector types whose waveform samples are written, none if empty
+    pub required_fields: Vec<String>, // fields an event needs to be a GoodEvent, no GoodEvent column if empty
+    pub included_fields: Vec<String>, // names or * patterns of the only fields written besides EventTime, all if empty
+    pub excluded_fields: Vec<String>, // names or * patterns of fields which are not written even when mapped
+    pub drop_bad_events: bool, // leave out the events which are not good instead of flagging them
+    pub drop_empty_events: bool, // leave out the events without a hit of a mapped, enabled channel
+}
+
+impl BuildParams {
+    // The params used for the given run, with focal_plane replaced by the first override containing it
+    pub fn for_run(&self, run: i32) -> BuildParams {
+        let mut params = self.clone();
+        if let Some(entry) = self.focal_plane_overrides.iter().find(|o| o.contains(run)) {
+            params.focal_plane = entry.focal_plane.clone();
+        }
+        params
+    }
+
+    // Value written for unset values when keep_invalid_sentinel is set
+    pub fn sentinel(&self) -> f64 {
+        self.sentinel_value.unwrap_or(INVALID_VALUE)
+    }
+
+    // Factor taking the file timestamps to ps, see time_unit
+    pub fn time_scale(&self) -> u64 {
+        self.time_unit.unwrap_or(1)
+    }
+
+    // Bounds in ps of the events built from each run, see slice_start and slice_stop
+    pub fn time_slice(&self) -> TimeSlice {
+        let to_ps = |seconds: f64| (seconds * 1.0e12).round() as i64;
+        TimeSlice {
+            start: self.slice_start.map(to_ps),
+            stop: self.slice_stop.map(to_ps),
+        }
+    }
+
+    // Whether a field given by the channel map is written, see included_fields and excluded_fields. Blank
+    // entries are ignored, and EventTime is always written
+    pub fn is_field_selected(&self, field: &ChannelDataField) -> bool {
+        if *field == ChannelDataField::EventTime {
+            return true;
+        }
+        let patterns = |list: &[String]| -> Vec<String> {
+            list.iter()
+                .map(|pattern| pattern.trim().to_string())
+                .filter(|pattern| !pattern.is_empty())
+                .collect()
+        };
+        let included = patterns(&self.included_fields);
+        let excluded = patterns(&self.excluded_fields);
+        (included.is_empty()
+            || included
+                .iter()
+                .any(|pattern| field.matches_pattern(pattern)))
+            && !excluded
+                .iter()
+                .any(|pattern| field.matches_pattern(pattern))
+    }
+
+    // Compression of the Parquet output files, fails for a level the codec does not accept
+    pub fn parquet_compression(&self) -> PolarsResult<ParquetCompression> {
+        self.parquet_codec.compression(self.compression_level)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("BuildGrid").show(ui, |ui| {
+            ui.checkbox(&mut self.hit_pattern, "Hit Pattern")
+                .on_hover_text("Write a HitPattern column with one bit set for each detector type that fired in the event");
+            ui.end_row();
+
+            ui.checkbox(&mut self.multiplicity, "Multiplicity")
+                .on_hover_text("Write the number of hits of each mapped detector in the event as <Detector>Multiplicity.\nOnly one hit of a detector is kept in its other fields, see Multi-Hit Policy");
+            ui.end_row();
+
+            ui.horizontal(|ui| {
+                ui.label("Multi-Hit Policy");
+                egui::ComboBox::from_id_salt("multi_hit_policy")
+                    .selected_text(self.multi_hit_policy.as_ref())
+                    .show_ui(ui, |ui| {
+                        for policy in MultiHitPolicy::iter() {
+                            ui.selectable_value(&mut self.multi_hit_policy, policy, policy.as_ref());
+                        }
+                    });
+            })
+            .response
+            .on_hover_text("Hit used for a detector which fires more than once in an event.\nMaxEnergy compares the uncalibrated energies. Relative times and positions use the same hit");
+            ui.end_row();
+
+            ui.checkbox(&mut self.normalize_xavg_weights, "Normalize Xavg Weights")
+                .on_hover_text("Divide the Xavg weights by their sum. By default the raw weighted sum is used");
+            ui.end_row();
+
+            let mut position_dependent = self.xavg_weight_slopes.is_some();
+            ui.checkbox(&mut position_dependent, "Position Dependent Xavg Weights")
+                .on_hover_text("Vary the kinematic Xavg weights linearly with X1, w1 + s1 * X1 and w2 + s2 * X1,\nfor a best weighting which drifts across the focal plane");
+            if position_dependent != self.xavg_weight_slopes.is_some() {
+                self.xavg_weight_slopes = position_dependent.then_some((0.0, 0.0));
+            }
+            if let Some((s1, s2)) = &mut self.xavg_weight_slopes {
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(s1).speed(1.0e-4).prefix("s1: ").suffix(" /mm"));
+                    ui.add(egui::DragValue::new(s2).speed(1.0e-4).prefix("s2: ").suffix(" /mm"));
+                });
+            }
+            ui.end_row();
+
+            ui.checkbox(&mut self.theta_degrees, "Theta in Degrees")
+                .on_hover_text("Also write Theta converted to degrees as ThetaDeg. Theta itself stays in radians");
+            ui.end_row();
+
+            ui.checkbox(&mut self.delay_line_time_diff, "Delay Line Time Difference")
+                .on_hover_text("Write the unscaled left - right delay line time differences as X1TimeDiff and X2TimeDiff");
+            ui.end_row();
+
+            ui.horizontal(|ui| {
+                let mut check = self.delay_line_check.is_some();
+                if ui
+                    .checkbox(&mut check, "Delay Line Check")
+                    .on_hover_text("Report the fraction of events with a negative left - right delay line time difference for X1 and X2 at the end of each run,\nand warn when it is above the threshold. Nearly every difference being negative usually means swapped cables")
+                    .changed()
+                {
+                    self.delay_line_check = check.then_some(0.9);
+                }
+                if let Some(threshold) = &mut self.delay_line_check {
+                    ui.add(
+                        egui::DragValue::new(threshold)
+                            .speed(0.01)
+                            .range(0.0..=1.0)
+                            .prefix("Threshold: "),
+                    );
+                }
+            });
+            ui.end_row();
+
+            ui.checkbox(&mut self.time_spread, "Time Resolution Report")
+                .on_hover_text("Report the mean and approximate FWHM (2.355 standard deviations) of every relative time at the end of each run,\nas a quick time resolution of each detector for monitoring. Off by default to save the per-event cost");
+            ui.end_row();
+
+            ui.checkbox(&mut self.keep_raw_energy, "Keep Raw Energy")
+                .on_hover_text("Write the uncalibrated energy of each detector as <Detector>EnergyRaw");
+            ui.end_row();
+
+            ui.checkbox(&mut self.clamp_pedestals, "Clamp Pedestals")
+                .on_hover_text("Store energies below their channel's pedestal (see the Pedestal Map) as zero instead of negative");
+            ui.end_row();
+
+            ui.checkbox(&mut self.no_short_gate, "No Short Gate")
+                .on_hover_text("Leave out the <Detector>Short and <Detector>PSD columns, for digitizer firmware without PSD.\nShort values are only stored for hits whose file header has the short gate energy bit set,\nso these columns would otherwise be empty. Set no_short_gate in a saved config to do the same");
+            ui.end_row();
+
+            ui.add_enabled(!self.no_short_gate, egui::Checkbox::new(&mut self.psd_fields, "PSD Fields"))
+                .on_hover_text("Write (energy - short) / energy of the scintillators and each CeBrA detector as <Detector>PSD, for particle/gamma discrimination.\nCalculated from the uncalibrated energies, and left unset for hits without a short gate energy or with an energy of zero");
+            ui.end_row();
+
+            ui.horizontal(|ui| {
+                let mut scaled = self.time_unit.is_some();
+                if ui
+                    .checkbox(&mut scaled, "Timestamp Unit")
+                    .on_hover_text("Picoseconds per timestamp tick of the CoMPASS files, for firmware which does not write ps (1000 for ns).\nApplied as the hits are read, so every Time, RelTime, and TimeDiff column, EventTime, X1/X2 and the positions\nderived from them, and the coincidence window all use the same unit. Files are read as ps when unchecked")
+                    .changed()
+                {
+                    self.time_unit = scaled.then_some(1000);
+                }
+                if let Some(unit) = &mut self.time_unit {
+                    ui.add(
+                        egui::DragValue::new(unit)
+                            .speed(1.0)
+                            .range(1..=u64::MAX)
+                            .suffix(" ps"),
+                    );
+                }
+            });
+            ui.end_row();
+
+            ui.horizontal(|ui| {
+                let mut suppress = self.copied_short_tolerance.is_some();
+                if ui
+                    .checkbox(&mut suppress, "Suppress Copied Short")
+                    .on_hover_text("Leave the short gate energy (and CATRINA PSD) of a hit unset when it is within the tolerance of the energy,\nfor firmware with the short gate disabled which copies the energy into it. Combine with Prune Empty Columns\nto drop the columns for long-gate-only runs")
+                    .changed()
+                {
+                    self.copied_short_tolerance = suppress.then_some(0.0);
+                }
+                if let Some(tolerance) = &mut self.copied_short_tolerance {
+                    ui.add(
+                        egui::DragValue::new(tolerance)
+                            .speed(0.1)
+                            .range(0.0..=f64::MAX)
+                            .prefix("Tolerance: "),
+                    );
+                }
+            });
+            ui.end_row();
+
+            ui.horizontal(|ui| {
+                let mut quantize = self.energy_step.is_some();
+                if ui
+                    .checkbox(&mut quantize, "Energy Step")
+                    .on_hover_text("Round every energy and short gate energy column to the nearest multiple of the step when it is written,\nso the output files do not store digits the ADC cannot resolve. Times, positions, and PSD are never rounded")
+                    .changed()
+                {
+                    self.energy_step = quantize.then_some(1.0);
+                }
+                if let Some(step) = &mut self.energy_step {
+                    ui.add(
+                        egui::DragValue::new(step)
+                            .speed(0.1)
+                            .range(f64::MIN_POSITIVE..=f64::MAX),
+                    );
+                }
+            });
+            ui.end_row();
+
+            ui.checkbox(&mut self.provenance_columns, "Board/Channel Columns")
+                .on_hover_text("Write the digitizer board and channel of each detector hit as <Detector>Board and <Detector>Channel,\nuseful for finding miswired channels");
+            ui.end_row();
+
+            // The default is ScintLeft, with ScintRight standing in when the fallback is enabled
+            let default_anchor = if self.scint_right_fallback {
+                "ScintLeft (fallback ScintRight)"
+            } else {
+                "ScintLeft"
+            };
+            ui.horizontal(|ui| {
+                ui.label("Anchor");
+                egui::ComboBox::from_id_salt("anchor")
+                    .selected_text(self.anchor.as_ref().map_or(default_anchor, |t| t.as_ref()))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.anchor, None, default_anchor);
+                        for variant in ChannelType::iter().filter(|t| *t != ChannelType::None) {
+                            ui.selectable_value(&mut self.anchor, Some(variant), variant.as_ref());
+                        }
+                    });
+            })
+            .response
+            .on_hover_text("Detector the relative times are referenced to, e.g. ScintRight for runs where it is the trigger.\nA chosen anchor opens the events: the coincidence window runs from each of its hits, and it is the EventTime.\nEvents without an anchor hit are opened by their first hit, as are all events with the default.\nThe default ScintLeft keeps EventTime as the earliest hit and can use the ScintRight fallback");
+            ui.end_row();
+
+            ui.checkbox(&mut self.scint_right_fallback, "ScintRight Fallback")
+                .on_hover_text("Reference relative times to ScintRight when ScintLeft did not fire, only with the default anchor.\nRelTimeReference records the reference used: 0 = none, 1 = ScintLeft, 2 = ScintRight");
+            ui.add_enabled(
+                self.scint_right_fallback,
+                egui::DragValue::new(&mut self.scint_right_offset)
+                    .speed(1.0)
+                    .prefix("Offset: ")
+                    .suffix(" ns"),
+            )
+            .on_hover_text("ScintRight time - ScintLeft time, subtracted from ScintRight when it is used as the reference");
+            ui.end_row();
+
+            ui.horizontal(|ui| {
+                ui.label("CeBrA Reference");
+                egui::ComboBox::from_id_salt("cebra_reference")
+                    .selected_text(self.cebra_reference.as_ref().map_or("Anchor", |t| t.as_ref()))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.cebra_reference, None, "Anchor");
+                        for variant in ChannelType::iter().filter(|t| *t != ChannelType::None) {
+                            ui.selectable_value(&mut self.cebra_reference, Some(variant), variant.as_ref());
+                        }
+                    });
+            })
+            .response
+            .on_hover_text("Detector the Cebra<N>RelTime columns are referenced to, e.g. an anode for beam-off runs.\nThe default uses the anchor, with the ScintRight fallback, and needs AnodeBack like the other relative times.\nThe reference is recorded as CebraRelTimeReference in the file metadata");
+            ui.end_row();
+
+            ui.checkbox(&mut self.cebra_rel_time_right, "CeBrA ScintRight Relative Time")
+                .on_hover_text("Also write CeBrA times relative to ScintRight as Cebra<N>RelTimeRight");
+            ui.end_row();
+
+            ui.checkbox(&mut self.prune_empty_columns, "Prune Empty Columns")
+                .on_hover_text("Drop columns which never received a value in a run (every row is -1e6).\nOutput files may then have different columns from run to run, or fragment to fragment");
+            ui.end_row();
+
+            ui.checkbox(&mut self.keep_invalid_sentinel, "Keep Sentinel")
+                .on_hover_text("Write a sentinel (-1e6 unless set) for values which were not set in an event, as older versions did.\nBy default these are written as nulls. GoodEvent and Vetoed stay boolean columns and are set in every event");
+            ui.horizontal(|ui| {
+                ui.add_enabled_ui(self.keep_invalid_sentinel, |ui| {
+                    let mut custom = self.sentinel_value.is_some();
+                    if ui.checkbox(&mut custom, "Custom").changed() {
+                        self.sentinel_value = custom.then_some(INVALID_VALUE);
+                    }
+                    if let Some(value) = &mut self.sentinel_value {
+                        ui.add(egui::DragValue::new(value).speed(1.0).prefix("Sentinel: "));
+                    }
+                });
+            })
+            .response
+            .on_hover_text("Sentinel for runs where -1e6 is a real value, e.g. relative times in long windows");
+            ui.end_row();
+
+            ui.checkbox(&mut self.drop_empty_events, "Drop Empty Events")
+                .on_hover_text("Leave out the events where no hit came from a mapped and enabled channel (or every such hit was dropped\nby an energy limit), which would otherwise be rows with nothing but EventTime.\nUnchecked, every coincidence window is a row");
+            ui.end_row();
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.parallel, "Parallel Event Building");
+                let mut custom = self.parallel_batch_hits.is_some();
+                if ui.checkbox(&mut custom, "Batch Size").changed() {
+                    self.parallel_batch_hits = custom.then_some(1_000_000);
+                }
+                if let Some(hits) = &mut self.parallel_batch_hits {
+                    ui.add(egui::DragValue::new(hits).speed(1000.0).range(1..=usize::MAX).suffix(" hits"));
+                }
+            })
+            .response
+            .on_hover_text("Build events on all CPU cores. The output is identical to the single-threaded build.\nThe hits are built in batches of about 1 million hits unless set, each batch is only ended at a gap\nlonger than the coincidence window so no event is split between two batches");
+            ui.end_row();
+
+            ui.horizontal(|ui| {
+                for (bound, label, default) in [
+                    (&mut self.slice_start, "Start At", 0.0),
+                    (&mut self.slice_stop, "Stop At", 30.0),
+                ] {
+                    let mut bounded = bound.is_some();
+                    if ui.checkbox(&mut bounded, label).changed() {
+                        *bound = bounded.then_some(default);
+                    }
+                    if let Some(seconds) = bound {
+                        ui.add(
+                            egui::DragValue::new(seconds)
+                                .speed(1.0)
+                                .range(0.0..=f64::MAX)
+                                .suffix(" s"),
+                        );
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Only build the events opened within this time of each run, e.g. the first 30 s for debugging.\nEvents straddling a bound are kept or left out whole, and reading stops at the stop.\nThe run is built on one core when either bound is set");
+            ui.end_row();
+
+            ui.checkbox(&mut self.check_time_order, "Check Time Order")
+                .on_hover_text("Count the hits which are earlier than the hit before them, and report the count and the first\nsuch timestamp at the end of each run. Out of order hits mean an input file was not sorted,\nwhich breaks the coincidence window and the relative times");
+            ui.end_row();
+
+            ui.checkbox(&mut self.channel_counts, "Channel Counts")
+                .on_hover_text("Count every hit of each board and channel, mapped or not, before event building.\nThe counts and rates are written to scalers/run_<N>_channel_counts.txt");
+            ui.end_row();
+
+            ui.checkbox(&mut self.strict_channel_map, "Strict Channel Map")
+                .on_hover_text("Fail a run at its first hit from a channel missing in the channel map, giving its board and channel.\nNothing is written when the channel has a file of its own. Otherwise those hits are dropped and a warning lists them");
+            ui.end_row();
+
+            ui.checkbox(&mut self.f32_storage, "Single Precision")
+                .on_hover_text("Store and write energies, positions, and relative times as 32-bit floats to halve their memory.\nAbsolute timestamps and flags are always kept as 64-bit values");
+            ui.end_row();
+
+            ui.checkbox(&mut self.integer_timestamps, "Integer Timestamps")
+                .on_hover_text("Write absolute timestamps as 64-bit integer picoseconds instead of floating point ns.\nTimestamps are always kept exact while building; floating point ns lose picosecond precision after about 2.5 hours");
+            ui.end_row();
+
+            ui.checkbox(&mut self.stream_output, "Stream Output")
+                .on_hover_text("Write each run to disk in row groups of 100000 events instead of holding the whole run in memory.\nThe columns are fixed by the channel map, so Prune Empty Columns is ignored");
+            ui.end_row();
+
+            ui.add_enabled(
+                self.stream_output,
+                egui::Checkbox::new(&mut self.resume_output, "Resume Output"),
+            )
+            .on_hover_text("Continue a run's existing output file, e.g. one stopped with Max Events or a time slice, instead of\noverwriting it. Its columns must match the current channel map and options. Only the events after its\nlast EventTime are built, on one core. A crashed build continues from its last checkpoint, every 16 row groups");
+            ui.end_row();
+
+            ui.horizontal(|ui| {
+                ui.label("Compression");
+                egui::ComboBox::from_id_salt("parquet_codec")
+                    .selected_text(self.parquet_codec.as_ref())
+                    .show_ui(ui, |ui| {
+                        for codec in ParquetCodec::iter() {
+                            ui.selectable_value(&mut self.parquet_codec, codec, codec.as_ref());
+                        }
+                    });
+                if let Some(levels) = self.parquet_codec.levels() {
+                    let mut custom = self.compression_level.is_some();
+                    if ui.checkbox(&mut custom, "Level").changed() {
+                        self.compression_level = custom.then_some(*levels.start());
+                    }
+                    if let Some(level) = &mut self.compression_level {
+                        ui.add(egui::DragValue::new(level).range(levels));
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Codec of the Parquet output files. Zstd is the default, use Snappy for older readers\nwhich do not handle it. Without a level the codec's default level is used");
+            ui.end_row();
+
+            ui.checkbox(&mut self.csv_preview, "CSV Preview")
+                .on_hover_text("Also write the first 10000 events of each run to run_<N>.csv for a quick look in a spreadsheet.\nUnset values are empty cells and the X/Z list columns are left out");
+            ui.end_row();
+
+            #[cfg(feature = "root")]
+            {
+                ui.checkbox(&mut self.root_output, "ROOT Output")
+                    .on_hover_text("Also write each output file as a flat TTree named \"events\" in a .root file next to it.\nEvery column is a double branch, unset values are NaN and the X/Z list columns are left out");
+                ui.end_row();
+            }
+        });
+
+        ui.collapsing("Focal Plane", |ui| {
+            self.focal_plane.ui(ui, "FocalPlaneGrid");
+
+            ui.horizontal(|ui| {
+                ui.label("Per-run overrides")
+                    .on_hover_text("Geometry used instead of the one above for a range of runs, e.g. after the detector was moved.\nThe first override containing a run is used");
+                if ui.button("+").clicked() {
+                    self.focal_plane_overrides.push(FocalPlaneOverride::default());
+                }
+            });
+
+            let mut remove_indices = vec![];
+            for (index, entry) in self.focal_plane_overrides.iter_mut().enumerate() {
+                entry.ui(ui, index, || {
+                    remove_indices.push(index);
+                });
+            }
+
+            for &index in remove_indices.iter().rev() {
+                self.focal_plane_overrides.remove(index);
+            }
+        });
+
+        ui.collapsing("Derived Fields", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Columns calculated from the other fields of each event")
+                    .on_hover_text("Use field names, numbers, + - * / and parentheses, e.g. AnodeFrontEnergy / CathodeEnergy.\nA derived value is unset whenever a field it uses is unset. Derived columns come after the built-in ones");
+                if ui.button("+").clicked() {
+                    self.derived_fields.push(DerivedFieldEntry::default());
+                }
+            });
+
+            let mut remove_indices = vec![];
+            for (index, entry) in self.derived_fields.iter_mut().enumerate() {
+                entry.ui(ui, || {
+                    remove_indices.push(index);
+                });
+            }
+
+            for &index in remove_indices.iter().rev() {
+                self.derived_fields.remove(index);
+            }
+        });
+
+        ui.collapsing("Energy Limits", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Valid uncalibrated energy range per detector type")
+                    .on_hover_text("Hits outside of the range are dropped, and not counted in the multiplicities, or clamped to the range.\nDetector types without a limit accept any energy");
+                if ui.button("+").clicked() {
+                    self.energy_limits.push(EnergyLimit::default());
+                }
+            });
+
+            let mut remove_indices = vec![];
+            for (index, limit) in self.energy_limits.iter_mut().enumerate() {
+                limit.ui(ui, index, || {
+                    remove_indices.push(index);
+                });
+            }
+
+            for &index in remove_indices.iter().rev() {
+                self.energy_limits.remove(index);
+            }
+        });
+
+        ui.collapsing("Column Aliases", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Output names for the built-in fields")
+                    .on_hover_text("Fields without an alias keep their default name. Aliases must not collide with each other or with another column.\nFiles written with an alias for X1 or X2 can not be used to fit the Xavg weights");
+                if ui.button("+").clicked() {
+                    self.column_aliases.push(ColumnAlias::default());
+                }
+            });
+
+            if let Err(e) = resolve_column_names(self) {
+                ui.colored_label(egui::Color32::RED, e.to_string());
+            }
+
+            let mut remove_indices = vec![];
+            for (index, alias) in self.column_aliases.iter_mut().enumerate() {
+                alias.ui(ui, index, || {
+                    remove_indices.push(index);
+                });
+            }
+
+            for &index in remove_indices.iter().rev() {
+                self.column_aliases.remove(index);
+            }
+        });
+
+        ui.collapsing("Column Prefixes", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Prefix")
+                    .on_hover_text("Prepended to the name of every built-in column without an alias, e.g. \"sps_\" for sps_X1.\nDerived fields are written as named. Only the output names change: required fields, derived field\nexpressions, and column aliases still use the field names. Files with a prefix can not be used to fit the Xavg weights");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.column_prefix)
+                        .hint_text("None")
+                        .desired_width(100.0),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Detector types with their own prefix")
+                    .on_hover_text("Used for the columns of that detector type in place of the prefix above, an empty prefix leaves them unprefixed");
+                if ui.button("+").clicked() {
+                    self.column_prefixes.push(ColumnPrefix::default());
+                }
+            });
+
+            if let Err(e) = resolve_column_names(self) {
+                ui.colored_label(egui::Color32::RED, e.to_string());
+            }
+
+            let mut remove_indices = vec![];
+            for (index, prefix) in self.column_prefixes.iter_mut().enumerate() {
+                prefix.ui(ui, index, || {
+                    remove_indices.push(index);
+                });
+            }
+
+            for &index in remove_indices.iter().rev() {
+                self.column_prefixes.remove(index);
+            }
+        });
+
+        ui.collapsing("Waveforms", |ui| {
+            ui.label("Detector types whose waveform samples are written")
+                .on_hover_text("Each selected detector gets a <Detector>Waveform list column with the samples of its hit in each event,
+empty for files recorded without waveforms. Waveforms are large, so none are kept by default");
+            ui.horizontal_wrapped(|ui| {
+                for channel_type in ChannelType::iter().filter(|t| *t != ChannelType::None) {
+                    let mut selected = self.waveform_channels.contains(&channel_type);
+                    if ui.checkbox(&mut selected, channel_type.as_ref()).changed() {
+                        if selected {
+                            self.waveform_channels.push(channel_type);
+                        } else {
+                            self.waveform_channels.retain(|t| *t != channel_type);
+                        }
+                    }
+                }
+            });
+        });
+
+        ui.collapsing("Good Events", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Fields every good event must have")
+                    .on_hover_text("GoodEvent is true for the events where all of these fields were set, e.g. ScintLeftEnergy, AnodeFrontEnergy, and X1.\nThere is no GoodEvent column without required fields");
+                if ui.button("+").clicked() {
+                    self.required_fields.push(String::new());
+                }
+            });
+            ui.checkbox(&mut self.drop_bad_events, "Drop Bad Events")
+                .on_hover_text("Leave out the events which are not good instead of writing a GoodEvent column");
+
+            let mut remove_indices = vec![];
+            for (index, name) in self.required_fields.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt(format!("required_field_{}", index))
+                        .selected_text(name.as_str())
+                        .show_ui(ui, |ui| {
+                            for field in ChannelDataField::iter() {
+                                ui.selectable_value(name, field.as_ref().to_string(), field.as_ref());
+                            }
+                        });
+                    if ui.button("❌").clicked() {
+                        remove_indices.push(index);
+                    }
+                });
+            }
+
+            for &index in remove_indices.iter().rev() {
+                self.required_fields.remove(index);
+            }
+        });
+
+        ui.collapsing("Written Fields", |ui| {
+            ui.label("Columns given by the channel map can be narrowed down without changing the map")
+                .on_hover_text("Field names, where * stands for any characters, e.g. *Short for every short gate energy.\nEventTime is always written. Leaving out a field another one is calculated from is reported when the configuration is checked");
+            for (label, hover, list, salt) in [
+                (
+                    "Only Write",
+                    "Only these fields are written when any are given",
+                    &mut self.included_fields,
+                    "included_field",
+                ),
+                (
+                    "Never Write",
+                    "These fields are not written, even when their detector is mapped",
+                    &mut self.excluded_fields,
+                    "excluded_field",
+                ),
+            ] {
+                ui.horizontal(|ui| {
+                    ui.label(label).on_hover_text(hover);
+                    if ui.button("+").clicked() {
+                        list.push(String::new());
+                    }
+                });
+
+                let mut remove_indices = vec![];
+                for (index, pattern) in list.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(pattern)
+                                .id_salt(format!("{}_{}", salt, index))
+                                .hint_text("*Short")
+                                .desired_width(200.0),
+                        );
+                        if ui.button("❌").clicked() {
+                            remove_indices.push(index);
+                        }
+                    });
+                }
+
+                for &index in remove_indices.iter().rev() {
+                    list.remove(index);
+                }
+            }
+        });
+    }
+}
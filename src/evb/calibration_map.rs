@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use super::compass_data::generate_board_channel_uuid;
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct CalibrationMapEntry {
+    pub board_number: u32,
+    pub channel_number: u32,
+    pub a: f64, // calibrated energy = a * E^2 + b * E + c
+    pub b: f64,
+    pub c: f64,
+}
+
+impl CalibrationMapEntry {
+    pub fn ui(&mut self, ui: &mut egui::Ui, on_remove: impl FnOnce()) {
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.board_number).prefix("Board: "));
+            ui.add(egui::DragValue::new(&mut self.channel_number).prefix("Channel: "));
+            ui.add(
+                egui::DragValue::new(&mut self.a)
+                    .speed(1.0e-6)
+                    .prefix("a: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.b)
+                    .speed(1.0e-3)
+                    .prefix("b: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.c)
+                    .speed(1.0e-1)
+                    .prefix("c: "),
+            );
+
+            if ui.button("❌").clicked() {
+                on_remove();
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+}
+
+impl Calibration {
+    pub fn apply(&self, energy: f64) -> f64 {
+        self.a * energy * energy + self.b * energy + self.c
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CalibrationMap {
+    map: HashMap<u32, Calibration>,
+}
+
+impl CalibrationMap {
+    pub fn new(entries: Vec<CalibrationMapEntry>) -> CalibrationMap {
+        let mut map = HashMap::new();
+        for entry in entries {
+            let id = generate_board_channel_uuid(&entry.board_number, &entry.channel_number);
+            map.insert(
+                id,
+                Calibration {
+                    a: entry.a,
+                    b: entry.b,
+                    c: entry.c,
+                },
+            );
+        }
+        CalibrationMap { map }
+    }
+
+    pub fn get_calibration(&self, id: &u32) -> Option<&Calibration> {
+        self.map.get(id)
+    }
+
+    // Channels without a calibration are left uncalibrated
+    pub fn calibrate(&self, id: &u32, energy: f64) -> f64 {
+        match self.map.get(id) {
+            Some(calibration) => calibration.apply(energy),
+            None => energy,
+        }
+    }
+}
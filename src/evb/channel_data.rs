@@ -1,143 +1,599 @@
-use super::channel_map::{ChannelMap, ChannelType};
-#[allow(unused_imports)]
-use super::compass_data::{decompose_uuid_to_board_channel, CompassData};
-use super::used_size::UsedSize;
+use super::build_params::BuildParams;
+use super::calibration_map::CalibrationMap;
+use super::channel_map::{ChannelMap, ChannelType, CEBRA_CHANNEL_TYPES};
+use super::column_alias::resolve_column_names;
+use super::compass_data::{decompose_uuid_to_board_channel, ns_to_ps, ps_to_ns, CompassData};
+use super::error::EVBError;
+use super::event_builder::{build_events, split_on_gaps};
+use super::event_view::{Event, EventIter};
+use super::expression::Expression;
+use super::histogram::Histogram;
+use super::pedestal_map::PedestalMap;
+use super::position::{DelayLineCheck, DelayLineData, DelayLineTiming, PositionReconstructor};
+use super::time_spread::TimeSpread;
+use super::used_size::{entry_used_size, UsedSize};
+use std::borrow::Cow;
 use std::hash::Hash;
+use std::sync::Arc;
 use std::{collections::BTreeMap, vec};
 
 use strum::IntoEnumIterator;
 use strum_macros::{AsRefStr, EnumCount, EnumIter};
 
 use polars::prelude::*;
+use rayon::prelude::*;
+
+// Sentinel for values which were not set in an event, used while the events are accumulated. Unset values
+// are written as nulls, or as BuildParams::sentinel() with BuildParams::keep_invalid_sentinel, so a real
+// value of -1e6 is only mistaken for a missing one in memory, not in the output files.
+pub const INVALID_VALUE: f64 = -1.0e6;
+// Sentinel for timestamps (integer ps) which were not set in an event
+pub const INVALID_TIME: i64 = i64::MIN;
+// Normalized Xavg weights summing to less than this leave Xavg unset instead of dividing by zero
+pub const MIN_XAVG_WEIGHT_SUM: f64 = 1.0e-9;
+
+// Declares ChannelDataField from the fields of the event as a whole and the fields of each detector type. A
+// detector field is named after its ChannelType and DetectorQuantity, e.g. Cebra3Energy, and the lookups between
+// the two are generated from the same list, so a new detector type only needs its line below.
+macro_rules! channel_data_fields {
+    (
+        event { $($event:ident = $event_index:literal,)* }
+        detectors { $($detector:ident { $($quantity:ident = $index:literal),* $(,)? })* }
+    ) => {
+        paste::paste! {
+            #[derive(Debug, Clone, Hash, Eq, PartialOrd, Ord, PartialEq, EnumIter, EnumCount, AsRefStr)]
+            pub enum ChannelDataField {
+                $($event = $event_index,)*
+                $($([<$detector $quantity>] = $index,)*)*
+            }
+
+            impl ChannelDataField {
+                // Detector type and quantity of a field holding a value of a single detector, None for the
+                // fields of the event as a whole and the ones calculated from several detectors
+                pub fn detector_field(&self) -> Option<(ChannelType, DetectorQuantity)> {
+                    match self {
+                        $(ChannelDataField::$event => None,)*
+                        $($(ChannelDataField::[<$detector $quantity>] => {
+                            Some((ChannelType::$detector, DetectorQuantity::$quantity))
+                        })*)*
+                    }
+                }
 
-const INVALID_VALUE: f64 = -1.0e6;
-
-#[derive(Debug, Clone, Hash, Eq, PartialOrd, Ord, PartialEq, EnumIter, EnumCount, AsRefStr)]
-pub enum ChannelDataField {
-    AnodeFrontEnergy,
-    AnodeFrontShort,
-    AnodeFrontTime,
-    AnodeBackEnergy,
-    AnodeBackShort,
-    AnodeBackTime,
-    ScintLeftEnergy,
-    ScintLeftShort,
-    ScintLeftTime,
-    ScintRightEnergy,
-    ScintRightShort,
-    ScintRightTime,
-    CathodeEnergy,
-    CathodeShort,
-    CathodeTime,
-    DelayFrontLeftEnergy,
-    DelayFrontLeftShort,
-    DelayFrontLeftTime,
-    DelayFrontRightEnergy,
-    DelayFrontRightShort,
-    DelayFrontRightTime,
-    DelayBackLeftEnergy,
-    DelayBackLeftShort,
-    DelayBackLeftTime,
-    DelayBackRightEnergy,
-    DelayBackRightShort,
-    DelayBackRightTime,
-    MonitorEnergy,
-    MonitorShort,
-    MonitorTime,
-    X1,
-    X2,
-    Xavg,
-    Theta,
-    X,
-    Z,
-
-    Cebra0Energy,
-    Cebra1Energy,
-    Cebra2Energy,
-    Cebra3Energy,
-    Cebra4Energy,
-    Cebra5Energy,
-    Cebra6Energy,
-    Cebra7Energy,
-    Cebra8Energy,
-
-    Cebra0Short,
-    Cebra1Short,
-    Cebra2Short,
-    Cebra3Short,
-    Cebra4Short,
-    Cebra5Short,
-    Cebra6Short,
-    Cebra7Short,
-    Cebra8Short,
-
-    Cebra0Time,
-    Cebra1Time,
-    Cebra2Time,
-    Cebra3Time,
-    Cebra4Time,
-    Cebra5Time,
-    Cebra6Time,
-    Cebra7Time,
-    Cebra8Time,
-
-    Cebra0RelTime,
-    Cebra1RelTime,
-    Cebra2RelTime,
-    Cebra3RelTime,
-    Cebra4RelTime,
-    Cebra5RelTime,
-    Cebra6RelTime,
-    Cebra7RelTime,
-    Cebra8RelTime,
-
-    PIPS1000Energy,
-    PIPS500Energy,
-    PIPS300Energy,
-    PIPS100Energy,
-
-    PIPS1000Time,
-    PIPS500Time,
-    PIPS300Time,
-    PIPS100Time,
-
-    PIPS1000RelTime,
-    PIPS500RelTime,
-    PIPS300RelTime,
-    PIPS100RelTime,
-
-    CATRINA0Energy,
-    CATRINA1Energy,
-    CATRINA2Energy,
-
-    CATRINA0Short,
-    CATRINA1Short,
-    CATRINA2Short,
-
-    CATRINA0Time,
-    CATRINA1Time,
-    CATRINA2Time,
-
-    CATRINA0PSD,
-    CATRINA1PSD,
-    CATRINA2PSD,
+                // Field holding the given quantity of a detector type, None if the type does not have one
+                pub fn of_detector(
+                    channel_type: ChannelType,
+                    quantity: DetectorQuantity,
+                ) -> Option<ChannelDataField> {
+                    match (channel_type, quantity) {
+                        $($((ChannelType::$detector, DetectorQuantity::$quantity) => {
+                            Some(ChannelDataField::[<$detector $quantity>])
+                        })*)*
+                        _ => None,
+                    }
+                }
+            }
+        }
+    };
 }
 
+// Quantity held by a field of a single detector type, see ChannelDataField::detector_field
+#[allow(clippy::upper_case_acronyms)] // named like the column suffix, e.g. ScintLeftPSD
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum DetectorQuantity {
+    Energy, // calibrated when a calibration is given
+    Short,
+    Time,
+    RelTime,
+    RelTimeRight, // CeBrA only, referenced to ScintRight with BuildParams::cebra_rel_time_right
+    PSD,          // (energy - short) / energy
+    EnergyRaw,    // uncalibrated energy, with BuildParams::keep_raw_energy
+    Board,        // with BuildParams::provenance_columns
+    Channel,      // with BuildParams::provenance_columns
+    Multiplicity, // number of hits of the type in the event, with BuildParams::multiplicity
+}
+
+// Columns are written in the order of the explicit index of each field (the derived Ord compares the
+// discriminants), not in the order they are declared, so that readers using column positions are not broken
+// by a reorder. The fields of the first release keep its column order, AnodeFrontEnergy to CATRINA2PSD. A new
+// field takes the next unused index, wherever it is declared, and an index is never reused.
+channel_data_fields! {
+    event {
+        EventTime = 348,
+        AnodeSumEnergy = 98, // AnodeFrontEnergy + AnodeBackEnergy, a dE proxy for particle ID
+        AnodeTimeDiff = 324, // AnodeFrontTime - AnodeBackTime (ns), for drift time diagnostics
+        ScintVerticalTimeDiff = 105, // ScintTopTime - ScintBottomTime (ns), for the vertical position
+        X1 = 30,
+        X2 = 31,
+        X1TimeDiff = 106,
+        X2TimeDiff = 107,
+        Xavg = 32,
+        XavgCharge = 108, // charge division between the anodes, (front - back) / (front + back)
+        Theta = 33,
+        ThetaDeg = 109,
+        X = 34,
+        Z = 35,
+        HitPattern = 322, // one bit for each detector type which fired, see ChannelType::bitmask_bit
+        RelTimeReference = 323,
+        CebraMultiplicity = 325, // number of CeBrA detectors which fired, for gamma multiplicity cuts
+        GoodEvent = 326, // every one of BuildParams::required_fields was set
+        Vetoed = 327, // the Veto channel fired in the event
+    }
+    detectors {
+        AnodeFront {
+            Energy = 0, Short = 1, Time = 2, RelTime = 96, EnergyRaw = 166, Board = 205, Channel = 206,
+            Multiplicity = 283
+        }
+        AnodeBack {
+            Energy = 3, Short = 4, Time = 5, RelTime = 97, EnergyRaw = 167, Board = 207, Channel = 208,
+            Multiplicity = 284
+        }
+        ScintLeft {
+            Energy = 6, Short = 7, Time = 8, PSD = 328, EnergyRaw = 168, Board = 209, Channel = 210,
+            Multiplicity = 285
+        }
+        ScintRight {
+            Energy = 9, Short = 10, Time = 11, PSD = 329, EnergyRaw = 169, Board = 211, Channel = 212,
+            Multiplicity = 286
+        }
+        ScintTop {
+            Energy = 99, Short = 100, Time = 101, PSD = 330, EnergyRaw = 170, Board = 213, Channel = 214,
+            Multiplicity = 287
+        }
+        ScintBottom {
+            Energy = 102, Short = 103, Time = 104, PSD = 331, EnergyRaw = 171, Board = 215, Channel = 216,
+            Multiplicity = 288
+        }
+        Cathode {
+            Energy = 12, Short = 13, Time = 14, EnergyRaw = 172, Board = 217, Channel = 218, Multiplicity = 289
+        }
+        DelayFrontLeft {
+            Energy = 15, Short = 16, Time = 17, EnergyRaw = 173, Board = 219, Channel = 220, Multiplicity = 290
+        }
+        DelayFrontRight {
+            Energy = 18, Short = 19, Time = 20, EnergyRaw = 174, Board = 221, Channel = 222, Multiplicity = 291
+        }
+        DelayBackLeft {
+            Energy = 21, Short = 22, Time = 23, EnergyRaw = 175, Board = 223, Channel = 224, Multiplicity = 292
+        }
+        DelayBackRight {
+            Energy = 24, Short = 25, Time = 26, EnergyRaw = 176, Board = 225, Channel = 226, Multiplicity = 293
+        }
+        Monitor {
+            Energy = 27, Short = 28, Time = 29, EnergyRaw = 177, Board = 227, Channel = 228, Multiplicity = 294
+        }
+        Cebra0 {
+            Energy = 36, Short = 45, Time = 54, RelTime = 63, RelTimeRight = 138, PSD = 332, EnergyRaw = 178,
+            Board = 229, Channel = 230, Multiplicity = 295
+        }
+        Cebra1 {
+            Energy = 37, Short = 46, Time = 55, RelTime = 64, RelTimeRight = 139, PSD = 333, EnergyRaw = 179,
+            Board = 231, Channel = 232, Multiplicity = 296
+        }
+        Cebra2 {
+            Energy = 38, Short = 47, Time = 56, RelTime = 65, RelTimeRight = 140, PSD = 334, EnergyRaw = 180,
+            Board = 233, Channel = 234, Multiplicity = 297
+        }
+        Cebra3 {
+            Energy = 39, Short = 48, Time = 57, RelTime = 66, RelTimeRight = 141, PSD = 335, EnergyRaw = 181,
+            Board = 235, Channel = 236, Multiplicity = 298
+        }
+        Cebra4 {
+            Energy = 40, Short = 49, Time = 58, RelTime = 67, RelTimeRight = 142, PSD = 336, EnergyRaw = 182,
+            Board = 237, Channel = 238, Multiplicity = 299
+        }
+        Cebra5 {
+            Energy = 41, Short = 50, Time = 59, RelTime = 68, RelTimeRight = 143, PSD = 337, EnergyRaw = 183,
+            Board = 239, Channel = 240, Multiplicity = 300
+        }
+        Cebra6 {
+            Energy = 42, Short = 51, Time = 60, RelTime = 69, RelTimeRight = 144, PSD = 338, EnergyRaw = 184,
+            Board = 241, Channel = 242, Multiplicity = 301
+        }
+        Cebra7 {
+            Energy = 43, Short = 52, Time = 61, RelTime = 70, RelTimeRight = 145, PSD = 339, EnergyRaw = 185,
+            Board = 243, Channel = 244, Multiplicity = 302
+        }
+        Cebra8 {
+            Energy = 44, Short = 53, Time = 62, RelTime = 71, RelTimeRight = 146, PSD = 340, EnergyRaw = 186,
+            Board = 245, Channel = 246, Multiplicity = 303
+        }
+        Cebra9 {
+            Energy = 110, Short = 117, Time = 124, RelTime = 131, RelTimeRight = 147, PSD = 341, EnergyRaw = 187,
+            Board = 247, Channel = 248, Multiplicity = 304
+        }
+        Cebra10 {
+            Energy = 111, Short = 118, Time = 125, RelTime = 132, RelTimeRight = 148, PSD = 342, EnergyRaw = 188,
+            Board = 249, Channel = 250, Multiplicity = 305
+        }
+        Cebra11 {
+            Energy = 112, Short = 119, Time = 126, RelTime = 133, RelTimeRight = 149, PSD = 343, EnergyRaw = 189,
+            Board = 251, Channel = 252, Multiplicity = 306
+        }
+        Cebra12 {
+            Energy = 113, Short = 120, Time = 127, RelTime = 134, RelTimeRight = 150, PSD = 344, EnergyRaw = 190,
+            Board = 253, Channel = 254, Multiplicity = 307
+        }
+        Cebra13 {
+            Energy = 114, Short = 121, Time = 128, RelTime = 135, RelTimeRight = 151, PSD = 345, EnergyRaw = 191,
+            Board = 255, Channel = 256, Multiplicity = 308
+        }
+        Cebra14 {
+            Energy = 115, Short = 122, Time = 129, RelTime = 136, RelTimeRight = 152, PSD = 346, EnergyRaw = 192,
+            Board = 257, Channel = 258, Multiplicity = 309
+        }
+        Cebra15 {
+            Energy = 116, Short = 123, Time = 130, RelTime = 137, RelTimeRight = 153, PSD = 347, EnergyRaw = 193,
+            Board = 259, Channel = 260, Multiplicity = 310
+        }
+        PIPS1000 {
+            Energy = 72, Time = 76, RelTime = 80, EnergyRaw = 194, Board = 261, Channel = 262,
+            Multiplicity = 311
+        }
+        PIPS500 {
+            Energy = 73, Time = 77, RelTime = 81, EnergyRaw = 195, Board = 263, Channel = 264,
+            Multiplicity = 312
+        }
+        PIPS300 {
+            Energy = 74, Time = 78, RelTime = 82, EnergyRaw = 196, Board = 265, Channel = 266,
+            Multiplicity = 313
+        }
+        PIPS100 {
+            Energy = 75, Time = 79, RelTime = 83, EnergyRaw = 197, Board = 267, Channel = 268,
+            Multiplicity = 314
+        }
+        CATRINA0 {
+            Energy = 84, Short = 87, Time = 90, PSD = 93, EnergyRaw = 198, Board = 269, Channel = 270,
+            Multiplicity = 315
+        }
+        CATRINA1 {
+            Energy = 85, Short = 88, Time = 91, PSD = 94, EnergyRaw = 199, Board = 271, Channel = 272,
+            Multiplicity = 316
+        }
+        CATRINA2 {
+            Energy = 86, Short = 89, Time = 92, PSD = 95, EnergyRaw = 200, Board = 273, Channel = 274,
+            Multiplicity = 317
+        }
+        Aux0 {
+            Energy = 154, Short = 158, Time = 162, EnergyRaw = 201, Board = 275, Channel = 276,
+            Multiplicity = 318
+        }
+        Aux1 {
+            Energy = 155, Short = 159, Time = 163, EnergyRaw = 202, Board = 277, Channel = 278,
+            Multiplicity = 319
+        }
+        Aux2 {
+            Energy = 156, Short = 160, Time = 164, EnergyRaw = 203, Board = 279, Channel = 280,
+            Multiplicity = 320
+        }
+        Aux3 {
+            Energy = 157, Short = 161, Time = 165, EnergyRaw = 204, Board = 281, Channel = 282,
+            Multiplicity = 321
+        }
+    }
+}
+
+// Detector a field belongs to, see ChannelDataField::detector_group
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DetectorGroup {
+    Detector(ChannelType),
+    FocalPlane, // positions and angles calculated from several focal plane detectors
+    Event,      // the event as a whole, e.g. EventTime and HitPattern
+}
+
+// What a field measures, see ChannelDataField::quantity_kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr, EnumIter)]
+pub enum QuantityKind {
+    Energy, // calibrated when a calibration is given
+    EnergyRaw,
+    Short,
+    Psd,
+    Time,
+    RelTime,
+    TimeDiff,
+    Position,
+    Angle,
+    Board,
+    Channel,
+    Multiplicity,
+    Flags, // HitPattern and RelTimeReference
+}
+
+// Time and relative time fields of each PIPS detector
+const PIPS_REL_TIME_FIELDS: [(ChannelDataField, ChannelDataField); 4] = [
+    (
+        ChannelDataField::PIPS1000Time,
+        ChannelDataField::PIPS1000RelTime,
+    ),
+    (
+        ChannelDataField::PIPS500Time,
+        ChannelDataField::PIPS500RelTime,
+    ),
+    (
+        ChannelDataField::PIPS300Time,
+        ChannelDataField::PIPS300RelTime,
+    ),
+    (
+        ChannelDataField::PIPS100Time,
+        ChannelDataField::PIPS100RelTime,
+    ),
+];
+
 impl ChannelDataField {
     //Returns a list of fields for iterating over
     pub fn get_field_vec() -> Vec<ChannelDataField> {
         ChannelDataField::iter().collect()
     }
 
-    pub fn get_filtered_field_vec(channel_map: &ChannelMap) -> Vec<ChannelDataField> {
-        let all_delay_lines_present = channel_map
-            .contains_channel_type(ChannelType::DelayFrontLeft)
-            && channel_map.contains_channel_type(ChannelType::DelayFrontRight)
-            && channel_map.contains_channel_type(ChannelType::DelayBackLeft)
-            && channel_map.contains_channel_type(ChannelType::DelayBackRight);
-        ChannelDataField::iter()
-            .filter(|field| {
-                match field {
+    // Stable position of this field among the output columns, see the declaration of ChannelDataField
+    pub fn output_index(&self) -> usize {
+        self.clone() as usize
+    }
+
+    // Field holding the uncalibrated value of this energy field
+    pub fn raw_energy_field(&self) -> Option<ChannelDataField> {
+        match self.detector_field()? {
+            (channel_type, DetectorQuantity::Energy) => {
+                ChannelDataField::of_detector(channel_type, DetectorQuantity::EnergyRaw)
+            }
+            _ => None,
+        }
+    }
+
+    // Channel type whose number of hits in an event this field counts
+    pub fn multiplicity_channel_type(&self) -> Option<ChannelType> {
+        match self.detector_field()? {
+            (channel_type, DetectorQuantity::Multiplicity) => Some(channel_type),
+            _ => None,
+        }
+    }
+
+    // Fields holding the board and channel number of the hit stored in this energy field
+    pub fn provenance_fields(&self) -> Option<(ChannelDataField, ChannelDataField)> {
+        match self.detector_field()? {
+            (channel_type, DetectorQuantity::Energy) => Some((
+                ChannelDataField::of_detector(channel_type, DetectorQuantity::Board)?,
+                ChannelDataField::of_detector(channel_type, DetectorQuantity::Channel)?,
+            )),
+            _ => None,
+        }
+    }
+
+    // Energy field of the detector whose board or channel number this field holds
+    pub fn provenance_energy_field(&self) -> Option<ChannelDataField> {
+        match self.detector_field()? {
+            (channel_type, DetectorQuantity::Board | DetectorQuantity::Channel) => {
+                ChannelDataField::of_detector(channel_type, DetectorQuantity::Energy)
+            }
+            _ => None,
+        }
+    }
+
+    // Energy field whose uncalibrated value this field holds
+    pub fn calibrated_energy_field(&self) -> Option<ChannelDataField> {
+        match self.detector_field()? {
+            (channel_type, DetectorQuantity::EnergyRaw) => {
+                ChannelDataField::of_detector(channel_type, DetectorQuantity::Energy)
+            }
+            _ => None,
+        }
+    }
+
+    // Flags and board/channel numbers, accumulated as floats like everything else but written as integers
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            ChannelDataField::HitPattern
+                | ChannelDataField::RelTimeReference
+                | ChannelDataField::CebraMultiplicity
+        ) || self.provenance_energy_field().is_some()
+            || self.multiplicity_channel_type().is_some()
+    }
+
+    // Accumulated as 1.0 or 0.0 like everything else but written as a boolean
+    pub fn is_boolean(&self) -> bool {
+        matches!(self, ChannelDataField::GoodEvent | ChannelDataField::Vetoed)
+    }
+
+    // Field with this column name, ignoring aliases. HitPattern is also found by its old name DetectorBitmask, so
+    // that saved required fields, aliases, and derived fields keep working.
+    pub fn from_name(name: &str) -> Option<ChannelDataField> {
+        match name.trim() {
+            "DetectorBitmask" => Some(ChannelDataField::HitPattern),
+            name => ChannelDataField::iter().find(|field| field.as_ref() == name),
+        }
+    }
+
+    // Set in every event it is written for, so it never holds the sentinel and is not retyped for it
+    pub fn is_set_in_every_event(&self) -> bool {
+        self.is_boolean() || *self == ChannelDataField::HitPattern
+    }
+
+    // Short gate energies and the PSD values calculated from them, only produced by PSD firmware
+    pub fn is_short_gate(&self) -> bool {
+        matches!(
+            self.detector_field(),
+            Some((_, DetectorQuantity::Short | DetectorQuantity::PSD))
+        )
+    }
+
+    // (energy - short) / energy of a detector, calculated from its short gate
+    pub fn is_psd(&self) -> bool {
+        matches!(self.detector_field(), Some((_, DetectorQuantity::PSD)))
+    }
+
+    // Whether this field is calculated from the event (positions, relative times, counts, ...) rather than
+    // copied from a single hit
+    pub fn is_derived(&self) -> bool {
+        let psd = self.is_psd();
+        let measured = self.raw_energy_field().is_some()
+            || self.calibrated_energy_field().is_some()
+            || self.provenance_energy_field().is_some()
+            || (self.is_short_gate() && !psd)
+            || (self.is_timestamp() && *self != ChannelDataField::EventTime);
+        !measured
+    }
+
+    // Absolute timestamps, which are stored as integer picoseconds so that they stay exact for long runs
+    pub fn is_timestamp(&self) -> bool {
+        *self == ChannelDataField::EventTime
+            || matches!(self.detector_field(), Some((_, DetectorQuantity::Time)))
+    }
+
+    // Detector this field belongs to. Fields calculated from several detectors belong to the focal plane or
+    // the event rather than to one of them.
+    pub fn detector_group(&self) -> DetectorGroup {
+        if let Some((channel_type, _)) = self.detector_field() {
+            return DetectorGroup::Detector(channel_type);
+        }
+
+        match self {
+            ChannelDataField::X1
+            | ChannelDataField::X2
+            | ChannelDataField::X1TimeDiff
+            | ChannelDataField::X2TimeDiff
+            | ChannelDataField::ScintVerticalTimeDiff
+            | ChannelDataField::Xavg
+            | ChannelDataField::XavgCharge
+            | ChannelDataField::AnodeSumEnergy
+            | ChannelDataField::AnodeTimeDiff
+            | ChannelDataField::Theta
+            | ChannelDataField::ThetaDeg
+            | ChannelDataField::X
+            | ChannelDataField::Z => DetectorGroup::FocalPlane,
+            ChannelDataField::Vetoed => DetectorGroup::Detector(ChannelType::Veto),
+            _ => DetectorGroup::Event,
+        }
+    }
+
+    // Kind of quantity this field holds, e.g. to treat every energy field the same way
+    pub fn quantity_kind(&self) -> QuantityKind {
+        if let Some((_, quantity)) = self.detector_field() {
+            return match quantity {
+                DetectorQuantity::Energy => QuantityKind::Energy,
+                DetectorQuantity::Short => QuantityKind::Short,
+                DetectorQuantity::Time => QuantityKind::Time,
+                DetectorQuantity::RelTime | DetectorQuantity::RelTimeRight => QuantityKind::RelTime,
+                DetectorQuantity::PSD => QuantityKind::Psd,
+                DetectorQuantity::EnergyRaw => QuantityKind::EnergyRaw,
+                DetectorQuantity::Board => QuantityKind::Board,
+                DetectorQuantity::Channel => QuantityKind::Channel,
+                DetectorQuantity::Multiplicity => QuantityKind::Multiplicity,
+            };
+        }
+
+        match self {
+            ChannelDataField::EventTime => QuantityKind::Time,
+            ChannelDataField::AnodeSumEnergy => QuantityKind::Energy,
+            ChannelDataField::CebraMultiplicity => QuantityKind::Multiplicity,
+            ChannelDataField::X1TimeDiff
+            | ChannelDataField::X2TimeDiff
+            | ChannelDataField::ScintVerticalTimeDiff
+            | ChannelDataField::AnodeTimeDiff => QuantityKind::TimeDiff,
+            ChannelDataField::Theta | ChannelDataField::ThetaDeg => QuantityKind::Angle,
+            ChannelDataField::X1
+            | ChannelDataField::X2
+            | ChannelDataField::Xavg
+            | ChannelDataField::XavgCharge
+            | ChannelDataField::X
+            | ChannelDataField::Z => QuantityKind::Position,
+            _ => QuantityKind::Flags,
+        }
+    }
+
+    // Whether the name of this field matches a name from the build options, where each * stands for any
+    // number of characters, e.g. *Short for every short gate energy
+    pub fn matches_pattern(&self, pattern: &str) -> bool {
+        let pattern = pattern.trim();
+        let parts: Vec<&str> = pattern.split('*').collect();
+        let [first, middle @ .., last] = parts.as_slice() else {
+            // No wildcard
+            return self.as_ref() == pattern;
+        };
+        let Some(mut rest) = self.as_ref().strip_prefix(first) else {
+            return false;
+        };
+        for part in middle {
+            match rest.find(part) {
+                Some(index) => rest = &rest[index + part.len()..],
+                None => return false,
+            }
+        }
+        rest.ends_with(last)
+    }
+
+    // Fields written with this channel map and these options, see get_mapped_field_vec, less the ones left out
+    // by BuildParams::included_fields and excluded_fields
+    pub fn get_filtered_field_vec(
+        channel_map: &ChannelMap,
+        params: &BuildParams,
+    ) -> Vec<ChannelDataField> {
+        let mut fields = ChannelDataField::get_mapped_field_vec(channel_map, params);
+        fields.retain(|field| params.is_field_selected(field));
+        fields
+    }
+
+    // Fields the channel map and the options give, before BuildParams::included_fields and excluded_fields
+    pub fn get_mapped_field_vec(
+        channel_map: &ChannelMap,
+        params: &BuildParams,
+    ) -> Vec<ChannelDataField> {
+        // The map is only scanned once, the fields are then filtered on the types it contains
+        let present: Vec<ChannelType> = channel_map
+            .channel_types()
+            .map(|(channel_type, _)| channel_type)
+            .collect();
+        let has = |channel_type: ChannelType| present.contains(&channel_type);
+        let all_delay_lines_present = has(ChannelType::DelayFrontLeft)
+            && has(ChannelType::DelayFrontRight)
+            && has(ChannelType::DelayBackLeft)
+            && has(ChannelType::DelayBackRight);
+        // Relative times need a reference, which is the anchor, or ScintLeft with optionally ScintRight as a
+        // fallback
+        let reference_present = match params.anchor {
+            Some(anchor) => has(anchor),
+            None => {
+                has(ChannelType::ScintLeft)
+                    || (params.scint_right_fallback && has(ChannelType::ScintRight))
+            }
+        };
+        // Raw energies and board/channel numbers are kept alongside the energy of a mapped detector when
+        // requested, multiplicities for every mapped channel type
+        let mut fields: Vec<ChannelDataField> = ChannelDataField::iter()
+            .filter(|field| !(params.no_short_gate && field.is_short_gate()))
+            .filter(|field| match field.detector_field() {
+                Some((channel_type, quantity)) => {
+                    has(channel_type)
+                        && match quantity {
+                            DetectorQuantity::Energy
+                            | DetectorQuantity::Short
+                            | DetectorQuantity::Time => true,
+                            DetectorQuantity::RelTime if channel_type.cebra_index().is_some() => {
+                                params.cebra_reference.map_or(reference_present, has)
+                            }
+                            DetectorQuantity::RelTime => reference_present,
+                            DetectorQuantity::RelTimeRight => {
+                                params.cebra_rel_time_right && has(ChannelType::ScintRight)
+                            }
+                            // CATRINA is identified by its PSD, which is always written
+                            DetectorQuantity::PSD => {
+                                params.psd_fields
+                                    || matches!(
+                                        channel_type,
+                                        ChannelType::CATRINA0
+                                            | ChannelType::CATRINA1
+                                            | ChannelType::CATRINA2
+                                    )
+                            }
+                            DetectorQuantity::EnergyRaw => params.keep_raw_energy,
+                            DetectorQuantity::Board | DetectorQuantity::Channel => {
+                                params.provenance_columns
+                            }
+                            DetectorQuantity::Multiplicity => params.multiplicity,
+                        }
+                }
+                None => match field {
+                    // Every event has a time, whatever the channel map
+                    ChannelDataField::EventTime => true,
                     // Include additional fields only if all delay line channels are present
                     ChannelDataField::X1
                     | ChannelDataField::X2
@@ -145,255 +601,423 @@ impl ChannelDataField {
                     | ChannelDataField::X
                     | ChannelDataField::Z
                     | ChannelDataField::Theta => all_delay_lines_present,
-                    // Filter other fields based on the channel map
-                    ChannelDataField::AnodeFrontEnergy
-                    | ChannelDataField::AnodeFrontShort
-                    | ChannelDataField::AnodeFrontTime => {
-                        channel_map.contains_channel_type(ChannelType::AnodeFront)
-                    }
-                    ChannelDataField::AnodeBackEnergy
-                    | ChannelDataField::AnodeBackShort
-                    | ChannelDataField::AnodeBackTime => {
-                        channel_map.contains_channel_type(ChannelType::AnodeBack)
-                    }
-                    ChannelDataField::ScintLeftEnergy
-                    | ChannelDataField::ScintLeftShort
-                    | ChannelDataField::ScintLeftTime => {
-                        channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
-                    ChannelDataField::ScintRightEnergy
-                    | ChannelDataField::ScintRightShort
-                    | ChannelDataField::ScintRightTime => {
-                        channel_map.contains_channel_type(ChannelType::ScintRight)
-                    }
-                    ChannelDataField::CathodeEnergy
-                    | ChannelDataField::CathodeShort
-                    | ChannelDataField::CathodeTime => {
-                        channel_map.contains_channel_type(ChannelType::Cathode)
-                    }
-                    ChannelDataField::DelayFrontLeftEnergy
-                    | ChannelDataField::DelayFrontLeftShort
-                    | ChannelDataField::DelayFrontLeftTime => {
-                        channel_map.contains_channel_type(ChannelType::DelayFrontLeft)
-                    }
-                    ChannelDataField::DelayFrontRightEnergy
-                    | ChannelDataField::DelayFrontRightShort
-                    | ChannelDataField::DelayFrontRightTime => {
-                        channel_map.contains_channel_type(ChannelType::DelayFrontRight)
+                    ChannelDataField::ThetaDeg => all_delay_lines_present && params.theta_degrees,
+                    ChannelDataField::XavgCharge
+                    | ChannelDataField::AnodeSumEnergy
+                    | ChannelDataField::AnodeTimeDiff => {
+                        has(ChannelType::AnodeFront) && has(ChannelType::AnodeBack)
                     }
-                    ChannelDataField::DelayBackLeftEnergy
-                    | ChannelDataField::DelayBackLeftShort
-                    | ChannelDataField::DelayBackLeftTime => {
-                        channel_map.contains_channel_type(ChannelType::DelayBackLeft)
+                    ChannelDataField::X1TimeDiff => {
+                        params.delay_line_time_diff
+                            && has(ChannelType::DelayFrontLeft)
+                            && has(ChannelType::DelayFrontRight)
                     }
-                    ChannelDataField::DelayBackRightEnergy
-                    | ChannelDataField::DelayBackRightShort
-                    | ChannelDataField::DelayBackRightTime => {
-                        channel_map.contains_channel_type(ChannelType::DelayBackRight)
+                    ChannelDataField::X2TimeDiff => {
+                        params.delay_line_time_diff
+                            && has(ChannelType::DelayBackLeft)
+                            && has(ChannelType::DelayBackRight)
                     }
-                    ChannelDataField::MonitorEnergy
-                    | ChannelDataField::MonitorShort
-                    | ChannelDataField::MonitorTime => {
-                        channel_map.contains_channel_type(ChannelType::Monitor)
+                    ChannelDataField::ScintVerticalTimeDiff => {
+                        has(ChannelType::ScintTop) && has(ChannelType::ScintBottom)
                     }
-
-                    ChannelDataField::Cebra0Energy
-                    | ChannelDataField::Cebra0Short
-                    | ChannelDataField::Cebra0Time => {
-                        channel_map.contains_channel_type(ChannelType::Cebra0)
-                    }
-                    ChannelDataField::Cebra0RelTime => {
-                        channel_map.contains_channel_type(ChannelType::Cebra0)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
-
-                    ChannelDataField::Cebra1Energy
-                    | ChannelDataField::Cebra1Short
-                    | ChannelDataField::Cebra1Time => {
-                        channel_map.contains_channel_type(ChannelType::Cebra1)
-                    }
-                    ChannelDataField::Cebra1RelTime => {
-                        channel_map.contains_channel_type(ChannelType::Cebra1)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
-
-                    ChannelDataField::Cebra2Energy
-                    | ChannelDataField::Cebra2Short
-                    | ChannelDataField::Cebra2Time => {
-                        channel_map.contains_channel_type(ChannelType::Cebra2)
-                    }
-                    ChannelDataField::Cebra2RelTime => {
-                        channel_map.contains_channel_type(ChannelType::Cebra2)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
-
-                    ChannelDataField::Cebra3Energy
-                    | ChannelDataField::Cebra3Short
-                    | ChannelDataField::Cebra3Time => {
-                        channel_map.contains_channel_type(ChannelType::Cebra3)
-                    }
-                    ChannelDataField::Cebra3RelTime => {
-                        channel_map.contains_channel_type(ChannelType::Cebra3)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
+                    ChannelDataField::HitPattern => params.hit_pattern,
+                    ChannelDataField::CebraMultiplicity => CEBRA_CHANNEL_TYPES.into_iter().any(has),
+                    // Every written row would be true when the other rows are dropped
+                    ChannelDataField::GoodEvent => {
+                        !params.required_fields.is_empty() && !params.drop_bad_events
                     }
-                    ChannelDataField::Cebra4Energy
-                    | ChannelDataField::Cebra4Short
-                    | ChannelDataField::Cebra4Time => {
-                        channel_map.contains_channel_type(ChannelType::Cebra4)
-                    }
-                    ChannelDataField::Cebra4RelTime => {
-                        channel_map.contains_channel_type(ChannelType::Cebra4)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
-                    ChannelDataField::Cebra5Energy
-                    | ChannelDataField::Cebra5Short
-                    | ChannelDataField::Cebra5Time => {
-                        channel_map.contains_channel_type(ChannelType::Cebra5)
-                    }
-                    ChannelDataField::Cebra5RelTime => {
-                        channel_map.contains_channel_type(ChannelType::Cebra5)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
-                    ChannelDataField::Cebra6Energy
-                    | ChannelDataField::Cebra6Short
-                    | ChannelDataField::Cebra6Time => {
-                        channel_map.contains_channel_type(ChannelType::Cebra6)
-                    }
-                    ChannelDataField::Cebra6RelTime => {
-                        channel_map.contains_channel_type(ChannelType::Cebra6)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
-                    ChannelDataField::Cebra7Energy
-                    | ChannelDataField::Cebra7Short
-                    | ChannelDataField::Cebra7Time => {
-                        channel_map.contains_channel_type(ChannelType::Cebra7)
-                    }
-                    ChannelDataField::Cebra7RelTime => {
-                        channel_map.contains_channel_type(ChannelType::Cebra7)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
-                    ChannelDataField::Cebra8Energy
-                    | ChannelDataField::Cebra8Short
-                    | ChannelDataField::Cebra8Time => {
-                        channel_map.contains_channel_type(ChannelType::Cebra8)
-                    }
-                    ChannelDataField::Cebra8RelTime => {
-                        channel_map.contains_channel_type(ChannelType::Cebra8)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
-
-                    ChannelDataField::PIPS1000Energy | ChannelDataField::PIPS1000Time => {
-                        channel_map.contains_channel_type(ChannelType::PIPS1000)
-                    }
-                    ChannelDataField::PIPS1000RelTime => {
-                        channel_map.contains_channel_type(ChannelType::PIPS1000)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
+                    ChannelDataField::Vetoed => has(ChannelType::Veto),
+                    ChannelDataField::RelTimeReference => {
+                        params.anchor.is_none() && params.scint_right_fallback && reference_present
                     }
+                    _ => unreachable!("{} is a detector field", field.as_ref()),
+                },
+            })
+            .collect();
+        // Returned in output order
+        fields.sort();
+        fields
+    }
+}
 
-                    ChannelDataField::PIPS500Energy | ChannelDataField::PIPS500Time => {
-                        channel_map.contains_channel_type(ChannelType::PIPS500)
-                    }
-                    ChannelDataField::PIPS500RelTime => {
-                        channel_map.contains_channel_type(ChannelType::PIPS500)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
+impl UsedSize for ChannelDataField {
+    fn get_used_size(&self) -> usize {
+        std::mem::size_of::<ChannelDataField>()
+    }
+}
 
-                    ChannelDataField::PIPS300Energy | ChannelDataField::PIPS300Time => {
-                        channel_map.contains_channel_type(ChannelType::PIPS300)
-                    }
-                    ChannelDataField::PIPS300RelTime => {
-                        channel_map.contains_channel_type(ChannelType::PIPS300)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
+// Memory held by a ChannelData, see ChannelData::memory_report
+#[derive(Debug, Clone, Default)]
+pub struct MemoryReport {
+    pub rows: usize,
+    // Bytes of each field: its map entry plus its values, as counted by get_used_size
+    pub field_bytes: BTreeMap<ChannelDataField, usize>,
+    pub derived_bytes: usize,  // all derived fields together
+    pub waveform_bytes: usize, // all waveform columns together, with their samples
+    pub total_bytes: usize,
+}
 
-                    ChannelDataField::PIPS100Energy | ChannelDataField::PIPS100Time => {
-                        channel_map.contains_channel_type(ChannelType::PIPS100)
-                    }
-                    ChannelDataField::PIPS100RelTime => {
-                        channel_map.contains_channel_type(ChannelType::PIPS100)
-                            && channel_map.contains_channel_type(ChannelType::ScintLeft)
-                    }
+impl MemoryReport {
+    // Average bytes per event, for estimating how many more events fit in memory
+    pub fn bytes_per_row(&self) -> usize {
+        if self.rows == 0 {
+            0
+        } else {
+            self.total_bytes / self.rows
+        }
+    }
+}
 
-                    ChannelDataField::CATRINA0Energy
-                    | ChannelDataField::CATRINA0Short
-                    | ChannelDataField::CATRINA0Time
-                    | ChannelDataField::CATRINA0PSD => {
-                        channel_map.contains_channel_type(ChannelType::CATRINA0)
-                    }
+impl std::fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} events in {:.1} MB ({} bytes per event, {} fields)",
+            self.rows,
+            self.total_bytes as f64 / 1.0e6,
+            self.bytes_per_row(),
+            self.field_bytes.len()
+        )
+    }
+}
 
-                    ChannelDataField::CATRINA1Energy
-                    | ChannelDataField::CATRINA1Short
-                    | ChannelDataField::CATRINA1Time
-                    | ChannelDataField::CATRINA1PSD => {
-                        channel_map.contains_channel_type(ChannelType::CATRINA1)
-                    }
+#[derive(Debug)]
+pub enum ChannelDataError {
+    FieldMismatch,
+    SchemaMismatch {
+        missing: Vec<String>,
+        unexpected: Vec<String>,
+    },
+    DataFrame(PolarsError),
+}
 
-                    ChannelDataField::CATRINA2Energy
-                    | ChannelDataField::CATRINA2Short
-                    | ChannelDataField::CATRINA2Time
-                    | ChannelDataField::CATRINA2PSD => {
-                        channel_map.contains_channel_type(ChannelType::CATRINA2)
-                    }
-                }
-            })
-            .collect()
+impl From<PolarsError> for ChannelDataError {
+    fn from(err: PolarsError) -> ChannelDataError {
+        ChannelDataError::DataFrame(err)
     }
 }
 
-impl UsedSize for ChannelDataField {
-    fn get_used_size(&self) -> usize {
-        std::mem::size_of::<ChannelDataField>()
+impl std::fmt::Display for ChannelDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelDataError::FieldMismatch => {
+                write!(f, "Channel data could not be combined, the fields differ")
+            }
+            ChannelDataError::SchemaMismatch {
+                missing,
+                unexpected,
+            } => write!(
+                f,
+                "DataFrame does not match the channel map, missing columns [{}], unexpected columns [{}]",
+                missing.join(", "),
+                unexpected.join(", ")
+            ),
+            ChannelDataError::DataFrame(x) => {
+                write!(f, "Channel data could not be read from the DataFrame: {}", x)
+            }
+        }
     }
 }
 
+impl std::error::Error for ChannelDataError {}
+
 #[derive(Debug, Clone)]
 pub struct ChannelData {
     //Columns must always come in same order, so use sorted map
     pub fields: BTreeMap<ChannelDataField, Vec<f64>>,
+    // Fields stored in single precision when BuildParams::f32_storage is set
+    pub compact_fields: BTreeMap<ChannelDataField, Vec<f32>>,
+    // Absolute timestamps in integer ps, see ChannelDataField::is_timestamp
+    pub time_fields: BTreeMap<ChannelDataField, Vec<i64>>,
     pub nested_fields: BTreeMap<ChannelDataField, Vec<Vec<f64>>>,
+    // Columns declared in BuildParams::derived_fields, in declaration order, with their parsed expressions
+    pub derived_fields: Vec<(String, Vec<f64>)>,
+    expressions: Arc<Vec<Expression>>,
+    // Samples of the hit of each detector type in BuildParams::waveform_channels which is mapped, None for
+    // events without a hit or hits without a waveform. Written as <Detector>Waveform after every other column
+    pub waveforms: Vec<(ChannelType, Vec<Option<Vec<i16>>>)>,
     pub rows: usize,
+    params: BuildParams,
+    reconstructor: Arc<dyn PositionReconstructor>,
+    pedestals: PedestalMap,
+    calibrations: CalibrationMap,
+    required: Vec<ChannelDataField>, // resolved BuildParams::required_fields
+    delay_line_check: Option<Arc<DelayLineCheck>>,
+    time_spread: Option<(Arc<TimeSpread>, Vec<ChannelDataField>)>, // with the relative time fields it covers
 }
 
+// Every field with the default BuildParams, whatever the channel map. Only meant for looking at the full set
+// of columns; data to build events into should come from ChannelData::new, since the options and the
+// relative times, positions, and pruning all assume the fields were filtered on the channel map.
 impl Default for ChannelData {
     fn default() -> Self {
-        let fields = ChannelDataField::get_field_vec();
-        let mut data = ChannelData {
-            fields: BTreeMap::new(),
-            nested_fields: BTreeMap::new(),
-            rows: 0,
-        };
-        fields.into_iter().for_each(|f| {
-            data.fields.insert(f, vec![]);
-        });
-        data
+        ChannelData::from_fields(ChannelDataField::get_field_vec(), &BuildParams::default())
     }
 }
 
 impl UsedSize for ChannelData {
     fn get_used_size(&self) -> usize {
-        self.fields.get_used_size() + self.nested_fields.get_used_size()
+        self.fields.get_used_size()
+            + self.compact_fields.get_used_size()
+            + self.time_fields.get_used_size()
+            + self.nested_fields.get_used_size()
+            + self.derived_used_size()
+            + self.waveform_used_size()
     }
 }
 
 impl ChannelData {
-    // Constructor accepting a channel map to initialize only valid fields
-    pub fn new(channel_map: &ChannelMap) -> Self {
-        let fields = ChannelDataField::get_filtered_field_vec(channel_map);
+    fn derived_used_size(&self) -> usize {
+        self.derived_fields
+            .iter()
+            .map(|(_, values)| values.get_used_size())
+            .sum()
+    }
+
+    fn waveform_used_size(&self) -> usize {
+        self.waveforms
+            .iter()
+            .flat_map(|(_, rows)| rows.iter())
+            .map(|row| {
+                std::mem::size_of::<Option<Vec<i16>>>()
+                    + row.as_ref().map_or(0, |samples| 2 * samples.len())
+            })
+            .sum()
+    }
+
+    // Breakdown of get_used_size by field, which decides when a run is written in fragments
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut field_bytes = BTreeMap::new();
+        for (field, values) in self.fields.iter() {
+            field_bytes.insert(field.clone(), entry_used_size(field, values));
+        }
+        for (field, values) in self.compact_fields.iter() {
+            field_bytes.insert(field.clone(), entry_used_size(field, values));
+        }
+        for (field, values) in self.time_fields.iter() {
+            field_bytes.insert(field.clone(), entry_used_size(field, values));
+        }
+        for (field, values) in self.nested_fields.iter() {
+            field_bytes.insert(field.clone(), entry_used_size(field, values));
+        }
+        let derived_bytes = self.derived_used_size();
+        let waveform_bytes = self.waveform_used_size();
+
+        MemoryReport {
+            rows: self.rows,
+            total_bytes: field_bytes.values().sum::<usize>() + derived_bytes + waveform_bytes,
+            field_bytes,
+            derived_bytes,
+            waveform_bytes,
+        }
+    }
+
+    // Constructor accepting a channel map to initialize only valid fields. Fields left out by
+    // BuildParams::included_fields and excluded_fields are still stored, so the fields calculated from them
+    // are set, and only dropped when the columns are written
+    pub fn new(channel_map: &ChannelMap, params: &BuildParams) -> Self {
+        let mut data = ChannelData::from_fields(
+            ChannelDataField::get_mapped_field_vec(channel_map, params),
+            params,
+        );
+        data.waveforms = waveform_channel_types(channel_map, params)
+            .into_iter()
+            .map(|channel_type| (channel_type, vec![]))
+            .collect();
+        data
+    }
+
+    // Empty columns for the given fields, each stored as nested, timestamp, single, or double precision
+    // values by the same rules whichever constructor is used
+    fn from_fields(fields: Vec<ChannelDataField>, params: &BuildParams) -> Self {
         let mut data = ChannelData {
             fields: BTreeMap::new(),
+            compact_fields: BTreeMap::new(),
+            time_fields: BTreeMap::new(),
             nested_fields: BTreeMap::new(),
+            derived_fields: vec![],
+            expressions: Arc::new(vec![]),
+            waveforms: vec![],
             rows: 0,
+            params: params.clone(),
+            reconstructor: Arc::new(DelayLineTiming::new(params.focal_plane.clone())),
+            pedestals: PedestalMap::default(),
+            calibrations: CalibrationMap::default(),
+            required: vec![],
+            delay_line_check: None,
+            time_spread: None,
         };
         fields.into_iter().for_each(|f| {
             if f == ChannelDataField::X || f == ChannelDataField::Z {
-                data.nested_fields.insert(f, vec![vec![]]);
+                data.nested_fields.insert(f, vec![]);
+            } else if f.is_timestamp() {
+                data.time_fields.insert(f, vec![]);
+            } else if params.f32_storage && !f.is_integer() && !f.is_boolean() {
+                data.compact_fields.insert(f, vec![]);
             } else {
                 data.fields.insert(f, vec![]);
             }
         });
+
+        // Invalid expressions are reported before a run starts, see process_runs
+        let mut expressions = vec![];
+        for entry in params.derived_fields.iter() {
+            match entry.parse() {
+                Ok(expression) => {
+                    data.derived_fields
+                        .push((entry.name.trim().to_string(), vec![]));
+                    expressions.push(expression);
+                }
+                Err(e) => log::error!("Skipping derived field {}: {}", entry.name, e),
+            }
+        }
+        data.expressions = Arc::new(expressions);
+
+        // Unknown names are reported before a run starts, see validate_configuration
+        for name in params.required_fields.iter() {
+            match ChannelDataField::from_name(name) {
+                Some(field) => data.required.push(field),
+                None => log::error!("Skipping unknown required field {}", name),
+            }
+        }
         data
     }
 
+    // Name of the column a field is written to, see resolve_column_names
+    pub fn column_name(&self, field: &ChannelDataField) -> String {
+        resolve_column_names(&self.params)
+            .ok()
+            .and_then(|mut names| names.remove(field))
+            .unwrap_or_else(|| field.as_ref().to_string())
+    }
+
+    // Columns written by a build with this map and these options, in output order and under their aliases,
+    // with whether each one is derived (see ChannelDataField::is_derived, derived fields from expressions are
+    // always derived). Columns which prune_empty_columns would remove are still listed, that is only known
+    // once the events are built.
+    pub fn output_columns(channel_map: &ChannelMap, params: &BuildParams) -> Vec<(String, bool)> {
+        let (nested, flat): (Vec<ChannelDataField>, Vec<ChannelDataField>) =
+            ChannelDataField::get_filtered_field_vec(channel_map, params)
+                .into_iter()
+                .partition(|field| *field == ChannelDataField::X || *field == ChannelDataField::Z);
+        let aliases = resolve_column_names(params).unwrap_or_default();
+        flat.iter()
+            .chain(nested.iter())
+            .map(|field| {
+                let name = aliases
+                    .get(field)
+                    .map_or(field.as_ref(), |alias| alias.as_str());
+                (name.to_string(), field.is_derived())
+            })
+            .chain(
+                params
+                    .derived_fields
+                    .iter()
+                    .filter(|entry| entry.parse().is_ok())
+                    .map(|entry| (entry.name.trim().to_string(), true)),
+            )
+            .chain(
+                waveform_channel_types(channel_map, params)
+                    .iter()
+                    .map(|channel_type| (waveform_column_name(channel_type), false)),
+            )
+            .collect()
+    }
+
+    // Same as new, with room for expected_rows events in every column so they do not reallocate as they grow
+    pub fn with_capacity(
+        channel_map: &ChannelMap,
+        params: &BuildParams,
+        expected_rows: usize,
+    ) -> Self {
+        let mut data = ChannelData::new(channel_map, params);
+        data.reserve(expected_rows);
+        data
+    }
+
+    // Make room for additional events in every column
+    pub fn reserve(&mut self, additional: usize) {
+        for values in self.fields.values_mut() {
+            values.reserve(additional);
+        }
+        for values in self.compact_fields.values_mut() {
+            values.reserve(additional);
+        }
+        for values in self.time_fields.values_mut() {
+            values.reserve(additional);
+        }
+        for values in self.nested_fields.values_mut() {
+            values.reserve(additional);
+        }
+        for (_, values) in self.derived_fields.iter_mut() {
+            values.reserve(additional);
+        }
+        for (_, rows) in self.waveforms.iter_mut() {
+            rows.reserve(additional);
+        }
+    }
+
+    // Remove every event but keep the fields, settings, and allocated capacity, so that one ChannelData can
+    // be reused for run after run without reallocating its columns
+    pub fn clear(&mut self) {
+        for values in self.fields.values_mut() {
+            values.clear();
+        }
+        for values in self.compact_fields.values_mut() {
+            values.clear();
+        }
+        for values in self.time_fields.values_mut() {
+            values.clear();
+        }
+        for values in self.nested_fields.values_mut() {
+            values.clear();
+        }
+        for (_, values) in self.derived_fields.iter_mut() {
+            values.clear();
+        }
+        for (_, rows) in self.waveforms.iter_mut() {
+            rows.clear();
+        }
+        self.rows = 0;
+    }
+
+    // Replace the algorithm used to calculate X1 and X2 from the delay lines
+    pub fn set_position_reconstructor(
+        &mut self,
+        reconstructor: impl PositionReconstructor + 'static,
+    ) {
+        self.reconstructor = Arc::new(reconstructor);
+    }
+
+    // Count the negative delay line differences of every event into check, which is shared with the copies
+    // made by empty_copy so that it covers a whole run
+    pub fn set_delay_line_check(&mut self, check: Arc<DelayLineCheck>) {
+        self.delay_line_check = Some(check);
+    }
+
+    // Accumulate the spread of every relative time field of this ChannelData into spread, which is shared
+    // with the copies made by empty_copy so that it covers a whole run
+    pub fn set_time_spread(&mut self, spread: Arc<TimeSpread>) {
+        let fields = self
+            .fields
+            .keys()
+            .chain(self.compact_fields.keys())
+            .filter(|field| field.quantity_kind() == QuantityKind::RelTime)
+            .cloned()
+            .collect();
+        self.time_spread = Some((spread, fields));
+    }
+
+    // Pedestals subtracted from the energy of each hit before it is calibrated, see set_calibration_map
+    pub fn set_pedestal_map(&mut self, pedestals: PedestalMap) {
+        self.pedestals = pedestals;
+    }
+
+    // Energy calibrations applied to each hit, channels without one are stored uncalibrated
+    pub fn set_calibration_map(&mut self, calibrations: CalibrationMap) {
+        self.calibrations = calibrations;
+    }
+
     //To keep columns all same length, push invalid values as necessary
     fn push_defaults(&mut self) {
         for field in self.fields.iter_mut() {
@@ -402,6 +1026,18 @@ impl ChannelData {
             }
         }
 
+        for field in self.compact_fields.iter_mut() {
+            if field.1.len() < self.rows {
+                field.1.push(INVALID_VALUE as f32)
+            }
+        }
+
+        for field in self.time_fields.iter_mut() {
+            if field.1.len() < self.rows {
+                field.1.push(INVALID_TIME)
+            }
+        }
+
         // Pad nested fields
         for field in self.nested_fields.iter_mut() {
             // Pad outer vector to match rows
@@ -409,272 +1045,550 @@ impl ChannelData {
                 field.1.push(vec![INVALID_VALUE]); // Push an empty vector if missing
             }
         }
-    }
-
-    //Update the last element to the given value
-    fn set_value(&mut self, field: &ChannelDataField, value: f64) {
-        if let Some(list) = self.fields.get_mut(field) {
-            if let Some(back) = list.last_mut() {
-                *back = value;
-            }
+
+        for (_, values) in self.derived_fields.iter_mut() {
+            if values.len() < self.rows {
+                values.push(INVALID_VALUE)
+            }
+        }
+
+        for (_, rows) in self.waveforms.iter_mut() {
+            if rows.len() < self.rows {
+                rows.push(None)
+            }
+        }
+    }
+
+    //Update the element of the current row to the given value
+    fn set_value(&mut self, field: &ChannelDataField, value: f64) {
+        let row = self.rows.wrapping_sub(1);
+        if let Some(list) = self.fields.get_mut(field) {
+            if let Some(back) = list.get_mut(row) {
+                *back = value;
+            }
+        } else if let Some(list) = self.compact_fields.get_mut(field) {
+            if let Some(back) = list.get_mut(row) {
+                *back = value as f32;
+            }
+        }
+    }
+
+    //Update the element of the current row of a timestamp field, in ps
+    fn set_time(&mut self, field: &ChannelDataField, ps: i64) {
+        let row = self.rows.wrapping_sub(1);
+        if let Some(list) = self.time_fields.get_mut(field) {
+            if let Some(back) = list.get_mut(row) {
+                *back = ps;
+            }
+        }
+    }
+
+    // Store the energy, short gate energy, and time of one hit together, so that the fields of a detector
+    // always describe the same hit whichever hit the MultiHitPolicy picks. The raw energy goes in the
+    // matching EnergyRaw field and the board/channel of the hit in the matching Board and Channel fields.
+    // The pedestal of the channel is subtracted before the calibration, the raw energy keeps it.
+    // The short field is left unset for detectors without one (None) and for firmware without a short gate.
+    fn set_hit(
+        &mut self,
+        energy_field: &ChannelDataField,
+        short_field: Option<&ChannelDataField>,
+        time_field: &ChannelDataField,
+        hit: &CompassData,
+    ) {
+        let energy = self
+            .pedestals
+            .subtract(&hit.uuid, hit.energy, self.params.clamp_pedestals);
+        let energy = self.calibrations.calibrate(&hit.uuid, energy);
+        self.set_value(energy_field, energy);
+        if let Some(raw_field) = energy_field.raw_energy_field() {
+            self.set_value(&raw_field, hit.energy);
+        }
+        if self.params.provenance_columns {
+            if let Some((board_field, channel_field)) = energy_field.provenance_fields() {
+                let (board, channel) = decompose_uuid_to_board_channel(&hit.uuid);
+                self.set_value(&board_field, board as f64);
+                self.set_value(&channel_field, channel as f64);
+            }
+        }
+        if let (Some(field), Some(short)) = (short_field, self.short_energy(hit)) {
+            self.set_value(field, short);
+        }
+        self.set_time(time_field, hit.timestamp);
+    }
+
+    // Short gate energy of a hit, None when it is only a copy of the energy (see
+    // BuildParams::copied_short_tolerance) since it then carries no information
+    fn short_energy(&self, hit: &CompassData) -> Option<f64> {
+        let short = hit.energy_short?;
+        match self.params.copied_short_tolerance {
+            Some(tolerance) if (short - hit.energy).abs() <= tolerance => None,
+            _ => Some(short),
+        }
+    }
+
+    // (energy - short) / energy of a hit, from the uncalibrated energies. Left invalid without a short gate
+    // energy, or for a raw energy of zero (less than one channel once the random fraction is added)
+    fn set_psd(&mut self, field: &ChannelDataField, hit: &CompassData) {
+        if let Some(short) = self.short_energy(hit) {
+            let long = hit.energy;
+            if long >= 1.0 {
+                self.set_value(field, (long - short) / long);
+            }
+        }
+    }
+
+    //Update the current row of a nested field, like set_value
+    fn set_nested_values(&mut self, field: &ChannelDataField, values: Vec<f64>) {
+        let row = self.rows.wrapping_sub(1);
+        if let Some(nested) = self.nested_fields.get_mut(field) {
+            if let Some(back) = nested.get_mut(row) {
+                *back = values;
+            }
+        }
+    }
+
+    // Empty ChannelData with the same fields, options, pedestals, calibrations, position reconstructor, and
+    // delay line check
+    pub fn empty_copy(&self) -> ChannelData {
+        ChannelData {
+            fields: self.fields.keys().map(|f| (f.clone(), vec![])).collect(),
+            compact_fields: self
+                .compact_fields
+                .keys()
+                .map(|f| (f.clone(), vec![]))
+                .collect(),
+            time_fields: self
+                .time_fields
+                .keys()
+                .map(|f| (f.clone(), vec![]))
+                .collect(),
+            nested_fields: self
+                .nested_fields
+                .keys()
+                .map(|f| (f.clone(), vec![]))
+                .collect(),
+            derived_fields: self
+                .derived_fields
+                .iter()
+                .map(|(name, _)| (name.clone(), vec![]))
+                .collect(),
+            expressions: self.expressions.clone(),
+            waveforms: self
+                .waveforms
+                .iter()
+                .map(|(channel_type, _)| (*channel_type, vec![]))
+                .collect(),
+            rows: 0,
+            params: self.params.clone(),
+            reconstructor: self.reconstructor.clone(),
+            pedestals: self.pedestals.clone(),
+            calibrations: self.calibrations.clone(),
+            required: self.required.clone(),
+            delay_line_check: self.delay_line_check.clone(),
+            time_spread: self.time_spread.clone(),
+        }
+    }
+
+    // Append the rows of other after the rows of self, e.g. to combine the results of builders which ran on
+    // separate chunks of a run. Both must have the same fields, i.e. the same channel map and BuildParams.
+    pub fn merge(&mut self, other: ChannelData) -> Result<(), ChannelDataError> {
+        if !self.fields.keys().eq(other.fields.keys())
+            || !self.compact_fields.keys().eq(other.compact_fields.keys())
+            || !self.time_fields.keys().eq(other.time_fields.keys())
+            || !self.nested_fields.keys().eq(other.nested_fields.keys())
+            || !self
+                .derived_fields
+                .iter()
+                .map(|(name, _)| name)
+                .eq(other.derived_fields.iter().map(|(name, _)| name))
+            || !self
+                .waveforms
+                .iter()
+                .map(|(channel_type, _)| channel_type)
+                .eq(other.waveforms.iter().map(|(channel_type, _)| channel_type))
+        {
+            return Err(ChannelDataError::FieldMismatch);
+        }
+        self.extend(other);
+        Ok(())
+    }
+
+    // Append the rows of other after the rows of self. Other must have the same fields, as from empty_copy
+    fn extend(&mut self, other: ChannelData) {
+        for (field, values) in other.fields {
+            if let Some(list) = self.fields.get_mut(&field) {
+                list.extend(values);
+            }
+        }
+        for (field, values) in other.compact_fields {
+            if let Some(list) = self.compact_fields.get_mut(&field) {
+                list.extend(values);
+            }
+        }
+        for (field, values) in other.time_fields {
+            if let Some(list) = self.time_fields.get_mut(&field) {
+                list.extend(values);
+            }
+        }
+        for (field, values) in other.nested_fields {
+            if let Some(nested) = self.nested_fields.get_mut(&field) {
+                nested.extend(values);
+            }
+        }
+        for ((_, list), (_, values)) in self.derived_fields.iter_mut().zip(other.derived_fields) {
+            list.extend(values);
+        }
+        for ((_, list), (_, rows)) in self.waveforms.iter_mut().zip(other.waveforms) {
+            list.extend(rows);
+        }
+        self.rows += other.rows;
+    }
+
+    // Keep only the first rows events, e.g. to cut a parallel batch down to a maximum number of events
+    pub fn truncate_rows(&mut self, rows: usize) {
+        for values in self.fields.values_mut() {
+            values.truncate(rows);
+        }
+        for values in self.compact_fields.values_mut() {
+            values.truncate(rows);
+        }
+        for values in self.time_fields.values_mut() {
+            values.truncate(rows);
+        }
+        for values in self.nested_fields.values_mut() {
+            values.truncate(rows);
+        }
+        for (_, values) in self.derived_fields.iter_mut() {
+            values.truncate(rows);
         }
+        for (_, list) in self.waveforms.iter_mut() {
+            list.truncate(rows);
+        }
+        self.rows = self.rows.min(rows);
     }
 
-    fn set_nested_values(&mut self, field: &ChannelDataField, values: Vec<f64>) {
-        if let Some(nested) = self.nested_fields.get_mut(field) {
-            nested.push(values);
+    // Build the events of a time-sorted stream of hits on the rayon thread pool. The hits are split into
+    // chunks only at gaps larger than the coincidence window (see split_on_gaps), each chunk is built into
+    // its own ChannelData, and the chunks are appended in time order, so the rows and columns are identical
+    // to pushing the hits through an EventBuilder and calling append_event on each event.
+    pub fn append_events_parallel(
+        &mut self,
+        hits: Vec<CompassData>,
+        coincidence_window: f64,
+        map: &ChannelMap,
+        weights: Option<(f64, f64)>,
+    ) {
+        // A few chunks per thread so that uneven chunks still balance out
+        let min_hits = hits.len() / (rayon::current_num_threads() * 4);
+        let chunks = split_on_gaps(hits, coincidence_window, min_hits.max(1));
+        let anchor = self
+            .params
+            .anchor
+            .map_or(vec![], |anchor| map.uuids_of(anchor));
+        let built: Vec<ChannelData> = chunks
+            .into_par_iter()
+            .map(|chunk| {
+                let mut data = self.empty_copy();
+                for event in build_events(chunk, coincidence_window).with_anchor(anchor.clone()) {
+                    data.append_event(event, map, weights);
+                }
+                data
+            })
+            .collect();
+
+        for data in built {
+            self.extend(data);
         }
     }
 
+    // Xavg is w.0 * x1 + w.1 * x2. Weights which do not sum to one scale the position unless
+    // BuildParams::normalize_xavg_weights is set, in which case they are divided by their sum.
+    // Without weights Xavg is the unweighted mean. It is INVALID_VALUE when a plane is missing, or when
+    // normalized weights sum to zero.
+    // With BuildParams::drop_bad_events, an event missing one of the required fields is not kept, and with
+    // BuildParams::drop_empty_events neither is an event without a hit of a mapped, enabled channel.
     pub fn append_event(
         &mut self,
         event: Vec<CompassData>,
         map: &ChannelMap,
         weights: Option<(f64, f64)>,
     ) {
+        // Nothing of the event would be written but EventTime
+        if self.params.drop_empty_events && self.mapped_hits(&event, map).is_empty() {
+            return;
+        }
         self.rows += 1;
         self.push_defaults();
+        let good = self.fill_event(&event, map, weights);
+        if !good && self.params.drop_bad_events {
+            self.truncate_rows(self.rows - 1);
+        }
+    }
 
-        let mut dfl_time = INVALID_VALUE;
-        let mut dfr_time = INVALID_VALUE;
-        let mut dbl_time = INVALID_VALUE;
-        let mut dbr_time = INVALID_VALUE;
-
-        let mut scint_left_time = INVALID_VALUE;
-        let mut anode_back_time = INVALID_VALUE;
-
-        // for cebra relative time
-        let mut cebra0_time = INVALID_VALUE;
-        let mut cebra1_time = INVALID_VALUE;
-        let mut cebra2_time = INVALID_VALUE;
-        let mut cebra3_time = INVALID_VALUE;
-        let mut cebra4_time = INVALID_VALUE;
-        let mut cebra5_time = INVALID_VALUE;
-        let mut cebra6_time = INVALID_VALUE;
-        let mut cebra7_time = INVALID_VALUE;
-        let mut cebra8_time = INVALID_VALUE;
-
-        // for pips relative time
-        let mut pips1000_time = INVALID_VALUE;
-        let mut pips500_time = INVALID_VALUE;
-        let mut pips300_time = INVALID_VALUE;
-        let mut pips100_time = INVALID_VALUE;
-
+    // Same as append_event, but tells a hit of a channel left without a type on purpose from a broken map.
+    // With BuildParams::strict_channel_map, an event with a hit of a channel which has no type is an
+    // UnmappedChannel error, or a ChannelMapError::UnknownChannel for a channel on none of the boards. An
+    // event with hits of one detector from two channels is a DuplicateMapping error, which a map passing
+    // ChannelMap::validate can not produce. The event is not appended when there is an error. Hits of disabled
+    // channels are still dropped, they are left out on purpose.
+    pub fn try_append_event(
+        &mut self,
+        event: Vec<CompassData>,
+        map: &ChannelMap,
+        weights: Option<(f64, f64)>,
+    ) -> Result<(), EVBError> {
+        let mut channels: Vec<(ChannelType, u32)> = vec![];
         for hit in event.iter() {
-            //Fill out detector fields using channel map
-            let channel_data = match map.get_channel_data(&hit.uuid) {
-                Some(data) => data,
-                None => continue,
-            };
-            match channel_data.channel_type {
-                ChannelType::ScintLeft => {
-                    self.set_value(&ChannelDataField::ScintLeftEnergy, hit.energy);
-                    self.set_value(&ChannelDataField::ScintLeftShort, hit.energy_short);
-                    self.set_value(&ChannelDataField::ScintLeftTime, hit.timestamp);
-                    scint_left_time = hit.timestamp;
-                }
-
-                ChannelType::ScintRight => {
-                    self.set_value(&ChannelDataField::ScintRightEnergy, hit.energy);
-                    self.set_value(&ChannelDataField::ScintRightShort, hit.energy_short);
-                    self.set_value(&ChannelDataField::ScintRightTime, hit.timestamp);
-                }
-
-                ChannelType::Cathode => {
-                    self.set_value(&ChannelDataField::CathodeEnergy, hit.energy);
-                    self.set_value(&ChannelDataField::CathodeShort, hit.energy_short);
-                    self.set_value(&ChannelDataField::CathodeTime, hit.timestamp);
-                }
-
-                ChannelType::DelayFrontRight => {
-                    self.set_value(&ChannelDataField::DelayFrontRightEnergy, hit.energy);
-                    self.set_value(&ChannelDataField::DelayFrontRightShort, hit.energy_short);
-                    self.set_value(&ChannelDataField::DelayFrontRightTime, hit.timestamp);
-                    dfr_time = hit.timestamp;
-                }
-
-                ChannelType::DelayFrontLeft => {
-                    self.set_value(&ChannelDataField::DelayFrontLeftEnergy, hit.energy);
-                    self.set_value(&ChannelDataField::DelayFrontLeftShort, hit.energy_short);
-                    self.set_value(&ChannelDataField::DelayFrontLeftTime, hit.timestamp);
-                    dfl_time = hit.timestamp;
-                }
-
-                ChannelType::DelayBackRight => {
-                    self.set_value(&ChannelDataField::DelayBackRightEnergy, hit.energy);
-                    self.set_value(&ChannelDataField::DelayBackRightShort, hit.energy_short);
-                    self.set_value(&ChannelDataField::DelayBackRightTime, hit.timestamp);
-                    dbr_time = hit.timestamp;
+            let channel_type = match map.lookup(&hit.uuid) {
+                Ok(Some(data)) => data.channel_type,
+                Ok(None) if self.params.strict_channel_map => {
+                    let (board, channel) = decompose_uuid_to_board_channel(&hit.uuid);
+                    return Err(EVBError::UnmappedChannel { board, channel });
                 }
-
-                ChannelType::DelayBackLeft => {
-                    self.set_value(&ChannelDataField::DelayBackLeftEnergy, hit.energy);
-                    self.set_value(&ChannelDataField::DelayBackLeftShort, hit.energy_short);
-                    self.set_value(&ChannelDataField::DelayBackLeftTime, hit.timestamp);
-                    dbl_time = hit.timestamp;
+                Err(e) if self.params.strict_channel_map => return Err(e.into()),
+                _ => continue,
+            };
+            match channels.iter().find(|(t, _)| *t == channel_type) {
+                Some((_, uuid)) if *uuid != hit.uuid => {
+                    return Err(EVBError::DuplicateMapping {
+                        channel_type,
+                        channels: vec![
+                            decompose_uuid_to_board_channel(uuid),
+                            decompose_uuid_to_board_channel(&hit.uuid),
+                        ],
+                    });
                 }
+                Some(_) => {}
+                None => channels.push((channel_type, hit.uuid)),
+            }
+        }
+        self.append_event(event, map, weights);
+        Ok(())
+    }
 
-                ChannelType::AnodeFront => {
-                    self.set_value(&ChannelDataField::AnodeFrontEnergy, hit.energy);
-                    self.set_value(&ChannelDataField::AnodeFrontShort, hit.energy_short);
-                    self.set_value(&ChannelDataField::AnodeFrontTime, hit.timestamp);
-                }
+    // Same result as calling append_event on each event in turn, but every column reserves room for all of
+    // the events once up front instead of reallocating as it grows
+    pub fn append_events_batch(
+        &mut self,
+        events: Vec<Vec<CompassData>>,
+        map: &ChannelMap,
+        weights: Option<(f64, f64)>,
+    ) {
+        self.reserve(events.len());
+        for event in events {
+            self.append_event(event, map, weights);
+        }
+    }
 
-                ChannelType::AnodeBack => {
-                    self.set_value(&ChannelDataField::AnodeBackEnergy, hit.energy);
-                    self.set_value(&ChannelDataField::AnodeBackShort, hit.energy_short);
-                    self.set_value(&ChannelDataField::AnodeBackTime, hit.timestamp);
-                    anode_back_time = hit.timestamp;
-                }
+    // Hits of disabled channels are dropped like unmapped ones, so a disabled delay line end
+    // leaves its plane invalid instead of giving a half-computed position. Energy limits are applied
+    // here too, so a dropped hit is not counted and a clamped one is used as if it were measured.
+    fn mapped_hits<'a>(
+        &self,
+        event: &'a [CompassData],
+        map: &ChannelMap,
+    ) -> Vec<(ChannelType, Cow<'a, CompassData>)> {
+        let mut hits = vec![];
+        for hit in event.iter() {
+            // Unmapped hits are counted by the caller, see UnmappedHits and try_append_event
+            let channel_type = match map.lookup(&hit.uuid) {
+                Ok(Some(data)) if !data.disabled => data.channel_type,
+                _ => continue,
+            };
+            let limit = self
+                .params
+                .energy_limits
+                .iter()
+                .find(|limit| limit.channel_type == channel_type);
+            match limit {
+                Some(limit) => match limit.apply(hit) {
+                    Some(kept) => hits.push((channel_type, kept)),
+                    None => continue,
+                },
+                None => hits.push((channel_type, Cow::Borrowed(hit))),
+            }
+        }
+        hits
+    }
 
-                ChannelType::Cebra0 => {
-                    self.set_value(&ChannelDataField::Cebra0Energy, hit.energy);
-                    self.set_value(&ChannelDataField::Cebra0Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::Cebra0Time, hit.timestamp);
-                    cebra0_time = hit.timestamp;
-                }
+    // Fill the current row from the hits of an event, the row must already be padded with defaults.
+    // Hit timestamps already include their ShiftMap offset (applied in CompassData::new, before event
+    // building), so the relative times, X1/X2, and everything derived from them use the corrected times.
+    // Returns whether every required field was set, see BuildParams::required_fields.
+    fn fill_event(
+        &mut self,
+        event: &[CompassData],
+        map: &ChannelMap,
+        weights: Option<(f64, f64)>,
+    ) -> bool {
+        let mut delay_lines = DelayLineData::default();
 
-                ChannelType::Cebra1 => {
-                    self.set_value(&ChannelDataField::Cebra1Energy, hit.energy);
-                    self.set_value(&ChannelDataField::Cebra1Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::Cebra1Time, hit.timestamp);
-                    cebra1_time = hit.timestamp;
-                }
+        // One bit per detector type that fired, see ChannelType::bitmask_bit
+        let mut hit_pattern: u64 = 0;
 
-                ChannelType::Cebra2 => {
-                    self.set_value(&ChannelDataField::Cebra2Energy, hit.energy);
-                    self.set_value(&ChannelDataField::Cebra2Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::Cebra2Time, hit.timestamp);
-                    cebra2_time = hit.timestamp;
-                }
+        // Number of CeBrA detectors which fired, each counted once whatever its number of hits
+        let mut cebra_multiplicity = 0u32;
+        // Whether the veto paddle fired, which flags the event without changing any other field
+        let mut vetoed = false;
 
-                ChannelType::Cebra3 => {
-                    self.set_value(&ChannelDataField::Cebra3Energy, hit.energy);
-                    self.set_value(&ChannelDataField::Cebra3Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::Cebra3Time, hit.timestamp);
-                    cebra3_time = hit.timestamp;
-                }
+        // Earliest hit of the event, mapped or not, so that events can be ordered without a reference detector
+        if let Some(event_time) = event.iter().map(|hit| hit.timestamp).min() {
+            self.set_time(&ChannelDataField::EventTime, event_time);
+        }
 
-                ChannelType::Cebra4 => {
-                    self.set_value(&ChannelDataField::Cebra4Energy, hit.energy);
-                    self.set_value(&ChannelDataField::Cebra4Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::Cebra4Time, hit.timestamp);
-                    cebra4_time = hit.timestamp;
+        let hits = self.mapped_hits(event, map);
+
+        // One hit per channel type fills that type's fields, chosen by the MultiHitPolicy. The derived
+        // quantities (relative times, X1/X2, ...) are taken from the same hit, while the bitmask and the
+        // multiplicities count every hit in the event.
+        let mut selected: Vec<(ChannelType, &CompassData)> = vec![];
+        for (channel_type, hit) in hits.iter() {
+            let channel_type = *channel_type;
+            let hit: &CompassData = hit;
+            if let Some(bit) = channel_type.bitmask_bit() {
+                hit_pattern |= 1 << bit;
+            }
+            match selected.iter_mut().find(|(t, _)| *t == channel_type) {
+                Some(entry) => {
+                    if self.params.multi_hit_policy.prefers(hit, entry.1) {
+                        entry.1 = hit;
+                    }
                 }
+                None => selected.push((channel_type, hit)),
+            }
+        }
 
-                ChannelType::Cebra5 => {
-                    self.set_value(&ChannelDataField::Cebra5Energy, hit.energy);
-                    self.set_value(&ChannelDataField::Cebra5Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::Cebra5Time, hit.timestamp);
-                    cebra5_time = hit.timestamp;
-                }
+        // The anchor is the hit chosen for that detector, and is the event time when it fired
+        let anchor_time = self.params.anchor.map(|anchor| {
+            selected
+                .iter()
+                .find(|(channel_type, _)| *channel_type == anchor)
+                .map(|(_, hit)| hit.timestamp)
+        });
+        if let Some(Some(time)) = anchor_time {
+            self.set_time(&ChannelDataField::EventTime, time);
+        }
 
-                ChannelType::Cebra6 => {
-                    self.set_value(&ChannelDataField::Cebra6Energy, hit.energy);
-                    self.set_value(&ChannelDataField::Cebra6Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::Cebra6Time, hit.timestamp);
-                    cebra6_time = hit.timestamp;
-                }
+        // A configured CeBrA reference is the hit chosen for that detector
+        let cebra_reference_time = self.params.cebra_reference.map(|reference| {
+            selected
+                .iter()
+                .find(|(channel_type, _)| *channel_type == reference)
+                .map(|(_, hit)| hit.timestamp)
+        });
 
-                ChannelType::Cebra7 => {
-                    self.set_value(&ChannelDataField::Cebra7Energy, hit.energy);
-                    self.set_value(&ChannelDataField::Cebra7Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::Cebra7Time, hit.timestamp);
-                    cebra7_time = hit.timestamp;
+        // Waveforms are copied from the chosen hit, like the other detector fields
+        if !self.waveforms.is_empty() {
+            let row = self.rows.wrapping_sub(1);
+            for (channel_type, rows) in self.waveforms.iter_mut() {
+                if let (Some((_, hit)), Some(slot)) = (
+                    selected.iter().find(|(t, _)| *t == *channel_type),
+                    rows.get_mut(row),
+                ) {
+                    slot.clone_from(&hit.waveform);
                 }
+            }
+        }
 
-                ChannelType::Cebra8 => {
-                    self.set_value(&ChannelDataField::Cebra8Energy, hit.energy);
-                    self.set_value(&ChannelDataField::Cebra8Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::Cebra8Time, hit.timestamp);
-                    cebra8_time = hit.timestamp;
-                }
+        for (channel_type, hit) in selected {
+            //Fill out detector fields using channel map
+            let field = |quantity| ChannelDataField::of_detector(channel_type, quantity);
+            if let (Some(energy), Some(time)) = (
+                field(DetectorQuantity::Energy),
+                field(DetectorQuantity::Time),
+            ) {
+                self.set_hit(&energy, field(DetectorQuantity::Short).as_ref(), &time, hit);
+            }
+            if let Some(psd) = field(DetectorQuantity::PSD) {
+                self.set_psd(&psd, hit);
+            }
 
-                ChannelType::PIPS1000 => {
-                    self.set_value(&ChannelDataField::PIPS1000Energy, hit.energy);
-                    self.set_value(&ChannelDataField::PIPS1000Time, hit.timestamp);
-                    pips1000_time = hit.timestamp;
-                }
+            match channel_type {
+                ChannelType::Veto => vetoed = true,
+                ChannelType::DelayFrontLeft => delay_lines.front_left = Some(hit.clone()),
+                ChannelType::DelayFrontRight => delay_lines.front_right = Some(hit.clone()),
+                ChannelType::DelayBackLeft => delay_lines.back_left = Some(hit.clone()),
+                ChannelType::DelayBackRight => delay_lines.back_right = Some(hit.clone()),
+                _ if channel_type.cebra_index().is_some() => cebra_multiplicity += 1,
+                _ => {}
+            }
+        }
 
-                ChannelType::PIPS500 => {
-                    self.set_value(&ChannelDataField::PIPS500Energy, hit.energy);
-                    self.set_value(&ChannelDataField::PIPS500Time, hit.timestamp);
-                    pips500_time = hit.timestamp;
+        self.set_value(&ChannelDataField::HitPattern, hit_pattern as f64);
+        self.set_value(
+            &ChannelDataField::CebraMultiplicity,
+            cebra_multiplicity as f64,
+        );
+        self.set_value(&ChannelDataField::Vetoed, if vetoed { 1.0 } else { 0.0 });
+        if self.params.multiplicity {
+            for channel_type in ChannelType::iter() {
+                let field =
+                    ChannelDataField::of_detector(channel_type, DetectorQuantity::Multiplicity);
+                if let Some(field) = field {
+                    let count = hits.iter().filter(|(t, _)| *t == channel_type).count();
+                    self.set_value(&field, count as f64);
                 }
+            }
+        }
 
-                ChannelType::PIPS300 => {
-                    self.set_value(&ChannelDataField::PIPS300Energy, hit.energy);
-                    self.set_value(&ChannelDataField::PIPS300Time, hit.timestamp);
-                    pips300_time = hit.timestamp;
-                }
+        //Physics
+        // Unscaled left - right time differences, useful for calibrating the drift velocities
+        if let (Some(left), Some(right)) = (&delay_lines.front_left, &delay_lines.front_right) {
+            self.set_value(
+                &ChannelDataField::X1TimeDiff,
+                ps_to_ns(left.timestamp - right.timestamp),
+            );
+        }
+        if let (Some(left), Some(right)) = (&delay_lines.back_left, &delay_lines.back_right) {
+            self.set_value(
+                &ChannelDataField::X2TimeDiff,
+                ps_to_ns(left.timestamp - right.timestamp),
+            );
+        }
 
-                ChannelType::PIPS100 => {
-                    self.set_value(&ChannelDataField::PIPS100Energy, hit.energy);
-                    self.set_value(&ChannelDataField::PIPS100Time, hit.timestamp);
-                    pips100_time = hit.timestamp;
-                }
+        // The times of the detectors which fired, as stored by set_hit
+        let scint_left_time = self.get_time(&ChannelDataField::ScintLeftTime);
+        let scint_right_time = self.get_time(&ChannelDataField::ScintRightTime);
+        let anode_front_time = self.get_time(&ChannelDataField::AnodeFrontTime);
+        let anode_back_time = self.get_time(&ChannelDataField::AnodeBackTime);
 
-                ChannelType::CATRINA0 => {
-                    self.set_value(&ChannelDataField::CATRINA0Energy, hit.energy);
-                    self.set_value(&ChannelDataField::CATRINA0Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::CATRINA0Time, hit.timestamp);
-                    let long = hit.energy;
-                    let short = hit.energy_short;
-                    let psd = (long - short) / long;
-                    self.set_value(&ChannelDataField::CATRINA0PSD, psd);
-                }
+        if let (Some(front), Some(back)) = (anode_front_time, anode_back_time) {
+            self.set_value(&ChannelDataField::AnodeTimeDiff, ps_to_ns(front - back));
+        }
 
-                ChannelType::CATRINA1 => {
-                    self.set_value(&ChannelDataField::CATRINA1Energy, hit.energy);
-                    self.set_value(&ChannelDataField::CATRINA1Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::CATRINA1Time, hit.timestamp);
-                    let long = hit.energy;
-                    let short = hit.energy_short;
-                    let psd = (long - short) / long;
-                    self.set_value(&ChannelDataField::CATRINA1PSD, psd);
-                }
+        // Unscaled top - bottom time difference, the vertical counterpart of the delay line differences
+        if let (Some(top), Some(bottom)) = (
+            self.get_time(&ChannelDataField::ScintTopTime),
+            self.get_time(&ChannelDataField::ScintBottomTime),
+        ) {
+            self.set_value(
+                &ChannelDataField::ScintVerticalTimeDiff,
+                ps_to_ns(top - bottom),
+            );
+        }
 
-                ChannelType::CATRINA2 => {
-                    self.set_value(&ChannelDataField::CATRINA2Energy, hit.energy);
-                    self.set_value(&ChannelDataField::CATRINA2Short, hit.energy_short);
-                    self.set_value(&ChannelDataField::CATRINA2Time, hit.timestamp);
-                    let long = hit.energy;
-                    let short = hit.energy_short;
-                    let psd = (long - short) / long;
-                    self.set_value(&ChannelDataField::CATRINA2PSD, psd);
-                }
-                _ => continue,
-            }
+        if let Some(check) = &self.delay_line_check {
+            check.push(&delay_lines);
         }
 
-        //Physics
-        let mut x1 = INVALID_VALUE;
-        let mut x2 = INVALID_VALUE;
-        if dfr_time != INVALID_VALUE && dfl_time != INVALID_VALUE {
-            x1 = (dfl_time - dfr_time) * 0.5 * 1.0 / 2.1;
+        // X1 and X2 are each written when their plane has both delay line ends. Everything derived from the
+        // positions (Theta, ThetaDeg, Xavg, X, Z) needs both planes and is left invalid otherwise. The
+        // positions are only read from this event's reconstruction, never from a sentinel local.
+        let (x1_position, x2_position) = self.reconstructor.reconstruct(&delay_lines);
+        if let Some(x1) = x1_position {
             self.set_value(&ChannelDataField::X1, x1);
         }
-        if dbr_time != INVALID_VALUE && dbl_time != INVALID_VALUE {
-            x2 = (dbl_time - dbr_time) * 0.5 * 1.0 / 1.98;
+        if let Some(x2) = x2_position {
             self.set_value(&ChannelDataField::X2, x2);
         }
-        if x1 != INVALID_VALUE && x2 != INVALID_VALUE {
-            let diff = x2 - x1;
-            if diff > 0.0 {
-                self.set_value(&ChannelDataField::Theta, (diff / 36.0).atan());
-            } else if diff < 0.0 {
-                self.set_value(
-                    &ChannelDataField::Theta,
-                    std::f64::consts::PI + (diff / 36.0).atan(),
-                );
-            } else {
-                self.set_value(&ChannelDataField::Theta, std::f64::consts::PI * 0.5);
-            }
+        if let (Some(x1), Some(x2)) = (x1_position, x2_position) {
+            let theta = self.params.focal_plane.theta(x1, x2);
+            self.set_value(&ChannelDataField::Theta, theta);
+            self.set_value(&ChannelDataField::ThetaDeg, theta.to_degrees());
 
-            match weights {
-                Some(w) => self.set_value(&ChannelDataField::Xavg, w.0 * x1 + w.1 * x2),
-                None => self.set_value(&ChannelDataField::Xavg, INVALID_VALUE),
-            };
+            if let Some(xavg) = self.xavg(x1, x2, weights) {
+                self.set_value(&ChannelDataField::Xavg, xavg);
+            }
 
             let z_values: Vec<f64> = (0..400)
                 .map(|i| -50.0 + (100.0 / 400.0) * i as f64)
@@ -682,136 +1596,741 @@ impl ChannelData {
 
             let x_values: Vec<f64> = z_values
                 .iter()
-                .map(|&z| (z / 42.8625 + 0.5) * (x2 - x1) + x1)
+                .map(|&z| self.params.focal_plane.x_at(x1, x2, z))
                 .collect();
 
             self.set_nested_values(&ChannelDataField::X, x_values);
             self.set_nested_values(&ChannelDataField::Z, z_values);
         }
 
-        if scint_left_time != INVALID_VALUE && anode_back_time != INVALID_VALUE {
-            if cebra0_time != INVALID_VALUE {
-                self.set_value(
-                    &ChannelDataField::Cebra0RelTime,
-                    cebra0_time - scint_left_time,
-                );
+        // Relative times are referenced to the anchor, by default ScintLeft. With the default, if enabled,
+        // ScintRight (less its offset) stands in for events where ScintLeft did not fire, and
+        // RelTimeReference records which one was used (0 = none, 1 = ScintLeft, 2 = ScintRight)
+        let (reference_time, reference) = match (anchor_time, scint_left_time, scint_right_time) {
+            (Some(anchor_time), _, _) => (anchor_time, 1.0),
+            (None, Some(left), _) => (Some(left), 1.0),
+            (None, None, Some(right)) if self.params.scint_right_fallback => {
+                (Some(right - ns_to_ps(self.params.scint_right_offset)), 2.0)
             }
+            _ => (None, 0.0),
+        };
+
+        let mut reference_used = false;
 
-            if cebra1_time != INVALID_VALUE {
+        // Anode times for time-of-flight cuts only need the anode and the reference
+        if let Some(reference_time) = reference_time {
+            if let Some(front) = anode_front_time {
                 self.set_value(
-                    &ChannelDataField::Cebra1RelTime,
-                    cebra1_time - scint_left_time,
+                    &ChannelDataField::AnodeFrontRelTime,
+                    ps_to_ns(front - reference_time),
                 );
+                reference_used = true;
             }
 
-            if cebra2_time != INVALID_VALUE {
+            if let Some(back) = anode_back_time {
                 self.set_value(
-                    &ChannelDataField::Cebra2RelTime,
-                    cebra2_time - scint_left_time,
+                    &ChannelDataField::AnodeBackRelTime,
+                    ps_to_ns(back - reference_time),
                 );
+                reference_used = true;
             }
+        }
 
-            if cebra3_time != INVALID_VALUE {
-                self.set_value(
-                    &ChannelDataField::Cebra3RelTime,
-                    cebra3_time - scint_left_time,
-                );
+        // CeBrA times use the configured reference on its own, or the scintillator reference with AnodeBack
+        let cebra_reference_time = match cebra_reference_time {
+            Some(time) => time,
+            None => reference_time.filter(|_| anode_back_time.is_some()),
+        };
+        if let Some(cebra_reference_time) = cebra_reference_time {
+            for cebra in CEBRA_CHANNEL_TYPES {
+                let time = ChannelDataField::of_detector(cebra, DetectorQuantity::Time);
+                let rel_time = ChannelDataField::of_detector(cebra, DetectorQuantity::RelTime);
+                if let (Some(time), Some(rel_time)) = (time, rel_time) {
+                    if let Some(time) = self.get_time(&time) {
+                        self.set_value(&rel_time, ps_to_ns(time - cebra_reference_time));
+                    }
+                }
             }
+        }
 
-            if cebra4_time != INVALID_VALUE {
-                self.set_value(
-                    &ChannelDataField::Cebra4RelTime,
-                    cebra4_time - scint_left_time,
-                );
+        if let (Some(reference_time), Some(_)) = (reference_time, anode_back_time) {
+            for (time_field, rel_time_field) in PIPS_REL_TIME_FIELDS.iter() {
+                if let Some(time) = self.get_time(time_field) {
+                    self.set_value(rel_time_field, ps_to_ns(time - reference_time));
+                }
             }
+        }
 
-            if cebra5_time != INVALID_VALUE {
-                self.set_value(
-                    &ChannelDataField::Cebra5RelTime,
-                    cebra5_time - scint_left_time,
-                );
+        if reference_used {
+            self.set_value(&ChannelDataField::RelTimeReference, reference);
+        } else {
+            self.set_value(&ChannelDataField::RelTimeReference, 0.0);
+        }
+
+        // Optional second set of CeBrA relative times, always referenced to ScintRight
+        if let (Some(right), Some(_)) = (scint_right_time, anode_back_time) {
+            for cebra in CEBRA_CHANNEL_TYPES {
+                let time = ChannelDataField::of_detector(cebra, DetectorQuantity::Time);
+                let rel_time = ChannelDataField::of_detector(cebra, DetectorQuantity::RelTimeRight);
+                if let (Some(time), Some(rel_time)) = (time, rel_time) {
+                    if let Some(time) = self.get_time(&time) {
+                        self.set_value(&rel_time, ps_to_ns(time - right));
+                    }
+                }
             }
+        }
 
-            if cebra6_time != INVALID_VALUE {
+        // Total anode energy, and the position from charge division between the anodes as a cross-check of
+        // the delay line position
+        if let (Some(front), Some(back)) = (
+            self.get_value(&ChannelDataField::AnodeFrontEnergy),
+            self.get_value(&ChannelDataField::AnodeBackEnergy),
+        ) {
+            self.set_value(&ChannelDataField::AnodeSumEnergy, front + back);
+            if front + back != 0.0 {
                 self.set_value(
-                    &ChannelDataField::Cebra6RelTime,
-                    cebra6_time - scint_left_time,
+                    &ChannelDataField::XavgCharge,
+                    (front - back) / (front + back),
                 );
             }
+        }
 
-            if cebra7_time != INVALID_VALUE {
-                self.set_value(
-                    &ChannelDataField::Cebra7RelTime,
-                    cebra7_time - scint_left_time,
-                );
+        // The quality cut, before the derived fields so they can use it
+        let good = self.required.iter().all(|field| self.is_set(field));
+        self.set_value(&ChannelDataField::GoodEvent, if good { 1.0 } else { 0.0 });
+
+        // Only the events which are written
+        if let Some((spread, fields)) = self
+            .time_spread
+            .as_ref()
+            .filter(|_| good || !self.params.drop_bad_events)
+        {
+            spread.push(
+                fields
+                    .iter()
+                    .map(|field| (field.clone(), self.get_value(field))),
+            );
+        }
+
+        // Derived fields, after all of the built-in physics so they can use any field
+        let expressions = self.expressions.clone();
+        let row = self.rows.wrapping_sub(1);
+        for (index, expression) in expressions.iter().enumerate() {
+            let value = expression
+                .evaluate(&|field| self.get_value(field))
+                .unwrap_or(INVALID_VALUE);
+            if let Some(slot) = self.derived_fields[index].1.get_mut(row) {
+                *slot = value;
             }
+        }
+        good
+    }
 
-            if cebra8_time != INVALID_VALUE {
-                self.set_value(
-                    &ChannelDataField::Cebra8RelTime,
-                    cebra8_time - scint_left_time,
-                );
+    // Whether a field was set in the current row. Fields which are not written are never set.
+    fn is_set(&self, field: &ChannelDataField) -> bool {
+        match self.nested_fields.get(field) {
+            Some(values) => values
+                .last()
+                .is_some_and(|values| values.first() != Some(&INVALID_VALUE)),
+            None => self.get_value(field).is_some(),
+        }
+    }
+
+    // Position dependent weights (BuildParams::xavg_weight_slopes) are evaluated at this event's X1, so
+    // slopes of zero give the constant weights. None when normalized weights sum to zero
+    fn xavg(&self, x1: f64, x2: f64, weights: Option<(f64, f64)>) -> Option<f64> {
+        let weights = match (weights, self.params.xavg_weight_slopes) {
+            (Some(w), Some(s)) => Some((w.0 + s.0 * x1, w.1 + s.1 * x1)),
+            (w, _) => w,
+        };
+        match weights {
+            Some(w) if self.params.normalize_xavg_weights => {
+                let sum = w.0 + w.1;
+                (sum.abs() >= MIN_XAVG_WEIGHT_SUM).then(|| (w.0 * x1 + w.1 * x2) / sum)
             }
+            Some(w) => Some(w.0 * x1 + w.1 * x2),
+            // Without kinematic weights Xavg is the plain mean of the two planes
+            None => Some(0.5 * x1 + 0.5 * x2),
+        }
+    }
 
-            if pips1000_time != INVALID_VALUE {
-                self.set_value(
-                    &ChannelDataField::PIPS1000RelTime,
-                    pips1000_time - scint_left_time,
-                );
+    // Recalculate Xavg of every event from its X1 and X2 with new weights, e.g. for data loaded with
+    // from_dataframe. Weights are applied as in append_event.
+    pub fn recalculate_xavg(&mut self, weights: Option<(f64, f64)>) {
+        for row in 0..self.rows {
+            let xavg = match (
+                self.value_at(&ChannelDataField::X1, row),
+                self.value_at(&ChannelDataField::X2, row),
+            ) {
+                (Some(x1), Some(x2)) => self.xavg(x1, x2, weights).unwrap_or(INVALID_VALUE),
+                _ => INVALID_VALUE,
+            };
+            if let Some(slot) = self
+                .fields
+                .get_mut(&ChannelDataField::Xavg)
+                .and_then(|values| values.get_mut(row))
+            {
+                *slot = xavg;
+            } else if let Some(slot) = self
+                .compact_fields
+                .get_mut(&ChannelDataField::Xavg)
+                .and_then(|values| values.get_mut(row))
+            {
+                *slot = xavg as f32;
             }
+        }
+    }
 
-            if pips500_time != INVALID_VALUE {
-                self.set_value(
-                    &ChannelDataField::PIPS500RelTime,
-                    pips500_time - scint_left_time,
-                );
+    // Value of a field in the event being built (the last row), None if it was not set or is not one of
+    // the fields. Timestamps are given in ns like the other times.
+    pub fn get_value(&self, field: &ChannelDataField) -> Option<f64> {
+        self.value_at(field, self.rows.checked_sub(1)?)
+    }
+
+    // Timestamp field of the event being built in integer ps, None if it was not set
+    fn get_time(&self, field: &ChannelDataField) -> Option<i64> {
+        let ps = *self
+            .time_fields
+            .get(field)?
+            .get(self.rows.checked_sub(1)?)?;
+        (ps != INVALID_TIME).then_some(ps)
+    }
+
+    fn value_at(&self, field: &ChannelDataField, row: usize) -> Option<f64> {
+        let value = if let Some(values) = self.fields.get(field) {
+            *values.get(row)?
+        } else if let Some(values) = self.compact_fields.get(field) {
+            *values.get(row)? as f64
+        } else {
+            let ps = *self.time_fields.get(field)?.get(row)?;
+            if ps == INVALID_TIME {
+                return None;
             }
+            ps_to_ns(ps)
+        };
+        (value != INVALID_VALUE && value != INVALID_VALUE as f32 as f64).then_some(value)
+    }
 
-            if pips300_time != INVALID_VALUE {
-                self.set_value(
-                    &ChannelDataField::PIPS300RelTime,
-                    pips300_time - scint_left_time,
-                );
+    // Remove the last event and return what was set in it, None without events
+    pub fn pop_event(&mut self) -> Option<Event> {
+        let row = self.rows.checked_sub(1)?;
+        let mut event = Event::default();
+        for field in self
+            .fields
+            .keys()
+            .chain(self.compact_fields.keys())
+            .chain(self.time_fields.keys())
+        {
+            if let Some(value) = self.value_at(field, row) {
+                event.values.insert(field.clone(), value);
+            }
+        }
+        for (field, values) in self.nested_fields.iter() {
+            if let Some(values) = values
+                .get(row)
+                .filter(|values| values.first() != Some(&INVALID_VALUE))
+            {
+                event.positions.insert(field.clone(), values.clone());
+            }
+        }
+        for (name, values) in self.derived_fields.iter() {
+            if let Some(value) = values.get(row).filter(|value| **value != INVALID_VALUE) {
+                event.derived.push((name.clone(), *value));
+            }
+        }
+        for (channel_type, rows) in self.waveforms.iter() {
+            if let Some(Some(samples)) = rows.get(row) {
+                event.waveforms.push((*channel_type, samples.clone()));
             }
+        }
+        self.truncate_rows(row);
+        Some(event)
+    }
 
-            if pips100_time != INVALID_VALUE {
-                self.set_value(
-                    &ChannelDataField::PIPS100RelTime,
-                    pips100_time - scint_left_time,
-                );
+    // Build each event of a stream, e.g. from build_events, into an Event as the stream is read, for
+    // consumers which want one event at a time rather than the columns of a whole run. Events are built
+    // exactly as append_event would into a copy of self with no rows, so the settings of self are used.
+    pub fn events<'a, I: IntoIterator<Item = Vec<CompassData>>>(
+        &self,
+        events: I,
+        map: &'a ChannelMap,
+        weights: Option<(f64, f64)>,
+    ) -> EventIter<'a, I::IntoIter> {
+        EventIter::new(events.into_iter(), self.empty_copy(), map, weights)
+    }
+
+    // Histogram of a field over the events built so far, skipping the events where it was not set. Times are
+    // in ns like get_value. None if the field is not stored, is one of the list fields (X, Z), or the range
+    // or number of bins is empty.
+    pub fn histogram(
+        &self,
+        field: &ChannelDataField,
+        range: (f64, f64),
+        bins: usize,
+    ) -> Option<Histogram> {
+        if !self.fields.contains_key(field)
+            && !self.compact_fields.contains_key(field)
+            && !self.time_fields.contains_key(field)
+        {
+            return None;
+        }
+        let mut histogram = Histogram::new(range, bins)?;
+        for row in 0..self.rows {
+            if let Some(value) = self.value_at(field, row) {
+                histogram.fill(value);
+            }
+        }
+        Some(histogram)
+    }
+
+    // Same as histogram, by column name. Derived fields are found by their name, the other fields by their
+    // default name, not their alias.
+    pub fn histogram_by_name(
+        &self,
+        name: &str,
+        range: (f64, f64),
+        bins: usize,
+    ) -> Option<Histogram> {
+        let name = name.trim();
+        match self
+            .derived_fields
+            .iter()
+            .find(|(derived, _)| derived == name)
+        {
+            Some((_, values)) => {
+                let mut histogram = Histogram::new(range, bins)?;
+                values
+                    .iter()
+                    .filter(|value| **value != INVALID_VALUE)
+                    .for_each(|value| histogram.fill(*value));
+                Some(histogram)
+            }
+            None => self.histogram(&ChannelDataField::from_name(name)?, range, bins),
+        }
+    }
+
+    // Remove the fields which never received a value, i.e. every row is INVALID_VALUE. Whole columns are
+    // removed so the remaining ones stay aligned and the row count is unchanged. Nothing is removed while
+    // the data is empty.
+    pub fn prune_empty_columns(&mut self) {
+        if self.rows == 0 {
+            return;
+        }
+
+        self.fields
+            .retain(|_, values| values.iter().any(|value| *value != INVALID_VALUE));
+        self.compact_fields
+            .retain(|_, values| values.iter().any(|value| *value != INVALID_VALUE as f32));
+        self.time_fields
+            .retain(|_, values| values.iter().any(|value| *value != INVALID_TIME));
+        self.nested_fields
+            .retain(|_, values| values.iter().flatten().any(|value| *value != INVALID_VALUE));
+
+        let keep: Vec<bool> = self
+            .derived_fields
+            .iter()
+            .map(|(_, values)| values.iter().any(|value| *value != INVALID_VALUE))
+            .collect();
+        let mut keep_field = keep.iter();
+        self.derived_fields
+            .retain(|_| *keep_field.next().unwrap_or(&true));
+        let mut keep_expression = keep.iter();
+        Arc::make_mut(&mut self.expressions).retain(|_| *keep_expression.next().unwrap_or(&true));
+
+        self.waveforms
+            .retain(|(_, rows)| rows.iter().any(|row| row.is_some()));
+    }
+
+    // Remove the fields left out by BuildParams::included_fields and excluded_fields
+    fn drop_unselected_fields(&mut self) {
+        let params = &self.params;
+        self.fields
+            .retain(|field, _| params.is_field_selected(field));
+        self.compact_fields
+            .retain(|field, _| params.is_field_selected(field));
+        self.time_fields
+            .retain(|field, _| params.is_field_selected(field));
+        self.nested_fields
+            .retain(|field, _| params.is_field_selected(field));
+    }
+
+    // The accumulated columns as a DataFrame, failing if any column does not have one value per event
+    pub fn into_dataframe(self) -> PolarsResult<DataFrame> {
+        let rows = self.rows;
+        let columns = self.convert_to_columns();
+        if let Some(column) = columns.iter().find(|column| column.len() != rows) {
+            polars_bail!(
+                ShapeMismatch: "column {} has {} values for {} events",
+                column.name(),
+                column.len(),
+                rows
+            );
+        }
+        DataFrame::new(columns)
+    }
+
+    // Inverse of into_dataframe, for re-processing an already built file with the map and options it was
+    // built with. The columns must be exactly those of ChannelData::output_columns, in any order. Nulls
+    // become the INVALID_VALUE and INVALID_TIME sentinels again, so the physics helpers can be rerun.
+    pub fn from_dataframe(
+        df: &DataFrame,
+        channel_map: &ChannelMap,
+        params: &BuildParams,
+    ) -> Result<ChannelData, ChannelDataError> {
+        let expected: Vec<String> = ChannelData::output_columns(channel_map, params)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        let present: Vec<String> = df
+            .get_column_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let missing: Vec<String> = expected
+            .iter()
+            .filter(|name| !present.contains(name))
+            .cloned()
+            .collect();
+        let unexpected: Vec<String> = present
+            .iter()
+            .filter(|name| !expected.contains(name))
+            .cloned()
+            .collect();
+        if !missing.is_empty() || !unexpected.is_empty() {
+            return Err(ChannelDataError::SchemaMismatch {
+                missing,
+                unexpected,
+            });
+        }
+
+        // Fields left out by BuildParams::included_fields and excluded_fields are not in the file
+        let mut data = ChannelData::new(channel_map, params);
+        data.drop_unselected_fields();
+        data.rows = df.height();
+        let aliases = resolve_column_names(params).unwrap_or_default();
+        let column_of = |field: &ChannelDataField| {
+            df.column(
+                aliases
+                    .get(field)
+                    .map_or(field.as_ref(), |alias| alias.as_str()),
+            )
+        };
+        let sentinel = params.keep_invalid_sentinel.then(|| params.sentinel());
+        let floats = |column: &Column| -> PolarsResult<Vec<f64>> {
+            Ok(column
+                .cast(&DataType::Float64)?
+                .f64()?
+                .into_iter()
+                .map(|value| match value {
+                    Some(v) if Some(v) != sentinel => v,
+                    _ => INVALID_VALUE,
+                })
+                .collect())
+        };
+
+        for (field, values) in data.fields.iter_mut() {
+            *values = floats(column_of(field)?)?;
+        }
+        for (field, values) in data.compact_fields.iter_mut() {
+            *values = floats(column_of(field)?)?
+                .into_iter()
+                .map(|value| value as f32)
+                .collect();
+        }
+        for (field, values) in data.time_fields.iter_mut() {
+            let column = column_of(field)?;
+            *values = if params.integer_timestamps {
+                column
+                    .cast(&DataType::Int64)?
+                    .i64()?
+                    .into_iter()
+                    .map(|value| match value {
+                        Some(v) if Some(v) != sentinel.map(|sentinel| sentinel as i64) => v,
+                        _ => INVALID_TIME,
+                    })
+                    .collect()
+            } else {
+                floats(column)?
+                    .into_iter()
+                    .map(|value| {
+                        if value == INVALID_VALUE {
+                            INVALID_TIME
+                        } else {
+                            ns_to_ps(value)
+                        }
+                    })
+                    .collect()
+            };
+        }
+        for (field, values) in data.nested_fields.iter_mut() {
+            *values = column_of(field)?
+                .list()?
+                .into_iter()
+                .map(|row| match row {
+                    Some(series) => {
+                        let values: Vec<f64> = series
+                            .cast(&DataType::Float64)?
+                            .f64()?
+                            .into_no_null_iter()
+                            .collect();
+                        if values.len() == 1 && Some(values[0]) == sentinel {
+                            Ok(vec![INVALID_VALUE])
+                        } else {
+                            Ok(values)
+                        }
+                    }
+                    None => Ok(vec![INVALID_VALUE]),
+                })
+                .collect::<PolarsResult<Vec<Vec<f64>>>>()?;
+        }
+        for (name, values) in data.derived_fields.iter_mut() {
+            *values = floats(df.column(name)?)?;
+        }
+        for (channel_type, rows) in data.waveforms.iter_mut() {
+            *rows = df
+                .column(&waveform_column_name(channel_type))?
+                .list()?
+                .into_iter()
+                .map(|row| {
+                    row.map(|series| {
+                        Ok(series
+                            .cast(&DataType::Int16)?
+                            .i16()?
+                            .into_no_null_iter()
+                            .collect())
+                    })
+                    .transpose()
+                })
+                .collect::<PolarsResult<Vec<Option<Vec<i16>>>>>()?;
+        }
+
+        Ok(data)
+    }
+
+    // Rounds the energies and short gate energies to the nearest multiple of step, times are left alone
+    fn quantize_energies(&mut self, step: f64) {
+        let is_energy = |field: &ChannelDataField| {
+            matches!(
+                field.quantity_kind(),
+                QuantityKind::Energy | QuantityKind::EnergyRaw | QuantityKind::Short
+            )
+        };
+        for (_, values) in self.fields.iter_mut().filter(|(field, _)| is_energy(field)) {
+            for value in values.iter_mut().filter(|value| **value != INVALID_VALUE) {
+                *value = (*value / step).round() * step;
+            }
+        }
+        let compact_step = step as f32;
+        for (_, values) in self
+            .compact_fields
+            .iter_mut()
+            .filter(|(field, _)| is_energy(field))
+        {
+            for value in values
+                .iter_mut()
+                .filter(|value| **value != INVALID_VALUE as f32)
+            {
+                *value = (*value / compact_step).round() * compact_step;
             }
         }
     }
 
-    pub fn convert_to_columns(self) -> Vec<Column> {
+    // Slots which never received a value are written as nulls, unless BuildParams::keep_invalid_sentinel
+    // is set, in which case they are written as BuildParams::sentinel(), -1e6 unless configured.
+    // Columns are named after their field, its alias from BuildParams::column_aliases, or with a prefix, see
+    // resolve_column_names.
+    // Timestamps are converted to f64 ns, or written as Int64 ps with BuildParams::integer_timestamps.
+    // Waveforms are List(Int16) columns, with nulls for events without a waveform whatever the sentinel.
+    // Energies are rounded to BuildParams::energy_step when it is set.
+    // Fields left out by BuildParams::included_fields and excluded_fields are dropped here.
+    pub fn convert_to_columns(mut self) -> Vec<Column> {
         let mut columns = vec![];
+        self.drop_unselected_fields();
+        if let Some(step) = self.params.energy_step.filter(|step| *step > 0.0) {
+            self.quantize_energies(step);
+        }
+        let keep_sentinel = self.params.keep_invalid_sentinel;
+        let valid = |value: f64| (value != INVALID_VALUE).then_some(value);
+        let sentinel = self.params.sentinel();
+        let written = |value: f64| {
+            if value == INVALID_VALUE {
+                sentinel
+            } else {
+                value
+            }
+        };
+        // Invalid aliases stop a run before it starts, see process_runs
+        let aliases = resolve_column_names(&self.params).unwrap_or_else(|e| {
+            log::error!("Ignoring the column aliases: {}", e);
+            BTreeMap::new()
+        });
+        let name_of = |field: &ChannelDataField| -> PlSmallStr {
+            aliases
+                .get(field)
+                .map_or(field.as_ref(), |alias| alias.as_str())
+                .into()
+        };
 
-        let normal_columns: Vec<Column> = self
+        // Single and double precision fields are interleaved again to keep the column order
+        let mut normal_columns: BTreeMap<ChannelDataField, Column> = self
             .fields
             .into_iter()
             .map(|(field, values)| {
-                let name = field.as_ref().into();
+                let name = name_of(&field);
                 // Convert each field into a Series and then into a Column
-                let series = Series::new(name, values);
-                Column::Series(series.into())
+                let series = match field {
+                    // Set in every event, so there is no sentinel to keep
+                    _ if field.is_boolean() => Series::new(
+                        name,
+                        values
+                            .into_iter()
+                            .map(|v| valid(v).map(|v| v != 0.0))
+                            .collect::<Vec<Option<bool>>>(),
+                    ),
+                    // Likewise the same unsigned column as without the sentinel
+                    _ if field.is_integer() && field.is_set_in_every_event() => Series::new(
+                        name,
+                        values
+                            .into_iter()
+                            .map(|v| valid(v).map(|v| v as u64))
+                            .collect::<Vec<Option<u64>>>(),
+                    ),
+                    _ if field.is_integer() => {
+                        // Signed so a negative sentinel survives, floats if it is not a whole number
+                        if keep_sentinel && sentinel.fract() == 0.0 {
+                            Series::new(
+                                name,
+                                values
+                                    .into_iter()
+                                    .map(|v| written(v) as i64)
+                                    .collect::<Vec<i64>>(),
+                            )
+                        } else if keep_sentinel {
+                            Series::new(name, values.into_iter().map(written).collect::<Vec<f64>>())
+                        } else {
+                            Series::new(
+                                name,
+                                values
+                                    .into_iter()
+                                    .map(|v| valid(v).map(|v| v as u64))
+                                    .collect::<Vec<Option<u64>>>(),
+                            )
+                        }
+                    }
+                    _ if keep_sentinel => {
+                        Series::new(name, values.into_iter().map(written).collect::<Vec<f64>>())
+                    }
+                    _ => Series::new(
+                        name,
+                        values.into_iter().map(valid).collect::<Vec<Option<f64>>>(),
+                    ),
+                };
+                (field, Column::Series(series.into()))
             })
             .collect();
 
-        columns.extend(normal_columns);
+        normal_columns.extend(self.compact_fields.into_iter().map(|(field, values)| {
+            let name = name_of(&field);
+            let series = if keep_sentinel {
+                Series::new(
+                    name,
+                    values
+                        .into_iter()
+                        .map(|v| {
+                            if v == INVALID_VALUE as f32 {
+                                sentinel as f32
+                            } else {
+                                v
+                            }
+                        })
+                        .collect::<Vec<f32>>(),
+                )
+            } else {
+                Series::new(
+                    name,
+                    values
+                        .into_iter()
+                        .map(|v| (v != INVALID_VALUE as f32).then_some(v))
+                        .collect::<Vec<Option<f32>>>(),
+                )
+            };
+            (field, Column::Series(series.into()))
+        }));
+
+        let integer_timestamps = self.params.integer_timestamps;
+        normal_columns.extend(self.time_fields.into_iter().map(|(field, values)| {
+            let name = name_of(&field);
+            let series = match (integer_timestamps, keep_sentinel) {
+                (true, true) => Series::new(
+                    name,
+                    values
+                        .into_iter()
+                        .map(|v| {
+                            if v == INVALID_TIME {
+                                sentinel as i64
+                            } else {
+                                v
+                            }
+                        })
+                        .collect::<Vec<i64>>(),
+                ),
+                (true, false) => Series::new(
+                    name,
+                    values
+                        .into_iter()
+                        .map(|v| (v != INVALID_TIME).then_some(v))
+                        .collect::<Vec<Option<i64>>>(),
+                ),
+                (false, true) => Series::new(
+                    name,
+                    values
+                        .into_iter()
+                        .map(|v| {
+                            if v == INVALID_TIME {
+                                sentinel
+                            } else {
+                                ps_to_ns(v)
+                            }
+                        })
+                        .collect::<Vec<f64>>(),
+                ),
+                (false, false) => Series::new(
+                    name,
+                    values
+                        .into_iter()
+                        .map(|v| (v != INVALID_TIME).then(|| ps_to_ns(v)))
+                        .collect::<Vec<Option<f64>>>(),
+                ),
+            };
+            (field, Column::Series(series.into()))
+        }));
+
+        columns.extend(normal_columns.into_values());
 
         let nested_columns: Vec<Column> = self
             .nested_fields
             .into_iter()
             .map(|(field, nested_values)| {
-                let name = field.as_ref().into();
-
-                // Convert Vec<Vec<f64>> into a ListChunked
-                let list_chunked = ListChunked::from_iter(
-                    nested_values
-                        .into_iter()
-                        .map(|inner_vec| Some(Series::new("".into(), inner_vec))),
-                )
-                .with_name(name);
+                let name = name_of(&field);
+
+                // Convert Vec<Vec<f64>> into a ListChunked, rows which were only padded become null lists.
+                // The builder keeps the dtype List(Float64) even when there are no rows
+                let mut builder = ListPrimitiveChunkedBuilder::<Float64Type>::new(
+                    name,
+                    nested_values.len(),
+                    nested_values.iter().map(|inner_vec| inner_vec.len()).sum(),
+                    DataType::Float64,
+                );
+                for inner_vec in nested_values {
+                    if inner_vec.iter().any(|v| *v != INVALID_VALUE) {
+                        builder.append_slice(&inner_vec);
+                    } else if keep_sentinel {
+                        builder.append_slice(&[sentinel]);
+                    } else {
+                        builder.append_null();
+                    }
+                }
+                let list_chunked = builder.finish();
 
                 Column::Series(list_chunked.into_series().into())
             })
@@ -819,6 +2338,52 @@ impl ChannelData {
 
         columns.extend(nested_columns);
 
+        // Derived columns come after all of the built-in ones
+        columns.extend(self.derived_fields.into_iter().map(|(name, values)| {
+            let series = if keep_sentinel {
+                Series::new(
+                    name.into(),
+                    values.into_iter().map(written).collect::<Vec<f64>>(),
+                )
+            } else {
+                Series::new(
+                    name.into(),
+                    values.into_iter().map(valid).collect::<Vec<Option<f64>>>(),
+                )
+            };
+            Column::Series(series.into())
+        }));
+
+        columns.extend(self.waveforms.into_iter().map(|(channel_type, rows)| {
+            let mut builder = ListPrimitiveChunkedBuilder::<Int16Type>::new(
+                waveform_column_name(&channel_type).into(),
+                rows.len(),
+                rows.iter().flatten().map(|samples| samples.len()).sum(),
+                DataType::Int16,
+            );
+            for row in rows {
+                match row {
+                    Some(samples) => builder.append_slice(&samples),
+                    None => builder.append_null(),
+                }
+            }
+            Column::Series(builder.finish().into_series().into())
+        }));
+
         columns
     }
 }
+
+// Detector types whose waveforms are written for this map, in declaration order
+fn waveform_channel_types(channel_map: &ChannelMap, params: &BuildParams) -> Vec<ChannelType> {
+    ChannelType::iter()
+        .filter(|channel_type| {
+            params.waveform_channels.contains(channel_type)
+                && channel_map.contains_channel_type(*channel_type)
+        })
+        .collect()
+}
+
+fn waveform_column_name(channel_type: &ChannelType) -> String {
+    format!("{}Waveform", channel_type.as_ref())
+}
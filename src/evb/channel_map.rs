@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::num::ParseIntError;
+use strum::IntoEnumIterator;
 use strum_macros::{AsRefStr, EnumIter};
 
-use super::compass_data::generate_board_channel_uuid;
+use super::compass_data::{decompose_uuid_to_board_channel, generate_board_channel_uuid};
 
 //Channels to be mapped in the ChannelMap, each variant is the verbatim keyword in the channel map
 #[derive(Debug, Clone, Copy, PartialEq, AsRefStr, EnumIter, Serialize, Deserialize)]
@@ -13,6 +14,8 @@ pub enum ChannelType {
     AnodeBack,
     ScintLeft,
     ScintRight,
+    ScintTop,
+    ScintBottom,
     Cathode,
     DelayFrontLeft,
     DelayFrontRight,
@@ -29,6 +32,13 @@ pub enum ChannelType {
     Cebra6,
     Cebra7,
     Cebra8,
+    Cebra9,
+    Cebra10,
+    Cebra11,
+    Cebra12,
+    Cebra13,
+    Cebra14,
+    Cebra15,
 
     PIPS1000,
     PIPS500,
@@ -39,22 +49,141 @@ pub enum ChannelType {
     CATRINA1,
     CATRINA2,
 
-    // make sure to update app.rs so the channel map combo box are updated
+    // Pulsers, clocks, and other channels stored as is, without any physics. A fixed set of four, see AUX_CHANNELS
+    Aux0,
+    Aux1,
+    Aux2,
+    Aux3,
+
+    // Veto paddle, its hits only set the Vetoed flag of the event
+    Veto,
+
+    // new detectors must also be given their fields in the channel_data_fields! list in channel_data.rs, new
+    // CeBrA detectors added to CEBRA_CHANNEL_TYPES and new auxiliary channels to AUX_CHANNEL_TYPES
 
     //Invalid channel
     None,
 }
 
+// Number of CeBrA detectors which can be channel mapped
+pub const CEBRA_DETECTORS: usize = 16;
+
+// CeBrA channel types indexed by detector number
+pub const CEBRA_CHANNEL_TYPES: [ChannelType; CEBRA_DETECTORS] = [
+    ChannelType::Cebra0,
+    ChannelType::Cebra1,
+    ChannelType::Cebra2,
+    ChannelType::Cebra3,
+    ChannelType::Cebra4,
+    ChannelType::Cebra5,
+    ChannelType::Cebra6,
+    ChannelType::Cebra7,
+    ChannelType::Cebra8,
+    ChannelType::Cebra9,
+    ChannelType::Cebra10,
+    ChannelType::Cebra11,
+    ChannelType::Cebra12,
+    ChannelType::Cebra13,
+    ChannelType::Cebra14,
+    ChannelType::Cebra15,
+];
+
+// Number of auxiliary channels which can be channel mapped. This is not a build option: each channel has its own
+// columns, so a fifth one is a new ChannelType variant with its line in channel_data_fields!, like any detector
+pub const AUX_CHANNELS: usize = 4;
+
+// Auxiliary channel types indexed by channel number
+pub const AUX_CHANNEL_TYPES: [ChannelType; AUX_CHANNELS] = [
+    ChannelType::Aux0,
+    ChannelType::Aux1,
+    ChannelType::Aux2,
+    ChannelType::Aux3,
+];
+
 impl ChannelType {
     fn default() -> Self {
         ChannelType::None // Default type
     }
+
+    // Every detector type is a single physical channel, only unused (None) channels may repeat
+    pub fn is_unique(&self) -> bool {
+        *self != ChannelType::None
+    }
+
+    // Detector number if this is a CeBrA channel
+    pub fn cebra_index(&self) -> Option<usize> {
+        CEBRA_CHANNEL_TYPES.iter().position(|t| t == self)
+    }
+
+    // Auxiliary channel number if this is an auxiliary channel
+    pub fn aux_index(&self) -> Option<usize> {
+        AUX_CHANNEL_TYPES.iter().position(|t| t == self)
+    }
+
+    // Bit used for this type in the HitPattern field. These are fixed so that masks stay comparable
+    // between versions; new types must take the next unused bit rather than renumbering existing ones.
+    pub fn bitmask_bit(&self) -> Option<u32> {
+        match self {
+            ChannelType::AnodeFront => Some(0),
+            ChannelType::AnodeBack => Some(1),
+            ChannelType::ScintLeft => Some(2),
+            ChannelType::ScintRight => Some(3),
+            ChannelType::Cathode => Some(4),
+            ChannelType::DelayFrontLeft => Some(5),
+            ChannelType::DelayFrontRight => Some(6),
+            ChannelType::DelayBackLeft => Some(7),
+            ChannelType::DelayBackRight => Some(8),
+            ChannelType::Monitor => Some(9),
+            ChannelType::Cebra0 => Some(10),
+            ChannelType::Cebra1 => Some(11),
+            ChannelType::Cebra2 => Some(12),
+            ChannelType::Cebra3 => Some(13),
+            ChannelType::Cebra4 => Some(14),
+            ChannelType::Cebra5 => Some(15),
+            ChannelType::Cebra6 => Some(16),
+            ChannelType::Cebra7 => Some(17),
+            ChannelType::Cebra8 => Some(18),
+            ChannelType::PIPS1000 => Some(19),
+            ChannelType::PIPS500 => Some(20),
+            ChannelType::PIPS300 => Some(21),
+            ChannelType::PIPS100 => Some(22),
+            ChannelType::CATRINA0 => Some(23),
+            ChannelType::CATRINA1 => Some(24),
+            ChannelType::CATRINA2 => Some(25),
+            ChannelType::Cebra9 => Some(26),
+            ChannelType::Cebra10 => Some(27),
+            ChannelType::Cebra11 => Some(28),
+            ChannelType::Cebra12 => Some(29),
+            ChannelType::Cebra13 => Some(30),
+            ChannelType::Cebra14 => Some(31),
+            ChannelType::Cebra15 => Some(32),
+            ChannelType::Aux0 => Some(33),
+            ChannelType::Aux1 => Some(34),
+            ChannelType::Aux2 => Some(35),
+            ChannelType::Aux3 => Some(36),
+            ChannelType::ScintTop => Some(37),
+            ChannelType::ScintBottom => Some(38),
+            ChannelType::Veto => Some(39),
+            ChannelType::None => None,
+        }
+    }
+
+    // HitPattern of an event where exactly these types fired, for exact topology cuts
+    pub fn bitmask(types: &[ChannelType]) -> u64 {
+        types
+            .iter()
+            .filter_map(|channel_type| channel_type.bitmask_bit())
+            .fold(0, |mask, bit| mask | 1 << bit)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Board {
     pub id: u32,                     // Board ID
     pub channels: [ChannelType; 16], // Each board has 16 channels
+    // Channels whose hits are ignored as if unmapped, e.g. one that turned noisy during the experiment
+    #[serde(default)]
+    pub disabled: [bool; 16],
 }
 
 impl Default for Board {
@@ -62,6 +191,7 @@ impl Default for Board {
         Board {
             id: 0,                                  // Default board ID
             channels: [ChannelType::default(); 16], // Initialize all channels with the default type
+            disabled: [false; 16],
         }
     }
 }
@@ -86,7 +216,11 @@ impl Board {
             ChannelType::None,
             ChannelType::AnodeBack,
         ];
-        Board { id, channels }
+        Board {
+            id,
+            channels,
+            disabled: [false; 16],
+        }
     }
 
     pub fn cebra(id: u32) -> Board {
@@ -108,13 +242,17 @@ impl Board {
             ChannelType::None,
             ChannelType::None,
         ];
-        Board { id, channels }
+        Board {
+            id,
+            channels,
+            disabled: [false; 16],
+        }
     }
 
     pub fn ui(&mut self, ui: &mut egui::Ui, board_idx: usize, on_remove: impl FnOnce()) {
         ui.vertical(|ui| {
             egui::Grid::new(format!("board_{}", board_idx))
-                .num_columns(2)
+                .num_columns(3)
                 .spacing([20.0, 4.0])
                 .show(ui, |ui| {
                     ui.add(egui::DragValue::new(&mut self.id).prefix("Board ID: "))
@@ -125,6 +263,7 @@ impl Board {
                                 for channel in self.channels.iter_mut() {
                                     *channel = ChannelType::None;
                                 }
+                                self.disabled = [false; 16];
                             }
 
                             ui.separator();
@@ -137,9 +276,15 @@ impl Board {
                     ui.end_row();
                     ui.label("#");
                     ui.label("Type");
+                    ui.label("Off").on_hover_text("Ignore the hits of a channel as if it were unmapped, without changing its type.\nIts columns are still written");
                     ui.end_row();
 
-                    for (channel_idx, channel_type) in self.channels.iter_mut().enumerate() {
+                    for (channel_idx, (channel_type, disabled)) in self
+                        .channels
+                        .iter_mut()
+                        .zip(self.disabled.iter_mut())
+                        .enumerate()
+                    {
                         ui.label(format!("{}", channel_idx));
                         egui::ComboBox::from_id_salt(format!(
                             "channel_type_{}_{}",
@@ -147,38 +292,11 @@ impl Board {
                         ))
                         .selected_text(format!("{:?}", channel_type))
                         .show_ui(ui, |ui| {
-                            for variant in [
-                                ChannelType::AnodeFront,
-                                ChannelType::AnodeBack,
-                                ChannelType::ScintLeft,
-                                ChannelType::ScintRight,
-                                ChannelType::Cathode,
-                                ChannelType::DelayFrontLeft,
-                                ChannelType::DelayFrontRight,
-                                ChannelType::DelayBackLeft,
-                                ChannelType::DelayBackRight,
-                                ChannelType::Monitor,
-                                ChannelType::Cebra0,
-                                ChannelType::Cebra1,
-                                ChannelType::Cebra2,
-                                ChannelType::Cebra3,
-                                ChannelType::Cebra4,
-                                ChannelType::Cebra5,
-                                ChannelType::Cebra6,
-                                ChannelType::Cebra7,
-                                ChannelType::Cebra8,
-                                ChannelType::PIPS1000,
-                                ChannelType::PIPS500,
-                                ChannelType::PIPS300,
-                                ChannelType::PIPS100,
-                                ChannelType::CATRINA0,
-                                ChannelType::CATRINA1,
-                                ChannelType::CATRINA2,
-                                ChannelType::None,
-                            ] {
+                            for variant in ChannelType::iter() {
                                 ui.selectable_value(channel_type, variant, variant.as_ref());
                             }
                         });
+                        ui.checkbox(disabled, "");
                         ui.end_row();
                     }
                 });
@@ -191,6 +309,16 @@ impl Board {
 pub enum ChannelMapError {
     IOError(std::io::Error),
     ParseError(ParseIntError),
+    // Types assigned to more than one (board, channel), and (board, channel)s assigned more than one type
+    Conflict {
+        duplicate_types: Vec<(ChannelType, Vec<(u32, u32)>)>,
+        conflicting_channels: Vec<((u32, u32), Vec<ChannelType>)>,
+    },
+    // A (board, channel) which is on none of the boards, e.g. from a board missing in the map
+    UnknownChannel {
+        board: u32,
+        channel: u32,
+    },
 }
 
 impl From<std::io::Error> for ChannelMapError {
@@ -216,6 +344,38 @@ impl std::fmt::Display for ChannelMapError {
                 "Channel map had an error parsing the channel map file: {}",
                 x
             ),
+            ChannelMapError::Conflict {
+                duplicate_types,
+                conflicting_channels,
+            } => {
+                let mut problems: Vec<String> = vec![];
+                for (channel_type, channels) in duplicate_types {
+                    let channels: Vec<String> = channels
+                        .iter()
+                        .map(|(board, channel)| format!("board {} channel {}", board, channel))
+                        .collect();
+                    problems.push(format!(
+                        "{} is mapped to {}",
+                        channel_type.as_ref(),
+                        channels.join(", ")
+                    ));
+                }
+                for ((board, channel), types) in conflicting_channels {
+                    let types: Vec<&str> = types.iter().map(|t| t.as_ref()).collect();
+                    problems.push(format!(
+                        "board {} channel {} is mapped to {}",
+                        board,
+                        channel,
+                        types.join(", ")
+                    ));
+                }
+                write!(f, "Channel map has conflicts: {}", problems.join("; "))
+            }
+            ChannelMapError::UnknownChannel { board, channel } => write!(
+                f,
+                "Board {} channel {} is not on any board of the channel map",
+                board, channel
+            ),
         }
     }
 }
@@ -225,12 +385,14 @@ impl std::error::Error for ChannelMapError {}
 #[derive(Debug, Clone)]
 pub struct ChannelData {
     pub channel_type: ChannelType,
+    pub disabled: bool,
 }
 
 impl Default for ChannelData {
     fn default() -> Self {
         ChannelData {
             channel_type: ChannelType::None,
+            disabled: false,
         }
     }
 }
@@ -238,36 +400,206 @@ impl Default for ChannelData {
 #[derive(Debug)]
 pub struct ChannelMap {
     map: HashMap<u32, ChannelData>,
+    // Every (board, channel, type) as given, including the ones overwritten in map by a repeated board
+    assignments: Vec<(u32, u32, ChannelType)>,
+    // (board, channel, type) of the channels disabled in their board
+    disabled: Vec<(u32, u32, ChannelType)>,
 }
 
 impl ChannelMap {
-    pub fn new(boards: &[Board]) -> ChannelMap {
+    // Fails if the boards assign a detector to more than one channel, or a channel to more than one type
+    pub fn new(boards: &[Board]) -> Result<ChannelMap, ChannelMapError> {
         let mut cmap = ChannelMap {
             map: HashMap::new(),
+            assignments: vec![],
+            disabled: vec![],
         };
         for board in boards.iter() {
             for (channel_index, channel) in board.channels.iter().enumerate() {
+                let disabled = board.disabled[channel_index];
                 let data = ChannelData {
                     channel_type: *channel,
+                    disabled,
                 };
 
                 cmap.map.insert(
                     generate_board_channel_uuid(&(board.id), &(channel_index as u32)),
                     data,
                 );
+                if *channel != ChannelType::None {
+                    cmap.assignments
+                        .push((board.id, channel_index as u32, *channel));
+                }
+                if disabled {
+                    cmap.disabled
+                        .push((board.id, channel_index as u32, *channel));
+                }
             }
         }
-        cmap
+        cmap.validate()?;
+        Ok(cmap)
+    }
+
+    // Check that every unique type is mapped to one channel and that no channel is given two types
+    pub fn validate(&self) -> Result<(), ChannelMapError> {
+        let mut duplicate_types = vec![];
+        for channel_type in ChannelType::iter().filter(|t| t.is_unique()) {
+            let channels: Vec<(u32, u32)> = self
+                .assignments
+                .iter()
+                .filter(|(_, _, assigned)| *assigned == channel_type)
+                .map(|(board, channel, _)| (*board, *channel))
+                .collect();
+            if channels.len() > 1 {
+                duplicate_types.push((channel_type, channels));
+            }
+        }
+
+        let mut conflicting_channels: Vec<((u32, u32), Vec<ChannelType>)> = vec![];
+        for (board, channel, channel_type) in self.assignments.iter() {
+            match conflicting_channels
+                .iter_mut()
+                .find(|(key, _)| *key == (*board, *channel))
+            {
+                Some((_, types)) => {
+                    if !types.contains(channel_type) {
+                        types.push(*channel_type);
+                    }
+                }
+                None => conflicting_channels.push(((*board, *channel), vec![*channel_type])),
+            }
+        }
+        conflicting_channels.retain(|(_, types)| types.len() > 1);
+
+        if duplicate_types.is_empty() && conflicting_channels.is_empty() {
+            Ok(())
+        } else {
+            Err(ChannelMapError::Conflict {
+                duplicate_types,
+                conflicting_channels,
+            })
+        }
     }
 
     pub fn get_channel_data(&self, uuid: &u32) -> Option<&ChannelData> {
         self.map.get(uuid)
     }
 
+    // Like get_channel_data, but tells the channels left without a type on purpose (None) apart from
+    // the ones which are on no board at all (an error), which usually means the map is missing a board
+    pub fn lookup(&self, uuid: &u32) -> Result<Option<&ChannelData>, ChannelMapError> {
+        match self.map.get(uuid) {
+            Some(data) if data.channel_type == ChannelType::None => Ok(None),
+            Some(data) => Ok(Some(data)),
+            None => {
+                let (board, channel) = decompose_uuid_to_board_channel(uuid);
+                Err(ChannelMapError::UnknownChannel { board, channel })
+            }
+        }
+    }
+
+    // Whether hits of this UUID fill any fields, i.e. its (board, channel) has a type other than None.
+    // Disabled channels count as mapped, they are left out on purpose
+    pub fn is_mapped(&self, uuid: &u32) -> bool {
+        self.map
+            .get(uuid)
+            .is_some_and(|data| data.channel_type != ChannelType::None)
+    }
+
+    // (board, channel, type) of every disabled channel. Disabled channels keep their type, so their
+    // fields are still created, but append_event skips their hits
+    pub fn disabled_channels(&self) -> &[(u32, u32, ChannelType)] {
+        &self.disabled
+    }
+
+    // UUIDs of the enabled channels of a type, e.g. those whose hits anchor the event window
+    pub fn uuids_of(&self, channel_type: ChannelType) -> Vec<u32> {
+        let mut uuids: Vec<u32> = self
+            .map
+            .iter()
+            .filter(|(_, data)| data.channel_type == channel_type && !data.disabled)
+            .map(|(uuid, _)| *uuid)
+            .collect();
+        uuids.sort_unstable();
+        uuids
+    }
+
     // Check if a channel type is present in the channel map
     pub fn contains_channel_type(&self, channel_type: ChannelType) -> bool {
         self.map
             .values()
             .any(|data| data.channel_type == channel_type)
     }
+
+    // Every mapped channel type with the number of channels (UUIDs) it is mapped to, in the declaration
+    // order of ChannelType. Unused (None) channels are left out.
+    pub fn channel_types(&self) -> impl Iterator<Item = (ChannelType, usize)> + '_ {
+        ChannelType::iter()
+            .filter(|channel_type| *channel_type != ChannelType::None)
+            .filter_map(|channel_type| {
+                let count = self
+                    .map
+                    .values()
+                    .filter(|data| data.channel_type == channel_type)
+                    .count();
+                (count > 0).then_some((channel_type, count))
+            })
+    }
+}
+
+// Number of hits of each UUID that is not in the channel map, which append_event drops. Collected for every
+// run so that a partly unmapped detector shows up. BuildParams::strict_channel_map fails at the first of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UnmappedHits {
+    pub counts: BTreeMap<u32, u64>,
+    pub unknown: BTreeSet<u32>, // UUIDs on none of the boards, see ChannelMap::lookup
+}
+
+impl UnmappedHits {
+    pub fn count(&mut self, map: &ChannelMap, uuid: u32) {
+        match map.lookup(&uuid) {
+            Ok(Some(_)) => return,
+            Ok(None) => {}
+            Err(_) => {
+                self.unknown.insert(uuid);
+            }
+        }
+        *self.counts.entry(uuid).or_insert(0) += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    // (board, channel, hits) of each unmapped channel
+    pub fn channels(&self) -> Vec<(u32, u32, u64)> {
+        self.counts
+            .iter()
+            .map(|(uuid, hits)| {
+                let (board, channel) = decompose_uuid_to_board_channel(uuid);
+                (board, channel, *hits)
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for UnmappedHits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let channels: Vec<String> = self
+            .channels()
+            .iter()
+            .map(|(board, channel, hits)| {
+                let uuid = generate_board_channel_uuid(board, channel);
+                if self.unknown.contains(&uuid) {
+                    format!(
+                        "board {} channel {} ({} hits, on no board of the map)",
+                        board, channel, hits
+                    )
+                } else {
+                    format!("board {} channel {} ({} hits)", board, channel, hits)
+                }
+            })
+            .collect();
+        write!(f, "{}", channels.join(", "))
+    }
 }
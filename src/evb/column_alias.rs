@@ -0,0 +1,188 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+
+use strum::IntoEnumIterator;
+
+use super::build_params::BuildParams;
+use super::channel_data::{ChannelDataField, DetectorGroup};
+use super::channel_map::ChannelType;
+use super::expression::DerivedFieldEntry;
+
+// Output name for one of the built-in fields, e.g. field "X1" with alias "fp_x1"
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Default)]
+pub struct ColumnAlias {
+    pub field: String,
+    pub alias: String,
+}
+
+impl ColumnAlias {
+    pub fn ui(&mut self, ui: &mut egui::Ui, index: usize, on_remove: impl FnOnce()) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt(format!("column_alias_field_{}", index))
+                .selected_text(self.field.as_str())
+                .show_ui(ui, |ui| {
+                    for field in ChannelDataField::iter() {
+                        ui.selectable_value(
+                            &mut self.field,
+                            field.as_ref().to_string(),
+                            field.as_ref(),
+                        );
+                    }
+                });
+            ui.label("→");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.alias)
+                    .hint_text("Output name")
+                    .desired_width(150.0),
+            );
+
+            if ui.button("❌").clicked() {
+                on_remove();
+            }
+        });
+    }
+}
+
+// Prefix of the output names of one detector type's fields, in place of BuildParams::column_prefix
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct ColumnPrefix {
+    pub channel_type: ChannelType,
+    pub prefix: String,
+}
+
+impl Default for ColumnPrefix {
+    fn default() -> Self {
+        ColumnPrefix {
+            channel_type: ChannelType::None,
+            prefix: String::new(),
+        }
+    }
+}
+
+impl ColumnPrefix {
+    pub fn ui(&mut self, ui: &mut egui::Ui, index: usize, on_remove: impl FnOnce()) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt(format!("column_prefix_type_{}", index))
+                .selected_text(self.channel_type.as_ref())
+                .show_ui(ui, |ui| {
+                    for variant in ChannelType::iter().filter(|t| *t != ChannelType::None) {
+                        ui.selectable_value(&mut self.channel_type, variant, variant.as_ref());
+                    }
+                });
+            ui.label("→");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.prefix)
+                    .hint_text("Prefix")
+                    .desired_width(100.0),
+            );
+
+            if ui.button("❌").clicked() {
+                on_remove();
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AliasError {
+    UnknownField(String),
+    EmptyAlias(String),
+    DuplicateField(String),
+    Collision(String),
+    DuplicatePrefix(String),
+}
+
+impl Display for AliasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AliasError::UnknownField(x) => write!(f, "Column alias for unknown field {}", x),
+            AliasError::EmptyAlias(x) => write!(f, "Column alias for {} is empty", x),
+            AliasError::DuplicateField(x) => write!(f, "Field {} has more than one alias", x),
+            AliasError::Collision(x) => write!(f, "More than one column would be named {}", x),
+            AliasError::DuplicatePrefix(x) => {
+                write!(f, "Detector type {} has more than one prefix", x)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AliasError {}
+
+// Output name of each aliased field. Aliases may not collide with each other, with the default name of a
+// field which is not aliased, or with a derived field.
+pub fn resolve_aliases(
+    aliases: &[ColumnAlias],
+    derived_fields: &[DerivedFieldEntry],
+) -> Result<BTreeMap<ChannelDataField, String>, AliasError> {
+    resolve_names(aliases, |_| "", derived_fields)
+}
+
+// Output name of each field which is not written under its own name. An alias is used as given, other
+// fields get the prefix of their detector type from BuildParams::column_prefixes, or else
+// BuildParams::column_prefix (focal plane and event fields always get the latter). Derived fields are
+// never prefixed. No two columns may end up with the same name.
+pub fn resolve_column_names(
+    params: &BuildParams,
+) -> Result<BTreeMap<ChannelDataField, String>, AliasError> {
+    let mut prefixes: BTreeMap<&str, &str> = BTreeMap::new();
+    for entry in &params.column_prefixes {
+        let channel_type = entry.channel_type.as_ref();
+        if prefixes.insert(channel_type, entry.prefix.trim()).is_some() {
+            return Err(AliasError::DuplicatePrefix(channel_type.to_string()));
+        }
+    }
+    let global = params.column_prefix.trim();
+    resolve_names(
+        &params.column_aliases,
+        |field| match field.detector_group() {
+            DetectorGroup::Detector(channel_type) => prefixes
+                .get(channel_type.as_ref())
+                .copied()
+                .unwrap_or(global),
+            DetectorGroup::FocalPlane | DetectorGroup::Event => global,
+        },
+        &params.derived_fields,
+    )
+}
+
+fn resolve_names<'a>(
+    aliases: &[ColumnAlias],
+    prefix_of: impl Fn(&ChannelDataField) -> &'a str,
+    derived_fields: &[DerivedFieldEntry],
+) -> Result<BTreeMap<ChannelDataField, String>, AliasError> {
+    let mut names: BTreeMap<ChannelDataField, String> = BTreeMap::new();
+    for entry in aliases {
+        let field = ChannelDataField::from_name(&entry.field)
+            .ok_or_else(|| AliasError::UnknownField(entry.field.clone()))?;
+        let alias = entry.alias.trim();
+        if alias.is_empty() {
+            return Err(AliasError::EmptyAlias(entry.field.clone()));
+        }
+        if names.insert(field, alias.to_string()).is_some() {
+            return Err(AliasError::DuplicateField(entry.field.clone()));
+        }
+    }
+    for field in ChannelDataField::iter() {
+        let prefix = prefix_of(&field);
+        if !prefix.is_empty() && !names.contains_key(&field) {
+            let name = format!("{}{}", prefix, field.as_ref());
+            names.insert(field, name);
+        }
+    }
+
+    let mut taken: HashSet<String> = ChannelDataField::iter()
+        .filter(|field| !names.contains_key(field))
+        .map(|field| field.as_ref().to_string())
+        .collect();
+    taken.extend(
+        derived_fields
+            .iter()
+            .map(|entry| entry.name.trim().to_string()),
+    );
+    for name in names.values() {
+        if !taken.insert(name.clone()) {
+            return Err(AliasError::Collision(name.clone()));
+        }
+    }
+    Ok(names)
+}
@@ -21,9 +21,13 @@ pub struct RawCompassData {
     pub timestamp: u64,
     pub energy: u16,
     pub energy_calibrated: u64,
-    pub energy_short: u16,
+    pub energy_short: Option<u16>, // None unless the file header has the ENERGY_SHORT bit (PSD firmware)
+    pub waveform: Option<Vec<i16>>, // None unless the file has the WAVES bit and the channel's waveforms are kept
 }
 
+// Unique id of a board and channel, using Szudzik's pairing: boards and channels up to n fill the ids below
+// (n + 1)^2, with (b, c) at b^2 + b + c when b >= c and at c^2 + b otherwise. Every u16 board and channel
+// fits in a u32, (65535, 65535) being u32::MAX. See decompose_uuid_to_board_channel for the inverse.
 pub const fn generate_board_channel_uuid(board: &u32, channel: &u32) -> u32 {
     let b = *board;
     let c = *channel;
@@ -34,7 +38,7 @@ pub const fn generate_board_channel_uuid(board: &u32, channel: &u32) -> u32 {
     }
 }
 
-#[allow(dead_code)]
+// Board and channel of a uuid made by generate_board_channel_uuid
 pub fn decompose_uuid_to_board_channel(uuid: &u32) -> (u32, u32) {
     let uuid_sqrt = (*uuid as f64).sqrt().floor() as u32;
     let test = uuid - uuid_sqrt * uuid_sqrt;
@@ -45,16 +49,36 @@ pub fn decompose_uuid_to_board_channel(uuid: &u32) -> (u32, u32) {
     }
 }
 
+// Uuid of a board and channel, the inverse of decompose_uuid_to_board_channel under the name maps built
+// from a channel list tend to look for. Same as generate_board_channel_uuid
+pub const fn compose_board_channel_to_uuid(board: u32, channel: u32) -> u32 {
+    generate_board_channel_uuid(&board, &channel)
+}
+
+// Timestamps are kept as the integer picoseconds written by CoMPASS, since an f64 can no longer hold every
+// picosecond beyond 2^53 ps (about 2.5 hours into a run). Time differences are taken in integer picoseconds
+// and only then converted to ns, see ps_to_ns.
+pub fn ps_to_ns(ps: i64) -> f64 {
+    ps as f64 * 1.0e-3
+}
+
+// Nearest whole picosecond of a time in ns, for the shifts and offsets which are configured in ns
+pub fn ns_to_ps(ns: f64) -> i64 {
+    (ns * 1.0e3).round() as i64
+}
+
 #[derive(Debug, Clone)]
 pub struct CompassData {
     pub uuid: u32,
     pub energy: f64,
-    pub energy_short: f64,
-    pub timestamp: f64,
+    pub energy_short: Option<f64>, // None for firmware without a short gate
+    pub timestamp: i64,            // ps, including the ShiftMap offset
+    // Raw samples of the hit, only read for BuildParams::waveform_channels, see CompassFile::set_waveform_uuids
+    pub waveform: Option<Vec<i16>>,
 }
 
 impl CompassData {
-    pub fn new(raw: &RawCompassData, shifts: &Option<ShiftMap>) -> Self {
+    pub fn new(raw: RawCompassData, shifts: &Option<ShiftMap>) -> Self {
         let mut rng = rand::rng();
         let board = raw.board as u32;
         let channel = raw.channel as u32;
@@ -63,16 +87,19 @@ impl CompassData {
         CompassData {
             uuid: id,
             energy: raw.energy as f64 + rng.random::<f64>(),
-            energy_short: raw.energy_short as f64 + rng.random::<f64>(),
+            energy_short: raw
+                .energy_short
+                .map(|short| short as f64 + rng.random::<f64>()),
             timestamp: match shifts {
-                Some(map) => raw.timestamp as f64 * 1.0e-3 + map.get_timeshift(&id),
-                None => raw.timestamp as f64 * 1.0e-3,
+                Some(map) => raw.timestamp as i64 + ns_to_ps(map.get_timeshift(&id)),
+                None => raw.timestamp as i64,
             },
+            waveform: raw.waveform,
         }
     }
 
     pub fn is_default(&self) -> bool {
-        self.timestamp == 0.0
+        self.timestamp == 0
     }
 
     #[allow(dead_code)]
@@ -86,8 +113,9 @@ impl Default for CompassData {
         CompassData {
             uuid: 0,
             energy: 0.0,
-            energy_short: 0.0,
-            timestamp: 0.0,
+            energy_short: None,
+            timestamp: 0,
+            waveform: None,
         }
     }
 }
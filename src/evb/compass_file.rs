@@ -1,15 +1,24 @@
-use super::compass_data::{CompassData, CompassDataType, RawCompassData};
+use super::compass_data::{
+    generate_board_channel_uuid, CompassData, CompassDataType, RawCompassData,
+};
 use super::error::EVBError;
 use super::shift_map::ShiftMap;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::BufReader;
+use std::io::{BufReader, SeekFrom};
 use std::path;
 
 use nom::number::complete::*;
 
 const BUFFER_SIZE_HITS: usize = 24000; // Size in Compass hits of the buffer for each binary data file
 
+fn parse_u8(buffer: &[u8]) -> Result<(&[u8], u8), EVBError> {
+    match le_u8::<&[u8], nom::error::Error<&[u8]>>(buffer) {
+        Err(_x) => Err(EVBError::Parser),
+        Ok(x) => Ok(x),
+    }
+}
+
 fn parse_u16(buffer: &[u8]) -> Result<(&[u8], u16), EVBError> {
     match le_u16::<&[u8], nom::error::Error<&[u8]>>(buffer) {
         Err(_x) => Err(EVBError::Parser),
@@ -31,12 +40,181 @@ fn parse_u64(buffer: &[u8]) -> Result<(&[u8], u64), EVBError> {
     }
 }
 
+// Data type given by the header word at the start of a CoMPASS binary file, and the size in bytes of the part
+// of each hit before the waveform samples
+fn parse_header_word(header_word: u16) -> (CompassDataType, usize) {
+    let mut datatype = CompassDataType::NONE;
+    let mut datasize: usize = 16; //minimum 16 bytes for board, channel, timestamp, flags
+
+    if header_word & CompassDataType::ENERGY.bits() != 0 {
+        datatype |= CompassDataType::ENERGY;
+        datasize += 2;
+    }
+    if header_word & CompassDataType::ENERGY_SHORT.bits() != 0 {
+        datatype |= CompassDataType::ENERGY_SHORT;
+        datasize += 2;
+    }
+    if header_word & CompassDataType::ENERGY_CALIBRATED.bits() != 0 {
+        datatype |= CompassDataType::ENERGY_CALIBRATED;
+        datasize += 8;
+    }
+    // Each hit then ends with a waveform code (u8), the number of samples (u32), and the u16 samples
+    if header_word & CompassDataType::WAVES.bits() != 0 {
+        datatype |= CompassDataType::WAVES;
+        datasize += 5;
+    }
+    (datatype, datasize)
+}
+
+// Data type of a CoMPASS binary file from its two header bytes, returning the bytes after the header
+pub fn parse_compass_header(buffer: &[u8]) -> Result<(&[u8], CompassDataType), EVBError> {
+    let (rest, header_word) = parse_u16(buffer)?;
+    Ok((rest, parse_header_word(header_word).0))
+}
+
+// One hit of a CoMPASS binary file of the given data type, returning the bytes after it. Board, channel,
+// timestamp (u64), then energy (u16), calibrated energy (u64), and short gate energy (u16) when the data type
+// has them, the flags (u32), and with WAVES the waveform code (u8), number of samples (u32), and samples (u16).
+// The samples are only kept for the board/channel uuids in waveform_uuids, none if it is empty. A hit cut
+// short fails with EVBError::Parser.
+pub fn parse_compass_hit<'b>(
+    buffer: &'b [u8],
+    data_type: &CompassDataType,
+    waveform_uuids: &[u32],
+) -> Result<(&'b [u8], RawCompassData), EVBError> {
+    parse_hit(buffer, data_type, true, waveform_uuids)
+}
+
+// Like parse_compass_hit, for CoMPASS 1 files without the waveform code
+fn parse_hit<'b>(
+    buffer: &'b [u8],
+    data_type: &CompassDataType,
+    waveform_code: bool,
+    waveform_uuids: &[u32],
+) -> Result<(&'b [u8], RawCompassData), EVBError> {
+    let mut raw_data = RawCompassData {
+        board: 0,
+        channel: 0,
+        timestamp: 0,
+        energy: 0,
+        energy_calibrated: 0,
+        energy_short: None,
+        waveform: None,
+    };
+
+    let mut dataslice = buffer;
+    (dataslice, raw_data.board) = parse_u16(dataslice)?;
+    (dataslice, raw_data.channel) = parse_u16(dataslice)?;
+    (dataslice, raw_data.timestamp) = parse_u64(dataslice)?;
+    if data_type.bits() & CompassDataType::ENERGY.bits() != 0 {
+        (dataslice, raw_data.energy) = parse_u16(dataslice)?;
+    }
+    if data_type.bits() & CompassDataType::ENERGY_CALIBRATED.bits() != 0 {
+        (dataslice, raw_data.energy_calibrated) = parse_u64(dataslice)?;
+    }
+    if data_type.bits() & CompassDataType::ENERGY_SHORT.bits() != 0 {
+        let short;
+        (dataslice, short) = parse_u16(dataslice)?;
+        raw_data.energy_short = Some(short);
+    }
+    (dataslice, _) = parse_u32(dataslice)?; // flags
+    if data_type.bits() & CompassDataType::WAVES.bits() != 0 {
+        let samples;
+        if waveform_code {
+            (dataslice, _) = parse_u8(dataslice)?;
+        }
+        (dataslice, samples) = parse_u32(dataslice)?;
+        let sample_bytes = 2 * samples as usize;
+        if dataslice.len() < sample_bytes {
+            return Err(EVBError::Parser);
+        }
+        let (sampleword, rest) = dataslice.split_at(sample_bytes);
+        let uuid =
+            generate_board_channel_uuid(&(raw_data.board as u32), &(raw_data.channel as u32));
+        if waveform_uuids.contains(&uuid) {
+            // Samples are at most 14 bits, so they fit in an i16
+            raw_data.waveform = Some(
+                sampleword
+                    .chunks_exact(2)
+                    .map(|sample| u16::from_le_bytes([sample[0], sample[1]]) as i16)
+                    .collect(),
+            );
+        }
+        dataslice = rest;
+    }
+
+    Ok((dataslice, raw_data))
+}
+
+// Layout of the hits of a CoMPASS binary file. Files of CoMPASS 2 start with a header word giving the parts of
+// each hit. Files of CoMPASS 1 have no header: each hit holds the energy, the short gate energy with PSD
+// firmware, and with saved waveforms the number of samples and the samples, without a waveform code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompassVersion {
+    V1 { short_gate: bool, waveforms: bool },
+    V2,
+}
+
+// Timestamp of a raw hit in ps, for firmware whose ticks are not ps, before the shift, which is in ns
+// whatever the unit of the file. Kept in integer ps, failing with EVBError::Parser if it does not fit
+fn scale_timestamp(raw_data: &mut RawCompassData, time_scale: u64) -> Result<(), EVBError> {
+    raw_data.timestamp = raw_data
+        .timestamp
+        .checked_mul(time_scale)
+        .ok_or(EVBError::Parser)?;
+    Ok(())
+}
+
+// Every hit of a whole CoMPASS binary file already in memory, header included, in the order of the file.
+// For data which does not come from a file on disk, e.g. decompressed or received elsewhere; CompassFile
+// reads files hit by hit instead. time_scale is in ps per timestamp tick, see CompassFile::set_time_scale.
+// The hits are neither sorted nor checked to be in time order, see TimeOrderCheck. Fails with
+// EVBError::Parser if the last hit is cut short or a scaled timestamp overflows.
+pub fn parse_compass_bytes(
+    buffer: &[u8],
+    version: CompassVersion,
+    time_scale: u64,
+    shifts: &Option<ShiftMap>,
+    waveform_uuids: &[u32],
+) -> Result<Vec<CompassData>, EVBError> {
+    let (mut rest, data_type, waveform_code) = match version {
+        CompassVersion::V1 {
+            short_gate,
+            waveforms,
+        } => {
+            let mut data_type = CompassDataType::ENERGY;
+            if short_gate {
+                data_type |= CompassDataType::ENERGY_SHORT;
+            }
+            if waveforms {
+                data_type |= CompassDataType::WAVES;
+            }
+            (buffer, data_type, false)
+        }
+        CompassVersion::V2 => {
+            let (rest, data_type) = parse_compass_header(buffer)?;
+            (rest, data_type, true)
+        }
+    };
+    let mut hits = vec![];
+    while !rest.is_empty() {
+        let mut raw_data;
+        (rest, raw_data) = parse_hit(rest, &data_type, waveform_code, waveform_uuids)?;
+        scale_timestamp(&mut raw_data, time_scale)?;
+        hits.push(CompassData::new(raw_data, shifts));
+    }
+    Ok(hits)
+}
+
 #[derive(Debug)]
 pub struct CompassFile<'a> {
     file_handle: BufReader<File>,
     size_bytes: u64,
     data_type: CompassDataType,
-    data_size_bytes: usize,
+    data_size_bytes: usize, // fixed part of a hit, without the waveform samples
+    hit_size_bytes: usize,  // whole hit, for estimating the number of hits
+    waveform_uuids: Vec<u32>, // board/channel uuids whose samples are kept
+    time_scale: u64,        // ps per timestamp tick
     current_hit: CompassData,
     shift_map: &'a Option<ShiftMap>,
     is_used: bool,
@@ -55,33 +233,34 @@ impl<'a> CompassFile<'a> {
         file.read_exact(&mut header)?;
         let header_word = u16::from_le_bytes(header);
 
-        let mut datatype = CompassDataType::NONE;
-        let mut datasize: usize = 16; //minimum 16 bytes for board, channel, timestamp, flags
+        let (datatype, datasize) = parse_header_word(header_word);
 
-        if header_word & CompassDataType::ENERGY.bits() != 0 {
-            datatype |= CompassDataType::ENERGY;
-            datasize += 2;
-        }
-        if header_word & CompassDataType::ENERGY_SHORT.bits() != 0 {
-            datatype |= CompassDataType::ENERGY_SHORT;
-            datasize += 2;
-        }
-        if header_word & CompassDataType::ENERGY_CALIBRATED.bits() != 0 {
-            datatype |= CompassDataType::ENERGY_CALIBRATED;
-            datasize += 8;
-        }
-        if header_word & CompassDataType::WAVES.bits() != 0 {
-            return Err(EVBError::Waves);
+        // The number of samples is set per board in CoMPASS, so the first hit gives the size of every hit
+        let mut hitsize = datasize;
+        if datatype.bits() & CompassDataType::WAVES.bits() != 0 {
+            let mut first_hit = vec![0; datasize];
+            hitsize = match file.read_exact(&mut first_hit) {
+                Ok(()) => {
+                    let (_, samples) = parse_u32(&first_hit[datasize - 4..])?;
+                    datasize + 2 * samples as usize
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => datasize,
+                Err(e) => return Err(EVBError::File(e)),
+            };
+            file.seek(SeekFrom::Start(2))?;
         }
 
         Ok(CompassFile {
-            file_handle: BufReader::with_capacity(datasize * BUFFER_SIZE_HITS, file),
+            file_handle: BufReader::with_capacity(hitsize * BUFFER_SIZE_HITS, file),
             size_bytes: total_size,
             data_type: datatype,
             data_size_bytes: datasize,
+            hit_size_bytes: hitsize,
+            waveform_uuids: vec![],
+            time_scale: 1,
             current_hit: CompassData::default(),
             shift_map: shifts,
-            is_used: false,
+            is_used: true, // no hit read yet
             is_eof: false,
         })
     }
@@ -108,34 +287,31 @@ impl<'a> CompassFile<'a> {
     }
 
     fn parse_top_hit(&mut self) -> Result<CompassData, EVBError> {
-        let mut raw_data = RawCompassData {
-            board: 0,
-            channel: 0,
-            timestamp: 0,
-            energy: 0,
-            energy_calibrated: 0,
-            energy_short: 0,
-        };
-
         let mut dataword: Vec<u8> = vec![0; self.data_size_bytes];
         self.file_handle.read_exact(&mut dataword)?;
-        let mut dataslice = dataword.as_slice();
-
-        (dataslice, raw_data.board) = parse_u16(dataslice)?;
-        (dataslice, raw_data.channel) = parse_u16(dataslice)?;
-        (dataslice, raw_data.timestamp) = parse_u64(dataslice)?;
-        if self.data_type.bits() & CompassDataType::ENERGY.bits() != 0 {
-            (dataslice, raw_data.energy) = parse_u16(dataslice)?;
-        }
-        if self.data_type.bits() & CompassDataType::ENERGY_CALIBRATED.bits() != 0 {
-            (dataslice, raw_data.energy_calibrated) = parse_u64(dataslice)?;
-        }
-        if self.data_type.bits() & CompassDataType::ENERGY_SHORT.bits() != 0 {
-            (dataslice, raw_data.energy_short) = parse_u16(dataslice)?;
+        // The samples follow, their number being the last u32 before them
+        if self.data_type.bits() & CompassDataType::WAVES.bits() != 0 {
+            let (_, samples) = parse_u32(&dataword[self.data_size_bytes - 4..])?;
+            let mut sampleword: Vec<u8> = vec![0; 2 * samples as usize];
+            self.file_handle.read_exact(&mut sampleword)?;
+            dataword.extend_from_slice(&sampleword);
         }
-        let (_dataslice, _flags) = parse_u32(dataslice)?;
+        let (_, mut raw_data) =
+            parse_compass_hit(&dataword, &self.data_type, &self.waveform_uuids)?;
+        scale_timestamp(&mut raw_data, self.time_scale)?;
+
+        Ok(CompassData::new(raw_data, self.shift_map))
+    }
+
+    // Keep the waveform samples of the hits of these board/channel uuids, the samples of other hits are skipped.
+    // Files without waveforms are unaffected
+    pub fn set_waveform_uuids(&mut self, uuids: Vec<u32>) {
+        self.waveform_uuids = uuids;
+    }
 
-        Ok(CompassData::new(&raw_data, self.shift_map))
+    // Picoseconds per timestamp tick, for firmware which does not write ps (1000 for ns)
+    pub fn set_time_scale(&mut self, scale: u64) {
+        self.time_scale = scale;
     }
 
     pub fn is_eof(&self) -> bool {
@@ -147,6 +323,23 @@ impl<'a> CompassFile<'a> {
     }
 
     pub fn get_number_of_hits(&self) -> u64 {
-        self.size_bytes / (self.data_size_bytes as u64)
+        self.size_bytes / (self.hit_size_bytes as u64)
+    }
+}
+
+// The hits of the file in order, ending at the end of the file
+impl Iterator for CompassFile<'_> {
+    type Item = Result<CompassData, EVBError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let hit = match self.get_top_hit() {
+            Ok(hit) => hit.clone(),
+            Err(e) => return Some(Err(e)),
+        };
+        if self.is_eof {
+            return None;
+        }
+        self.set_hit_used();
+        Some(Ok(hit))
     }
 }
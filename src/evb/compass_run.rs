@@ -2,24 +2,59 @@ use std::fs::File;
 use std::path::{Path, PathBuf};
 
 use flate2::read::GzDecoder;
-use log::info;
+use log::{info, warn};
+use polars::frame::chunk_df_for_writing;
 use polars::prelude::*;
+use polars_parquet::parquet::metadata::KeyValue;
 use std::sync::{Arc, Mutex};
 use tar::Archive;
 
-use super::channel_data::ChannelData;
-use super::channel_map::{Board, ChannelMap};
+use super::build_params::BuildParams;
+use super::calibration_map::{CalibrationMap, CalibrationMapEntry};
+use super::channel_data::{ChannelData, MIN_XAVG_WEIGHT_SUM};
+use super::channel_map::{Board, ChannelMap, UnmappedHits};
+use super::compass_data::{decompose_uuid_to_board_channel, ps_to_ns};
 use super::compass_file::CompassFile;
+use super::csv_export::write_csv;
 use super::error::EVBError;
-use super::event_builder::EventBuilder;
+use super::event_builder::{merge_hits, EventBuilder, HitBatcher, TimeOrderCheck};
 use super::kinematics::{calculate_weights, KineParameters};
+use super::metadata::{
+    channel_map_hash, run_metadata, time_resolution_metadata, TimeResolutionEntry,
+};
 use super::nuclear_data::MassMap;
-use super::scaler_list::{ScalerEntryUI, ScalerList};
+use super::pedestal_map::{PedestalMap, PedestalMapEntry};
+use super::position::DelayLineCheck;
+#[cfg(feature = "root")]
+use super::root_export::write_root;
+use super::scaler_list::{ScalerCounts, ScalerEntryUI, ScalerList};
 use super::shift_map::{ShiftMap, ShiftMapEntry};
+use super::stream_writer::StreamWriter;
+use super::time_spread::TimeSpread;
 use super::used_size::UsedSize;
+use super::validation::validate_configuration;
 
 //Maximum allowed size for a single dataframe: 8GB
 const MAX_USED_SIZE: usize = 8_000_000_000;
+//Number of events in each row group when streaming the output
+const STREAM_ROW_GROUP_EVENTS: usize = 100_000;
+//Number of events written to the csv preview of each run
+const CSV_PREVIEW_EVENTS: usize = 10_000;
+//Number of hits gathered before they are built in parallel
+const PARALLEL_BATCH_HITS: usize = 1_000_000;
+//Number of events between calls of the progress callback
+const PROGRESS_CALLBACK_EVENTS: u64 = 100_000;
+
+// State of a run being built, passed to the ProcessParams::progress_callback
+#[derive(Debug, Clone, Copy)]
+pub struct BuildProgress {
+    pub run_number: i32,
+    pub events: u64,       // events built so far in this run
+    pub used_bytes: usize, // estimated memory held by the events not yet written
+}
+
+// Called from the processing thread, so it must be Send + Sync
+pub type ProgressCallback = Arc<dyn Fn(BuildProgress) + Send + Sync>;
 
 #[derive(Debug)]
 struct RunParams<'a> {
@@ -31,7 +66,12 @@ struct RunParams<'a> {
     pub nuc_map: &'a MassMap,
     pub channel_map: &'a ChannelMap,
     pub shift_map: &'a Option<ShiftMap>,
+    pub pedestal_map: &'a PedestalMap,
+    pub calibration_map: &'a CalibrationMap,
+    pub build_params: &'a BuildParams,
+    pub metadata: &'a [KeyValue],
     pub coincidence_window: f64,
+    pub max_events: Option<u64>,
     pub run_number: i32,
 }
 
@@ -45,13 +85,37 @@ fn clean_up_unpack_dir(unpack_dir: &Path) -> Result<(), EVBError> {
     Ok(())
 }
 
-fn write_dataframe(data: ChannelData, filepath: &Path) -> Result<(), PolarsError> {
+// Error for a hit of a channel without a type with BuildParams::strict_channel_map, None for a mapped channel
+fn unmapped_channel_error(channel_map: &ChannelMap, uuid: &u32) -> Option<EVBError> {
+    match channel_map.lookup(uuid) {
+        Ok(Some(_)) => None,
+        Ok(None) => {
+            let (board, channel) = decompose_uuid_to_board_channel(uuid);
+            Some(EVBError::UnmappedChannel { board, channel })
+        }
+        Err(e) => Some(e.into()),
+    }
+}
+
+fn write_dataframe(
+    data: ChannelData,
+    filepath: &Path,
+    metadata: &[KeyValue],
+    compression: ParquetCompression,
+) -> Result<(), PolarsError> {
     info!("Writing dataframe to disk at {}", filepath.display());
-    // let columns: Vec<Series> = data.convert_to_series();
-    let columns = data.convert_to_columns();
-    let mut df = DataFrame::new(columns)?;
+    let mut df = data.into_dataframe()?;
+    let df = chunk_df_for_writing(&mut df, 512 * 512)?;
     let mut output_file = File::create(filepath)?;
-    ParquetWriter::new(&mut output_file).finish(&mut df)?;
+    let mut writer = ParquetWriter::new(&mut output_file)
+        .with_compression(compression)
+        .batched(df.schema())?;
+    writer.write_batch(&df)?;
+    // Finish by hand so that the run metadata lands in the file footer
+    match writer.get_writer().lock() {
+        Ok(mut file_writer) => file_writer.end(Some(metadata.to_vec()))?,
+        Err(_) => polars_bail!(ComputeError: "parquet writer lock was poisoned"),
+    };
     Ok(())
 }
 
@@ -59,6 +123,7 @@ fn process_run(
     params: RunParams<'_>,
     k_params: &KineParameters,
     progress: Arc<Mutex<f32>>,
+    callback: Option<&ProgressCallback>,
 ) -> Result<(), EVBError> {
     // Protective, ensure no loose files
     clean_up_unpack_dir(&params.unpack_dir_path)?;
@@ -70,6 +135,12 @@ fn process_run(
     let mut scaler_list = Some(ScalerList::new(params.scalerlist));
 
     // Collect all files from unpack, separate scalers from normal files
+    let waveform_uuids: Vec<u32> = params
+        .build_params
+        .waveform_channels
+        .iter()
+        .flat_map(|channel_type| params.channel_map.uuids_of(*channel_type))
+        .collect();
     let mut files: Vec<CompassFile<'_>> = vec![];
     let mut total_count: u64 = 0;
     for item in params.unpack_dir_path.read_dir()? {
@@ -80,17 +151,91 @@ fn process_run(
             }
         };
 
-        files.push(CompassFile::new(filepath, params.shift_map)?);
-        files.last_mut().unwrap().set_hit_used();
-        files.last_mut().unwrap().get_top_hit()?;
+        let mut file = CompassFile::new(filepath, params.shift_map)?;
+        file.set_waveform_uuids(waveform_uuids.clone());
+        file.set_time_scale(params.build_params.time_scale());
+        files.push(file);
         total_count += files.last().unwrap().get_number_of_hits();
     }
 
-    let mut evb = EventBuilder::new(&params.coincidence_window);
-    let mut analyzed_data = ChannelData::new(params.channel_map);
-    let x_weights = calculate_weights(k_params, params.nuc_map);
+    // CoMPASS writes each channel to its own file, so the first hit of each file finds an unmapped channel
+    // before any output is opened
+    if params.build_params.strict_channel_map {
+        for file in files.iter_mut() {
+            let uuid = file.get_top_hit()?.uuid;
+            if file.is_eof() {
+                continue;
+            }
+            if let Some(e) = unmapped_channel_error(params.channel_map, &uuid) {
+                drop(files);
+                clean_up_unpack_dir(&params.unpack_dir_path)?;
+                return Err(e);
+            }
+        }
+    }
+
+    let disabled: Vec<String> = params
+        .channel_map
+        .disabled_channels()
+        .iter()
+        .map(|(board, channel, channel_type)| {
+            format!(
+                "{} (board {} channel {})",
+                channel_type.as_ref(),
+                board,
+                channel
+            )
+        })
+        .collect();
+    if !disabled.is_empty() {
+        info!(
+            "Run {} ignores the hits of disabled channels: {}",
+            params.run_number,
+            disabled.join(", ")
+        );
+    }
 
-    let mut earliest_file_index: Option<usize>;
+    let anchor = params
+        .build_params
+        .anchor
+        .map_or(vec![], |anchor| params.channel_map.uuids_of(anchor));
+    let mut evb = EventBuilder::new(&params.coincidence_window).with_anchor(anchor);
+    let mut slice = params.build_params.time_slice();
+    let delay_line_check = params
+        .build_params
+        .delay_line_check
+        .map(|_| Arc::new(DelayLineCheck::default()));
+    let time_spread = params
+        .build_params
+        .time_spread
+        .then(|| Arc::new(TimeSpread::default()));
+    let mut analyzed_data = ChannelData::new(params.channel_map, params.build_params);
+    analyzed_data.set_pedestal_map(params.pedestal_map.clone());
+    analyzed_data.set_calibration_map(params.calibration_map.clone());
+    if let Some(check) = &delay_line_check {
+        analyzed_data.set_delay_line_check(check.clone());
+    }
+    if let Some(spread) = &time_spread {
+        analyzed_data.set_time_spread(spread.clone());
+    }
+    let x_weights = calculate_weights(k_params, params.nuc_map);
+    if let Some((w1, w2)) = x_weights {
+        if params.build_params.normalize_xavg_weights {
+            if (w1 + w2).abs() < MIN_XAVG_WEIGHT_SUM {
+                warn!(
+                    "Xavg weights ({}, {}) sum to zero and can not be normalized, Xavg will be left unset",
+                    w1, w2
+                );
+            }
+        } else if ((w1 + w2) - 1.0).abs() > 1.0e-9 {
+            warn!(
+                "Xavg weights ({}, {}) do not sum to one, Xavg will be scaled by {}",
+                w1,
+                w2,
+                w1 + w2
+            );
+        }
+    }
 
     let mut count: u64 = 0;
     let mut flush_count: u64 = 0;
@@ -98,54 +243,180 @@ fn process_run(
     let flush_val: u64 = ((total_count as f64) * flush_percent) as u64;
 
     let mut frag_number = 0;
+    let mut event_count: u64 = 0;
+    let mut reported_event_count: u64 = 0;
+    let mut stopped_early = false;
+    // Events still to build before max_events is reached, None without a limit
+    let events_left = |event_count: u64| {
+        params
+            .max_events
+            .map(|max_events| max_events.saturating_sub(event_count))
+    };
+    let mut batcher = HitBatcher::new(
+        params.coincidence_window,
+        params
+            .build_params
+            .parallel_batch_hits
+            .unwrap_or(PARALLEL_BATCH_HITS),
+    );
+    let mut unmapped = UnmappedHits::default();
+    let mut strict_error: Option<EVBError> = None;
+    let mut time_order = params
+        .build_params
+        .check_time_order
+        .then(TimeOrderCheck::default);
+    let mut channel_counts = params
+        .build_params
+        .channel_counts
+        .then(ScalerCounts::default);
+    let compression = params.build_params.parquet_compression()?;
+    let mut stream_writer = if !params.build_params.stream_output {
+        None
+    } else if params.build_params.resume_output
+        && StreamWriter::can_resume(&params.output_file_path)
+    {
+        Some(StreamWriter::resume(
+            &params.output_file_path,
+            &analyzed_data,
+            STREAM_ROW_GROUP_EVENTS,
+            compression,
+        )?)
+    } else {
+        Some(StreamWriter::new(
+            &params.output_file_path,
+            &analyzed_data,
+            STREAM_ROW_GROUP_EVENTS,
+            compression,
+        )?)
+    };
+    // The next event opens more than a coincidence window after the last one kept, so after its EventTime
+    if let Some(after) = stream_writer.as_ref().and_then(|w| w.resumed_after()) {
+        slice.start = Some(slice.start.map_or(after + 1, |start| start.max(after + 1)));
+        info!(
+            "Run {} resumes after {} ns",
+            params.run_number,
+            ps_to_ns(after)
+        );
+    }
+    // Only the single-threaded build skips the events outside a slice
+    let parallel = params.build_params.parallel && slice.is_whole_run();
+    if params.build_params.parallel && !parallel {
+        info!(
+            "Run {} is built on one core to keep only the events of the time slice",
+            params.run_number
+        );
+    }
 
-    loop {
-        // Bulk of the work ... look for the earliest hit in the file collection
-        earliest_file_index = Option::None;
-        for i in 0..files.len() {
-            if !files[i].is_eof() {
-                let hit = files[i].get_top_hit()?;
-                if hit.is_default() {
-                    continue;
-                }
-
-                earliest_file_index = match earliest_file_index {
-                    None => Some(i),
-                    Some(index) => {
-                        if hit.timestamp < files[index].get_top_hit()?.timestamp {
-                            Some(i)
-                        } else {
-                            Some(index)
-                        }
-                    }
-                };
+    // Bulk of the work ... the hits of every file in time order, until no more hits are to be found
+    let mut hits = merge_hits(files)?;
+    while let Some(hit) = hits.next().transpose()? {
+        // Stop reading early once the requested number of events is built, checked before any is added
+        if events_left(event_count) == Some(0) {
+            info!(
+                "Reached maximum of {} events for run {}",
+                event_count, params.run_number
+            );
+            stopped_early = true;
+            break;
+        }
+        let hit = &hit;
+        let mut events_appended = false;
+        unmapped.count(params.channel_map, hit.uuid);
+        // A file holding more than one channel, the events before it are written and the file closed first
+        if params.build_params.strict_channel_map {
+            if let Some(e) = unmapped_channel_error(params.channel_map, &hit.uuid) {
+                strict_error = Some(e);
+                stopped_early = true;
+                break;
             }
         }
-
-        match earliest_file_index {
-            None => break, // This is how we exit, no more hits to be found
-            Some(i) => {
-                // else we pop the earliest hit off to the event builder
-                let hit = files[i].get_top_hit()?;
-                evb.push_hit(hit);
-                files[i].set_hit_used();
+        if let Some(check) = &mut time_order {
+            check.push(hit.timestamp);
+        }
+        if let Some(counts) = &mut channel_counts {
+            counts.count(hit);
+        }
+        if parallel {
+            // A batch is only handed off at a gap no event can straddle
+            if let Some(batch) = batcher.push(hit.clone()) {
+                let rows = analyzed_data.rows;
+                analyzed_data.append_events_parallel(
+                    batch,
+                    params.coincidence_window,
+                    params.channel_map,
+                    x_weights,
+                );
+                // The batch may overshoot the limit, keep exactly max_events events
+                if let Some(left) = events_left(event_count) {
+                    analyzed_data.truncate_rows(analyzed_data.rows.min(rows + left as usize));
+                }
+                event_count += (analyzed_data.rows - rows) as u64;
+                events_appended = true;
+            }
+        } else {
+            evb.push_hit(hit);
+            if evb.is_event_ready() {
+                // Events are opened in time order, so none after this one is in the slice either
+                let event = evb.get_ready_event();
+                if slice.is_past(event[0].timestamp) {
+                    info!(
+                        "Reached the end of the time slice for run {}",
+                        params.run_number
+                    );
+                    stopped_early = true;
+                    break;
+                }
+                if slice.contains(event[0].timestamp) {
+                    // Count the rows kept, an event left out by drop_empty_events or drop_bad_events is not one
+                    let rows = analyzed_data.rows;
+                    analyzed_data.append_event(event, params.channel_map, x_weights);
+                    event_count += (analyzed_data.rows - rows) as u64;
+                    events_appended = true;
+                }
             }
         }
 
-        if evb.is_event_ready() {
-            analyzed_data.append_event(evb.get_ready_event(), params.channel_map, x_weights);
-            // Check to see if we need to fragment
-            if analyzed_data.get_used_size() > MAX_USED_SIZE {
+        if events_appended {
+            if let Some(writer) = &mut stream_writer {
+                writer.write_if_full(&mut analyzed_data)?;
+            } else if analyzed_data.get_used_size() > MAX_USED_SIZE {
+                // Check to see if we need to fragment
+                info!(
+                    "Run {} fragment {} holds {}",
+                    params.run_number,
+                    frag_number,
+                    analyzed_data.memory_report()
+                );
+                // The next fragment keeps every field and setting, taken before any column is pruned
+                let next = analyzed_data.empty_copy();
+                if params.build_params.prune_empty_columns {
+                    analyzed_data.prune_empty_columns();
+                }
                 write_dataframe_fragment(
                     analyzed_data,
                     params.output_file_path.parent().unwrap(),
                     &params.run_number,
                     &frag_number,
+                    params.metadata,
+                    compression,
                 )?;
-                // Allocate new vector
-                analyzed_data = ChannelData::new(params.channel_map);
+                analyzed_data = next;
                 frag_number += 1;
             }
+
+            // Parallel batches add many events at once, so report whenever a multiple was passed
+            if let Some(callback) = callback {
+                if event_count / PROGRESS_CALLBACK_EVENTS
+                    > reported_event_count / PROGRESS_CALLBACK_EVENTS
+                {
+                    reported_event_count = event_count;
+                    callback(BuildProgress {
+                        run_number: params.run_number,
+                        events: event_count,
+                        used_bytes: analyzed_data.get_used_size(),
+                    });
+                }
+            }
         }
 
         // Progress report
@@ -161,27 +432,192 @@ fn process_run(
         }
     }
 
-    if frag_number == 0 {
-        write_dataframe(analyzed_data, &params.output_file_path)?;
+    // Build the hits still waiting once the files are exhausted
+    if !stopped_early && events_left(event_count) != Some(0) {
+        if parallel {
+            let rows = analyzed_data.rows;
+            analyzed_data.append_events_parallel(
+                batcher.flush(),
+                params.coincidence_window,
+                params.channel_map,
+                x_weights,
+            );
+            if let Some(left) = events_left(event_count) {
+                analyzed_data.truncate_rows(analyzed_data.rows.min(rows + left as usize));
+            }
+        } else if let Some(event) = evb.flush() {
+            if slice.contains(event[0].timestamp) {
+                analyzed_data.append_event(event, params.channel_map, x_weights);
+            }
+        }
+    }
+
+    if !unmapped.is_empty() && strict_error.is_none() {
+        warn!(
+            "Run {} dropped hits from channels missing in the channel map: {}",
+            params.run_number, unmapped
+        );
+    }
+
+    if let Some(check) = time_order {
+        match check.first_out_of_order {
+            Some(first) => warn!(
+                "Run {} has {} hits earlier than the hit before them, the first at {} ns. The input files are not sorted",
+                params.run_number,
+                check.out_of_order,
+                ps_to_ns(first)
+            ),
+            None => info!("Run {} hits are in time order", params.run_number),
+        }
+    }
+
+    if let (Some(check), Some(threshold)) =
+        (&delay_line_check, params.build_params.delay_line_check)
+    {
+        for (plane, fraction) in ["X1", "X2"].iter().zip(check.negative_fractions()) {
+            match fraction {
+                Some(fraction) if fraction > threshold => warn!(
+                    "Run {} has a negative {} delay line difference in {:.1}% of its events. Check that the left and right cables are not swapped",
+                    params.run_number,
+                    plane,
+                    fraction * 100.0
+                ),
+                Some(fraction) => info!(
+                    "Run {} has a negative {} delay line difference in {:.1}% of its events",
+                    params.run_number,
+                    plane,
+                    fraction * 100.0
+                ),
+                None => {}
+            }
+        }
+    }
+
+    if let Some(spread) = &time_spread {
+        for (field, stats) in spread.stats() {
+            if let (Some(mean), Some(fwhm)) = (stats.mean(), stats.fwhm()) {
+                info!(
+                    "Run {} {}: mean {:.3} ns, FWHM ~{:.3} ns over {} events",
+                    params.run_number,
+                    field.as_ref(),
+                    mean,
+                    fwhm,
+                    stats.count()
+                );
+            }
+        }
+    }
+
+    info!(
+        "Run {} holds {}",
+        params.run_number,
+        analyzed_data.memory_report()
+    );
+    if let Some(writer) = stream_writer {
+        writer.finish(&mut analyzed_data, params.metadata)?;
         println!("\tWriting run {}", params.run_number);
     } else {
-        write_dataframe_fragment(
-            analyzed_data,
-            params.output_file_path.parent().unwrap(),
-            &params.run_number,
-            &frag_number,
+        if params.build_params.prune_empty_columns {
+            analyzed_data.prune_empty_columns();
+        }
+        if frag_number == 0 {
+            write_dataframe(
+                analyzed_data,
+                &params.output_file_path,
+                params.metadata,
+                compression,
+            )?;
+            println!("\tWriting run {}", params.run_number);
+        } else {
+            write_dataframe_fragment(
+                analyzed_data,
+                params.output_file_path.parent().unwrap(),
+                &params.run_number,
+                &frag_number,
+                params.metadata,
+                compression,
+            )?;
+            println!(
+                "\tWriting run {} with {} fragments",
+                params.run_number, frag_number
+            );
+        }
+    }
+
+    if let Some(e) = strict_error {
+        drop(hits);
+        clean_up_unpack_dir(&params.unpack_dir_path)?;
+        return Err(e);
+    }
+
+    #[cfg(feature = "root")]
+    if params.build_params.root_output {
+        let parquet_paths: Vec<PathBuf> = if frag_number == 0 || params.build_params.stream_output {
+            vec![params.output_file_path.clone()]
+        } else {
+            (0..=frag_number)
+                .map(|frag| {
+                    params
+                        .output_file_path
+                        .parent()
+                        .unwrap()
+                        .join(format!("run_{}_{}.parquet", params.run_number, frag))
+                })
+                .collect()
+        };
+        for parquet_path in parquet_paths {
+            let df = ParquetReader::new(File::open(&parquet_path)?).finish()?;
+            write_root(
+                &df,
+                &parquet_path.with_extension("root"),
+                params.build_params.sentinel(),
+            )?;
+        }
+    }
+    #[cfg(not(feature = "root"))]
+    if params.build_params.root_output {
+        warn!("ROOT output was requested, but the eventbuilder was built without the root feature");
+    }
+
+    // Preview of the first events, taken back from the first file written for the run
+    if params.build_params.csv_preview {
+        let parquet_path = if frag_number == 0 || params.build_params.stream_output {
+            params.output_file_path.clone()
+        } else {
+            params
+                .output_file_path
+                .parent()
+                .unwrap()
+                .join(format!("run_{}_0.parquet", params.run_number))
+        };
+        let df = LazyFrame::scan_parquet(&parquet_path, ScanArgsParquet::default())?
+            .limit(CSV_PREVIEW_EVENTS as IdxSize)
+            .collect()?;
+        write_csv(
+            &df,
+            &params.output_file_path.with_extension("csv"),
+            CSV_PREVIEW_EVENTS,
+            params.build_params.sentinel(),
         )?;
-        println!(
-            "\tWriting run {} with {} fragments",
-            params.run_number, frag_number
-        );
     }
     if let Some(list) = scaler_list {
         list.write_scalers(&params.scalerout_file_path)?
     }
+    if let Some(counts) = channel_counts {
+        info!(
+            "Run {} had {} hits before event building",
+            params.run_number,
+            counts.total()
+        );
+        counts.write(
+            &params
+                .scalerout_file_path
+                .with_file_name(format!("run_{}_channel_counts.txt", params.run_number)),
+        )?;
+    }
 
     // To be safe, manually drop all files in unpack dir before deleting all the files
-    drop(files);
+    drop(hits);
 
     clean_up_unpack_dir(&params.unpack_dir_path)?;
 
@@ -193,9 +629,11 @@ fn write_dataframe_fragment(
     out_dir: &Path,
     run_number: &i32,
     frag_number: &i32,
+    metadata: &[KeyValue],
+    compression: ParquetCompression,
 ) -> Result<(), PolarsError> {
     let frag_file_path = out_dir.join(format!("run_{}_{}.parquet", run_number, frag_number));
-    write_dataframe(data, &frag_file_path)?;
+    write_dataframe(data, &frag_file_path, metadata, compression)?;
     Ok(())
 }
 
@@ -206,9 +644,15 @@ pub struct ProcessParams {
     pub channel_map: Vec<Board>,
     pub scaler_list: Vec<ScalerEntryUI>,
     pub shift_map: Vec<ShiftMapEntry>,
+    pub pedestal_map: Vec<PedestalMapEntry>,
+    pub calibration_map: Vec<CalibrationMapEntry>,
+    pub time_resolutions: Vec<TimeResolutionEntry>,
+    pub build_params: BuildParams,
     pub coincidence_window: f64,
+    pub max_events: Option<u64>,
     pub run_min: i32,
     pub run_max: i32,
+    pub progress_callback: Option<ProgressCallback>, // called every PROGRESS_CALLBACK_EVENTS events
 }
 
 //Function which handles processing multiple runs, this is what the UI actually calls
@@ -217,9 +661,36 @@ pub fn process_runs(
     k_params: KineParameters,
     progress: Arc<Mutex<f32>>,
 ) -> Result<(), EVBError> {
-    let channel_map = ChannelMap::new(&params.channel_map);
+    let channel_map = ChannelMap::new(&params.channel_map)?;
+    let problems = validate_configuration(
+        &channel_map,
+        &params.calibration_map,
+        &params.shift_map,
+        &params.build_params,
+    );
+    for problem in problems.iter().filter(|problem| !problem.is_error()) {
+        warn!("{}", problem);
+    }
+    if let Some(problem) = problems.into_iter().find(|problem| problem.is_error()) {
+        return Err(EVBError::Config(problem));
+    }
     let mass_map = MassMap::new()?;
     let shift_map = ShiftMap::new(params.shift_map);
+    let pedestal_map = PedestalMap::new(params.pedestal_map);
+    let calibration_map = CalibrationMap::new(params.calibration_map);
+    let mut metadata = time_resolution_metadata(&params.time_resolutions, &channel_map);
+    metadata.push(KeyValue::new(
+        "CebraRelTimeReference".to_string(),
+        params
+            .build_params
+            .cebra_reference
+            .or(params.build_params.anchor)
+            .as_ref()
+            .map_or("ScintLeft", |reference| reference.as_ref())
+            .to_string(),
+    ));
+
+    let map_hash = channel_map_hash(&params.channel_map);
 
     println!(
         "Processing runs {} to {}",
@@ -227,6 +698,9 @@ pub fn process_runs(
         params.run_max - 1
     );
     for run in params.run_min..params.run_max {
+        let mut run_file_metadata = metadata.clone();
+        run_file_metadata.extend(run_metadata(run, &map_hash));
+        let run_build_params = params.build_params.for_run(run);
         let local_params = RunParams {
             run_archive_path: params.archive_dir.join(format!("run_{}.tar.gz", run)),
             unpack_dir_path: params.unpack_dir.clone(),
@@ -242,7 +716,12 @@ pub fn process_runs(
             nuc_map: &mass_map,
             channel_map: &channel_map,
             shift_map: &Some(shift_map.clone()),
+            pedestal_map: &pedestal_map,
+            calibration_map: &calibration_map,
+            build_params: &run_build_params,
+            metadata: &run_file_metadata,
             coincidence_window: params.coincidence_window,
+            max_events: params.max_events,
             run_number: run,
         };
 
@@ -253,7 +732,12 @@ pub fn process_runs(
 
         //Skip over run if it doesnt exist
         if local_params.run_archive_path.exists() {
-            process_run(local_params, &k_params, progress.clone())?;
+            process_run(
+                local_params,
+                &k_params,
+                progress.clone(),
+                params.progress_callback.as_ref(),
+            )?;
         }
     }
 
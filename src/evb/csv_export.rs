@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::path::Path;
+
+use log::info;
+use polars::prelude::*;
+
+// Write the first max_rows events of a built DataFrame as CSV, for a quick look in a spreadsheet. Columns
+// keep the Parquet order. Nulls and sentinels (see BuildParams::sentinel) are written as empty cells, and
+// the list columns (X, Z, and any waveforms) are left out since CSV has no list type.
+pub fn write_csv(
+    df: &DataFrame,
+    filepath: &Path,
+    max_rows: usize,
+    sentinel: f64,
+) -> PolarsResult<()> {
+    info!("Writing csv to disk at {}", filepath.display());
+    let head = df.head(Some(max_rows));
+    let columns: Vec<Column> = head
+        .get_columns()
+        .iter()
+        .filter(|column| !matches!(column.dtype(), DataType::List(_)))
+        .map(|column| blank_invalid(column, sentinel))
+        .collect::<PolarsResult<_>>()?;
+    let mut csv_df = DataFrame::new(columns)?;
+
+    let mut output_file = File::create(filepath)?;
+    CsvWriter::new(&mut output_file)
+        .include_header(true)
+        .finish(&mut csv_df)
+}
+
+// Replace the sentinel with null in the float and signed integer columns. Integer fields and integer
+// timestamps hold the sentinel truncated to i64 (see ChannelData::convert_to_columns); the unsigned and
+// boolean columns are set in every event and are returned unchanged
+fn blank_invalid(column: &Column, sentinel: f64) -> PolarsResult<Column> {
+    let values: Column = match column.dtype() {
+        DataType::Float64 => column
+            .f64()?
+            .into_iter()
+            .map(|value| value.filter(|v| *v != sentinel))
+            .collect::<Float64Chunked>()
+            .into_column(),
+        DataType::Float32 => column
+            .f32()?
+            .into_iter()
+            .map(|value| value.filter(|v| *v != sentinel as f32))
+            .collect::<Float32Chunked>()
+            .into_column(),
+        DataType::Int64 => column
+            .i64()?
+            .into_iter()
+            .map(|value| value.filter(|v| *v != sentinel as i64))
+            .collect::<Int64Chunked>()
+            .into_column(),
+        _ => return Ok(column.clone()),
+    };
+    Ok(values.with_name(column.name().clone()))
+}
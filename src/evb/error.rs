@@ -1,6 +1,7 @@
-use super::channel_map::ChannelMapError;
+use super::channel_map::{ChannelMapError, ChannelType};
 use super::nuclear_data::MassError;
 use super::shift_map::ShiftError;
+use super::validation::ConfigProblem;
 use flate2::DecompressError;
 use polars::error::PolarsError;
 use std::error::Error;
@@ -9,14 +10,26 @@ use std::fmt::Display;
 #[derive(Debug)]
 pub enum EVBError {
     Compressor(DecompressError),
-    Waves,
     File(std::io::Error),
     Parser,
     Channel(ChannelMapError),
+    // A hit of a channel the map leaves without a type, with BuildParams::strict_channel_map
+    UnmappedChannel {
+        board: u32,
+        channel: u32,
+    },
+    // Hits of one detector from more than one (board, channel) in the same event, i.e. a broken map
+    DuplicateMapping {
+        channel_type: ChannelType,
+        channels: Vec<(u32, u32)>,
+    },
     DataFrame(PolarsError),
     MassMap(MassError),
     ShiftMap(ShiftError),
     Sync,
+    Config(ConfigProblem),
+    #[cfg(feature = "root")]
+    Root(String),
 }
 
 impl From<std::io::Error> for EVBError {
@@ -59,19 +72,38 @@ impl Display for EVBError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             EVBError::Compressor(x) => write!(f, "Run had a decompression error: {}", x),
-            EVBError::Waves => write!(
-                f,
-                "Run found a file with waveform data, which is not supported!"
-            ),
             EVBError::File(x) => write!(f, "Run had a file I/O error: {}", x),
             EVBError::Parser => write!(f, "Run had an error parsing the data from files"),
             EVBError::Channel(x) => {
                 write!(f, "Run had an error occur with the channel map: {}", x)
             }
+            EVBError::UnmappedChannel { board, channel } => write!(
+                f,
+                "Run has a hit of board {} channel {}, which has no type in the channel map",
+                board, channel
+            ),
+            EVBError::DuplicateMapping {
+                channel_type,
+                channels,
+            } => {
+                let channels: Vec<String> = channels
+                    .iter()
+                    .map(|(board, channel)| format!("board {} channel {}", board, channel))
+                    .collect();
+                write!(
+                    f,
+                    "Run has {} hits from more than one channel in an event: {}",
+                    channel_type.as_ref(),
+                    channels.join(", ")
+                )
+            }
             EVBError::DataFrame(x) => write!(f, "Run had an error using polars: {}", x),
             EVBError::MassMap(x) => write!(f, "Run had an error with the mass data: {}", x),
             EVBError::ShiftMap(x) => write!(f, "Run had an error with the shift map: {}", x),
             EVBError::Sync => write!(f, "Run was unable to access shared progress resource"),
+            EVBError::Config(x) => write!(f, "Run has an error in the configuration: {}", x),
+            #[cfg(feature = "root")]
+            EVBError::Root(x) => write!(f, "Run had an error writing the root file: {}", x),
         }
     }
 }
@@ -1,8 +1,12 @@
-use super::compass_data::CompassData;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use super::compass_data::{ps_to_ns, CompassData};
 
 #[derive(Debug)]
 pub struct EventBuilder {
     coincidence_window: f64,
+    anchor: Vec<u32>, // UUIDs of the channels opening events, see with_anchor
     event: Vec<CompassData>,
     ready_event: Vec<CompassData>,
     is_event_ready: bool,
@@ -12,12 +16,21 @@ impl EventBuilder {
     pub fn new(window: &f64) -> EventBuilder {
         EventBuilder {
             coincidence_window: *window,
+            anchor: vec![],
             event: vec![],
             ready_event: vec![],
             is_event_ready: false,
         }
     }
 
+    // Anchor the window on the hits of these channels, e.g. those of BuildParams::anchor: a hit of one of
+    // them closes an event opened by any other hit and opens its own, so the window runs from the anchor.
+    // Events without an anchor hit are still opened by their first hit.
+    pub fn with_anchor(mut self, anchor: Vec<u32>) -> EventBuilder {
+        self.anchor = anchor;
+        self
+    }
+
     pub fn is_event_ready(&self) -> bool {
         self.is_event_ready
     }
@@ -29,7 +42,11 @@ impl EventBuilder {
             return;
         }
 
-        if hit.timestamp - self.event[0].timestamp < self.coincidence_window {
+        let is_anchor = |hit: &CompassData| self.anchor.contains(&hit.uuid);
+        let takes_over = is_anchor(hit) && !is_anchor(&self.event[0]);
+        if !takes_over
+            && ps_to_ns(hit.timestamp - self.event[0].timestamp) < self.coincidence_window
+        {
             self.event.push(hit.clone());
         } else {
             self.ready_event.clone_from(&self.event);
@@ -43,4 +60,264 @@ impl EventBuilder {
         self.is_event_ready = false;
         self.ready_event.clone()
     }
+
+    // Timestamp of the first hit of the event still being built
+    pub fn event_start(&self) -> Option<i64> {
+        self.event.first().map(|hit| hit.timestamp)
+    }
+
+    // Take the event still being built, used once the hit stream is exhausted
+    pub fn flush(&mut self) -> Option<Vec<CompassData>> {
+        if self.event.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.event))
+        }
+    }
+}
+
+// Counts the hits of a stream whose timestamp is earlier than the hit before them, which only happens when
+// the input was not sorted
+#[derive(Debug, Clone, Default)]
+pub struct TimeOrderCheck {
+    previous: Option<i64>,
+    pub out_of_order: u64,
+    pub first_out_of_order: Option<i64>, // ps, timestamp of the first hit found out of order
+}
+
+impl TimeOrderCheck {
+    pub fn push(&mut self, timestamp: i64) {
+        if self.previous.is_some_and(|previous| timestamp < previous) {
+            self.out_of_order += 1;
+            self.first_out_of_order.get_or_insert(timestamp);
+        }
+        self.previous = Some(timestamp);
+    }
+}
+
+// Time-ordered merge of several time-ordered hit sources, e.g. one CompassFile per board, see merge_hits
+#[derive(Debug)]
+pub struct MergedHits<I, E> {
+    sources: Vec<I>,
+    heads: Vec<Option<CompassData>>, // next hit of each source
+    queue: BinaryHeap<Reverse<(i64, usize)>>, // (timestamp, source) of each head
+    error: Option<E>,                // met while reading ahead, returned next
+}
+
+impl<I, E> MergedHits<I, E>
+where
+    I: Iterator<Item = Result<CompassData, E>>,
+{
+    // Read the next hit of a source into its head, an exhausted source is simply left empty
+    fn advance(&mut self, source: usize) -> Result<(), E> {
+        if let Some(hit) = self.sources[source].next().transpose()? {
+            self.queue.push(Reverse((hit.timestamp, source)));
+            self.heads[source] = Some(hit);
+        }
+        Ok(())
+    }
+}
+
+impl<I, E> Iterator for MergedHits<I, E>
+where
+    I: Iterator<Item = Result<CompassData, E>>,
+{
+    type Item = Result<CompassData, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        let Reverse((_, source)) = self.queue.pop()?;
+        let hit = self.heads[source].take()?;
+        if let Err(e) = self.advance(source) {
+            self.error = Some(e);
+        }
+        Some(Ok(hit))
+    }
+}
+
+// Merges sources which are each sorted in time into one time-sorted stream, holding only the next hit of
+// each source so that boards with very different rates are never buffered. Hits with the same timestamp
+// come from the earlier source first. An error from a source is returned right after that source's last good hit.
+pub fn merge_hits<I, E>(sources: Vec<I>) -> Result<MergedHits<I, E>, E>
+where
+    I: Iterator<Item = Result<CompassData, E>>,
+{
+    let count = sources.len();
+    let mut merged = MergedHits {
+        sources,
+        heads: vec![None; count],
+        queue: BinaryHeap::with_capacity(count),
+        error: None,
+    };
+    for source in 0..count {
+        merged.advance(source)?;
+    }
+    Ok(merged)
+}
+
+// Bounds of the events kept from a run, see build_events_in. An event belongs to the slice when its first hit
+// does, so events are kept or left out whole and never cut at a bound.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeSlice {
+    pub start: Option<i64>, // ps, inclusive, from the start of the run if None
+    pub stop: Option<i64>,  // ps, exclusive, to the end of the run if None
+}
+
+impl TimeSlice {
+    // Whether every event is kept
+    pub fn is_whole_run(&self) -> bool {
+        self.start.is_none() && self.stop.is_none()
+    }
+
+    // Whether an event whose first hit is at timestamp is kept
+    pub fn contains(&self, timestamp: i64) -> bool {
+        self.start.is_none_or(|start| timestamp >= start) && !self.is_past(timestamp)
+    }
+
+    // Whether an event whose first hit is at timestamp, and so every later event, is past the stop
+    pub fn is_past(&self, timestamp: i64) -> bool {
+        self.stop.is_some_and(|stop| timestamp >= stop)
+    }
+}
+
+// Iterator over the events built from a time-sorted stream of hits, see build_events
+#[derive(Debug)]
+pub struct EventStream<I> {
+    hits: I,
+    builder: EventBuilder,
+    slice: TimeSlice,
+    done: bool,
+}
+
+impl<I: Iterator<Item = CompassData>> EventStream<I> {
+    // Anchor the window on the hits of these channels, see EventBuilder::with_anchor
+    pub fn with_anchor(mut self, anchor: Vec<u32>) -> Self {
+        self.builder.anchor = anchor;
+        self
+    }
+
+    fn next_event(&mut self) -> Option<Vec<CompassData>> {
+        for hit in self.hits.by_ref() {
+            self.builder.push_hit(&hit);
+            if self.builder.is_event_ready() {
+                return Some(self.builder.get_ready_event());
+            }
+        }
+        self.builder.flush()
+    }
+}
+
+impl<I: Iterator<Item = CompassData>> Iterator for EventStream<I> {
+    type Item = Vec<CompassData>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let Some(event) = self.next_event() else {
+                self.done = true;
+                break;
+            };
+            let first = event[0].timestamp;
+            if self.slice.is_past(first) {
+                self.done = true;
+            } else if self.slice.contains(first) {
+                // Stop reading once the event being built is already past the stop
+                self.done = self
+                    .builder
+                    .event_start()
+                    .is_some_and(|start| self.slice.is_past(start));
+                return Some(event);
+            }
+        }
+        None
+    }
+}
+
+// Groups a time-sorted stream of hits into events using a coincidence window in ns.
+// The window is a leading-edge window anchored on the first hit of an event: a hit belongs to the event
+// if hit.timestamp - first.timestamp < window, otherwise it starts a new event. A hit exactly one window
+// after the first hit therefore starts a new event, and hits sharing the same timestamp always land in
+// the same event, in the order they were given, unless the window is anchored, see EventStream::with_anchor.
+// The last event is returned once the stream runs out.
+pub fn build_events<I: IntoIterator<Item = CompassData>>(
+    hits: I,
+    window: f64,
+) -> EventStream<I::IntoIter> {
+    build_events_in(hits, window, TimeSlice::default())
+}
+
+// Like build_events, but only returns the events whose first hit is within the slice. The hits before the
+// start are still grouped, so that an event straddling the start is left out whole rather than its later hits
+// being returned as an event of their own, and the events returned are exactly those of the whole run.
+// The hits after the stop are never read, except the one opening the first event past it.
+pub fn build_events_in<I: IntoIterator<Item = CompassData>>(
+    hits: I,
+    window: f64,
+    slice: TimeSlice,
+) -> EventStream<I::IntoIter> {
+    EventStream {
+        hits: hits.into_iter(),
+        builder: EventBuilder::new(&window),
+        slice,
+        done: false,
+    }
+}
+
+// Gathers a time-sorted stream of hits into batches to be built independently, e.g. on the rayon thread pool.
+// Once a batch holds batch_hits hits, the hits which follow are carried over into it until the gap to the
+// next hit is larger than the coincidence window, so an event straddling the batch boundary is built whole
+// and every batch builds the same events as the whole stream.
+#[derive(Debug)]
+pub struct HitBatcher {
+    window: f64,
+    batch_hits: usize,
+    batch: Vec<CompassData>,
+}
+
+impl HitBatcher {
+    pub fn new(window: f64, batch_hits: usize) -> HitBatcher {
+        HitBatcher {
+            window,
+            batch_hits,
+            batch: vec![],
+        }
+    }
+
+    // Add the next hit, returns the finished batch when the hit starts a new one
+    pub fn push(&mut self, hit: CompassData) -> Option<Vec<CompassData>> {
+        let at_gap = self
+            .batch
+            .last()
+            .is_some_and(|last| ps_to_ns(hit.timestamp - last.timestamp) > self.window);
+        let finished = (at_gap && self.batch.len() >= self.batch_hits)
+            .then(|| std::mem::take(&mut self.batch));
+        self.batch.push(hit);
+        finished
+    }
+
+    // Take the hits of the batch still being gathered, used once the hit stream is exhausted
+    pub fn flush(&mut self) -> Vec<CompassData> {
+        std::mem::take(&mut self.batch)
+    }
+}
+
+// Splits a time-sorted stream of hits into chunks which can be built into events independently. A chunk is
+// only ended once it holds at least min_hits hits and the gap to the next hit is larger than the coincidence
+// window, see HitBatcher.
+pub fn split_on_gaps(
+    hits: Vec<CompassData>,
+    window: f64,
+    min_hits: usize,
+) -> Vec<Vec<CompassData>> {
+    let mut batcher = HitBatcher::new(window, min_hits);
+    let mut chunks: Vec<Vec<CompassData>> = hits
+        .into_iter()
+        .filter_map(|hit| batcher.push(hit))
+        .collect();
+    let last = batcher.flush();
+    if !last.is_empty() {
+        chunks.push(last);
+    }
+    chunks
 }
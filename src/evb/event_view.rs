@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+
+use super::channel_data::{ChannelData, ChannelDataField};
+use super::channel_map::{ChannelMap, ChannelType};
+use super::compass_data::CompassData;
+
+// Row-major view of one built event, holding only what was set. Times are in ns like ChannelData::get_value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Event {
+    pub values: BTreeMap<ChannelDataField, f64>,
+    pub positions: BTreeMap<ChannelDataField, Vec<f64>>, // the list fields, X and Z
+    pub derived: Vec<(String, f64)>, // BuildParams::derived_fields, in declaration order
+    pub waveforms: Vec<(ChannelType, Vec<i16>)>,
+}
+
+impl Event {
+    pub fn get(&self, field: &ChannelDataField) -> Option<f64> {
+        self.values.get(field).copied()
+    }
+
+    pub fn get_derived(&self, name: &str) -> Option<f64> {
+        self.derived
+            .iter()
+            .find(|(derived, _)| derived == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+// Iterator over the events of a stream as they are built, see ChannelData::events
+pub struct EventIter<'a, I> {
+    events: I,
+    data: ChannelData,
+    map: &'a ChannelMap,
+    weights: Option<(f64, f64)>,
+}
+
+impl<'a, I> EventIter<'a, I> {
+    pub fn new(
+        events: I,
+        data: ChannelData,
+        map: &'a ChannelMap,
+        weights: Option<(f64, f64)>,
+    ) -> Self {
+        EventIter {
+            events,
+            data,
+            map,
+            weights,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Vec<CompassData>>> Iterator for EventIter<'_, I> {
+    type Item = Event;
+
+    // Events dropped by BuildParams::drop_bad_events or drop_empty_events are skipped
+    fn next(&mut self) -> Option<Self::Item> {
+        for hits in self.events.by_ref() {
+            self.data.append_event(hits, self.map, self.weights);
+            if let Some(event) = self.data.pop_event() {
+                return Some(event);
+            }
+        }
+        None
+    }
+}
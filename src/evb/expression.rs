@@ -0,0 +1,226 @@
+use std::fmt::Display;
+
+use super::channel_data::ChannelDataField;
+
+// A derived field declared in the build options, e.g. name "AnodeRatio" with expression
+// "AnodeFrontEnergy / CathodeEnergy". See Expression for the syntax.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Default)]
+pub struct DerivedFieldEntry {
+    pub name: String,
+    pub expression: String,
+}
+
+impl DerivedFieldEntry {
+    pub fn ui(&mut self, ui: &mut egui::Ui, on_remove: impl FnOnce()) {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.name)
+                    .hint_text("Name")
+                    .desired_width(120.0),
+            );
+            ui.label("=");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.expression)
+                    .hint_text("AnodeFrontEnergy / CathodeEnergy")
+                    .desired_width(300.0),
+            );
+
+            if ui.button("❌").clicked() {
+                on_remove();
+            }
+        });
+        if let Err(e) = self.parse() {
+            ui.colored_label(egui::Color32::RED, e.to_string());
+        }
+    }
+
+    pub fn parse(&self) -> Result<Expression, ExpressionError> {
+        if self.name.trim().is_empty() {
+            return Err(ExpressionError::MissingName);
+        }
+        if ChannelDataField::from_name(&self.name).is_some() {
+            return Err(ExpressionError::ReservedName(self.name.trim().to_string()));
+        }
+        Expression::parse(&self.expression)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionError {
+    MissingName,
+    ReservedName(String),
+    UnknownField(String),
+    UnexpectedToken(String),
+    UnexpectedEnd,
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpressionError::MissingName => write!(f, "Derived field has no name"),
+            ExpressionError::ReservedName(x) => {
+                write!(f, "Derived field name {} is already a built-in field", x)
+            }
+            ExpressionError::UnknownField(x) => write!(f, "Expression uses unknown field {}", x),
+            ExpressionError::UnexpectedToken(x) => {
+                write!(f, "Expression has an unexpected {}", x)
+            }
+            ExpressionError::UnexpectedEnd => write!(f, "Expression ends unexpectedly"),
+        }
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+// Arithmetic over the fields of an event: numbers, field names, + - * /, unary minus, and parentheses,
+// with the usual precedence. A result is only valid if every field it uses is valid in the event, and
+// non-finite results (e.g. division by zero) are invalid too.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(f64),
+    Field(ChannelDataField),
+    Negate(Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    Divide(Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Operator(char),
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, ExpressionError> {
+        let tokens = tokenize(text)?;
+        let mut position = 0;
+        let expression = parse_sum(&tokens, &mut position)?;
+        match tokens.get(position) {
+            None => Ok(expression),
+            Some(token) => Err(ExpressionError::UnexpectedToken(describe(token))),
+        }
+    }
+
+    // Value of the expression given the value of each field in the event, None for fields which were not set
+    pub fn evaluate(&self, value: &impl Fn(&ChannelDataField) -> Option<f64>) -> Option<f64> {
+        let result = match self {
+            Expression::Number(x) => *x,
+            Expression::Field(field) => value(field)?,
+            Expression::Negate(x) => -x.evaluate(value)?,
+            Expression::Add(a, b) => a.evaluate(value)? + b.evaluate(value)?,
+            Expression::Subtract(a, b) => a.evaluate(value)? - b.evaluate(value)?,
+            Expression::Multiply(a, b) => a.evaluate(value)? * b.evaluate(value)?,
+            Expression::Divide(a, b) => a.evaluate(value)? / b.evaluate(value)?,
+        };
+        result.is_finite().then_some(result)
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(x) => format!("number {}", x),
+        Token::Name(x) => format!("name {}", x),
+        Token::Operator(x) => format!("'{}'", x),
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, ExpressionError> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Operator(c));
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&d) = chars.peek() {
+                // Exponents, e.g. 1.5e-3
+                let exponent_sign = (d == '-' || d == '+') && number.ends_with(['e', 'E']);
+                if d.is_ascii_digit() || d == '.' || d == 'e' || d == 'E' || exponent_sign {
+                    number.push(d);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            match number.parse() {
+                Ok(x) => tokens.push(Token::Number(x)),
+                Err(_) => return Err(ExpressionError::UnexpectedToken(number)),
+            }
+        } else if c.is_alphanumeric() || c == '_' {
+            let mut name = String::new();
+            while let Some(&d) = chars.peek() {
+                if d.is_alphanumeric() || d == '_' {
+                    name.push(d);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Name(name));
+        } else {
+            return Err(ExpressionError::UnexpectedToken(format!("'{}'", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+// sum = product (('+' | '-') product)*
+fn parse_sum(tokens: &[Token], position: &mut usize) -> Result<Expression, ExpressionError> {
+    let mut lhs = parse_product(tokens, position)?;
+    while let Some(Token::Operator(op @ ('+' | '-'))) = tokens.get(*position) {
+        *position += 1;
+        let rhs = parse_product(tokens, position)?;
+        lhs = match op {
+            '+' => Expression::Add(Box::new(lhs), Box::new(rhs)),
+            _ => Expression::Subtract(Box::new(lhs), Box::new(rhs)),
+        };
+    }
+    Ok(lhs)
+}
+
+// product = unary (('*' | '/') unary)*
+fn parse_product(tokens: &[Token], position: &mut usize) -> Result<Expression, ExpressionError> {
+    let mut lhs = parse_unary(tokens, position)?;
+    while let Some(Token::Operator(op @ ('*' | '/'))) = tokens.get(*position) {
+        *position += 1;
+        let rhs = parse_unary(tokens, position)?;
+        lhs = match op {
+            '*' => Expression::Multiply(Box::new(lhs), Box::new(rhs)),
+            _ => Expression::Divide(Box::new(lhs), Box::new(rhs)),
+        };
+    }
+    Ok(lhs)
+}
+
+// unary = '-' unary | number | field | '(' sum ')'
+fn parse_unary(tokens: &[Token], position: &mut usize) -> Result<Expression, ExpressionError> {
+    let token = tokens
+        .get(*position)
+        .ok_or(ExpressionError::UnexpectedEnd)?;
+    *position += 1;
+    match token {
+        Token::Operator('-') => Ok(Expression::Negate(Box::new(parse_unary(tokens, position)?))),
+        Token::Number(x) => Ok(Expression::Number(*x)),
+        Token::Name(name) => ChannelDataField::from_name(name)
+            .map(Expression::Field)
+            .ok_or_else(|| ExpressionError::UnknownField(name.clone())),
+        Token::Operator('(') => {
+            let inner = parse_sum(tokens, position)?;
+            match tokens.get(*position) {
+                Some(Token::Operator(')')) => {
+                    *position += 1;
+                    Ok(inner)
+                }
+                Some(token) => Err(ExpressionError::UnexpectedToken(describe(token))),
+                None => Err(ExpressionError::UnexpectedEnd),
+            }
+        }
+        token => Err(ExpressionError::UnexpectedToken(describe(token))),
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt::Display;
+
+use polars::prelude::*;
+
+use super::channel_data::INVALID_VALUE;
+
+// Mismatched values of a column which is in both frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDiff {
+    pub name: String,
+    pub mismatches: usize,
+    pub first_row: usize,
+    pub max_difference: Option<f64>, // largest |left - right| of the rows set in both, None if there were none
+}
+
+// Differences between two built frames, see diff_dataframes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameDiff {
+    pub left_rows: usize,
+    pub right_rows: usize,
+    pub only_left: Vec<String>,
+    pub only_right: Vec<String>,
+    pub type_changes: Vec<(String, DataType, DataType)>, // (column, left type, right type)
+    pub columns: Vec<ColumnDiff>, // columns with mismatches, in the order of left
+}
+
+impl FrameDiff {
+    pub fn is_identical(&self) -> bool {
+        self.left_rows == self.right_rows
+            && self.only_left.is_empty()
+            && self.only_right.is_empty()
+            && self.type_changes.is_empty()
+            && self.columns.is_empty()
+    }
+
+    // Mismatched values over every column
+    pub fn mismatches(&self) -> usize {
+        self.columns.iter().map(|column| column.mismatches).sum()
+    }
+}
+
+impl Display for FrameDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_identical() {
+            return write!(f, "Identical, {} rows", self.left_rows);
+        }
+        if self.left_rows != self.right_rows {
+            writeln!(f, "Rows: {} vs {}", self.left_rows, self.right_rows)?;
+        }
+        if !self.only_left.is_empty() {
+            writeln!(f, "Only in left: {}", self.only_left.join(", "))?;
+        }
+        if !self.only_right.is_empty() {
+            writeln!(f, "Only in right: {}", self.only_right.join(", "))?;
+        }
+        for (name, left, right) in &self.type_changes {
+            writeln!(f, "{}: type {} vs {}", name, left, right)?;
+        }
+        for column in &self.columns {
+            write!(
+                f,
+                "{}: {} mismatched rows, the first at row {}",
+                column.name, column.mismatches, column.first_row
+            )?;
+            if let Some(difference) = column.max_difference {
+                write!(f, ", largest difference {}", difference)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// One cell of a column, see values
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Unset, // null or the -1e6 sentinel
+    Float(f64),
+    Integer(i64),
+    List(Vec<f64>),
+    Other(String),
+}
+
+// Only integers up to 2^53 convert exactly, so integer columns (e.g. Int64 ps timestamps) are kept as such
+fn values(column: &Column) -> PolarsResult<Vec<Value>> {
+    let unset_float = |value: Option<f64>| match value {
+        Some(value) if value != INVALID_VALUE => Value::Float(value),
+        _ => Value::Unset,
+    };
+    let values = match column.dtype() {
+        DataType::List(_) => column
+            .list()?
+            .into_iter()
+            .map(|row| match row {
+                Some(series) => {
+                    let values: Vec<f64> = series
+                        .cast(&DataType::Float64)?
+                        .f64()?
+                        .into_iter()
+                        .map(|value| value.unwrap_or(f64::NAN))
+                        .collect();
+                    Ok(if values == [INVALID_VALUE] {
+                        Value::Unset
+                    } else {
+                        Value::List(values)
+                    })
+                }
+                None => Ok(Value::Unset),
+            })
+            .collect::<PolarsResult<Vec<Value>>>()?,
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64 => column
+            .cast(&DataType::Int64)?
+            .i64()?
+            .into_iter()
+            .map(|value| match value {
+                Some(value) if value != INVALID_VALUE as i64 => Value::Integer(value),
+                _ => Value::Unset,
+            })
+            .collect(),
+        DataType::Float32 | DataType::Float64 | DataType::Boolean => column
+            .cast(&DataType::Float64)?
+            .f64()?
+            .into_iter()
+            .map(unset_float)
+            .collect(),
+        _ => column
+            .cast(&DataType::String)?
+            .str()?
+            .into_iter()
+            .map(|value| value.map_or(Value::Unset, |value| Value::Other(value.to_string())))
+            .collect(),
+    };
+    Ok(values)
+}
+
+// None if the cells match, otherwise Some with the difference when both were numbers. A NaN matches only a
+// NaN, and against a number it is a mismatch without a difference
+fn compare(left: &Value, right: &Value, tolerance: f64) -> Option<Option<f64>> {
+    let same = |a: &f64, b: &f64| a == b || (a.is_nan() && b.is_nan());
+    let numbers = |difference: f64| {
+        if difference.is_nan() {
+            Some(None)
+        } else {
+            (difference > tolerance).then_some(Some(difference))
+        }
+    };
+    match (left, right) {
+        (Value::Float(a), Value::Float(b)) if same(a, b) => None,
+        (Value::Float(a), Value::Float(b)) => numbers((a - b).abs()),
+        (Value::Integer(a), Value::Integer(b)) => numbers((*a as i128 - *b as i128).abs() as f64),
+        (Value::Integer(a), Value::Float(b)) | (Value::Float(b), Value::Integer(a)) => {
+            numbers((*a as f64 - b).abs())
+        }
+        (Value::List(a), Value::List(b)) if a.len() == b.len() => {
+            // f64::max would drop a NaN difference, so it is carried through instead
+            let difference = a
+                .iter()
+                .zip(b)
+                .filter(|(a, b)| !same(a, b))
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, |max: f64, difference| {
+                    if max.is_nan() || difference.is_nan() {
+                        f64::NAN
+                    } else {
+                        max.max(difference)
+                    }
+                });
+            numbers(difference)
+        }
+        (a, b) if a == b => None,
+        _ => Some(None),
+    }
+}
+
+// Compares two frames written by this crate, e.g. a new build against a reference build of the same run.
+// Columns are matched by name and rows by position, up to the shorter frame. Nulls and the -1e6 sentinel
+// are both taken as unset, so a build with BuildParams::keep_invalid_sentinel matches one without.
+// Numbers, including the elements of the list columns, match when they differ by no more than tolerance.
+pub fn diff_dataframes(
+    left: &DataFrame,
+    right: &DataFrame,
+    tolerance: f64,
+) -> PolarsResult<FrameDiff> {
+    let mut diff = FrameDiff {
+        left_rows: left.height(),
+        right_rows: right.height(),
+        ..Default::default()
+    };
+    let left_names: Vec<String> = left
+        .get_column_names()
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    let right_names: Vec<String> = right
+        .get_column_names()
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    diff.only_left = left_names
+        .iter()
+        .filter(|name| !right_names.contains(name))
+        .cloned()
+        .collect();
+    diff.only_right = right_names
+        .iter()
+        .filter(|name| !left_names.contains(name))
+        .cloned()
+        .collect();
+
+    for name in left_names.iter().filter(|name| right_names.contains(name)) {
+        let (left_column, right_column) = (left.column(name)?, right.column(name)?);
+        if left_column.dtype() != right_column.dtype() {
+            diff.type_changes.push((
+                name.clone(),
+                left_column.dtype().clone(),
+                right_column.dtype().clone(),
+            ));
+        }
+        let (left_values, right_values) = (values(left_column)?, values(right_column)?);
+        let mut column: Option<ColumnDiff> = None;
+        for (row, (a, b)) in left_values.iter().zip(&right_values).enumerate() {
+            if let Some(difference) = compare(a, b, tolerance) {
+                let column = column.get_or_insert_with(|| ColumnDiff {
+                    name: name.clone(),
+                    mismatches: 0,
+                    first_row: row,
+                    max_difference: None,
+                });
+                column.mismatches += 1;
+                if let Some(difference) = difference {
+                    column.max_difference = Some(
+                        column
+                            .max_difference
+                            .map_or(difference, |max| max.max(difference)),
+                    );
+                }
+            }
+        }
+        diff.columns.extend(column);
+    }
+    Ok(diff)
+}
@@ -0,0 +1,55 @@
+// Counts of a column in equal width bins, for quick diagnostics without going through polars
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub edges: Vec<f64>, // bins + 1 edges, bin i is [edges[i], edges[i + 1])
+    pub counts: Vec<u64>,
+    pub underflow: u64,
+    pub overflow: u64, // values at or above the upper edge
+}
+
+impl Histogram {
+    // None for no bins or an empty range
+    pub fn new(range: (f64, f64), bins: usize) -> Option<Histogram> {
+        let (min, max) = range;
+        if bins == 0 || !min.is_finite() || !max.is_finite() || min >= max {
+            return None;
+        }
+        let width = (max - min) / bins as f64;
+        let mut edges: Vec<f64> = (0..bins).map(|i| min + width * i as f64).collect();
+        edges.push(max);
+        Some(Histogram {
+            edges,
+            counts: vec![0; bins],
+            underflow: 0,
+            overflow: 0,
+        })
+    }
+
+    pub fn fill(&mut self, value: f64) {
+        let (min, max) = (self.edges[0], self.edges[self.edges.len() - 1]);
+        if value.is_nan() {
+            return;
+        }
+        if value < min {
+            self.underflow += 1;
+        } else if value >= max {
+            self.overflow += 1;
+        } else {
+            let bins = self.counts.len();
+            let bin = ((value - min) / (max - min) * bins as f64) as usize;
+            self.counts[bin.min(bins - 1)] += 1;
+        }
+    }
+
+    // Counts inside the range
+    pub fn entries(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn bin_centers(&self) -> Vec<f64> {
+        self.edges
+            .windows(2)
+            .map(|edges| 0.5 * (edges[0] + edges[1]))
+            .collect()
+    }
+}
@@ -1,5 +1,9 @@
+use super::build_params::BuildParams;
+use super::channel_data::ChannelDataField;
+use super::column_alias::resolve_column_names;
 use super::nuclear_data::MassMap;
 use log::info;
+use polars::prelude::*;
 use serde::{Deserialize, Serialize};
 
 const C: f64 = 2.99792458e8; //speed of light in m/s
@@ -148,3 +152,55 @@ pub fn calculate_weights(params: &KineParameters, nuc_map: &MassMap) -> Option<(
     let w2 = 1.0 - w1;
     Some((w1, w2))
 }
+
+//Fit the weights to a calibration run instead of calculating them from the kinematics
+//Least squares solution of x1 * w1 + x2 * w2 = target with w1 + w2 = 1, where x1/x2 are the positions of
+//events in a single peak whose Xavg should be target. Returns None if there are no events to fit.
+pub fn fit_weights(x1: &[f64], x2: &[f64], target: f64) -> Option<(f64, f64)> {
+    // With w2 = 1 - w1 the residual is w1 * (x1 - x2) + x2 - target, linear in w1
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+    for (x1, x2) in x1.iter().zip(x2.iter()) {
+        let diff = x1 - x2;
+        numerator += diff * (target - x2);
+        denominator += diff * diff;
+    }
+    if denominator == 0.0 {
+        return None;
+    }
+    let w1 = numerator / denominator;
+    Some((w1, 1.0 - w1))
+}
+
+//Same as fit_weights, using the X1 and X2 columns of a dataframe built with params, under their aliases or
+//prefixed names (see resolve_column_names). Events missing either are skipped.
+pub fn fit_weights_from_dataframe(
+    df: &DataFrame,
+    params: &BuildParams,
+    target: f64,
+) -> PolarsResult<Option<(f64, f64)>> {
+    let names = resolve_column_names(params).map_err(|e| polars_err!(ComputeError: "{}", e))?;
+    let name_of = |field: ChannelDataField| {
+        names
+            .get(&field)
+            .cloned()
+            .unwrap_or_else(|| field.as_ref().to_string())
+    };
+    let sentinel = params.sentinel();
+    let x1_column = df
+        .column(&name_of(ChannelDataField::X1))?
+        .cast(&DataType::Float64)?;
+    let x2_column = df
+        .column(&name_of(ChannelDataField::X2))?
+        .cast(&DataType::Float64)?;
+    let (x1, x2): (Vec<f64>, Vec<f64>) = x1_column
+        .f64()?
+        .into_iter()
+        .zip(x2_column.f64()?)
+        .filter_map(|pair| match pair {
+            (Some(x1), Some(x2)) if x1 != sentinel && x2 != sentinel => Some((x1, x2)),
+            _ => None,
+        })
+        .unzip();
+    Ok(fit_weights(&x1, &x2, target))
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use polars::prelude::{ParquetReader, SerReader};
+use polars_parquet::parquet::metadata::KeyValue;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use super::channel_map::{Board, ChannelMap, ChannelType};
+use super::error::EVBError;
+
+// Intrinsic timing resolution of a detector type, written to the output file metadata (not per-row)
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TimeResolutionEntry {
+    pub channel_type: ChannelType,
+    pub resolution: f64, // ns
+}
+
+impl TimeResolutionEntry {
+    pub fn ui(&mut self, ui: &mut egui::Ui, index: usize, on_remove: impl FnOnce()) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt(format!("time_resolution_type_{}", index))
+                .selected_text(self.channel_type.as_ref())
+                .show_ui(ui, |ui| {
+                    for variant in ChannelType::iter() {
+                        ui.selectable_value(&mut self.channel_type, variant, variant.as_ref());
+                    }
+                });
+            ui.add(
+                egui::DragValue::new(&mut self.resolution)
+                    .speed(0.1)
+                    .range(0.0..=f64::MAX)
+                    .suffix(" ns")
+                    .prefix("Resolution: "),
+            );
+
+            if ui.button("❌").clicked() {
+                on_remove();
+            }
+        });
+    }
+}
+
+// Key-value pairs describing the time resolution of each mapped detector type, keyed like the columns
+// e.g. ScintLeftTimeResolution = 0.5 (ns)
+pub fn time_resolution_metadata(
+    entries: &[TimeResolutionEntry],
+    channel_map: &ChannelMap,
+) -> Vec<KeyValue> {
+    entries
+        .iter()
+        .filter(|entry| channel_map.contains_channel_type(entry.channel_type))
+        .map(|entry| {
+            KeyValue::new(
+                format!("{}TimeResolution", entry.channel_type.as_ref()),
+                entry.resolution.to_string(),
+            )
+        })
+        .collect()
+}
+
+// Hash of the channel map configuration, written with each run so that files built with different maps can be
+// told apart. FNV-1a over the serialized boards, so the same map always gives the same hash on any machine.
+pub fn channel_map_hash(boards: &[Board]) -> String {
+    let text = serde_yaml::to_string(boards).unwrap_or_default();
+    let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+// Key-value pairs recording what produced a run's file: RunNumber, ChannelMapHash (see channel_map_hash),
+// EventBuilderVersion, and BuildTimestamp (seconds since the Unix epoch)
+pub fn run_metadata(run_number: i32, channel_map_hash: &str) -> Vec<KeyValue> {
+    let build_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    vec![
+        KeyValue::new("RunNumber".to_string(), run_number.to_string()),
+        KeyValue::new("ChannelMapHash".to_string(), channel_map_hash.to_string()),
+        KeyValue::new(
+            "EventBuilderVersion".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        ),
+        KeyValue::new("BuildTimestamp".to_string(), build_time.to_string()),
+    ]
+}
+
+// Key-value metadata of a built parquet file, for auditing which configuration produced it
+pub fn read_metadata(filepath: &Path) -> Result<BTreeMap<String, String>, EVBError> {
+    let mut reader = ParquetReader::new(File::open(filepath)?);
+    let metadata = reader.get_metadata()?;
+    Ok(metadata
+        .key_value_metadata()
+        .iter()
+        .flatten()
+        .map(|kv| (kv.key.clone(), kv.value.clone().unwrap_or_default()))
+        .collect())
+}
@@ -1,13 +1,29 @@
 pub mod archivist;
+pub mod build_params;
+pub mod calibration_map;
 pub mod channel_data;
 pub mod channel_map;
+pub mod column_alias;
 pub mod compass_data;
 pub mod compass_file;
 pub mod compass_run;
+pub mod csv_export;
 pub mod error;
 pub mod event_builder;
+pub mod event_view;
+pub mod expression;
+pub mod frame_diff;
+pub mod histogram;
 pub mod kinematics;
+pub mod metadata;
 pub mod nuclear_data;
+pub mod pedestal_map;
+pub mod position;
+#[cfg(feature = "root")]
+pub mod root_export;
 pub mod scaler_list;
 pub mod shift_map;
+pub mod stream_writer;
+pub mod time_spread;
 pub mod used_size;
+pub mod validation;
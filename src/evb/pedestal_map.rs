@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use super::compass_data::generate_board_channel_uuid;
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct PedestalMapEntry {
+    pub board_number: u32,
+    pub channel_number: u32,
+    pub pedestal: f64, // subtracted from the uncalibrated energy
+}
+
+impl PedestalMapEntry {
+    pub fn ui(&mut self, ui: &mut egui::Ui, on_remove: impl FnOnce()) {
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.board_number).prefix("Board: "));
+            ui.add(egui::DragValue::new(&mut self.channel_number).prefix("Channel: "));
+            ui.add(
+                egui::DragValue::new(&mut self.pedestal)
+                    .speed(1.0)
+                    .prefix("Pedestal: "),
+            );
+
+            if ui.button("❌").clicked() {
+                on_remove();
+            }
+        });
+    }
+}
+
+// Baselines of the channels, subtracted from each energy before it is calibrated
+#[derive(Debug, Clone, Default)]
+pub struct PedestalMap {
+    map: HashMap<u32, f64>,
+}
+
+impl PedestalMap {
+    pub fn new(entries: Vec<PedestalMapEntry>) -> PedestalMap {
+        let mut map = HashMap::new();
+        for entry in entries {
+            let id = generate_board_channel_uuid(&entry.board_number, &entry.channel_number);
+            map.insert(id, entry.pedestal);
+        }
+        PedestalMap { map }
+    }
+
+    pub fn get_pedestal(&self, id: &u32) -> Option<f64> {
+        self.map.get(id).copied()
+    }
+
+    // Channels without a pedestal are left unchanged. With clamp, energies below the pedestal become zero
+    pub fn subtract(&self, id: &u32, energy: f64, clamp: bool) -> f64 {
+        match self.map.get(id) {
+            Some(pedestal) if clamp => (energy - pedestal).max(0.0),
+            Some(pedestal) => energy - pedestal,
+            None => energy,
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::compass_data::{ps_to_ns, CompassData};
+
+// Geometry of the focal plane detector. The defaults are the values for the SE-SPS focal plane detector.
+// x1 = (front left - front right) * 0.5 / x1_scale, likewise for x2 with the back delay line, so the scales
+// are the effective drift velocities of the delay lines. Theta is atan((x2 - x1) / theta_separation), or
+// atan((x1 - x2) / theta_separation) with reverse_theta for delay lines mounted in the opposite orientation.
+// theta_gain scales the ratio before the arctangent and theta_offset (radians) is added to the angle, to
+// calibrate for a focal plane normal which is not exactly perpendicular to the delay lines. legacy_theta_range
+// moves negative angles up by pi into (pi/2, pi), the range written by earlier versions, for old analysis chains.
+// The X/Z track is projected between the wire planes at z = -plane_separation / 2 (x1) and +plane_separation / 2
+// (x2), independently of the Theta separation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FocalPlaneConfig {
+    pub x1_scale: f64,
+    pub x2_scale: f64,
+    pub theta_separation: f64,
+    pub plane_separation: f64, // mm between the front and back wire planes, for the X/Z track
+    pub reverse_theta: bool,
+    pub theta_gain: f64,
+    pub theta_offset: f64,
+    pub legacy_theta_range: bool,
+}
+
+impl Default for FocalPlaneConfig {
+    fn default() -> Self {
+        FocalPlaneConfig {
+            x1_scale: 2.1,
+            x2_scale: 1.98,
+            theta_separation: 36.0,
+            plane_separation: 42.8625,
+            reverse_theta: false,
+            theta_gain: 1.0,
+            theta_offset: 0.0,
+            legacy_theta_range: false,
+        }
+    }
+}
+
+impl FocalPlaneConfig {
+    pub fn ui(&mut self, ui: &mut egui::Ui, id_salt: &str) {
+        egui::Grid::new(id_salt).show(ui, |ui| {
+            ui.label("X1 Scale");
+            ui.add(egui::widgets::DragValue::new(&mut self.x1_scale).speed(0.01))
+                .on_hover_text(
+                    "Front delay line drift velocity, x1 = (left - right) * 0.5 / scale",
+                );
+            ui.label("X2 Scale");
+            ui.add(egui::widgets::DragValue::new(&mut self.x2_scale).speed(0.01))
+                .on_hover_text("Back delay line drift velocity, x2 = (left - right) * 0.5 / scale");
+            ui.end_row();
+
+            ui.label("Theta Separation");
+            ui.add(egui::widgets::DragValue::new(&mut self.theta_separation).speed(0.1))
+                .on_hover_text("Separation between the front and back delay lines used for Theta");
+            ui.checkbox(&mut self.reverse_theta, "Reverse Theta")
+                .on_hover_text("Use x1 - x2 instead of x2 - x1 for Theta, for delay lines mounted in the opposite orientation");
+            ui.end_row();
+
+            ui.label("Plane Separation");
+            ui.add(
+                egui::widgets::DragValue::new(&mut self.plane_separation)
+                    .speed(0.1)
+                    .suffix(" mm"),
+            )
+            .on_hover_text("Distance between the front (X1) and back (X2) wire planes, used to project the X/Z track.\nIndependent of the Theta separation");
+            ui.end_row();
+
+            ui.label("Theta Gain");
+            ui.add(egui::widgets::DragValue::new(&mut self.theta_gain).speed(0.001))
+                .on_hover_text("Scale of the position difference / separation ratio before the arctangent");
+            ui.label("Theta Offset");
+            ui.add(
+                egui::widgets::DragValue::new(&mut self.theta_offset)
+                    .speed(0.001)
+                    .suffix(" rad"),
+            )
+            .on_hover_text("Added to Theta, for a focal plane normal which is not perpendicular to the delay lines");
+            ui.end_row();
+
+            ui.checkbox(&mut self.legacy_theta_range, "Legacy Theta Range")
+                .on_hover_text("Move negative angles up by pi into (pi/2, pi) before the offset, as written by earlier versions");
+            ui.end_row();
+        });
+    }
+
+    // Position of the track at z (mm from midway between the wire planes), x1 at -plane_separation / 2 and x2
+    // at +plane_separation / 2
+    pub fn x_at(&self, x1: f64, x2: f64, z: f64) -> f64 {
+        (z / self.plane_separation + 0.5) * (x2 - x1) + x1
+    }
+
+    // Angle of the track through the focal plane in radians, in (-pi/2, pi/2) before the offset. It is
+    // continuous in the difference, zero included: a difference of zero gives 0, and tracks leaning the
+    // other way have negative angles. pi / 2 is only approached by a very large difference. With
+    // legacy_theta_range the negative angles are moved up by pi, giving the [0, pi) of earlier versions.
+    pub fn theta(&self, x1: f64, x2: f64) -> f64 {
+        let diff = if self.reverse_theta { x1 - x2 } else { x2 - x1 };
+        let theta = (self.theta_gain * diff / self.theta_separation).atan();
+        if self.legacy_theta_range && theta < 0.0 {
+            theta + std::f64::consts::PI + self.theta_offset
+        } else {
+            theta + self.theta_offset
+        }
+    }
+}
+
+// Geometry used in place of BuildParams::focal_plane for the runs first_run to last_run (inclusive), e.g. after
+// the detector was moved partway through a campaign
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FocalPlaneOverride {
+    pub first_run: i32,
+    pub last_run: i32,
+    pub focal_plane: FocalPlaneConfig,
+}
+
+impl FocalPlaneOverride {
+    pub fn contains(&self, run: i32) -> bool {
+        run >= self.first_run && run <= self.last_run
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, index: usize, on_remove: impl FnOnce()) {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.first_run)
+                    .speed(1.0)
+                    .prefix("First Run: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.last_run)
+                    .speed(1.0)
+                    .prefix("Last Run: "),
+            );
+            if ui.button("❌").clicked() {
+                on_remove();
+            }
+        });
+        self.focal_plane
+            .ui(ui, &format!("FocalPlaneOverrideGrid_{}", index));
+        ui.separator();
+    }
+}
+
+// Delay line hits of a single event, None when that end of the line did not fire
+#[derive(Debug, Clone, Default)]
+pub struct DelayLineData {
+    pub front_left: Option<CompassData>,
+    pub front_right: Option<CompassData>,
+    pub back_left: Option<CompassData>,
+    pub back_right: Option<CompassData>,
+}
+
+// Number of events with both ends of each delay line and how many of them had a negative left - right time
+// difference. Swapped cables make nearly every difference negative (or positive), which a normal position
+// spectrum does not. Shared between the ChannelData of a run, see ChannelData::set_delay_line_check.
+#[derive(Debug, Default)]
+pub struct DelayLineCheck {
+    x1_events: AtomicU64,
+    x1_negative: AtomicU64,
+    x2_events: AtomicU64,
+    x2_negative: AtomicU64,
+}
+
+impl DelayLineCheck {
+    pub fn push(&self, data: &DelayLineData) {
+        let planes = [
+            (
+                &data.front_left,
+                &data.front_right,
+                &self.x1_events,
+                &self.x1_negative,
+            ),
+            (
+                &data.back_left,
+                &data.back_right,
+                &self.x2_events,
+                &self.x2_negative,
+            ),
+        ];
+        for (left, right, events, negative) in planes {
+            if let (Some(left), Some(right)) = (left, right) {
+                events.fetch_add(1, Ordering::Relaxed);
+                if left.timestamp < right.timestamp {
+                    negative.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    // (events, negative differences) of X1 and of X2
+    pub fn counts(&self) -> [(u64, u64); 2] {
+        [
+            (
+                self.x1_events.load(Ordering::Relaxed),
+                self.x1_negative.load(Ordering::Relaxed),
+            ),
+            (
+                self.x2_events.load(Ordering::Relaxed),
+                self.x2_negative.load(Ordering::Relaxed),
+            ),
+        ]
+    }
+
+    // Fraction of the differences of X1 and of X2 which were negative, None for a plane without events
+    pub fn negative_fractions(&self) -> [Option<f64>; 2] {
+        self.counts()
+            .map(|(events, negative)| (events > 0).then(|| negative as f64 / events as f64))
+    }
+}
+
+// Reconstructs the front (X1) and back (X2) focal plane positions from the delay line data of an event.
+// ChannelData::append_event collects the delay line hits of each event, hands them to the reconstructor,
+// and then derives Theta, Xavg, X, and Z from the returned positions. A plane returned as None is left
+// invalid, and the derived fields are only calculated when both planes are valid.
+// Use ChannelData::set_position_reconstructor to replace the default DelayLineTiming.
+pub trait PositionReconstructor: std::fmt::Debug + Send + Sync {
+    fn reconstruct(&self, data: &DelayLineData) -> (Option<f64>, Option<f64>);
+}
+
+// Default reconstruction, position is proportional to the time difference between the two ends of a line
+#[derive(Debug, Clone, Default)]
+pub struct DelayLineTiming {
+    pub config: FocalPlaneConfig,
+}
+
+impl DelayLineTiming {
+    pub fn new(config: FocalPlaneConfig) -> Self {
+        DelayLineTiming { config }
+    }
+}
+
+impl PositionReconstructor for DelayLineTiming {
+    fn reconstruct(&self, data: &DelayLineData) -> (Option<f64>, Option<f64>) {
+        let x1 = match (&data.front_left, &data.front_right) {
+            (Some(left), Some(right)) => {
+                Some(ps_to_ns(left.timestamp - right.timestamp) * 0.5 / self.config.x1_scale)
+            }
+            _ => None,
+        };
+        let x2 = match (&data.back_left, &data.back_right) {
+            (Some(left), Some(right)) => {
+                Some(ps_to_ns(left.timestamp - right.timestamp) * 0.5 / self.config.x2_scale)
+            }
+            _ => None,
+        };
+        (x1, x2)
+    }
+}
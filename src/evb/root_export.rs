@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use log::info;
+use oxyroot::{RootFile, WriterTree};
+use polars::prelude::*;
+
+use super::error::EVBError;
+
+// Name of the TTree holding the events
+pub const TREE_NAME: &str = "events";
+
+// Write a built DataFrame as a flat TTree with one double branch per column, named after the column.
+// Nulls and sentinels (see BuildParams::sentinel) become NaN, integer columns are converted to doubles, and the list columns
+// (X, Z, and any waveforms) are left out since the tree is flat. The file is uncompressed.
+pub fn write_root(df: &DataFrame, filepath: &Path, sentinel: f64) -> Result<(), EVBError> {
+    info!("Writing root file to disk at {}", filepath.display());
+    let mut tree = WriterTree::new(TREE_NAME);
+    for column in df.get_columns() {
+        if matches!(column.dtype(), DataType::List(_)) {
+            continue;
+        }
+        let values: Vec<f64> = column
+            .cast(&DataType::Float64)?
+            .f64()?
+            .into_iter()
+            .map(|value| match value {
+                Some(v) if v != sentinel => v,
+                _ => f64::NAN,
+            })
+            .collect();
+        tree.new_branch(column.name().as_str(), values.into_iter());
+    }
+
+    let mut file = RootFile::create(filepath).map_err(|e| EVBError::Root(e.to_string()))?;
+    tree.write(&mut file)
+        .map_err(|e| EVBError::Root(e.to_string()))?;
+    file.close().map_err(|e| EVBError::Root(e.to_string()))?;
+    Ok(())
+}
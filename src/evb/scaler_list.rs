@@ -1,7 +1,11 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::Path;
 
+use super::compass_data::{
+    decompose_uuid_to_board_channel, generate_board_channel_uuid, ps_to_ns, CompassData,
+};
 use super::compass_file::CompassFile;
 
 const INVALID_SCALER_PATTERN: &str = "InvalidScalerPattern";
@@ -119,3 +123,85 @@ impl ScalerList {
         Ok(())
     }
 }
+
+// Hits seen on each channel before event building, mapped or not, for dead time and normalization.
+// Rates are counts over the time between the first and last hit counted on any channel.
+#[derive(Debug, Clone, Default)]
+pub struct ScalerCounts {
+    counts: BTreeMap<u32, u64>, // keyed by board/channel uuid
+    first_timestamp: Option<i64>,
+    last_timestamp: Option<i64>,
+}
+
+impl ScalerCounts {
+    pub fn count(&mut self, hit: &CompassData) {
+        *self.counts.entry(hit.uuid).or_insert(0) += 1;
+        self.first_timestamp = Some(
+            self.first_timestamp
+                .map_or(hit.timestamp, |first| first.min(hit.timestamp)),
+        );
+        self.last_timestamp = Some(
+            self.last_timestamp
+                .map_or(hit.timestamp, |last| last.max(hit.timestamp)),
+        );
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    pub fn channel_count(&self, board: u32, channel: u32) -> u64 {
+        self.counts
+            .get(&generate_board_channel_uuid(&board, &channel))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // Time covered by the counted hits in ns
+    pub fn duration(&self) -> f64 {
+        match (self.first_timestamp, self.last_timestamp) {
+            (Some(first), Some(last)) => ps_to_ns(last - first),
+            _ => 0.0,
+        }
+    }
+
+    // Rate of a channel in Hz, None until the hits cover some time
+    pub fn channel_rate(&self, board: u32, channel: u32) -> Option<f64> {
+        let duration = self.duration();
+        (duration > 0.0).then(|| self.channel_count(board, channel) as f64 / (duration * 1.0e-9))
+    }
+
+    // (board, channel, count) for every channel which had a hit, ordered by board and channel
+    pub fn channels(&self) -> Vec<(u32, u32, u64)> {
+        let mut channels: Vec<(u32, u32, u64)> = self
+            .counts
+            .iter()
+            .map(|(uuid, count)| {
+                let (board, channel) = decompose_uuid_to_board_channel(uuid);
+                (board, channel, *count)
+            })
+            .collect();
+        channels.sort_unstable();
+        channels
+    }
+
+    pub fn write(&self, filepath: &Path) -> Result<(), std::io::Error> {
+        let file = File::create(filepath)?;
+        let mut writer = BufWriter::new(file);
+
+        let duration = self.duration();
+        writer.write_all("Channel Counts\n".as_bytes())?;
+        writer.write_all(format!("Duration(ns) {}\n", duration).as_bytes())?;
+        writer.write_all("Board Channel Counts Rate(Hz)\n".as_bytes())?;
+        for (board, channel, count) in self.channels() {
+            let rate = if duration > 0.0 {
+                count as f64 / (duration * 1.0e-9)
+            } else {
+                0.0
+            };
+            writer.write_all(format!("{} {} {} {}\n", board, channel, count, rate).as_bytes())?;
+        }
+        writer.write_all(format!("Total {}\n", self.total()).as_bytes())?;
+        Ok(())
+    }
+}
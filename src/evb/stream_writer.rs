@@ -0,0 +1,290 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use polars::io::parquet::write::BatchedWriter;
+use polars::prelude::*;
+use polars_parquet::parquet::metadata::KeyValue;
+
+use super::channel_data::{ChannelData, ChannelDataField};
+use super::compass_data::ns_to_ps;
+
+// Row groups written to a part file before it is closed, see StreamWriter
+pub const CHECKPOINT_ROW_GROUPS: usize = 16;
+
+// Writes the events of a run to a single Parquet file a row group at a time, so that only the events
+// since the last flush are held in memory. The schema (column set, order, and types) is taken once from
+// the ChannelData the writer is created with, i.e. from the channel map and BuildParams, and every row
+// group must match it. Columns are therefore never pruned when streaming.
+//
+// The row groups go to part files next to the output file (run_1.parquet.part0, ...), and a part is closed
+// with its footer every checkpoint_groups row groups. A closed part is a checkpoint: it stays readable if
+// the build crashes, and resume continues after its last event. finish joins the parts into a temporary
+// file and only then renames it over the output file, so the output file is never left half written.
+pub struct StreamWriter {
+    filepath: PathBuf,
+    writer: BatchedWriter<File>,
+    schema: SchemaRef,
+    compression: ParquetCompression,
+    parts: Vec<PathBuf>, // closed parts, then the one being written
+    part_groups: usize,  // row groups in the part being written
+    checkpoint_groups: usize,
+    rows_per_group: usize,
+    rows_written: usize,
+    resumed_after: Option<i64>, // ps, EventTime of the last event kept by resume
+}
+
+// Path of the nth part file of an output file
+fn part_path(filepath: &Path, index: usize) -> PathBuf {
+    let mut name = filepath.as_os_str().to_owned();
+    name.push(format!(".part{}", index));
+    PathBuf::from(name)
+}
+
+// Part files left next to an output file, in order, up to the first missing index
+fn existing_parts(filepath: &Path) -> Vec<PathBuf> {
+    (0..)
+        .map(|index| part_path(filepath, index))
+        .take_while(|path| path.exists())
+        .collect()
+}
+
+fn open_part(
+    path: &Path,
+    schema: &Schema,
+    compression: ParquetCompression,
+) -> PolarsResult<BatchedWriter<File>> {
+    ParquetWriter::new(File::create(path)?)
+        .with_compression(compression)
+        .batched(schema)
+}
+
+fn close(writer: &BatchedWriter<File>, metadata: Option<Vec<KeyValue>>) -> PolarsResult<()> {
+    match writer.get_writer().lock() {
+        Ok(mut file_writer) => file_writer.end(metadata)?,
+        Err(_) => polars_bail!(ComputeError: "parquet writer lock was poisoned"),
+    };
+    Ok(())
+}
+
+impl StreamWriter {
+    pub fn new(
+        filepath: &Path,
+        data: &ChannelData,
+        rows_per_group: usize,
+        compression: ParquetCompression,
+    ) -> PolarsResult<Self> {
+        info!("Streaming dataframe to disk at {}", filepath.display());
+        // Parts of an earlier build of the same file would be taken for checkpoints of this one
+        for part in existing_parts(filepath) {
+            std::fs::remove_file(part)?;
+        }
+        let schema = data.empty_copy().into_dataframe()?.schema().clone();
+        StreamWriter::with_parts(filepath, schema, vec![], rows_per_group, compression)
+    }
+
+    fn with_parts(
+        filepath: &Path,
+        schema: SchemaRef,
+        mut parts: Vec<PathBuf>,
+        rows_per_group: usize,
+        compression: ParquetCompression,
+    ) -> PolarsResult<Self> {
+        let path = part_path(filepath, parts.len());
+        let writer = open_part(&path, &schema, compression)?;
+        parts.push(path);
+        Ok(StreamWriter {
+            filepath: filepath.to_path_buf(),
+            writer,
+            schema,
+            compression,
+            parts,
+            part_groups: 0,
+            checkpoint_groups: CHECKPOINT_ROW_GROUPS,
+            rows_per_group,
+            rows_written: 0,
+            resumed_after: None,
+        })
+    }
+
+    // Whether resume has anything to continue: the output file, or the parts of an interrupted build
+    pub fn can_resume(filepath: &Path) -> bool {
+        filepath.exists() || part_path(filepath, 0).exists()
+    }
+
+    // Continue the build of filepath with the same channel map and BuildParams. The parts closed by an
+    // interrupted build are kept and the one it was writing, which has no footer, is removed. Without parts,
+    // a finished file is copied into the first part, and is left as it is until finish replaces it. Files
+    // with other columns are a SchemaMismatch error and are left untouched.
+    pub fn resume(
+        filepath: &Path,
+        data: &ChannelData,
+        rows_per_group: usize,
+        compression: ParquetCompression,
+    ) -> PolarsResult<Self> {
+        let schema = data.empty_copy().into_dataframe()?.schema().clone();
+        let mut parts = existing_parts(filepath);
+        let closed = parts
+            .iter()
+            .take_while(|part| {
+                File::open(part)
+                    .map(|file| ParquetReader::new(file).get_metadata().is_ok())
+                    .unwrap_or(false)
+            })
+            .count();
+        let unclosed = parts.split_off(closed);
+        // Without a checkpoint, e.g. a crash while a finished file was being copied, the file is continued
+        let copy_output = parts.is_empty() && filepath.exists();
+        let sources = if copy_output {
+            vec![filepath.to_path_buf()]
+        } else {
+            parts.clone()
+        };
+        for source in &sources {
+            let columns = LazyFrame::scan_parquet(source, ScanArgsParquet::default())?
+                .limit(0)
+                .collect()?;
+            if columns.schema() != &schema {
+                polars_bail!(SchemaMismatch: "{} was not written with the current channel map and options", source.display());
+            }
+        }
+        for part in unclosed {
+            warn!("Removing {}, which was not closed", part.display());
+            std::fs::remove_file(part)?;
+        }
+
+        let mut rows = 0;
+        let mut resumed_after = None;
+        for source in &sources {
+            rows += ParquetReader::new(File::open(source)?)
+                .get_metadata()?
+                .num_rows;
+            resumed_after = resumed_after.max(last_event_time(source, data)?);
+        }
+        info!("Resuming {} after its {} events", filepath.display(), rows);
+
+        let mut writer =
+            StreamWriter::with_parts(filepath, schema, parts, rows_per_group, compression)?;
+        if copy_output {
+            writer.copy_from(filepath)?;
+            writer.checkpoint()?;
+        }
+        writer.rows_written = rows;
+        writer.resumed_after = resumed_after;
+        Ok(writer)
+    }
+
+    // Latest EventTime (ps) of the events kept by resume, only the events after it still need to be built
+    pub fn resumed_after(&self) -> Option<i64> {
+        self.resumed_after
+    }
+
+    // Number of row groups after which a part is closed, CHECKPOINT_ROW_GROUPS unless set
+    pub fn set_checkpoint_groups(&mut self, groups: usize) {
+        self.checkpoint_groups = groups.max(1);
+    }
+
+    // Flush the buffered events once there are at least rows_per_group of them
+    pub fn write_if_full(&mut self, data: &mut ChannelData) -> PolarsResult<()> {
+        if data.rows >= self.rows_per_group {
+            self.write(data)?;
+        }
+        Ok(())
+    }
+
+    // Write the buffered events as a row group and leave data empty, keeping its fields and settings
+    pub fn write(&mut self, data: &mut ChannelData) -> PolarsResult<()> {
+        if data.rows == 0 {
+            return Ok(());
+        }
+
+        let rows = data.rows;
+        let buffer = std::mem::replace(data, data.empty_copy());
+        let df = buffer.into_dataframe()?;
+        if df.schema() != &self.schema {
+            polars_bail!(SchemaMismatch: "row group columns do not match the columns of the output file");
+        }
+        self.write_group(&df)?;
+        self.rows_written += rows;
+        Ok(())
+    }
+
+    fn write_group(&mut self, df: &DataFrame) -> PolarsResult<()> {
+        self.writer.write_batch(df)?;
+        self.part_groups += 1;
+        if self.part_groups >= self.checkpoint_groups {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    // Close the part being written and start the next one
+    fn checkpoint(&mut self) -> PolarsResult<()> {
+        close(&self.writer, None)?;
+        let path = part_path(&self.filepath, self.parts.len());
+        self.writer = open_part(&path, &self.schema, self.compression)?;
+        self.parts.push(path);
+        self.part_groups = 0;
+        Ok(())
+    }
+
+    // Copy the events of a finished file into the part being written, rows_per_group at a time
+    fn copy_from(&mut self, source: &Path) -> PolarsResult<()> {
+        let rows = ParquetReader::new(File::open(source)?)
+            .get_metadata()?
+            .num_rows;
+        let step = self.rows_per_group.max(1);
+        for offset in (0..rows).step_by(step) {
+            let group = LazyFrame::scan_parquet(source, ScanArgsParquet::default())?
+                .slice(offset as i64, step as IdxSize)
+                .collect()?;
+            self.writer.write_batch(&group)?;
+        }
+        Ok(())
+    }
+
+    pub fn rows_written(&self) -> usize {
+        self.rows_written
+    }
+
+    // Write the remaining events and replace the output file with the parts, with the run metadata in the
+    // file footer
+    pub fn finish(mut self, data: &mut ChannelData, metadata: &[KeyValue]) -> PolarsResult<()> {
+        self.write(data)?;
+        if self.parts.len() == 1 {
+            // Nothing was checkpointed, the only part becomes the output file
+            close(&self.writer, Some(metadata.to_vec()))?;
+            std::fs::rename(&self.parts[0], &self.filepath)?;
+            return Ok(());
+        }
+
+        close(&self.writer, None)?;
+        let parts = std::mem::take(&mut self.parts);
+        let mut joined = self.filepath.as_os_str().to_owned();
+        joined.push(".tmp");
+        let joined = PathBuf::from(joined);
+        self.writer = open_part(&joined, &self.schema, self.compression)?;
+        for part in &parts {
+            self.copy_from(part)?;
+        }
+        close(&self.writer, Some(metadata.to_vec()))?;
+        std::fs::rename(&joined, &self.filepath)?;
+        for part in parts {
+            std::fs::remove_file(part)?;
+        }
+        Ok(())
+    }
+}
+
+// Latest EventTime (ps) of the events of a finished file
+fn last_event_time(filepath: &Path, data: &ChannelData) -> PolarsResult<Option<i64>> {
+    let event_time = data.column_name(&ChannelDataField::EventTime);
+    let last = LazyFrame::scan_parquet(filepath, ScanArgsParquet::default())?
+        .select([col(event_time.as_str()).max()])
+        .collect()?;
+    let last = last.column(event_time.as_str())?;
+    Ok(match last.dtype() {
+        DataType::Int64 => last.i64()?.get(0),
+        _ => last.cast(&DataType::Float64)?.f64()?.get(0).map(ns_to_ps),
+    })
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use super::channel_data::ChannelDataField;
+
+// FWHM of a normal distribution in units of its standard deviation, 2 sqrt(2 ln 2)
+pub const FWHM_PER_SIGMA: f64 = 2.354_820_045_030_949;
+
+// Running mean and variance of a stream of values, updated one value at a time with Welford's algorithm
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunningStats {
+    count: u64,
+    mean: f64,
+    m2: f64, // sum of the squared differences from the mean
+}
+
+impl RunningStats {
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    // None without values
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    // Sample variance, None with fewer than two values
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 1).then(|| self.m2 / (self.count - 1) as f64)
+    }
+
+    // Approximate FWHM, assuming the values are normally distributed
+    pub fn fwhm(&self) -> Option<f64> {
+        self.variance()
+            .map(|variance| variance.sqrt() * FWHM_PER_SIGMA)
+    }
+}
+
+// Spread of each relative time field over a run, for a quick time resolution without a separate analysis.
+// Shared between the ChannelData of a run, see ChannelData::set_time_spread.
+#[derive(Debug, Default)]
+pub struct TimeSpread {
+    stats: Mutex<BTreeMap<ChannelDataField, RunningStats>>,
+}
+
+impl TimeSpread {
+    // Adds the relative times (ns) of one event, None for the fields which were not set
+    pub fn push(&self, values: impl IntoIterator<Item = (ChannelDataField, Option<f64>)>) {
+        let Ok(mut stats) = self.stats.lock() else {
+            return;
+        };
+        for (field, value) in values {
+            if let Some(value) = value {
+                stats.entry(field).or_default().push(value);
+            }
+        }
+    }
+
+    // Statistics of every field which was set in at least one event, in field order
+    pub fn stats(&self) -> Vec<(ChannelDataField, RunningStats)> {
+        match self.stats.lock() {
+            Ok(stats) => stats
+                .iter()
+                .map(|(field, stats)| (field.clone(), *stats))
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+}
@@ -3,15 +3,16 @@ pub trait UsedSize {
     fn get_used_size(&self) -> usize;
 }
 
+// Share of one entry in the used size of a BTreeMap
+pub fn entry_used_size<K: UsedSize, V: UsedSize>(key: &K, val: &V) -> usize {
+    std::mem::size_of::<K>() + std::mem::size_of::<V>() + key.get_used_size() + val.get_used_size()
+}
+
 impl<K: UsedSize, V: UsedSize> UsedSize for BTreeMap<K, V> {
     fn get_used_size(&self) -> usize {
-        let element_size: usize = std::mem::size_of::<K>() + std::mem::size_of::<V>();
-        let directly_owned = self.len() * element_size;
-        let transitively_owned: usize = self
-            .iter()
-            .map(|(key, val)| key.get_used_size() + val.get_used_size())
-            .sum();
-        directly_owned + transitively_owned
+        self.iter()
+            .map(|(key, val)| entry_used_size(key, val))
+            .sum()
     }
 }
 
@@ -27,8 +28,20 @@ impl UsedSize for i32 {
     }
 }
 
+impl UsedSize for i64 {
+    fn get_used_size(&self) -> usize {
+        std::mem::size_of::<i64>()
+    }
+}
+
 impl UsedSize for f64 {
     fn get_used_size(&self) -> usize {
         std::mem::size_of::<f64>()
     }
 }
+
+impl UsedSize for f32 {
+    fn get_used_size(&self) -> usize {
+        std::mem::size_of::<f32>()
+    }
+}
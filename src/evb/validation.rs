@@ -0,0 +1,225 @@
+use std::fmt::Display;
+
+use strum::IntoEnumIterator;
+
+use super::build_params::BuildParams;
+use super::calibration_map::CalibrationMapEntry;
+use super::channel_data::{ChannelDataField, DetectorGroup};
+use super::channel_map::ChannelMap;
+use super::column_alias::{resolve_column_names, AliasError};
+use super::compass_data::generate_board_channel_uuid;
+use super::expression::ExpressionError;
+use super::shift_map::ShiftMapEntry;
+
+// Something wrong with a configuration, found before any run is built. See validate_configuration
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigProblem {
+    // A calibration for a channel which is not in the channel map, e.g. a typo'd board or channel
+    OrphanCalibration { board: u32, channel: u32 },
+    // A time shift for a channel which is not in the channel map
+    OrphanShift { board: u32, channel: u32 },
+    // More than one calibration or time shift for a channel, the last one is used
+    DuplicateCalibration { board: u32, channel: u32 },
+    DuplicateShift { board: u32, channel: u32 },
+    // The channel map gives no columns besides EventTime
+    EmptyFieldSet,
+    DerivedField(String, ExpressionError),
+    // More than one derived field with this name, which would be written as duplicate columns
+    DuplicateDerivedField(String),
+    Alias(AliasError),
+    // A compression level the Parquet codec does not accept
+    CompressionLevel(i32),
+    // A timestamp unit which is not a positive number of ps
+    TimeUnit(u64),
+    // An energy step which is not a positive number
+    EnergyStep(f64),
+    // A time slice which stops before it starts, in s
+    TimeSlice { start: f64, stop: f64 },
+    // A required field which is not one of the fields
+    UnknownRequiredField(String),
+    // A required field which this channel map does not give, so no event is good
+    UnwrittenRequiredField(String),
+    // An included or excluded field name or pattern which matches none of the fields
+    UnknownFieldPattern(String),
+}
+
+impl ConfigProblem {
+    // Errors stop a build, the rest are warnings which are only reported
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            ConfigProblem::EmptyFieldSet
+                | ConfigProblem::DerivedField(..)
+                | ConfigProblem::DuplicateDerivedField(_)
+                | ConfigProblem::Alias(_)
+                | ConfigProblem::CompressionLevel(_)
+                | ConfigProblem::UnknownRequiredField(_)
+                | ConfigProblem::UnknownFieldPattern(_)
+                | ConfigProblem::TimeUnit(_)
+                | ConfigProblem::EnergyStep(_)
+                | ConfigProblem::TimeSlice { .. }
+        )
+    }
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigProblem::OrphanCalibration { board, channel } => write!(
+                f,
+                "Calibration for board {} channel {}, which is not in the channel map",
+                board, channel
+            ),
+            ConfigProblem::OrphanShift { board, channel } => write!(
+                f,
+                "Time shift for board {} channel {}, which is not in the channel map",
+                board, channel
+            ),
+            ConfigProblem::DuplicateCalibration { board, channel } => write!(
+                f,
+                "More than one calibration for board {} channel {}, the last is used",
+                board, channel
+            ),
+            ConfigProblem::DuplicateShift { board, channel } => write!(
+                f,
+                "More than one time shift for board {} channel {}, the last is used",
+                board, channel
+            ),
+            ConfigProblem::EmptyFieldSet => {
+                write!(f, "Channel map gives no columns besides EventTime")
+            }
+            ConfigProblem::DerivedField(name, x) => {
+                write!(f, "Derived field {}: {}", name, x)
+            }
+            ConfigProblem::DuplicateDerivedField(x) => {
+                write!(f, "More than one derived field is named {}", x)
+            }
+            ConfigProblem::Alias(x) => write!(f, "Column aliases: {}", x),
+            ConfigProblem::CompressionLevel(x) => {
+                write!(f, "Compression level {} is not accepted by the codec", x)
+            }
+            ConfigProblem::TimeUnit(x) => {
+                write!(f, "Timestamp unit {} ps is not a positive number of ps", x)
+            }
+            ConfigProblem::EnergyStep(x) => {
+                write!(f, "Energy step {} is not a positive number", x)
+            }
+            ConfigProblem::TimeSlice { start, stop } => write!(
+                f,
+                "Time slice stops at {} s, which is not after its start at {} s",
+                stop, start
+            ),
+            ConfigProblem::UnknownRequiredField(x) => write!(f, "Unknown required field {}", x),
+            ConfigProblem::UnwrittenRequiredField(x) => write!(
+                f,
+                "Required field {} is not written with this channel map, so no event is good",
+                x
+            ),
+            ConfigProblem::UnknownFieldPattern(x) => {
+                write!(f, "Included or excluded field {} matches no field", x)
+            }
+        }
+    }
+}
+
+// Check that the calibration and shift maps only refer to mapped channels and that the build would write
+// something, without reading any data. Every problem is returned, see ConfigProblem::is_error.
+pub fn validate_configuration(
+    channel_map: &ChannelMap,
+    calibrations: &[CalibrationMapEntry],
+    shifts: &[ShiftMapEntry],
+    params: &BuildParams,
+) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+
+    let mut calibrated: Vec<(u32, u32)> = vec![];
+    for entry in calibrations {
+        let (board, channel) = (entry.board_number, entry.channel_number);
+        if !channel_map.is_mapped(&generate_board_channel_uuid(&board, &channel)) {
+            problems.push(ConfigProblem::OrphanCalibration { board, channel });
+        }
+        if calibrated.contains(&(board, channel)) {
+            problems.push(ConfigProblem::DuplicateCalibration { board, channel });
+        }
+        calibrated.push((board, channel));
+    }
+
+    let mut shifted: Vec<(u32, u32)> = vec![];
+    for entry in shifts {
+        let (board, channel) = (entry.board_number, entry.channel_number);
+        if !channel_map.is_mapped(&generate_board_channel_uuid(&board, &channel)) {
+            problems.push(ConfigProblem::OrphanShift { board, channel });
+        }
+        if shifted.contains(&(board, channel)) {
+            problems.push(ConfigProblem::DuplicateShift { board, channel });
+        }
+        shifted.push((board, channel));
+    }
+
+    // EventTime and the other event fields (HitPattern, CebraMultiplicity, ...) say nothing without a detector
+    let fields = ChannelDataField::get_filtered_field_vec(channel_map, params);
+    if fields
+        .iter()
+        .all(|field| field.detector_group() == DetectorGroup::Event)
+    {
+        problems.push(ConfigProblem::EmptyFieldSet);
+    }
+
+    // Required fields are checked on the stored values, which include the ones left out of the output
+    let mapped = ChannelDataField::get_mapped_field_vec(channel_map, params);
+    for name in params.required_fields.iter() {
+        match ChannelDataField::from_name(name) {
+            Some(field) if !mapped.contains(&field) => {
+                problems.push(ConfigProblem::UnwrittenRequiredField(name.clone()))
+            }
+            Some(_) => {}
+            None => problems.push(ConfigProblem::UnknownRequiredField(name.clone())),
+        }
+    }
+
+    for pattern in params
+        .included_fields
+        .iter()
+        .chain(params.excluded_fields.iter())
+    {
+        let pattern = pattern.trim();
+        if !pattern.is_empty() && !ChannelDataField::iter().any(|f| f.matches_pattern(pattern)) {
+            problems.push(ConfigProblem::UnknownFieldPattern(pattern.to_string()));
+        }
+    }
+
+    let mut derived_names: Vec<&str> = vec![];
+    for entry in params.derived_fields.iter() {
+        if let Err(e) = entry.parse() {
+            problems.push(ConfigProblem::DerivedField(entry.name.clone(), e));
+        }
+        let name = entry.name.trim();
+        if !name.is_empty() && derived_names.contains(&name) {
+            problems.push(ConfigProblem::DuplicateDerivedField(name.to_string()));
+        }
+        derived_names.push(name);
+    }
+    if let Err(e) = resolve_column_names(params) {
+        problems.push(ConfigProblem::Alias(e));
+    }
+    if let (Some(level), Err(_)) = (params.compression_level, params.parquet_compression()) {
+        problems.push(ConfigProblem::CompressionLevel(level));
+    }
+    if params.time_unit == Some(0) {
+        problems.push(ConfigProblem::TimeUnit(0));
+    }
+
+    if let Some(step) = params.energy_step {
+        if !(step.is_finite() && step > 0.0) {
+            problems.push(ConfigProblem::EnergyStep(step));
+        }
+    }
+
+    if let (Some(start), Some(stop)) = (params.slice_start, params.slice_stop) {
+        if stop <= start {
+            problems.push(ConfigProblem::TimeSlice { start, stop });
+        }
+    }
+
+    problems
+}
@@ -1,4 +1,4 @@
-use log::{error, info};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 
 use std::fs::File;
@@ -13,24 +13,39 @@ use eframe::App;
 
 use super::ws::{Workspace, WorkspaceError};
 use crate::evb::archivist::Archivist;
-use crate::evb::channel_map::Board;
+use crate::evb::build_params::BuildParams;
+use crate::evb::calibration_map::CalibrationMapEntry;
+use crate::evb::channel_map::{Board, ChannelMap, ChannelType};
 use crate::evb::compass_run::{process_runs, ProcessParams};
 use crate::evb::error::EVBError;
 use crate::evb::kinematics::KineParameters;
+use crate::evb::metadata::TimeResolutionEntry;
 use crate::evb::nuclear_data::MassMap;
+use crate::evb::pedestal_map::PedestalMapEntry;
 use crate::evb::scaler_list::ScalerEntryUI;
 use crate::evb::shift_map::ShiftMapEntry;
+use crate::evb::validation::validate_configuration;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct EvbAppParams {
     pub workspace: Option<Workspace>,
     pub kinematics: KineParameters,
     pub coincidence_window: f64,
+    #[serde(default)]
+    pub max_events: Option<u64>,
     pub run_min: i32,
     pub run_max: i32,
     pub channel_map_entries: Vec<Board>,
     pub shift_map_entries: Vec<ShiftMapEntry>,
     pub scaler_list_entries: Vec<ScalerEntryUI>,
+    #[serde(default)]
+    pub pedestal_map_entries: Vec<PedestalMapEntry>,
+    #[serde(default)]
+    pub calibration_map_entries: Vec<CalibrationMapEntry>,
+    #[serde(default)]
+    pub time_resolution_entries: Vec<TimeResolutionEntry>,
+    #[serde(default)]
+    pub build: BuildParams,
 }
 
 impl Default for EvbAppParams {
@@ -39,11 +54,16 @@ impl Default for EvbAppParams {
             workspace: None,
             kinematics: KineParameters::default(),
             coincidence_window: 3.0e3,
+            max_events: None,
             run_min: 0,
             run_max: 0,
             channel_map_entries: Vec::new(),
             shift_map_entries: Vec::new(),
             scaler_list_entries: Vec::new(),
+            pedestal_map_entries: Vec::new(),
+            calibration_map_entries: Vec::new(),
+            time_resolution_entries: Vec::new(),
+            build: BuildParams::default(),
         }
     }
 }
@@ -55,7 +75,10 @@ enum ActiveTab {
     Kinematics,
     ChannelMap,
     ShiftMap,
+    PedestalMap,
+    CalibrationMap,
     ScalerList,
+    TimeResolution,
 }
 
 impl Default for ActiveTab {
@@ -129,9 +152,15 @@ impl EVBApp {
                 channel_map: self.parameters.channel_map_entries.clone(),
                 scaler_list: self.parameters.scaler_list_entries.clone(),
                 shift_map: self.parameters.shift_map_entries.clone(),
+                pedestal_map: self.parameters.pedestal_map_entries.clone(),
+                calibration_map: self.parameters.calibration_map_entries.clone(),
+                time_resolutions: self.parameters.time_resolution_entries.clone(),
+                build_params: self.parameters.build.clone(),
                 coincidence_window: self.parameters.coincidence_window,
+                max_events: self.parameters.max_events,
                 run_min: self.parameters.run_min,
                 run_max: self.parameters.run_max + 1, //Make it [run_min, run_max]
+                progress_callback: None,
             };
 
             match self.progress.lock() {
@@ -148,6 +177,33 @@ impl EVBApp {
         Ok(())
     }
 
+    // Dry run of the checks process_runs makes before building, with every problem written to the log
+    fn validate(&self) {
+        let channel_map = match ChannelMap::new(&self.parameters.channel_map_entries) {
+            Ok(map) => map,
+            Err(e) => {
+                error!("{}", e);
+                return;
+            }
+        };
+        let problems = validate_configuration(
+            &channel_map,
+            &self.parameters.calibration_map_entries,
+            &self.parameters.shift_map_entries,
+            &self.parameters.build,
+        );
+        if problems.is_empty() {
+            info!("Configuration has no problems");
+        }
+        for problem in problems {
+            if problem.is_error() {
+                error!("{}", problem);
+            } else {
+                warn!("{}", problem);
+            }
+        }
+    }
+
     fn check_and_shutdown_processing_thread(&mut self) {
         if self.thread_handle.is_some() && self.thread_handle.as_ref().unwrap().is_finished() {
             match self.thread_handle.take().unwrap().join() {
@@ -247,6 +303,22 @@ impl EVBApp {
             );
             ui.end_row();
 
+            let mut limit_events = self.parameters.max_events.is_some();
+            ui.checkbox(&mut limit_events, "Max Events")
+                .on_hover_text("Stop each run after building this many events");
+            match (limit_events, &mut self.parameters.max_events) {
+                (true, Some(max_events)) => {
+                    ui.add(
+                        egui::widgets::DragValue::new(max_events)
+                            .speed(1000)
+                            .range(1..=u64::MAX),
+                    );
+                }
+                (true, None) => self.parameters.max_events = Some(100_000),
+                (false, _) => self.parameters.max_events = None,
+            }
+            ui.end_row();
+
             ui.label("Run Min");
             ui.add(egui::widgets::DragValue::new(&mut self.parameters.run_min).speed(1));
             ui.end_row();
@@ -254,6 +326,16 @@ impl EVBApp {
             ui.label("Run Max");
             ui.add(egui::widgets::DragValue::new(&mut self.parameters.run_max).speed(1));
         });
+
+        ui.separator();
+
+        ui.label(
+            RichText::new("Output Options")
+                .color(Color32::LIGHT_BLUE)
+                .size(18.0),
+        );
+
+        self.parameters.build.ui(ui);
     }
 
     fn kinematics_ui(&mut self, ui: &mut egui::Ui) {
@@ -371,6 +453,76 @@ impl EVBApp {
         }
     }
 
+    fn pedestal_map_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new("Pedestal Map")
+                    .color(Color32::LIGHT_BLUE)
+                    .size(18.0),
+            )
+            .on_hover_text("Pedestal subtracted from the energy of each channel before the calibration, E - pedestal.\nChannels without an entry are left unchanged. See Clamp Pedestals in the build options.");
+
+            if ui.button("+").clicked() {
+                self.parameters.pedestal_map_entries.push(PedestalMapEntry {
+                    board_number: 0,
+                    channel_number: 0,
+                    pedestal: 0.0,
+                });
+            }
+        });
+
+        let mut remove_indices = vec![];
+        for (index, entry) in self.parameters.pedestal_map_entries.iter_mut().enumerate() {
+            entry.ui(ui, || {
+                remove_indices.push(index);
+            });
+        }
+
+        for &index in remove_indices.iter().rev() {
+            self.parameters.pedestal_map_entries.remove(index);
+        }
+    }
+
+    fn calibration_map_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new("Energy Calibration Map")
+                    .color(Color32::LIGHT_BLUE)
+                    .size(18.0),
+            )
+            .on_hover_text("Quadratic calibration applied to the energy of each channel, E' = a * E^2 + b * E + c.\nChannels without an entry are left uncalibrated.");
+
+            if ui.button("+").clicked() {
+                // Start from the identity calibration
+                self.parameters
+                    .calibration_map_entries
+                    .push(CalibrationMapEntry {
+                        board_number: 0,
+                        channel_number: 0,
+                        a: 0.0,
+                        b: 1.0,
+                        c: 0.0,
+                    });
+            }
+        });
+
+        let mut remove_indices = vec![];
+        for (index, entry) in self
+            .parameters
+            .calibration_map_entries
+            .iter_mut()
+            .enumerate()
+        {
+            entry.ui(ui, || {
+                remove_indices.push(index);
+            });
+        }
+
+        for &index in remove_indices.iter().rev() {
+            self.parameters.calibration_map_entries.remove(index);
+        }
+    }
+
     fn scaler_list_ui(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.label(
@@ -404,6 +556,44 @@ impl EVBApp {
         // });
     }
 
+    fn time_resolution_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new("Time Resolution")
+                    .color(Color32::LIGHT_BLUE)
+                    .size(18.0),
+            )
+            .on_hover_text(
+                "Intrinsic timing resolution per detector type, stored in the output file metadata",
+            );
+
+            if ui.button("+").clicked() {
+                self.parameters
+                    .time_resolution_entries
+                    .push(TimeResolutionEntry {
+                        channel_type: ChannelType::None,
+                        resolution: 0.0,
+                    });
+            }
+        });
+
+        let mut remove_indices = vec![];
+        for (index, entry) in self
+            .parameters
+            .time_resolution_entries
+            .iter_mut()
+            .enumerate()
+        {
+            entry.ui(ui, index, || {
+                remove_indices.push(index);
+            });
+        }
+
+        for &index in remove_indices.iter().rev() {
+            self.parameters.time_resolution_entries.remove(index);
+        }
+    }
+
     fn ui_tabs(&mut self, ui: &mut egui::Ui) {
         egui::TopBottomPanel::top("cebra_sps_top_panel").show_inside(ui, |ui| {
             ui.horizontal(|ui| {
@@ -446,6 +636,24 @@ impl EVBApp {
                 {
                     self.active_tab = ActiveTab::ShiftMap;
                 }
+                if ui
+                    .selectable_label(
+                        matches!(self.active_tab, ActiveTab::PedestalMap),
+                        "Pedestal Map",
+                    )
+                    .clicked()
+                {
+                    self.active_tab = ActiveTab::PedestalMap;
+                }
+                if ui
+                    .selectable_label(
+                        matches!(self.active_tab, ActiveTab::CalibrationMap),
+                        "Calibration Map",
+                    )
+                    .clicked()
+                {
+                    self.active_tab = ActiveTab::CalibrationMap;
+                }
                 if ui
                     .selectable_label(
                         matches!(self.active_tab, ActiveTab::ScalerList),
@@ -455,6 +663,15 @@ impl EVBApp {
                 {
                     self.active_tab = ActiveTab::ScalerList;
                 }
+                if ui
+                    .selectable_label(
+                        matches!(self.active_tab, ActiveTab::TimeResolution),
+                        "Time Resolution",
+                    )
+                    .clicked()
+                {
+                    self.active_tab = ActiveTab::TimeResolution;
+                }
             });
         });
 
@@ -464,7 +681,10 @@ impl EVBApp {
             ActiveTab::Kinematics => self.kinematics_ui(ui),
             ActiveTab::ChannelMap => self.channel_map_ui(ui),
             ActiveTab::ShiftMap => self.shift_map_ui(ui),
+            ActiveTab::PedestalMap => self.pedestal_map_ui(ui),
+            ActiveTab::CalibrationMap => self.calibration_map_ui(ui),
             ActiveTab::ScalerList => self.scaler_list_ui(ui),
+            ActiveTab::TimeResolution => self.time_resolution_ui(ui),
         });
     }
 
@@ -505,6 +725,14 @@ impl EVBApp {
             } else {
                 self.check_and_shutdown_processing_thread();
             }
+
+            if ui
+                .button("Validate")
+                .on_hover_text("Check the channel, shift, and calibration maps and the build options without\nbuilding any runs. Problems are written to the log")
+                .clicked()
+            {
+                self.validate();
+            }
         });
     }
 
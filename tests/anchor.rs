@@ -0,0 +1,58 @@
+mod common;
+
+use common::{build_data, hit, ANODE_FRONT, SCINT_LEFT, SCINT_RIGHT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelData, ChannelDataField};
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+use eventbuilder::evb::compass_data::ns_to_ps;
+
+fn build(params: &BuildParams) -> ChannelData {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let events = vec![
+        vec![
+            hit(SCINT_LEFT, ns_to_ps(100.0), 1000.0, None, None),
+            hit(SCINT_RIGHT, ns_to_ps(110.0), 1000.0, None, None),
+            hit(ANODE_FRONT, ns_to_ps(150.0), 1000.0, None, None),
+        ],
+        // The anchor did not fire
+        vec![
+            hit(SCINT_LEFT, ns_to_ps(1000.0), 1000.0, None, None),
+            hit(ANODE_FRONT, ns_to_ps(1040.0), 1000.0, None, None),
+        ],
+    ];
+    build_data(&map, params, events)
+}
+
+fn event_times(data: &ChannelData) -> Vec<i64> {
+    data.time_fields[&ChannelDataField::EventTime].clone()
+}
+
+#[test]
+fn default_anchor_is_scint_left() {
+    let data = build(&BuildParams::default());
+    let rel_times = &data.fields[&ChannelDataField::AnodeFrontRelTime];
+    assert!((rel_times[0] - 50.0).abs() < 1.0e-9);
+    assert!((rel_times[1] - 40.0).abs() < 1.0e-9);
+    // The earliest hit
+    assert_eq!(event_times(&data), vec![ns_to_ps(100.0), ns_to_ps(1000.0)]);
+}
+
+#[test]
+fn scint_right_anchors_the_relative_and_event_times() {
+    let params = BuildParams {
+        anchor: Some(ChannelType::ScintRight),
+        // Only used with the default anchor
+        scint_right_fallback: true,
+        ..Default::default()
+    };
+    let data = build(&params);
+    let rel_times = &data.fields[&ChannelDataField::AnodeFrontRelTime];
+    assert!((rel_times[0] - 40.0).abs() < 1.0e-9);
+    assert!(data
+        .get_value(&ChannelDataField::AnodeFrontRelTime)
+        .is_none());
+    assert!(!data
+        .fields
+        .contains_key(&ChannelDataField::RelTimeReference));
+    assert_eq!(event_times(&data), vec![ns_to_ps(110.0), ns_to_ps(1000.0)]);
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{build, hit, values, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelDataField, DetectorQuantity};
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType, AUX_CHANNEL_TYPES};
+
+// Unused channels of Board::sps as a pulser and the beam monitor
+const PULSER: u32 = 2;
+const MONITOR: u32 = 3;
+
+#[test]
+fn aux_and_monitor_hits_are_written_as_is() {
+    let mut board = Board::sps(0);
+    board.channels[PULSER as usize] = ChannelType::Aux3;
+    board.channels[MONITOR as usize] = ChannelType::Monitor;
+    let map = ChannelMap::new(&[board]).unwrap();
+    let df = build(
+        &map,
+        &BuildParams::default(),
+        vec![vec![
+            hit(SCINT_LEFT, 0, 1000.0, None, None),
+            hit(PULSER, 10, 2500.0, None, None),
+            hit(MONITOR, 20, 300.0, None, None),
+        ]],
+    );
+
+    assert_eq!(values(&df, "Aux3Energy"), vec![Some(2500.0)]);
+    assert_eq!(values(&df, "MonitorEnergy"), vec![Some(300.0)]);
+    // Only the mapped auxiliary channel has columns
+    assert!(!df
+        .get_column_names()
+        .iter()
+        .any(|name| name.as_str() == "Aux0Energy"));
+}
+
+#[test]
+fn every_aux_channel_has_its_fields() {
+    assert_eq!(AUX_CHANNEL_TYPES.len(), 4);
+    for aux in AUX_CHANNEL_TYPES {
+        for quantity in [
+            DetectorQuantity::Energy,
+            DetectorQuantity::Short,
+            DetectorQuantity::Time,
+        ] {
+            assert!(ChannelDataField::of_detector(aux, quantity).is_some());
+        }
+    }
+}
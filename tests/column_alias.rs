@@ -0,0 +1,168 @@
+mod common;
+
+use common::{build, build_data, hit, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::ChannelData;
+use eventbuilder::evb::channel_data::ChannelDataField;
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+use eventbuilder::evb::column_alias::{
+    resolve_aliases, resolve_column_names, AliasError, ColumnAlias, ColumnPrefix,
+};
+
+fn alias(field: &str, alias: &str) -> ColumnAlias {
+    ColumnAlias {
+        field: field.to_string(),
+        alias: alias.to_string(),
+    }
+}
+
+#[test]
+fn aliased_columns_are_renamed() {
+    let params = BuildParams {
+        column_aliases: vec![alias("X1", "fp_x1"), alias("AnodeFrontEnergy", "anode_e")],
+        ..Default::default()
+    };
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let df = build(
+        &map,
+        &params,
+        vec![vec![hit(ANODE_FRONT, 0, 1000.0, None, None)]],
+    );
+
+    assert!(df.column("fp_x1").is_ok());
+    assert!(df.column("X1").is_err());
+    assert_eq!(
+        df.column("anode_e").unwrap().f64().unwrap().get(0),
+        Some(1000.0)
+    );
+    // Unmapped fields keep their name
+    assert!(df.column("X2").is_ok());
+
+    let names: Vec<String> = ChannelData::output_columns(&map, &params)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    let written: Vec<String> = df
+        .get_column_names()
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    assert_eq!(names, written);
+}
+
+#[test]
+fn colliding_aliases_are_rejected() {
+    assert_eq!(
+        resolve_aliases(&[alias("X1", "X2")], &[]),
+        Err(AliasError::Collision("X2".to_string()))
+    );
+    assert_eq!(
+        resolve_aliases(&[alias("X1", "x"), alias("X2", "x")], &[]),
+        Err(AliasError::Collision("x".to_string()))
+    );
+    assert_eq!(
+        resolve_aliases(&[alias("NotAField", "x")], &[]),
+        Err(AliasError::UnknownField("NotAField".to_string()))
+    );
+    // Swapping two names is fine
+    assert!(resolve_aliases(&[alias("X1", "X2"), alias("X2", "X1")], &[]).is_ok());
+}
+
+#[test]
+fn prefixes_rename_the_written_columns() {
+    let params = BuildParams {
+        column_prefix: "sps_".to_string(),
+        column_prefixes: vec![ColumnPrefix {
+            channel_type: ChannelType::ScintLeft,
+            prefix: "ss_".to_string(),
+        }],
+        column_aliases: vec![alias("X2", "back_x")],
+        ..Default::default()
+    };
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let data = build_data(
+        &map,
+        &params,
+        vec![vec![hit(SCINT_LEFT, 0, 1000.0, None, None)]],
+    );
+    // The fields themselves are not renamed
+    assert_eq!(
+        data.get_value(&ChannelDataField::ScintLeftEnergy),
+        Some(1000.0)
+    );
+    let df = data.into_dataframe().unwrap();
+
+    assert_eq!(
+        df.column("ss_ScintLeftEnergy")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .get(0),
+        Some(1000.0)
+    );
+    assert!(df.column("sps_X1").is_ok());
+    assert!(df.column("sps_AnodeFrontEnergy").is_ok());
+    // Aliases are used as given
+    assert!(df.column("back_x").is_ok());
+    assert!(df
+        .get_column_names()
+        .iter()
+        .all(|name| name.starts_with("sps_")
+            || name.starts_with("ss_")
+            || name.as_str() == "back_x"));
+
+    let names: Vec<String> = ChannelData::output_columns(&map, &params)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    let written: Vec<String> = df
+        .get_column_names()
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    assert_eq!(names, written);
+}
+
+#[test]
+fn colliding_prefixes_are_rejected() {
+    // An alias taking the prefixed name of another field
+    let params = BuildParams {
+        column_prefix: "sps_".to_string(),
+        column_aliases: vec![alias("X2", "sps_X1")],
+        ..Default::default()
+    };
+    assert_eq!(
+        resolve_column_names(&params),
+        Err(AliasError::Collision("sps_X1".to_string()))
+    );
+
+    let prefix = |channel_type, prefix: &str| ColumnPrefix {
+        channel_type,
+        prefix: prefix.to_string(),
+    };
+    let params = BuildParams {
+        column_prefixes: vec![
+            prefix(ChannelType::ScintLeft, "a_"),
+            prefix(ChannelType::ScintLeft, "b_"),
+        ],
+        ..Default::default()
+    };
+    assert_eq!(
+        resolve_column_names(&params),
+        Err(AliasError::DuplicatePrefix("ScintLeft".to_string()))
+    );
+
+    // ScintLeft prefixed with "Scint" meets nothing, but an alias to its name does
+    let params = BuildParams {
+        column_prefixes: vec![prefix(ChannelType::ScintLeft, "Scint")],
+        column_aliases: vec![alias("X1", "ScintScintLeftEnergy")],
+        ..Default::default()
+    };
+    assert_eq!(
+        resolve_column_names(&params),
+        Err(AliasError::Collision("ScintScintLeftEnergy".to_string()))
+    );
+    assert!(resolve_column_names(&BuildParams::default())
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,85 @@
+// Hits and builds shared by the integration tests, not every test file uses all of them
+#![allow(dead_code)]
+
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::ChannelData;
+use eventbuilder::evb::channel_map::ChannelMap;
+use eventbuilder::evb::compass_data::{
+    decompose_uuid_to_board_channel, generate_board_channel_uuid, CompassData,
+};
+use polars::prelude::*;
+
+// Channels of Board::sps
+pub const SCINT_RIGHT: u32 = 0;
+pub const SCINT_LEFT: u32 = 1;
+pub const CATHODE: u32 = 7;
+pub const DELAY_FRONT_LEFT: u32 = 8;
+pub const DELAY_FRONT_RIGHT: u32 = 9;
+pub const DELAY_BACK_LEFT: u32 = 10;
+pub const DELAY_BACK_RIGHT: u32 = 11;
+pub const ANODE_FRONT: u32 = 13;
+pub const ANODE_BACK: u32 = 15;
+
+// Channel of Board::cebra
+pub const CEBRA0: u32 = 0;
+
+// A hit of a channel on board 0, timestamp in ps
+pub fn hit(
+    channel: u32,
+    timestamp: i64,
+    energy: f64,
+    short: Option<f64>,
+    waveform: Option<Vec<i16>>,
+) -> CompassData {
+    CompassData {
+        uuid: generate_board_channel_uuid(&0, &channel),
+        energy,
+        energy_short: short,
+        timestamp,
+        waveform,
+    }
+}
+
+// The same hit from the same channel of another board
+pub fn on_board(board: u32, hit: CompassData) -> CompassData {
+    let (_, channel) = decompose_uuid_to_board_channel(&hit.uuid);
+    CompassData {
+        uuid: generate_board_channel_uuid(&board, &channel),
+        ..hit
+    }
+}
+
+// Each event appended in turn, without Xavg weights
+pub fn build_data(
+    map: &ChannelMap,
+    params: &BuildParams,
+    events: Vec<Vec<CompassData>>,
+) -> ChannelData {
+    let mut data = ChannelData::new(map, params);
+    for event in events {
+        data.append_event(event, map, None);
+    }
+    data
+}
+
+// The dataframe written for the events
+pub fn build(map: &ChannelMap, params: &BuildParams, events: Vec<Vec<CompassData>>) -> DataFrame {
+    let mut data = build_data(map, params, events);
+    // Done by process_runs
+    if params.prune_empty_columns {
+        data.prune_empty_columns();
+    }
+    data.into_dataframe().unwrap()
+}
+
+// A column as floats, whatever type it was written with
+pub fn values(df: &DataFrame, name: &str) -> Vec<Option<f64>> {
+    df.column(name)
+        .unwrap()
+        .cast(&DataType::Float64)
+        .unwrap()
+        .f64()
+        .unwrap()
+        .into_iter()
+        .collect()
+}
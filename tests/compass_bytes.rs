@@ -0,0 +1,157 @@
+use std::io::Write;
+
+use eventbuilder::evb::compass_data::{generate_board_channel_uuid, CompassData};
+use eventbuilder::evb::compass_file::{
+    parse_compass_bytes, parse_compass_header, parse_compass_hit, CompassFile, CompassVersion,
+};
+use eventbuilder::evb::error::EVBError;
+
+// A file with every optional part: energy, calibrated energy, short gate energy, and two waveform samples
+fn full_file() -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend_from_slice(&0x000Fu16.to_le_bytes());
+    for (channel, timestamp, energy) in [(3u16, 1_000u64, 700u16), (4, 2_500, 800)] {
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // board
+        bytes.extend_from_slice(&channel.to_le_bytes());
+        bytes.extend_from_slice(&timestamp.to_le_bytes());
+        bytes.extend_from_slice(&energy.to_le_bytes());
+        bytes.extend_from_slice(&12345u64.to_le_bytes()); // calibrated energy
+        bytes.extend_from_slice(&(energy / 2).to_le_bytes()); // short gate energy
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // flags
+        bytes.push(1); // waveform code
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        for sample in [10u16, 20] {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+#[test]
+fn hits_are_decoded_from_bytes() {
+    let bytes = full_file();
+    let (rest, data_type) = parse_compass_header(&bytes).unwrap();
+    assert_eq!(rest.len(), bytes.len() - 2);
+
+    let both = [
+        generate_board_channel_uuid(&1, &3),
+        generate_board_channel_uuid(&1, &4),
+    ];
+    let (rest, raw) = parse_compass_hit(rest, &data_type, &both).unwrap();
+    assert_eq!((raw.board, raw.channel, raw.timestamp), (1, 3, 1_000));
+    assert_eq!(raw.energy, 700);
+    assert_eq!(raw.energy_calibrated, 12345);
+    assert_eq!(raw.energy_short, Some(350));
+    assert_eq!(raw.waveform, Some(vec![10, 20]));
+    let (rest, raw) = parse_compass_hit(rest, &data_type, &[]).unwrap();
+    assert_eq!((raw.channel, raw.timestamp), (4, 2_500));
+    assert_eq!(raw.waveform, None);
+    assert!(rest.is_empty());
+
+    // Only the samples of channel 4 are kept
+    let hits = parse_compass_bytes(&bytes, CompassVersion::V2, 1, &None, &both[1..]).unwrap();
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].uuid, generate_board_channel_uuid(&1, &3));
+    assert_eq!(hits[0].waveform, None);
+    assert_eq!(hits[1].waveform, Some(vec![10, 20]));
+    assert_eq!(hits[1].timestamp, 2_500);
+    // Energies get the usual random fraction of a channel
+    assert_eq!(hits[1].energy.floor(), 800.0);
+    assert_eq!(hits[1].energy_short.map(f64::floor), Some(400.0));
+}
+
+#[test]
+fn bytes_and_file_give_the_same_hits() {
+    let bytes = full_file();
+    let path = std::env::temp_dir().join(format!("compass_bytes_{}.bin", std::process::id()));
+    std::fs::File::create(&path)
+        .unwrap()
+        .write_all(&bytes)
+        .unwrap();
+
+    let mut compass_file = CompassFile::new(&path, &None).unwrap();
+    let uuids = vec![generate_board_channel_uuid(&1, &3)];
+    compass_file.set_waveform_uuids(uuids.clone());
+    let from_file: Vec<CompassData> = compass_file.map(|hit| hit.unwrap()).collect();
+    let from_bytes = parse_compass_bytes(&bytes, CompassVersion::V2, 1, &None, &uuids).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(from_file.len(), from_bytes.len());
+    for (file_hit, bytes_hit) in from_file.iter().zip(from_bytes.iter()) {
+        assert_eq!(file_hit.uuid, bytes_hit.uuid);
+        assert_eq!(file_hit.timestamp, bytes_hit.timestamp);
+        assert_eq!(file_hit.waveform, bytes_hit.waveform);
+    }
+}
+
+#[test]
+fn truncated_hits_are_rejected() {
+    let bytes = full_file();
+    // Cut inside the samples of the last hit, then inside its fixed part
+    for cut in [1, 10] {
+        assert!(matches!(
+            parse_compass_bytes(
+                &bytes[..bytes.len() - cut],
+                CompassVersion::V2,
+                1,
+                &None,
+                &[]
+            ),
+            Err(EVBError::Parser)
+        ));
+    }
+    assert!(matches!(
+        parse_compass_header(&bytes[..1]),
+        Err(EVBError::Parser)
+    ));
+    // A header alone is a file without hits
+    assert!(
+        parse_compass_bytes(&bytes[..2], CompassVersion::V2, 1, &None, &[])
+            .unwrap()
+            .is_empty()
+    );
+}
+
+// CoMPASS 1 files have no header and no waveform code, here with PSD firmware and saved waveforms
+#[test]
+fn version_1_files_are_decoded() {
+    let mut bytes = vec![];
+    // Out of time order, as written
+    for (channel, timestamp) in [(3u16, 2_000u64), (4, 1_500)] {
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // board
+        bytes.extend_from_slice(&channel.to_le_bytes());
+        bytes.extend_from_slice(&timestamp.to_le_bytes());
+        bytes.extend_from_slice(&700u16.to_le_bytes()); // energy
+        bytes.extend_from_slice(&300u16.to_le_bytes()); // short gate energy
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // flags
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        for sample in [10u16, 20] {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+    let version = CompassVersion::V1 {
+        short_gate: true,
+        waveforms: true,
+    };
+
+    // Timestamps in ns, scaled to ps
+    let uuids = [
+        generate_board_channel_uuid(&1, &3),
+        generate_board_channel_uuid(&1, &4),
+    ];
+    let hits = parse_compass_bytes(&bytes, version, 1000, &None, &uuids).unwrap();
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].uuid, generate_board_channel_uuid(&1, &3));
+    assert_eq!(hits[0].timestamp, 2_000_000);
+    assert_eq!(hits[1].timestamp, 1_500_000);
+    assert_eq!(hits[1].energy.floor(), 700.0);
+    assert_eq!(hits[1].energy_short.map(f64::floor), Some(300.0));
+    assert_eq!(hits[1].waveform, Some(vec![10, 20]));
+
+    // Without the short gate the hits are two bytes shorter, so these bytes do not divide into them
+    let no_short = CompassVersion::V1 {
+        short_gate: false,
+        waveforms: true,
+    };
+    assert!(parse_compass_bytes(&bytes, no_short, 1, &None, &[]).is_err());
+}
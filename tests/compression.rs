@@ -0,0 +1,63 @@
+use std::fs::File;
+
+use eventbuilder::evb::build_params::{BuildParams, ParquetCodec};
+use polars::prelude::*;
+use polars_parquet::parquet::read::read_metadata;
+use strum::IntoEnumIterator;
+
+#[test]
+fn every_codec_round_trips() {
+    let path = std::env::temp_dir().join(format!("compression_{}.parquet", std::process::id()));
+    let df = df!("X1" => [1.0, 2.0, 3.0]).unwrap();
+    let expected = [
+        (ParquetCodec::Zstd, "Zstd"),
+        (ParquetCodec::Snappy, "Snappy"),
+        (ParquetCodec::Gzip, "Gzip"),
+        (ParquetCodec::Lz4, "Lz4Raw"),
+        (ParquetCodec::Uncompressed, "Uncompressed"),
+    ];
+    assert_eq!(expected.len(), ParquetCodec::iter().count());
+
+    for (codec, written) in expected {
+        let params = BuildParams {
+            parquet_codec: codec,
+            compression_level: codec.levels().map(|levels| *levels.end()),
+            ..Default::default()
+        };
+        ParquetWriter::new(File::create(&path).unwrap())
+            .with_compression(params.parquet_compression().unwrap())
+            .finish(&mut df.clone())
+            .unwrap();
+
+        let metadata = read_metadata(&mut File::open(&path).unwrap()).unwrap();
+        let column = metadata.row_groups[0]
+            .columns_under_root_iter("X1")
+            .unwrap()
+            .next()
+            .unwrap();
+        assert_eq!(format!("{:?}", column.compression()), written);
+        let read = ParquetReader::new(File::open(&path).unwrap())
+            .finish()
+            .unwrap();
+        assert!(read.equals(&df), "{}", codec.as_ref());
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn out_of_range_levels_are_rejected() {
+    let params = BuildParams {
+        parquet_codec: ParquetCodec::Gzip,
+        compression_level: Some(10),
+        ..Default::default()
+    };
+    assert!(params.parquet_compression().is_err());
+
+    // Codecs without levels ignore them
+    let params = BuildParams {
+        parquet_codec: ParquetCodec::Snappy,
+        compression_level: Some(10),
+        ..Default::default()
+    };
+    assert!(params.parquet_compression().is_ok());
+}
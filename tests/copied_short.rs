@@ -0,0 +1,52 @@
+mod common;
+
+use common::{hit, values, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use polars::prelude::*;
+
+fn build(params: &BuildParams) -> DataFrame {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let events = vec![
+        vec![
+            hit(SCINT_LEFT, 0, 1000.0, Some(1000.0), None),
+            hit(ANODE_FRONT, 10, 800.0, Some(400.0), None),
+        ],
+        vec![hit(SCINT_LEFT, 1_000_000, 900.0, Some(900.5), None)],
+    ];
+    common::build(&map, params, events)
+}
+
+#[test]
+fn copied_short_energies_are_kept_by_default() {
+    let df = build(&BuildParams::default());
+    assert_eq!(
+        values(&df, "ScintLeftShort"),
+        vec![Some(1000.0), Some(900.5)]
+    );
+}
+
+#[test]
+fn copied_short_energies_are_suppressed_within_the_tolerance() {
+    let params = BuildParams {
+        copied_short_tolerance: Some(0.0),
+        ..Default::default()
+    };
+    let df = build(&params);
+    assert_eq!(values(&df, "ScintLeftShort"), vec![None, Some(900.5)]);
+    assert_eq!(values(&df, "AnodeFrontShort"), vec![Some(400.0), None]);
+    // The energies are untouched
+    assert_eq!(
+        values(&df, "ScintLeftEnergy"),
+        vec![Some(1000.0), Some(900.0)]
+    );
+
+    let params = BuildParams {
+        copied_short_tolerance: Some(1.0),
+        prune_empty_columns: true,
+        ..Default::default()
+    };
+    let df = build(&params);
+    assert!(df.column("ScintLeftShort").is_err());
+    assert_eq!(values(&df, "AnodeFrontShort"), vec![Some(400.0), None]);
+}
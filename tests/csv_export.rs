@@ -0,0 +1,41 @@
+mod common;
+
+use common::{hit, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::csv_export::write_csv;
+
+#[test]
+fn integer_sentinels_are_written_as_empty_cells() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let path = std::env::temp_dir().join(format!("csv_sentinel_{}.csv", std::process::id()));
+    for sentinel_value in [None, Some(-9999.0)] {
+        let params = BuildParams {
+            keep_invalid_sentinel: true,
+            sentinel_value,
+            integer_timestamps: true,
+            provenance_columns: true,
+            ..Default::default()
+        };
+        let df = common::build(
+            &map,
+            &params,
+            vec![vec![hit(SCINT_LEFT, 5_000, 1000.0, None, None)]],
+        );
+        write_csv(&df, &path, 10, params.sentinel()).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let mut lines = text.lines();
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        let row: Vec<&str> = lines.next().unwrap().split(',').collect();
+        let cell = |name: &str| row[header.iter().position(|column| *column == name).unwrap()];
+        // Integer timestamps and provenance columns of a detector which did not fire
+        assert_eq!(cell("AnodeFrontTime"), "");
+        assert_eq!(cell("AnodeFrontBoard"), "");
+        assert_eq!(cell("ScintLeftTime"), "5000");
+        assert_eq!(cell("ScintLeftBoard"), "0");
+        let sentinel = (params.sentinel() as i64).to_string();
+        assert!(!row.contains(&sentinel.as_str()));
+    }
+    std::fs::remove_file(&path).unwrap();
+}
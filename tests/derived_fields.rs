@@ -0,0 +1,94 @@
+mod common;
+
+use common::{build, build_data, hit, ANODE_FRONT, CATHODE};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelData, INVALID_VALUE};
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::expression::{DerivedFieldEntry, Expression, ExpressionError};
+
+fn derived(name: &str, expression: &str) -> DerivedFieldEntry {
+    DerivedFieldEntry {
+        name: name.to_string(),
+        expression: expression.to_string(),
+    }
+}
+
+#[test]
+fn derived_fields_follow_the_built_in_columns() {
+    let params = BuildParams {
+        derived_fields: vec![
+            derived("AnodeRatio", "AnodeFrontEnergy / CathodeEnergy"),
+            derived("Scaled", "-(AnodeFrontEnergy + 2) * 0.5"),
+        ],
+        ..Default::default()
+    };
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let events = vec![
+        vec![
+            hit(ANODE_FRONT, 0, 1000.0, None, None),
+            hit(CATHODE, 10, 500.0, None, None),
+        ],
+        // No cathode, so the ratio is invalid
+        vec![hit(ANODE_FRONT, 1_000_000, 200.0, None, None)],
+    ];
+    let data = build_data(&map, &params, events);
+
+    assert_eq!(data.derived_fields[0].1, vec![2.0, INVALID_VALUE]);
+    assert_eq!(data.derived_fields[1].1, vec![-501.0, -101.0]);
+
+    let df = data.into_dataframe().unwrap();
+    let names = df.get_column_names();
+    assert_eq!(names[names.len() - 2].as_str(), "AnodeRatio");
+    assert_eq!(names[names.len() - 1].as_str(), "Scaled");
+    let ratio = df.column("AnodeRatio").unwrap().f64().unwrap();
+    assert_eq!(ratio.get(0), Some(2.0));
+    assert_eq!(ratio.get(1), None);
+}
+
+#[test]
+fn bad_expressions_are_rejected() {
+    assert_eq!(
+        Expression::parse("AnodeFrontEnergy +"),
+        Err(ExpressionError::UnexpectedEnd)
+    );
+    assert_eq!(
+        Expression::parse("NotAField * 2"),
+        Err(ExpressionError::UnknownField("NotAField".to_string()))
+    );
+    assert_eq!(
+        derived("X1", "X2").parse(),
+        Err(ExpressionError::ReservedName("X1".to_string()))
+    );
+}
+
+#[test]
+fn output_columns_match_the_built_dataframe() {
+    let params = BuildParams {
+        multiplicity: true,
+        derived_fields: vec![derived("AnodeRatio", "AnodeFrontEnergy / CathodeEnergy")],
+        ..Default::default()
+    };
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let columns = ChannelData::output_columns(&map, &params);
+
+    let df = build(
+        &map,
+        &params,
+        vec![vec![hit(ANODE_FRONT, 0, 1000.0, None, None)]],
+    );
+    let names: Vec<&str> = df
+        .get_column_names()
+        .iter()
+        .map(|name| name.as_str())
+        .collect();
+    let expected: Vec<&str> = columns.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, expected);
+
+    let is_derived = |name: &str| columns.iter().find(|(n, _)| n == name).unwrap().1;
+    assert!(!is_derived("AnodeFrontEnergy"));
+    assert!(!is_derived("CathodeTime"));
+    assert!(is_derived("X1"));
+    assert!(is_derived("AnodeFrontRelTime"));
+    assert!(is_derived("AnodeFrontMultiplicity"));
+    assert!(is_derived("AnodeRatio"));
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::{build_data, hit, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::{BuildParams, EnergyLimit, EnergyLimitPolicy};
+use eventbuilder::evb::channel_data::{ChannelData, ChannelDataField, INVALID_VALUE};
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+
+fn build(policy: EnergyLimitPolicy) -> ChannelData {
+    let params = BuildParams {
+        multiplicity: true,
+        energy_limits: vec![EnergyLimit {
+            channel_type: ChannelType::AnodeFront,
+            min: 10.0,
+            max: 4000.0,
+            policy,
+        }],
+        ..Default::default()
+    };
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    // An overflow next to a good hit, then an overflow alone
+    let events = vec![
+        vec![
+            hit(SCINT_LEFT, 0, 60000.0, None, None),
+            hit(ANODE_FRONT, 10, 1000.0, None, None),
+            hit(ANODE_FRONT, 20, 65535.0, None, None),
+        ],
+        vec![hit(ANODE_FRONT, 1_000_000, 65535.0, None, None)],
+    ];
+    build_data(&map, &params, events)
+}
+
+#[test]
+fn dropped_hits_are_not_counted() {
+    let data = build(EnergyLimitPolicy::Drop);
+    assert_eq!(
+        data.fields[&ChannelDataField::AnodeFrontEnergy],
+        vec![1000.0, INVALID_VALUE]
+    );
+    assert_eq!(
+        data.fields[&ChannelDataField::AnodeFrontMultiplicity],
+        vec![1.0, 0.0]
+    );
+    // Types without a limit are untouched
+    assert_eq!(data.fields[&ChannelDataField::ScintLeftEnergy][0], 60000.0);
+}
+
+#[test]
+fn clamped_hits_are_kept() {
+    let data = build(EnergyLimitPolicy::Clamp);
+    assert_eq!(
+        data.fields[&ChannelDataField::AnodeFrontEnergy],
+        vec![4000.0, 4000.0]
+    );
+    assert_eq!(
+        data.fields[&ChannelDataField::AnodeFrontMultiplicity],
+        vec![2.0, 1.0]
+    );
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::{hit, values, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::validation::{validate_configuration, ConfigProblem};
+use polars::prelude::*;
+
+fn build(params: &BuildParams) -> DataFrame {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let events = vec![
+        vec![
+            hit(SCINT_LEFT, 1_234, 1000.3, Some(400.8), None),
+            hit(ANODE_FRONT, 5_678, 800.7, Some(399.2), None),
+        ],
+        vec![hit(SCINT_LEFT, 1_000_000, 900.2, Some(0.0), None)],
+    ];
+    common::build(&map, params, events)
+}
+
+#[test]
+fn energies_are_rounded_to_the_step() {
+    let df = build(&BuildParams::default());
+    assert_eq!(
+        values(&df, "ScintLeftEnergy"),
+        vec![Some(1000.3), Some(900.2)]
+    );
+
+    let params = BuildParams {
+        energy_step: Some(0.5),
+        ..Default::default()
+    };
+    let df = build(&params);
+    assert_eq!(
+        values(&df, "ScintLeftEnergy"),
+        vec![Some(1000.5), Some(900.0)]
+    );
+    assert_eq!(values(&df, "ScintLeftShort"), vec![Some(401.0), Some(0.0)]);
+    assert_eq!(values(&df, "AnodeFrontEnergy"), vec![Some(800.5), None]);
+    assert_eq!(values(&df, "AnodeFrontShort"), vec![Some(399.0), None]);
+}
+
+#[test]
+fn times_are_not_rounded() {
+    let params = BuildParams {
+        energy_step: Some(10.0),
+        ..Default::default()
+    };
+    let df = build(&params);
+    assert_eq!(
+        values(&df, "ScintLeftTime"),
+        values(&build(&BuildParams::default()), "ScintLeftTime")
+    );
+    assert_eq!(values(&df, "ScintLeftTime")[0], Some(1.234));
+}
+
+#[test]
+fn a_non_positive_step_is_an_error() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        energy_step: Some(0.0),
+        ..Default::default()
+    };
+    let problems = validate_configuration(&map, &[], &[], &params);
+    assert!(problems
+        .iter()
+        .any(|problem| matches!(problem, ConfigProblem::EnergyStep(_)) && problem.is_error()));
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::{ANODE_FRONT, SCINT_LEFT, SCINT_RIGHT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelData, ChannelDataField};
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+use eventbuilder::evb::compass_data::{ns_to_ps, ps_to_ns, CompassData};
+use eventbuilder::evb::event_builder::{build_events, EventBuilder, HitBatcher};
+
+fn hit(time_ns: f64) -> CompassData {
+    common::hit(SCINT_RIGHT, ns_to_ps(time_ns), 1000.0, Some(500.0), None)
+}
+
+// process_run flushes the builder once the files are exhausted, or the last event of a run is lost
+#[test]
+fn last_event_is_only_returned_by_flush() {
+    let mut evb = EventBuilder::new(&100.0);
+    let mut events = vec![];
+    for hit in [hit(0.0), hit(50.0), hit(1000.0), hit(1020.0)] {
+        evb.push_hit(&hit);
+        if evb.is_event_ready() {
+            events.push(evb.get_ready_event());
+        }
+    }
+    assert_eq!(events.len(), 1);
+
+    let last = evb.flush().unwrap();
+    let times: Vec<f64> = last.iter().map(|hit| ps_to_ns(hit.timestamp)).collect();
+    assert_eq!(times, vec![1000.0, 1020.0]);
+    assert!(evb.flush().is_none());
+}
+
+fn times(groups: &[Vec<CompassData>]) -> Vec<Vec<f64>> {
+    groups
+        .iter()
+        .map(|group| group.iter().map(|hit| ps_to_ns(hit.timestamp)).collect())
+        .collect()
+}
+
+// A batch is full after two hits, but the hit 50 ns later still belongs to the event of the second one
+#[test]
+fn batches_only_end_between_events() {
+    let hits = vec![hit(0.0), hit(1000.0), hit(1050.0), hit(5000.0), hit(9000.0)];
+    let mut batcher = HitBatcher::new(100.0, 2);
+    let mut batches: Vec<Vec<CompassData>> = hits
+        .clone()
+        .into_iter()
+        .filter_map(|hit| batcher.push(hit))
+        .collect();
+    batches.push(batcher.flush());
+    assert!(batcher.flush().is_empty());
+    assert_eq!(
+        times(&batches),
+        vec![vec![0.0, 1000.0, 1050.0], vec![5000.0, 9000.0]]
+    );
+
+    // Built batch by batch, the events are those of the whole stream
+    let events: Vec<Vec<CompassData>> = batches
+        .into_iter()
+        .flat_map(|batch| build_events(batch, 100.0))
+        .collect();
+    let whole: Vec<Vec<CompassData>> = build_events(hits, 100.0).collect();
+    assert_eq!(events.len(), 4);
+    assert_eq!(times(&events), times(&whole));
+}
+
+// ScintLeft 20 ns before the ScintRight anchor is left in an event of its own
+#[test]
+fn anchor_hits_open_the_events() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let anchor = map.uuids_of(ChannelType::ScintRight);
+    let at =
+        |channel: u32, time_ns: f64| common::hit(channel, ns_to_ps(time_ns), 1000.0, None, None);
+    let hits = vec![
+        at(SCINT_LEFT, 0.0),
+        at(SCINT_RIGHT, 20.0),
+        at(ANODE_FRONT, 60.0),
+        at(SCINT_LEFT, 90.0),
+        at(SCINT_RIGHT, 130.0),
+        at(ANODE_FRONT, 150.0),
+        at(ANODE_FRONT, 500.0),
+    ];
+
+    let whole: Vec<Vec<CompassData>> = build_events(hits.clone(), 100.0).collect();
+    assert_eq!(
+        times(&whole),
+        vec![vec![0.0, 20.0, 60.0, 90.0], vec![130.0, 150.0], vec![500.0]]
+    );
+    let anchored: Vec<Vec<CompassData>> = build_events(hits.clone(), 100.0)
+        .with_anchor(anchor.clone())
+        .collect();
+    assert_eq!(
+        times(&anchored),
+        vec![
+            vec![0.0],
+            vec![20.0, 60.0, 90.0],
+            vec![130.0, 150.0],
+            vec![500.0]
+        ]
+    );
+
+    // The parallel build anchors its events the same way
+    let params = BuildParams {
+        anchor: Some(ChannelType::ScintRight),
+        ..Default::default()
+    };
+    let mut parallel = ChannelData::new(&map, &params);
+    parallel.append_events_parallel(hits, 100.0, &map, None);
+    let sequential = common::build_data(&map, &params, anchored);
+    assert_eq!(
+        parallel.time_fields[&ChannelDataField::EventTime],
+        sequential.time_fields[&ChannelDataField::EventTime]
+    );
+    assert_eq!(
+        sequential.time_fields[&ChannelDataField::EventTime],
+        vec![0, ns_to_ps(20.0), ns_to_ps(130.0), ns_to_ps(500.0)]
+    );
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::{build_data, hit, ANODE_FRONT, DELAY_FRONT_LEFT, DELAY_FRONT_RIGHT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelData, ChannelDataField};
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_data::{ns_to_ps, CompassData};
+use eventbuilder::evb::event_builder::build_events;
+use eventbuilder::evb::event_view::Event;
+use eventbuilder::evb::expression::DerivedFieldEntry;
+
+// Energies of 1000 + channel, to tell the detectors apart
+fn hits() -> Vec<CompassData> {
+    vec![
+        hit(SCINT_LEFT, ns_to_ps(0.0), 1001.0, None, None),
+        hit(ANODE_FRONT, ns_to_ps(1.0), 1013.0, None, None),
+        hit(DELAY_FRONT_LEFT, ns_to_ps(10.0), 1008.0, None, None),
+        hit(DELAY_FRONT_RIGHT, ns_to_ps(30.0), 1009.0, None, None),
+        hit(ANODE_FRONT, ns_to_ps(10_000.0), 1013.0, None, None),
+        hit(SCINT_LEFT, ns_to_ps(20_000.0), 1001.0, None, None),
+    ]
+}
+
+fn params() -> BuildParams {
+    BuildParams {
+        derived_fields: vec![DerivedFieldEntry {
+            name: "TwiceX1".to_string(),
+            expression: "2 * X1".to_string(),
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn events_hold_the_values_which_were_set() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let data = ChannelData::new(&map, &params());
+    let events: Vec<Event> = data
+        .events(build_events(hits(), 3000.0), &map, None)
+        .collect();
+    assert_eq!(events.len(), 3);
+
+    let first = &events[0];
+    assert_eq!(first.get(&ChannelDataField::ScintLeftEnergy), Some(1001.0));
+    assert_eq!(first.get(&ChannelDataField::AnodeFrontRelTime), Some(1.0));
+    let x1 = first.get(&ChannelDataField::X1).unwrap();
+    assert!(x1 < 0.0);
+    assert_eq!(first.get_derived("TwiceX1"), Some(2.0 * x1));
+    assert_eq!(first.get(&ChannelDataField::X2), None);
+
+    // Without ScintLeft there is no relative time
+    assert_eq!(
+        events[1].get(&ChannelDataField::AnodeFrontEnergy),
+        Some(1013.0)
+    );
+    assert_eq!(events[1].get(&ChannelDataField::AnodeFrontRelTime), None);
+    assert_eq!(events[1].get(&ChannelDataField::EventTime), Some(10_000.0));
+    assert_eq!(events[1].get_derived("TwiceX1"), None);
+
+    // Nothing is kept once an event is handed out
+    assert_eq!(data.rows, 0);
+}
+
+#[test]
+fn events_match_the_columns() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let events: Vec<Event> = ChannelData::new(&map, &params())
+        .events(build_events(hits(), 3000.0), &map, None)
+        .collect();
+    let mut data = build_data(&map, &params(), build_events(hits(), 3000.0).collect());
+
+    for (row, event) in events.iter().enumerate().rev() {
+        let mut popped = data.pop_event().unwrap();
+        assert_eq!(data.rows, row);
+        assert_eq!(&popped, event);
+        popped.values.clear();
+        assert_ne!(&popped, event);
+    }
+    assert!(data.pop_event().is_none());
+}
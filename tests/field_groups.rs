@@ -0,0 +1,125 @@
+use eventbuilder::evb::channel_data::{
+    ChannelDataField, DetectorGroup, DetectorQuantity, QuantityKind,
+};
+use eventbuilder::evb::channel_map::{ChannelType, CEBRA_CHANNEL_TYPES};
+use strum::IntoEnumIterator;
+
+#[test]
+fn fields_report_their_detector_and_quantity() {
+    let cases = [
+        (
+            ChannelDataField::Cebra3Energy,
+            DetectorGroup::Detector(ChannelType::Cebra3),
+            QuantityKind::Energy,
+        ),
+        (
+            ChannelDataField::Cebra3RelTimeRight,
+            DetectorGroup::Detector(ChannelType::Cebra3),
+            QuantityKind::RelTime,
+        ),
+        (
+            ChannelDataField::AnodeFrontRelTime,
+            DetectorGroup::Detector(ChannelType::AnodeFront),
+            QuantityKind::RelTime,
+        ),
+        (
+            ChannelDataField::ScintLeftEnergyRaw,
+            DetectorGroup::Detector(ChannelType::ScintLeft),
+            QuantityKind::EnergyRaw,
+        ),
+        (
+            ChannelDataField::Aux2Channel,
+            DetectorGroup::Detector(ChannelType::Aux2),
+            QuantityKind::Channel,
+        ),
+        (
+            ChannelDataField::CATRINA1PSD,
+            DetectorGroup::Detector(ChannelType::CATRINA1),
+            QuantityKind::Psd,
+        ),
+        (
+            ChannelDataField::Xavg,
+            DetectorGroup::FocalPlane,
+            QuantityKind::Position,
+        ),
+        (
+            ChannelDataField::EventTime,
+            DetectorGroup::Event,
+            QuantityKind::Time,
+        ),
+        (
+            ChannelDataField::HitPattern,
+            DetectorGroup::Event,
+            QuantityKind::Flags,
+        ),
+    ];
+    for (field, group, kind) in cases {
+        assert_eq!(field.detector_group(), group, "{}", field.as_ref());
+        assert_eq!(field.quantity_kind(), kind, "{}", field.as_ref());
+    }
+}
+
+#[test]
+fn detector_fields_are_named_after_their_detector() {
+    for field in ChannelDataField::iter() {
+        if let DetectorGroup::Detector(channel_type) = field.detector_group() {
+            let name = field.as_ref();
+            let rest = name
+                .strip_prefix(channel_type.as_ref())
+                .unwrap_or_else(|| panic!("{} is not a {} field", name, channel_type.as_ref()));
+            // Cebra1 must not claim the Cebra10-15 fields
+            assert!(!rest.starts_with(char::is_numeric), "{}", name);
+        }
+    }
+}
+
+#[test]
+fn detector_fields_round_trip() {
+    for field in ChannelDataField::iter() {
+        if let Some((channel_type, quantity)) = field.detector_field() {
+            assert_eq!(
+                ChannelDataField::of_detector(channel_type, quantity),
+                Some(field.clone())
+            );
+            assert_eq!(
+                format!("{}{:?}", channel_type.as_ref(), quantity),
+                field.as_ref()
+            );
+        }
+    }
+    assert_eq!(ChannelDataField::Xavg.detector_field(), None);
+    assert_eq!(
+        ChannelDataField::of_detector(ChannelType::PIPS100, DetectorQuantity::Short),
+        None
+    );
+}
+
+#[test]
+fn every_cebra_detector_has_the_same_fields() {
+    for cebra in CEBRA_CHANNEL_TYPES {
+        for quantity in DetectorQuantity::iter() {
+            assert!(
+                ChannelDataField::of_detector(cebra, quantity).is_some(),
+                "{} {:?}",
+                cebra.as_ref(),
+                quantity
+            );
+        }
+    }
+}
+
+#[test]
+fn raw_energy_fields_pair_with_their_energy() {
+    for field in ChannelDataField::iter() {
+        if let Some(raw) = field.raw_energy_field() {
+            assert_eq!(raw.calibrated_energy_field(), Some(field.clone()));
+            assert_eq!(raw.as_ref(), format!("{}Raw", field.as_ref()));
+        }
+    }
+    assert_eq!(
+        ChannelDataField::Cebra12Energy.raw_energy_field(),
+        Some(ChannelDataField::Cebra12EnergyRaw)
+    );
+    assert_eq!(ChannelDataField::AnodeSumEnergy.raw_energy_field(), None);
+    assert_eq!(ChannelDataField::ScintLeftTime.raw_energy_field(), None);
+}
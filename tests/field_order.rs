@@ -0,0 +1,442 @@
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelData, ChannelDataField};
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+use strum::IntoEnumIterator;
+
+// Every field in output order, starting with the columns of the first release. Columns may only ever be added
+// to the end of this list.
+const OUTPUT_ORDER: [&str; 349] = [
+    "AnodeFrontEnergy",
+    "AnodeFrontShort",
+    "AnodeFrontTime",
+    "AnodeBackEnergy",
+    "AnodeBackShort",
+    "AnodeBackTime",
+    "ScintLeftEnergy",
+    "ScintLeftShort",
+    "ScintLeftTime",
+    "ScintRightEnergy",
+    "ScintRightShort",
+    "ScintRightTime",
+    "CathodeEnergy",
+    "CathodeShort",
+    "CathodeTime",
+    "DelayFrontLeftEnergy",
+    "DelayFrontLeftShort",
+    "DelayFrontLeftTime",
+    "DelayFrontRightEnergy",
+    "DelayFrontRightShort",
+    "DelayFrontRightTime",
+    "DelayBackLeftEnergy",
+    "DelayBackLeftShort",
+    "DelayBackLeftTime",
+    "DelayBackRightEnergy",
+    "DelayBackRightShort",
+    "DelayBackRightTime",
+    "MonitorEnergy",
+    "MonitorShort",
+    "MonitorTime",
+    "X1",
+    "X2",
+    "Xavg",
+    "Theta",
+    "X",
+    "Z",
+    "Cebra0Energy",
+    "Cebra1Energy",
+    "Cebra2Energy",
+    "Cebra3Energy",
+    "Cebra4Energy",
+    "Cebra5Energy",
+    "Cebra6Energy",
+    "Cebra7Energy",
+    "Cebra8Energy",
+    "Cebra0Short",
+    "Cebra1Short",
+    "Cebra2Short",
+    "Cebra3Short",
+    "Cebra4Short",
+    "Cebra5Short",
+    "Cebra6Short",
+    "Cebra7Short",
+    "Cebra8Short",
+    "Cebra0Time",
+    "Cebra1Time",
+    "Cebra2Time",
+    "Cebra3Time",
+    "Cebra4Time",
+    "Cebra5Time",
+    "Cebra6Time",
+    "Cebra7Time",
+    "Cebra8Time",
+    "Cebra0RelTime",
+    "Cebra1RelTime",
+    "Cebra2RelTime",
+    "Cebra3RelTime",
+    "Cebra4RelTime",
+    "Cebra5RelTime",
+    "Cebra6RelTime",
+    "Cebra7RelTime",
+    "Cebra8RelTime",
+    "PIPS1000Energy",
+    "PIPS500Energy",
+    "PIPS300Energy",
+    "PIPS100Energy",
+    "PIPS1000Time",
+    "PIPS500Time",
+    "PIPS300Time",
+    "PIPS100Time",
+    "PIPS1000RelTime",
+    "PIPS500RelTime",
+    "PIPS300RelTime",
+    "PIPS100RelTime",
+    "CATRINA0Energy",
+    "CATRINA1Energy",
+    "CATRINA2Energy",
+    "CATRINA0Short",
+    "CATRINA1Short",
+    "CATRINA2Short",
+    "CATRINA0Time",
+    "CATRINA1Time",
+    "CATRINA2Time",
+    "CATRINA0PSD",
+    "CATRINA1PSD",
+    "CATRINA2PSD",
+    // Added since the first release
+    "AnodeFrontRelTime",
+    "AnodeBackRelTime",
+    "AnodeSumEnergy",
+    "ScintTopEnergy",
+    "ScintTopShort",
+    "ScintTopTime",
+    "ScintBottomEnergy",
+    "ScintBottomShort",
+    "ScintBottomTime",
+    "ScintVerticalTimeDiff",
+    "X1TimeDiff",
+    "X2TimeDiff",
+    "XavgCharge",
+    "ThetaDeg",
+    "Cebra9Energy",
+    "Cebra10Energy",
+    "Cebra11Energy",
+    "Cebra12Energy",
+    "Cebra13Energy",
+    "Cebra14Energy",
+    "Cebra15Energy",
+    "Cebra9Short",
+    "Cebra10Short",
+    "Cebra11Short",
+    "Cebra12Short",
+    "Cebra13Short",
+    "Cebra14Short",
+    "Cebra15Short",
+    "Cebra9Time",
+    "Cebra10Time",
+    "Cebra11Time",
+    "Cebra12Time",
+    "Cebra13Time",
+    "Cebra14Time",
+    "Cebra15Time",
+    "Cebra9RelTime",
+    "Cebra10RelTime",
+    "Cebra11RelTime",
+    "Cebra12RelTime",
+    "Cebra13RelTime",
+    "Cebra14RelTime",
+    "Cebra15RelTime",
+    "Cebra0RelTimeRight",
+    "Cebra1RelTimeRight",
+    "Cebra2RelTimeRight",
+    "Cebra3RelTimeRight",
+    "Cebra4RelTimeRight",
+    "Cebra5RelTimeRight",
+    "Cebra6RelTimeRight",
+    "Cebra7RelTimeRight",
+    "Cebra8RelTimeRight",
+    "Cebra9RelTimeRight",
+    "Cebra10RelTimeRight",
+    "Cebra11RelTimeRight",
+    "Cebra12RelTimeRight",
+    "Cebra13RelTimeRight",
+    "Cebra14RelTimeRight",
+    "Cebra15RelTimeRight",
+    "Aux0Energy",
+    "Aux1Energy",
+    "Aux2Energy",
+    "Aux3Energy",
+    "Aux0Short",
+    "Aux1Short",
+    "Aux2Short",
+    "Aux3Short",
+    "Aux0Time",
+    "Aux1Time",
+    "Aux2Time",
+    "Aux3Time",
+    "AnodeFrontEnergyRaw",
+    "AnodeBackEnergyRaw",
+    "ScintLeftEnergyRaw",
+    "ScintRightEnergyRaw",
+    "ScintTopEnergyRaw",
+    "ScintBottomEnergyRaw",
+    "CathodeEnergyRaw",
+    "DelayFrontLeftEnergyRaw",
+    "DelayFrontRightEnergyRaw",
+    "DelayBackLeftEnergyRaw",
+    "DelayBackRightEnergyRaw",
+    "MonitorEnergyRaw",
+    "Cebra0EnergyRaw",
+    "Cebra1EnergyRaw",
+    "Cebra2EnergyRaw",
+    "Cebra3EnergyRaw",
+    "Cebra4EnergyRaw",
+    "Cebra5EnergyRaw",
+    "Cebra6EnergyRaw",
+    "Cebra7EnergyRaw",
+    "Cebra8EnergyRaw",
+    "Cebra9EnergyRaw",
+    "Cebra10EnergyRaw",
+    "Cebra11EnergyRaw",
+    "Cebra12EnergyRaw",
+    "Cebra13EnergyRaw",
+    "Cebra14EnergyRaw",
+    "Cebra15EnergyRaw",
+    "PIPS1000EnergyRaw",
+    "PIPS500EnergyRaw",
+    "PIPS300EnergyRaw",
+    "PIPS100EnergyRaw",
+    "CATRINA0EnergyRaw",
+    "CATRINA1EnergyRaw",
+    "CATRINA2EnergyRaw",
+    "Aux0EnergyRaw",
+    "Aux1EnergyRaw",
+    "Aux2EnergyRaw",
+    "Aux3EnergyRaw",
+    "AnodeFrontBoard",
+    "AnodeFrontChannel",
+    "AnodeBackBoard",
+    "AnodeBackChannel",
+    "ScintLeftBoard",
+    "ScintLeftChannel",
+    "ScintRightBoard",
+    "ScintRightChannel",
+    "ScintTopBoard",
+    "ScintTopChannel",
+    "ScintBottomBoard",
+    "ScintBottomChannel",
+    "CathodeBoard",
+    "CathodeChannel",
+    "DelayFrontLeftBoard",
+    "DelayFrontLeftChannel",
+    "DelayFrontRightBoard",
+    "DelayFrontRightChannel",
+    "DelayBackLeftBoard",
+    "DelayBackLeftChannel",
+    "DelayBackRightBoard",
+    "DelayBackRightChannel",
+    "MonitorBoard",
+    "MonitorChannel",
+    "Cebra0Board",
+    "Cebra0Channel",
+    "Cebra1Board",
+    "Cebra1Channel",
+    "Cebra2Board",
+    "Cebra2Channel",
+    "Cebra3Board",
+    "Cebra3Channel",
+    "Cebra4Board",
+    "Cebra4Channel",
+    "Cebra5Board",
+    "Cebra5Channel",
+    "Cebra6Board",
+    "Cebra6Channel",
+    "Cebra7Board",
+    "Cebra7Channel",
+    "Cebra8Board",
+    "Cebra8Channel",
+    "Cebra9Board",
+    "Cebra9Channel",
+    "Cebra10Board",
+    "Cebra10Channel",
+    "Cebra11Board",
+    "Cebra11Channel",
+    "Cebra12Board",
+    "Cebra12Channel",
+    "Cebra13Board",
+    "Cebra13Channel",
+    "Cebra14Board",
+    "Cebra14Channel",
+    "Cebra15Board",
+    "Cebra15Channel",
+    "PIPS1000Board",
+    "PIPS1000Channel",
+    "PIPS500Board",
+    "PIPS500Channel",
+    "PIPS300Board",
+    "PIPS300Channel",
+    "PIPS100Board",
+    "PIPS100Channel",
+    "CATRINA0Board",
+    "CATRINA0Channel",
+    "CATRINA1Board",
+    "CATRINA1Channel",
+    "CATRINA2Board",
+    "CATRINA2Channel",
+    "Aux0Board",
+    "Aux0Channel",
+    "Aux1Board",
+    "Aux1Channel",
+    "Aux2Board",
+    "Aux2Channel",
+    "Aux3Board",
+    "Aux3Channel",
+    "AnodeFrontMultiplicity",
+    "AnodeBackMultiplicity",
+    "ScintLeftMultiplicity",
+    "ScintRightMultiplicity",
+    "ScintTopMultiplicity",
+    "ScintBottomMultiplicity",
+    "CathodeMultiplicity",
+    "DelayFrontLeftMultiplicity",
+    "DelayFrontRightMultiplicity",
+    "DelayBackLeftMultiplicity",
+    "DelayBackRightMultiplicity",
+    "MonitorMultiplicity",
+    "Cebra0Multiplicity",
+    "Cebra1Multiplicity",
+    "Cebra2Multiplicity",
+    "Cebra3Multiplicity",
+    "Cebra4Multiplicity",
+    "Cebra5Multiplicity",
+    "Cebra6Multiplicity",
+    "Cebra7Multiplicity",
+    "Cebra8Multiplicity",
+    "Cebra9Multiplicity",
+    "Cebra10Multiplicity",
+    "Cebra11Multiplicity",
+    "Cebra12Multiplicity",
+    "Cebra13Multiplicity",
+    "Cebra14Multiplicity",
+    "Cebra15Multiplicity",
+    "PIPS1000Multiplicity",
+    "PIPS500Multiplicity",
+    "PIPS300Multiplicity",
+    "PIPS100Multiplicity",
+    "CATRINA0Multiplicity",
+    "CATRINA1Multiplicity",
+    "CATRINA2Multiplicity",
+    "Aux0Multiplicity",
+    "Aux1Multiplicity",
+    "Aux2Multiplicity",
+    "Aux3Multiplicity",
+    "HitPattern",
+    "RelTimeReference",
+    "AnodeTimeDiff",
+    "CebraMultiplicity",
+    "GoodEvent",
+    "Vetoed",
+    "ScintLeftPSD",
+    "ScintRightPSD",
+    "ScintTopPSD",
+    "ScintBottomPSD",
+    "Cebra0PSD",
+    "Cebra1PSD",
+    "Cebra2PSD",
+    "Cebra3PSD",
+    "Cebra4PSD",
+    "Cebra5PSD",
+    "Cebra6PSD",
+    "Cebra7PSD",
+    "Cebra8PSD",
+    "Cebra9PSD",
+    "Cebra10PSD",
+    "Cebra11PSD",
+    "Cebra12PSD",
+    "Cebra13PSD",
+    "Cebra14PSD",
+    "Cebra15PSD",
+    "EventTime",
+];
+
+#[test]
+fn output_order_is_pinned() {
+    let mut fields: Vec<ChannelDataField> = ChannelDataField::iter().collect();
+    fields.sort();
+    let names: Vec<&str> = fields.iter().map(|field| field.as_ref()).collect();
+    assert_eq!(names, OUTPUT_ORDER);
+    for (index, field) in fields.iter().enumerate() {
+        assert_eq!(field.output_index(), index, "{}", field.as_ref());
+    }
+}
+
+#[test]
+fn columns_are_written_in_output_order() {
+    let params = BuildParams {
+        keep_raw_energy: true,
+        multiplicity: true,
+        ..Default::default()
+    };
+    let map = ChannelMap::new(&[Board::sps(0), Board::cebra(1)]).unwrap();
+    let df = ChannelData::new(&map, &params).into_dataframe().unwrap();
+    let columns: Vec<&str> = df
+        .get_column_names()
+        .into_iter()
+        .map(|name| name.as_str())
+        .collect();
+
+    // The list columns follow the flat ones, each in output order
+    let (mut expected, nested): (Vec<&str>, Vec<&str>) = OUTPUT_ORDER
+        .into_iter()
+        .filter(|name| columns.contains(name))
+        .partition(|name| !["X", "Z"].contains(name));
+    expected.extend(nested);
+    assert_eq!(columns, expected);
+}
+
+#[test]
+fn default_has_every_field_stored_like_new() {
+    let data = ChannelData::default();
+    let df = data.clone().into_dataframe().unwrap();
+    assert_eq!(df.width(), OUTPUT_ORDER.len());
+    // The position lists are nested like in a map-filtered ChannelData
+    assert!(data.nested_fields.contains_key(&ChannelDataField::X));
+    assert!(data.nested_fields.contains_key(&ChannelDataField::Z));
+    assert!(!data.fields.contains_key(&ChannelDataField::X));
+}
+
+#[test]
+fn every_field_is_mapped_with_every_detector_and_option() {
+    // Every channel type once, spread over as many boards as it takes
+    let types: Vec<ChannelType> = ChannelType::iter()
+        .filter(|channel_type| *channel_type != ChannelType::None)
+        .collect();
+    let boards: Vec<Board> = types
+        .chunks(16)
+        .enumerate()
+        .map(|(id, chunk)| {
+            let mut board = Board {
+                id: id as u32,
+                ..Default::default()
+            };
+            board.channels[..chunk.len()].copy_from_slice(chunk);
+            board
+        })
+        .collect();
+    let map = ChannelMap::new(&boards).unwrap();
+    let params = BuildParams {
+        hit_pattern: true,
+        multiplicity: true,
+        theta_degrees: true,
+        delay_line_time_diff: true,
+        keep_raw_energy: true,
+        psd_fields: true,
+        provenance_columns: true,
+        scint_right_fallback: true,
+        cebra_rel_time_right: true,
+        required_fields: vec!["X1".to_string()],
+        ..Default::default()
+    };
+    let mapped = ChannelDataField::get_mapped_field_vec(&map, &params);
+    let names: Vec<&str> = mapped.iter().map(|field| field.as_ref()).collect();
+    assert_eq!(names, OUTPUT_ORDER);
+}
@@ -0,0 +1,56 @@
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::position::{FocalPlaneConfig, FocalPlaneOverride};
+
+fn geometry(x1_scale: f64) -> FocalPlaneConfig {
+    FocalPlaneConfig {
+        x1_scale,
+        ..FocalPlaneConfig::default()
+    }
+}
+
+#[test]
+fn runs_in_an_override_use_its_geometry() {
+    let params = BuildParams {
+        focal_plane: geometry(2.0),
+        focal_plane_overrides: vec![
+            FocalPlaneOverride {
+                first_run: 10,
+                last_run: 19,
+                focal_plane: geometry(3.0),
+            },
+            FocalPlaneOverride {
+                first_run: 15,
+                last_run: 30,
+                focal_plane: geometry(4.0),
+            },
+        ],
+        ..BuildParams::default()
+    };
+
+    let scale = |run| params.for_run(run).focal_plane.x1_scale;
+    assert_eq!(scale(9), 2.0);
+    assert_eq!(scale(10), 3.0);
+    // The first override containing the run wins
+    assert_eq!(scale(15), 3.0);
+    assert_eq!(scale(19), 3.0);
+    assert_eq!(scale(20), 4.0);
+    assert_eq!(scale(30), 4.0);
+    assert_eq!(scale(31), 2.0);
+}
+
+#[test]
+fn overrides_only_change_the_focal_plane() {
+    let params = BuildParams {
+        parallel: true,
+        focal_plane_overrides: vec![FocalPlaneOverride {
+            first_run: 1,
+            last_run: 1,
+            focal_plane: geometry(3.0),
+        }],
+        ..BuildParams::default()
+    };
+
+    let run_params = params.for_run(1);
+    assert!(run_params.parallel);
+    assert_eq!(run_params.focal_plane_overrides.len(), 1);
+}
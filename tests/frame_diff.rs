@@ -0,0 +1,156 @@
+mod common;
+
+use common::{hit, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::frame_diff::diff_dataframes;
+use polars::prelude::*;
+
+fn build(params: &BuildParams) -> DataFrame {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let events = vec![
+        vec![
+            hit(SCINT_LEFT, 0, 1000.2, None, None),
+            hit(ANODE_FRONT, 10, 800.4, None, None),
+        ],
+        vec![hit(SCINT_LEFT, 1_000_000, 900.0, None, None)],
+    ];
+    common::build(&map, params, events)
+}
+
+#[test]
+fn identical_builds_have_no_differences() {
+    let reference = build(&BuildParams::default());
+    let diff = diff_dataframes(&reference, &build(&BuildParams::default()), 0.0).unwrap();
+    assert!(diff.is_identical());
+    assert_eq!(diff.to_string(), "Identical, 2 rows");
+
+    // Nulls and the sentinel are both unset
+    let sentinel = BuildParams {
+        keep_invalid_sentinel: true,
+        ..Default::default()
+    };
+    assert!(diff_dataframes(&reference, &build(&sentinel), 0.0)
+        .unwrap()
+        .is_identical());
+
+    // The hit pattern is set in every event, so it is the same column with the sentinel
+    let hit_pattern = BuildParams {
+        hit_pattern: true,
+        ..Default::default()
+    };
+    let with_sentinel = BuildParams {
+        keep_invalid_sentinel: true,
+        ..hit_pattern.clone()
+    };
+    assert!(
+        diff_dataframes(&build(&hit_pattern), &build(&with_sentinel), 0.0)
+            .unwrap()
+            .is_identical()
+    );
+}
+
+#[test]
+fn mismatches_are_located() {
+    let reference = build(&BuildParams::default());
+    let rounded = BuildParams {
+        energy_step: Some(1.0),
+        ..Default::default()
+    };
+    let diff = diff_dataframes(&reference, &build(&rounded), 1.0e-9).unwrap();
+    assert!(!diff.is_identical());
+    assert!(diff.only_left.is_empty() && diff.only_right.is_empty());
+    let mut names: Vec<&str> = diff
+        .columns
+        .iter()
+        .map(|column| column.name.as_str())
+        .collect();
+    names.sort_unstable();
+    assert_eq!(names, vec!["AnodeFrontEnergy", "ScintLeftEnergy"]);
+    let scint = diff
+        .columns
+        .iter()
+        .find(|column| column.name == "ScintLeftEnergy")
+        .unwrap();
+    assert_eq!((scint.mismatches, scint.first_row), (1, 0));
+    assert!((scint.max_difference.unwrap() - 0.2).abs() < 1.0e-9);
+    assert_eq!(diff.mismatches(), 2);
+
+    // Within the tolerance
+    assert!(diff_dataframes(&reference, &build(&rounded), 0.5)
+        .unwrap()
+        .is_identical());
+}
+
+#[test]
+fn columns_rows_and_types_are_compared() {
+    let left = df!(
+        "A" => [Some(1.0), None, Some(3.0)],
+        "B" => [1i64, 2, 3],
+        "OnlyLeft" => [0.0, 0.0, 0.0]
+    )
+    .unwrap();
+    let right = df!(
+        "A" => [Some(1.0), Some(2.0)],
+        "B" => [1.0f32, 2.5],
+        "OnlyRight" => [0.0, 0.0]
+    )
+    .unwrap();
+    let diff = diff_dataframes(&left, &right, 0.0).unwrap();
+    assert_eq!((diff.left_rows, diff.right_rows), (3, 2));
+    assert_eq!(diff.only_left, vec!["OnlyLeft".to_string()]);
+    assert_eq!(diff.only_right, vec!["OnlyRight".to_string()]);
+    assert_eq!(
+        diff.type_changes,
+        vec![("B".to_string(), DataType::Int64, DataType::Float32)]
+    );
+    // Unset against set has no difference to report
+    assert_eq!(diff.columns[0].name, "A");
+    assert_eq!(diff.columns[0].first_row, 1);
+    assert_eq!(diff.columns[0].max_difference, None);
+    assert_eq!(diff.columns[1].name, "B");
+    assert_eq!(diff.columns[1].max_difference, Some(0.5));
+    assert!(diff.to_string().contains("Rows: 3 vs 2"));
+}
+
+#[test]
+fn nan_on_one_side_is_a_mismatch() {
+    let list = |rows: Vec<Vec<f64>>| {
+        let rows: Vec<Series> = rows
+            .into_iter()
+            .map(|row| Series::new("".into(), row))
+            .collect();
+        Series::new("X".into(), rows)
+    };
+    let left = DataFrame::new(vec![
+        Series::new("A".into(), [f64::NAN, 1.0, f64::NAN, f64::INFINITY]).into(),
+        list(vec![
+            vec![1.0, f64::NAN],
+            vec![1.0, 2.0],
+            vec![f64::NAN, 2.0],
+            vec![f64::INFINITY, 2.0],
+        ])
+        .into(),
+    ])
+    .unwrap();
+    let right = DataFrame::new(vec![
+        Series::new("A".into(), [f64::NAN, f64::NAN, 3.0, f64::INFINITY]).into(),
+        list(vec![
+            vec![1.0, f64::NAN],
+            vec![1.0, f64::NAN],
+            vec![f64::NAN, 2.5],
+            vec![f64::INFINITY, 2.0],
+        ])
+        .into(),
+    ])
+    .unwrap();
+    let diff = diff_dataframes(&left, &right, 0.0).unwrap();
+
+    // Rows 1 and 2 of A and row 1 of X, row 2 of X only differs by 0.5
+    let a = &diff.columns[0];
+    assert_eq!((a.name.as_str(), a.mismatches, a.first_row), ("A", 2, 1));
+    assert_eq!(a.max_difference, None);
+    let x = &diff.columns[1];
+    assert_eq!((x.name.as_str(), x.mismatches, x.first_row), ("X", 2, 1));
+    assert_eq!(x.max_difference, Some(0.5));
+}
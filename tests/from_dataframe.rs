@@ -0,0 +1,98 @@
+mod common;
+
+use common::{
+    hit, ANODE_FRONT, DELAY_BACK_LEFT, DELAY_BACK_RIGHT, DELAY_FRONT_LEFT, DELAY_FRONT_RIGHT,
+    SCINT_LEFT,
+};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{
+    ChannelData, ChannelDataError, ChannelDataField, INVALID_TIME, INVALID_VALUE,
+};
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_data::ns_to_ps;
+
+fn built(map: &ChannelMap, params: &BuildParams) -> ChannelData {
+    // Hits with a short gate, times in ns
+    let hit_at =
+        |channel: u32, time_ns: f64| hit(channel, ns_to_ps(time_ns), 1000.0, Some(500.0), None);
+    let mut data = ChannelData::new(map, params);
+    // x1 = 10, x2 = -10
+    data.append_event(
+        vec![
+            hit_at(SCINT_LEFT, 1000.0),
+            hit_at(ANODE_FRONT, 1001.0),
+            hit_at(DELAY_FRONT_LEFT, 1052.0),
+            hit_at(DELAY_FRONT_RIGHT, 1010.0),
+            hit_at(DELAY_BACK_LEFT, 1010.0),
+            hit_at(DELAY_BACK_RIGHT, 1049.6),
+        ],
+        map,
+        Some((0.6, 0.4)),
+    );
+    // No delay lines
+    data.append_event(vec![hit_at(SCINT_LEFT, 2000.0)], map, Some((0.6, 0.4)));
+    data
+}
+
+#[test]
+fn roundtrip_and_recalculate_xavg() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams::default();
+    let data = built(&map, &params);
+    let df = data.clone().into_dataframe().unwrap();
+
+    let mut loaded = ChannelData::from_dataframe(&df, &map, &params).unwrap();
+    assert_eq!(loaded.rows, 2);
+    assert_eq!(loaded.fields, data.fields);
+    assert_eq!(loaded.time_fields, data.time_fields);
+    assert_eq!(loaded.nested_fields, data.nested_fields);
+    assert_eq!(
+        loaded.time_fields[&ChannelDataField::AnodeFrontTime][1],
+        INVALID_TIME
+    );
+
+    loaded.recalculate_xavg(None);
+    let xavg = &loaded.fields[&ChannelDataField::Xavg];
+    assert!(xavg[0].abs() < 1.0e-9);
+    assert_eq!(xavg[1], data.fields[&ChannelDataField::Xavg][1]);
+}
+
+#[test]
+fn normalized_weights_summing_to_zero_leave_xavg_unset() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        normalize_xavg_weights: true,
+        ..Default::default()
+    };
+    let mut data = built(&map, &params);
+    let xavg = &data.fields[&ChannelDataField::Xavg];
+    assert!((xavg[0] - 2.0).abs() < 1.0e-9);
+
+    data.recalculate_xavg(Some((1.0, -1.0)));
+    assert_eq!(data.fields[&ChannelDataField::Xavg][0], INVALID_VALUE);
+    let df = data.into_dataframe().unwrap();
+    let xavg = df.column("Xavg").unwrap().f64().unwrap();
+    assert_eq!(xavg.get(0), None);
+}
+
+#[test]
+fn mismatched_schema_is_rejected() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams::default();
+    let mut df = built(&map, &params).into_dataframe().unwrap();
+    df.drop_in_place("X1").unwrap();
+
+    match ChannelData::from_dataframe(&df, &map, &params) {
+        Err(ChannelDataError::SchemaMismatch {
+            missing,
+            unexpected,
+        }) => {
+            assert_eq!(missing, vec!["X1".to_string()]);
+            assert!(unexpected.is_empty());
+        }
+        other => panic!(
+            "expected a schema mismatch, got {:?}",
+            other.map(|data| data.rows)
+        ),
+    }
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::{hit, ANODE_FRONT, DELAY_FRONT_LEFT, DELAY_FRONT_RIGHT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::ChannelData;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_data::CompassData;
+use eventbuilder::evb::validation::{validate_configuration, ConfigProblem};
+use polars::prelude::*;
+
+// A full event, one without X1, and one without the scintillator
+fn events() -> Vec<Vec<CompassData>> {
+    vec![
+        vec![
+            hit(SCINT_LEFT, 0, 1000.0, None, None),
+            hit(ANODE_FRONT, 10, 1000.0, None, None),
+            hit(DELAY_FRONT_LEFT, 20, 1000.0, None, None),
+            hit(DELAY_FRONT_RIGHT, 30, 1000.0, None, None),
+        ],
+        vec![
+            hit(SCINT_LEFT, 1_000_000, 1000.0, None, None),
+            hit(ANODE_FRONT, 1_000_010, 1000.0, None, None),
+        ],
+        vec![
+            hit(ANODE_FRONT, 2_000_000, 1000.0, None, None),
+            hit(DELAY_FRONT_LEFT, 2_000_020, 1000.0, None, None),
+            hit(DELAY_FRONT_RIGHT, 2_000_030, 1000.0, None, None),
+        ],
+    ]
+}
+
+fn standard_cut() -> Vec<String> {
+    ["ScintLeftEnergy", "AnodeFrontEnergy", "X1"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn build(params: &BuildParams, batch: bool) -> DataFrame {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    if !batch {
+        return common::build(&map, params, events());
+    }
+    let mut data = ChannelData::new(&map, params);
+    data.append_events_batch(events(), &map, None);
+    data.into_dataframe().unwrap()
+}
+
+#[test]
+fn good_event_flags_the_required_fields() {
+    assert!(build(&BuildParams::default(), false)
+        .column("GoodEvent")
+        .is_err());
+
+    let params = BuildParams {
+        required_fields: standard_cut(),
+        ..Default::default()
+    };
+    let df = build(&params, false);
+    let column = df.column("GoodEvent").unwrap();
+    assert_eq!(column.dtype(), &DataType::Boolean);
+    let good: Vec<Option<bool>> = column.bool().unwrap().into_iter().collect();
+    assert_eq!(good, vec![Some(true), Some(false), Some(false)]);
+    assert!(build(&params, true).equals_missing(&df));
+}
+
+#[test]
+fn bad_events_can_be_dropped() {
+    let params = BuildParams {
+        required_fields: standard_cut(),
+        drop_bad_events: true,
+        ..Default::default()
+    };
+    for batch in [false, true] {
+        let df = build(&params, batch);
+        assert_eq!(df.height(), 1);
+        assert!(df.column("GoodEvent").is_err());
+        for name in standard_cut() {
+            assert_eq!(df.column(&name).unwrap().null_count(), 0);
+        }
+    }
+}
+
+#[test]
+fn required_fields_are_validated() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        required_fields: vec!["X1".to_string(), "ScintLeftEnergi".to_string()],
+        ..Default::default()
+    };
+    let problems = validate_configuration(&map, &[], &[], &params);
+    assert_eq!(
+        problems,
+        vec![ConfigProblem::UnknownRequiredField(
+            "ScintLeftEnergi".to_string()
+        )]
+    );
+    assert!(problems[0].is_error());
+
+    // Cebra0 is not on the SPS board, so no event could be good
+    let params = BuildParams {
+        required_fields: vec!["Cebra0Energy".to_string()],
+        ..Default::default()
+    };
+    let problems = validate_configuration(&map, &[], &[], &params);
+    assert_eq!(
+        problems,
+        vec![ConfigProblem::UnwrittenRequiredField(
+            "Cebra0Energy".to_string()
+        )]
+    );
+    assert!(!problems[0].is_error());
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::{build_data, hit, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelData, ChannelDataField};
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::expression::DerivedFieldEntry;
+use eventbuilder::evb::histogram::Histogram;
+
+fn build(params: &BuildParams) -> ChannelData {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let mut events: Vec<_> = [5.0, 15.0, 15.5, 35.0, -1.0, 40.0]
+        .into_iter()
+        .enumerate()
+        .map(|(index, energy)| {
+            let time = index as i64 * 1_000_000;
+            vec![hit(ANODE_FRONT, time, energy, None, None)]
+        })
+        .collect();
+    // AnodeFrontEnergy is not set in this event
+    events.push(vec![hit(SCINT_LEFT, 10_000_000, 10.0, None, None)]);
+    build_data(&map, params, events)
+}
+
+#[test]
+fn histogram_counts_the_set_values() {
+    let data = build(&BuildParams::default());
+    let histogram = data
+        .histogram(&ChannelDataField::AnodeFrontEnergy, (0.0, 40.0), 4)
+        .unwrap();
+    assert_eq!(histogram.edges, vec![0.0, 10.0, 20.0, 30.0, 40.0]);
+    assert_eq!(histogram.counts, vec![1, 2, 0, 1]);
+    assert_eq!((histogram.underflow, histogram.overflow), (1, 1));
+    assert_eq!(histogram.entries(), 4);
+    assert_eq!(histogram.bin_centers(), vec![5.0, 15.0, 25.0, 35.0]);
+
+    // Not stored for this map, and empty binnings
+    assert!(data
+        .histogram(&ChannelDataField::Cebra0Energy, (0.0, 40.0), 4)
+        .is_none());
+    assert!(data
+        .histogram(&ChannelDataField::AnodeFrontEnergy, (0.0, 40.0), 0)
+        .is_none());
+    assert!(Histogram::new((1.0, 1.0), 4).is_none());
+}
+
+#[test]
+fn histogram_by_name_finds_derived_fields() {
+    let params = BuildParams {
+        derived_fields: vec![DerivedFieldEntry {
+            name: "Double".to_string(),
+            expression: "2 * AnodeFrontEnergy".to_string(),
+        }],
+        f32_storage: true,
+        ..Default::default()
+    };
+    let data = build(&params);
+    let histogram = data.histogram_by_name("Double", (0.0, 80.0), 2).unwrap();
+    assert_eq!(histogram.counts, vec![3, 1]);
+    assert_eq!((histogram.underflow, histogram.overflow), (1, 1));
+
+    let histogram = data
+        .histogram_by_name("AnodeFrontEnergy", (0.0, 40.0), 4)
+        .unwrap();
+    assert_eq!(histogram.counts, vec![1, 2, 0, 1]);
+    assert!(data.histogram_by_name("Triple", (0.0, 80.0), 2).is_none());
+}
@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::Board;
+use eventbuilder::evb::compass_run::{process_runs, ProcessParams};
+use eventbuilder::evb::kinematics::KineParameters;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use polars::prelude::*;
+
+// CoMPASS file with the energy only: board, channel, timestamp, energy, flags for each hit
+fn compass_file(hits: &[(u16, u16, u64)]) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend_from_slice(&0x0001u16.to_le_bytes());
+    for (board, channel, timestamp) in hits {
+        bytes.extend_from_slice(&board.to_le_bytes());
+        bytes.extend_from_slice(&channel.to_le_bytes());
+        bytes.extend_from_slice(&timestamp.to_le_bytes());
+        bytes.extend_from_slice(&1000u16.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+    }
+    bytes
+}
+
+// Directories of a run 1 archive holding this file, cleared first
+fn run_dir(name: &str, file: Vec<u8>) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("max_events_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for sub in ["archive", "unpack", "output", "scalers"] {
+        std::fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    let archive = File::create(dir.join("archive").join("run_1.tar.gz")).unwrap();
+    let mut builder = tar::Builder::new(GzEncoder::new(archive, Compression::default()));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(file.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "Data_V1725_0.BIN", file.as_slice())
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+    dir
+}
+
+// Rows of the output of run 1 built with at most max_events events
+fn built_rows(dir: &Path, build_params: BuildParams, max_events: u64) -> usize {
+    let params = ProcessParams {
+        archive_dir: dir.join("archive"),
+        unpack_dir: dir.join("unpack"),
+        output_dir: dir.join("output"),
+        channel_map: vec![Board::sps(0)],
+        scaler_list: vec![],
+        shift_map: vec![],
+        pedestal_map: vec![],
+        calibration_map: vec![],
+        time_resolutions: vec![],
+        build_params,
+        coincidence_window: 3000.0,
+        max_events: Some(max_events),
+        run_min: 1,
+        run_max: 2,
+        progress_callback: None,
+    };
+    process_runs(params, KineParameters::default(), Arc::new(Mutex::new(0.0))).unwrap();
+    let output = dir.join("output").join("run_1.parquet");
+    let df = ParquetReader::new(File::open(&output).unwrap())
+        .finish()
+        .unwrap();
+    std::fs::remove_file(&output).unwrap();
+    df.height()
+}
+
+#[test]
+fn dropped_events_do_not_count_towards_max_events() {
+    // Windows 10 us apart, alternating between channel 2, which has no type, and the AnodeFront on channel 13
+    let hits: Vec<(u16, u16, u64)> = (0..8)
+        .map(|i| (0, if i % 2 == 0 { 2 } else { 13 }, 1_000 + i * 10_000_000))
+        .collect();
+    let dir = run_dir("drop", compass_file(&hits));
+
+    let drop_empty = BuildParams {
+        drop_empty_events: true,
+        ..Default::default()
+    };
+    // The windows of channel 2 are both empty and missing the required AnodeFrontEnergy
+    let drop_bad = BuildParams {
+        required_fields: vec!["AnodeFrontEnergy".to_string()],
+        drop_bad_events: true,
+        ..Default::default()
+    };
+    for params in [drop_empty, drop_bad] {
+        for parallel in [false, true] {
+            let params = BuildParams {
+                parallel,
+                ..params.clone()
+            };
+            assert_eq!(built_rows(&dir, params, 3), 3, "parallel: {}", parallel);
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
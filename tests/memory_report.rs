@@ -0,0 +1,54 @@
+mod common;
+
+use common::{
+    build_data, hit, ANODE_FRONT, DELAY_BACK_LEFT, DELAY_BACK_RIGHT, DELAY_FRONT_LEFT,
+    DELAY_FRONT_RIGHT, SCINT_LEFT,
+};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::ChannelDataField;
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+use eventbuilder::evb::expression::DerivedFieldEntry;
+use eventbuilder::evb::used_size::UsedSize;
+
+#[test]
+fn report_adds_up_to_the_used_size() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        f32_storage: true,
+        waveform_channels: vec![ChannelType::ScintLeft],
+        derived_fields: vec![DerivedFieldEntry {
+            name: "Ratio".to_string(),
+            expression: "ScintLeftEnergy / AnodeFrontEnergy".to_string(),
+        }],
+        ..Default::default()
+    };
+    let events = (0..10)
+        .map(|i| {
+            let start = i * 10_000_000;
+            vec![
+                hit(SCINT_LEFT, start, 1000.0, None, Some(vec![1, 2, 3])),
+                hit(ANODE_FRONT, start + 1_000, 500.0, None, None),
+                hit(DELAY_FRONT_LEFT, start + 2_000, 10.0, None, None),
+                hit(DELAY_FRONT_RIGHT, start + 3_000, 10.0, None, None),
+                hit(DELAY_BACK_LEFT, start + 2_000, 10.0, None, None),
+                hit(DELAY_BACK_RIGHT, start + 3_000, 10.0, None, None),
+            ]
+        })
+        .collect();
+    let data = build_data(&map, &params, events);
+
+    let report = data.memory_report();
+    assert_eq!(report.rows, 10);
+    assert_eq!(report.total_bytes, data.get_used_size());
+    assert!(report.derived_bytes > 0);
+    assert!(report.waveform_bytes > 0);
+    // Every stored field, the nested positions and timestamps included
+    for field in [
+        ChannelDataField::X,
+        ChannelDataField::EventTime,
+        ChannelDataField::ScintLeftEnergy,
+    ] {
+        assert!(report.field_bytes[&field] > 0, "{}", field.as_ref());
+    }
+    assert_eq!(report.bytes_per_row(), report.total_bytes / 10);
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::{
+    hit, on_board, ANODE_BACK, ANODE_FRONT, CEBRA0, DELAY_BACK_LEFT, DELAY_BACK_RIGHT,
+    DELAY_FRONT_LEFT, DELAY_FRONT_RIGHT, SCINT_LEFT,
+};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::ChannelData;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_data::CompassData;
+
+// Focal plane events 1 us apart (times in ps), every other one with a CeBrA hit
+fn make_events(first: usize, count: usize) -> Vec<Vec<CompassData>> {
+    (first..first + count)
+        .map(|i| {
+            let start = i as i64 * 1_000_000;
+            let mut event: Vec<CompassData> = [
+                SCINT_LEFT,
+                DELAY_FRONT_LEFT,
+                DELAY_FRONT_RIGHT,
+                DELAY_BACK_LEFT,
+                DELAY_BACK_RIGHT,
+                ANODE_FRONT,
+                ANODE_BACK,
+            ]
+            .iter()
+            .map(|channel| {
+                let timestamp = start + *channel as i64 * 1000;
+                hit(*channel, timestamp, 1000.0, Some(500.0), None)
+            })
+            .collect();
+            if i % 2 == 0 {
+                let cebra = hit(CEBRA0, start + 20_000, 2000.0, Some(1000.0), None);
+                event.push(on_board(1, cebra));
+            }
+            event
+        })
+        .collect()
+}
+
+fn build(map: &ChannelMap, params: &BuildParams, events: Vec<Vec<CompassData>>) -> ChannelData {
+    let mut data = ChannelData::new(map, params);
+    for event in events {
+        data.append_event(event, map, Some((0.5, 0.5)));
+    }
+    data
+}
+
+#[test]
+fn merge_keeps_columns_aligned() {
+    let map = ChannelMap::new(&[Board::sps(0), Board::cebra(1)]).unwrap();
+    let params = BuildParams::default();
+    let mut first = build(&map, &params, make_events(0, 10));
+    let second = build(&map, &params, make_events(10, 5));
+
+    first.merge(second).unwrap();
+
+    assert_eq!(first.rows, 15);
+    for values in first.fields.values() {
+        assert_eq!(values.len(), 15);
+    }
+    for values in first.nested_fields.values() {
+        assert_eq!(values.len(), 15);
+    }
+
+    // Same rows as building every event in one go
+    let whole = build(&map, &params, make_events(0, 15));
+    assert_eq!(first.fields, whole.fields);
+    assert_eq!(first.time_fields, whole.time_fields);
+    assert_eq!(first.nested_fields, whole.nested_fields);
+}
+
+#[test]
+fn merge_rejects_different_fields() {
+    let map = ChannelMap::new(&[Board::sps(0), Board::cebra(1)]).unwrap();
+    let mut first = build(&map, &BuildParams::default(), make_events(0, 3));
+    let with_hit_pattern = BuildParams {
+        hit_pattern: true,
+        ..Default::default()
+    };
+    let second = build(&map, &with_hit_pattern, make_events(3, 3));
+
+    assert!(first.merge(second).is_err());
+    assert_eq!(first.rows, 3);
+}
+
+#[test]
+fn cleared_data_builds_like_new_data() {
+    let map = ChannelMap::new(&[Board::sps(0), Board::cebra(1)]).unwrap();
+    let params = BuildParams::default();
+    let mut reused = build(&map, &params, make_events(0, 10));
+    let capacity = reused.fields.values().next().unwrap().capacity();
+
+    reused.clear();
+    assert_eq!(reused.rows, 0);
+    assert_eq!(reused.fields.values().next().unwrap().capacity(), capacity);
+    for event in make_events(20, 5) {
+        reused.append_event(event, &map, Some((0.5, 0.5)));
+    }
+
+    let fresh = build(&map, &params, make_events(20, 5));
+    // Unset values are nulls, which only compare equal with equals_missing
+    assert!(reused
+        .into_dataframe()
+        .unwrap()
+        .equals_missing(&fresh.into_dataframe().unwrap()));
+}
@@ -0,0 +1,68 @@
+use eventbuilder::evb::compass_data::CompassData;
+use eventbuilder::evb::event_builder::merge_hits;
+
+fn hit(uuid: u32, timestamp: i64) -> CompassData {
+    CompassData {
+        uuid,
+        energy: 1000.0,
+        energy_short: None,
+        timestamp,
+        waveform: None,
+    }
+}
+
+fn source(uuid: u32, timestamps: &[i64]) -> Vec<Result<CompassData, String>> {
+    timestamps.iter().map(|t| Ok(hit(uuid, *t))).collect()
+}
+
+#[test]
+fn merges_sources_of_different_rates_in_time_order() {
+    let sources = vec![
+        source(0, &[0, 10, 20, 30, 40, 50]).into_iter(),
+        source(1, &[25]).into_iter(),
+        source(2, &[]).into_iter(),
+        source(3, &[5, 20, 60]).into_iter(),
+    ];
+    let merged: Vec<CompassData> = merge_hits(sources)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    let order: Vec<(u32, i64)> = merged.iter().map(|h| (h.uuid, h.timestamp)).collect();
+    assert_eq!(
+        order,
+        vec![
+            (0, 0),
+            (3, 5),
+            (0, 10),
+            // Same time, earlier source first
+            (0, 20),
+            (3, 20),
+            (1, 25),
+            (0, 30),
+            (0, 40),
+            (0, 50),
+            (3, 60),
+        ]
+    );
+}
+
+#[test]
+fn source_errors_are_returned() {
+    let mut failing = source(1, &[15]);
+    failing.push(Err("bad hit".to_string()));
+    let sources = vec![source(0, &[10, 20, 30]).into_iter(), failing.into_iter()];
+    let merged: Vec<Result<CompassData, String>> = merge_hits(sources).unwrap().collect();
+
+    // The error follows the last good hit of its source
+    let timestamps: Vec<i64> = merged[..2]
+        .iter()
+        .map(|h| h.as_ref().unwrap().timestamp)
+        .collect();
+    assert_eq!(timestamps, vec![10, 15]);
+    assert!(matches!(&merged[2], Err(e) if e == "bad hit"));
+    assert_eq!(merged.len(), 5);
+
+    let sources = vec![vec![Err("empty".to_string())].into_iter()];
+    assert!(merge_hits(sources).is_err());
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::{build, hit, values, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::{BuildParams, MultiHitPolicy};
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_data::ns_to_ps;
+use polars::prelude::*;
+
+// Value of the only row, None if it was not set
+fn value(df: &DataFrame, name: &str) -> Option<f64> {
+    values(df, name)[0]
+}
+
+#[test]
+fn energy_short_and_time_come_from_the_same_hit() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    // ScintLeft fires three times, the last hit has no short gate
+    let event = vec![
+        hit(ANODE_FRONT, ns_to_ps(50.0), 500.0, None, None),
+        hit(SCINT_LEFT, ns_to_ps(10.0), 1000.0, Some(100.0), None),
+        hit(SCINT_LEFT, ns_to_ps(20.0), 3000.0, Some(300.0), None),
+        hit(SCINT_LEFT, ns_to_ps(30.0), 2000.0, None, None),
+    ];
+
+    // (energy, short, time, anode time relative to the chosen ScintLeft) of the hit each policy picks
+    let expected = [
+        (MultiHitPolicy::Last, (2000.0, None, 30.0, 20.0)),
+        (MultiHitPolicy::First, (1000.0, Some(100.0), 10.0, 40.0)),
+        (MultiHitPolicy::MaxEnergy, (3000.0, Some(300.0), 20.0, 30.0)),
+        (MultiHitPolicy::MinTime, (1000.0, Some(100.0), 10.0, 40.0)),
+    ];
+    for (policy, (energy, short, time, rel_time)) in expected {
+        let params = BuildParams {
+            multi_hit_policy: policy,
+            ..Default::default()
+        };
+        let df = build(&map, &params, vec![event.clone()]);
+
+        let policy = policy.as_ref();
+        assert_eq!(value(&df, "ScintLeftEnergy"), Some(energy), "{policy}");
+        // A hit without a short gate never borrows the short of another hit
+        assert_eq!(value(&df, "ScintLeftShort"), short, "{policy}");
+        assert_eq!(value(&df, "ScintLeftTime"), Some(time), "{policy}");
+        assert_eq!(value(&df, "AnodeFrontRelTime"), Some(rel_time), "{policy}");
+    }
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{
+    build, ANODE_BACK, ANODE_FRONT, DELAY_BACK_LEFT, DELAY_BACK_RIGHT, DELAY_FRONT_LEFT,
+    DELAY_FRONT_RIGHT, SCINT_LEFT,
+};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_data::{ns_to_ps, CompassData};
+
+fn hit(channel: u32, time_ns: f64) -> CompassData {
+    common::hit(channel, ns_to_ps(time_ns), 1000.0, Some(500.0), None)
+}
+
+fn focal_plane_event(start: f64) -> Vec<CompassData> {
+    vec![
+        hit(SCINT_LEFT, start),
+        hit(ANODE_FRONT, start + 1.0),
+        hit(ANODE_BACK, start + 2.0),
+        hit(DELAY_FRONT_LEFT, start + 10.0),
+        hit(DELAY_FRONT_RIGHT, start + 20.0),
+        hit(DELAY_BACK_LEFT, start + 10.0),
+        hit(DELAY_BACK_RIGHT, start + 30.0),
+    ]
+}
+
+#[test]
+fn track_is_written_in_the_row_of_its_event() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    // No delay lines in the second event, so no track
+    let events = vec![focal_plane_event(0.0), vec![hit(SCINT_LEFT, 10_000.0)]];
+    let df = build(&map, &BuildParams::default(), events);
+    let x = df.column("X").unwrap().list().unwrap();
+    assert_eq!(x.len(), 2);
+    assert_eq!(x.get_as_series(0).map(|row| row.len()), Some(400));
+    assert!(x.get_as_series(1).is_none());
+}
+
+#[test]
+fn no_events_no_track_rows() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let df = build(&map, &BuildParams::default(), vec![]);
+    assert_eq!(df.height(), 0);
+    assert_eq!(df.column("X").unwrap().len(), 0);
+}
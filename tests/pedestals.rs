@@ -0,0 +1,90 @@
+mod common;
+
+use common::{hit, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::calibration_map::{CalibrationMap, CalibrationMapEntry};
+use eventbuilder::evb::channel_data::ChannelData;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_data::{generate_board_channel_uuid, CompassData};
+use eventbuilder::evb::pedestal_map::{PedestalMap, PedestalMapEntry};
+
+fn pedestal(channel: u32, pedestal: f64) -> PedestalMapEntry {
+    PedestalMapEntry {
+        board_number: 0,
+        channel_number: channel,
+        pedestal,
+    }
+}
+
+fn energies(params: &BuildParams, hits: Vec<CompassData>) -> (f64, f64, f64) {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let mut data = ChannelData::new(&map, params);
+    data.set_pedestal_map(PedestalMap::new(vec![pedestal(SCINT_LEFT, 100.0)]));
+    data.set_calibration_map(CalibrationMap::new(vec![CalibrationMapEntry {
+        board_number: 0,
+        channel_number: SCINT_LEFT,
+        a: 0.0,
+        b: 2.0,
+        c: 1.0,
+    }]));
+    data.append_event(hits, &map, None);
+    let df = data.into_dataframe().unwrap();
+    let value = |name: &str| df.column(name).unwrap().f64().unwrap().get(0).unwrap();
+    (
+        value("ScintLeftEnergy"),
+        value("ScintLeftEnergyRaw"),
+        value("AnodeFrontEnergy"),
+    )
+}
+
+#[test]
+fn pedestal_is_subtracted_before_calibration() {
+    let params = BuildParams {
+        keep_raw_energy: true,
+        ..Default::default()
+    };
+    let (energy, raw, anode) = energies(
+        &params,
+        vec![
+            hit(SCINT_LEFT, 0, 600.0, None, None),
+            hit(ANODE_FRONT, 0, 50.0, None, None),
+        ],
+    );
+    assert_eq!(energy, 2.0 * (600.0 - 100.0) + 1.0);
+    assert_eq!(raw, 600.0);
+    // No pedestal for the anode
+    assert_eq!(anode, 50.0);
+}
+
+#[test]
+fn clamped_pedestals_do_not_go_negative() {
+    let hits = || {
+        vec![
+            hit(SCINT_LEFT, 0, 40.0, None, None),
+            hit(ANODE_FRONT, 0, 50.0, None, None),
+        ]
+    };
+    let params = BuildParams {
+        keep_raw_energy: true,
+        ..Default::default()
+    };
+    let (energy, _, _) = energies(&params, hits());
+    assert_eq!(energy, 2.0 * -60.0 + 1.0);
+
+    let params = BuildParams {
+        keep_raw_energy: true,
+        clamp_pedestals: true,
+        ..Default::default()
+    };
+    let (energy, raw, _) = energies(&params, hits());
+    assert_eq!(energy, 1.0);
+    assert_eq!(raw, 40.0);
+}
+
+#[test]
+fn empty_map_leaves_energies_unchanged() {
+    let pedestals = PedestalMap::default();
+    let id = generate_board_channel_uuid(&0, &SCINT_LEFT);
+    assert_eq!(pedestals.subtract(&id, 123.0, true), 123.0);
+    assert_eq!(pedestals.get_pedestal(&id), None);
+}
@@ -0,0 +1,533 @@
+use std::sync::Arc;
+
+mod common;
+
+use common::{
+    hit, on_board, ANODE_BACK, ANODE_FRONT, CEBRA0, DELAY_BACK_LEFT, DELAY_BACK_RIGHT,
+    DELAY_FRONT_LEFT, DELAY_FRONT_RIGHT, SCINT_LEFT,
+};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelData, ChannelDataField, INVALID_TIME, INVALID_VALUE};
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+use eventbuilder::evb::compass_data::{ns_to_ps, CompassData};
+use eventbuilder::evb::expression::Expression;
+use eventbuilder::evb::position::DelayLineCheck;
+
+const TOLERANCE: f64 = 1.0e-9;
+
+// Anodes and ScintLeft at the start of the event, delay line ends at the given offsets (ns).
+// With the default focal plane, x1 = (front left - front right) * 0.5 / 2.1 and
+// x2 = (back left - back right) * 0.5 / 1.98.
+fn focal_plane_event(start: f64, delays: [Option<f64>; 4]) -> Vec<CompassData> {
+    let mut event = vec![
+        hit(SCINT_LEFT, ns_to_ps(start), 1000.0, None, None),
+        hit(ANODE_FRONT, ns_to_ps(start + 1.0), 1000.0, None, None),
+        hit(ANODE_BACK, ns_to_ps(start + 2.0), 1000.0, None, None),
+    ];
+    let channels = [
+        DELAY_FRONT_LEFT,
+        DELAY_FRONT_RIGHT,
+        DELAY_BACK_LEFT,
+        DELAY_BACK_RIGHT,
+    ];
+    for (channel, offset) in channels.into_iter().zip(delays) {
+        if let Some(offset) = offset {
+            event.push(hit(channel, ns_to_ps(start + offset), 1000.0, None, None));
+        }
+    }
+    event
+}
+
+fn build(events: Vec<Vec<CompassData>>) -> ChannelData {
+    build_with_weights(events, Some((0.6, 0.4)))
+}
+
+fn build_with_weights(events: Vec<Vec<CompassData>>, weights: Option<(f64, f64)>) -> ChannelData {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let mut data = ChannelData::new(&map, &BuildParams::default());
+    for event in events {
+        data.append_event(event, &map, weights);
+    }
+    data
+}
+
+fn column(data: &ChannelData, field: ChannelDataField) -> &[f64] {
+    data.fields
+        .get(&field)
+        .unwrap_or_else(|| panic!("missing column {}", field.as_ref()))
+}
+
+fn assert_close(values: &[f64], expected: &[f64]) {
+    assert_eq!(values.len(), expected.len());
+    for (row, (value, expected)) in values.iter().zip(expected).enumerate() {
+        assert!(
+            (value - expected).abs() < TOLERANCE,
+            "row {row}: got {value}, expected {expected}"
+        );
+    }
+}
+
+#[test]
+fn focal_plane_positions() {
+    let data = build(vec![
+        // x1 = 10, x2 = -10
+        focal_plane_event(1000.0, [Some(52.0), Some(10.0), Some(10.0), Some(49.6)]),
+        // x1 = x2 = 0, centered track
+        focal_plane_event(2000.0, [Some(10.0), Some(10.0), Some(20.0), Some(20.0)]),
+        // x1 = -5, x2 = 10
+        focal_plane_event(3000.0, [Some(10.0), Some(31.0), Some(49.6), Some(10.0)]),
+    ]);
+
+    assert_eq!(data.rows, 3);
+    assert_close(column(&data, ChannelDataField::X1), &[10.0, 0.0, -5.0]);
+    assert_close(column(&data, ChannelDataField::X2), &[-10.0, 0.0, 10.0]);
+    assert_close(
+        column(&data, ChannelDataField::Theta),
+        &[(-20.0f64 / 36.0).atan(), 0.0, (15.0f64 / 36.0).atan()],
+    );
+    // Raw weighted sum 0.6 * x1 + 0.4 * x2
+    assert_close(column(&data, ChannelDataField::Xavg), &[2.0, 0.0, 1.0]);
+    assert_close(
+        column(&data, ChannelDataField::AnodeFrontRelTime),
+        &[1.0, 1.0, 1.0],
+    );
+}
+
+#[test]
+fn xavg_without_weights_is_the_mean() {
+    // x1 = 10, x2 = -5
+    let event = || focal_plane_event(1000.0, [Some(52.0), Some(10.0), Some(10.0), Some(29.8)]);
+
+    let weighted = build_with_weights(vec![event()], Some((0.6, 0.4)));
+    assert_close(column(&weighted, ChannelDataField::Xavg), &[4.0]);
+
+    let unweighted = build_with_weights(vec![event()], None);
+    assert_close(column(&unweighted, ChannelDataField::Xavg), &[2.5]);
+
+    // Still invalid when a plane is missing
+    let missing = build_with_weights(
+        vec![focal_plane_event(
+            1000.0,
+            [Some(52.0), Some(10.0), Some(10.0), None],
+        )],
+        None,
+    );
+    assert_close(column(&missing, ChannelDataField::Xavg), &[INVALID_VALUE]);
+}
+
+#[test]
+fn missing_delay_lines_leave_positions_invalid() {
+    let data = build(vec![
+        // No back right, x1 = 10 only
+        focal_plane_event(1000.0, [Some(52.0), Some(10.0), Some(10.0), None]),
+        // No delay lines at all
+        focal_plane_event(2000.0, [None, None, None, None]),
+    ]);
+
+    assert_eq!(data.rows, 2);
+    assert_close(column(&data, ChannelDataField::X1), &[10.0, INVALID_VALUE]);
+    for field in [
+        ChannelDataField::X2,
+        ChannelDataField::Theta,
+        ChannelDataField::Xavg,
+    ] {
+        assert_close(column(&data, field), &[INVALID_VALUE, INVALID_VALUE]);
+    }
+    for field in [ChannelDataField::X, ChannelDataField::Z] {
+        assert_eq!(
+            data.nested_fields[&field],
+            vec![vec![INVALID_VALUE], vec![INVALID_VALUE]]
+        );
+    }
+}
+
+#[test]
+fn disabled_delay_line_leaves_its_plane_invalid() {
+    let mut board = Board::sps(0);
+    board.disabled[DELAY_FRONT_LEFT as usize] = true;
+    let map = ChannelMap::new(&[board]).unwrap();
+    let mut data = ChannelData::new(&map, &BuildParams::default());
+    // x1 = 10 and x2 = -10 if every channel were enabled
+    data.append_event(
+        focal_plane_event(1000.0, [Some(52.0), Some(10.0), Some(10.0), Some(49.6)]),
+        &map,
+        Some((0.6, 0.4)),
+    );
+
+    assert_eq!(
+        data.time_fields[&ChannelDataField::DelayFrontLeftTime],
+        vec![INVALID_TIME]
+    );
+    assert_close(column(&data, ChannelDataField::X1), &[INVALID_VALUE]);
+    assert_close(column(&data, ChannelDataField::X2), &[-10.0]);
+    assert_close(column(&data, ChannelDataField::Xavg), &[INVALID_VALUE]);
+}
+
+#[test]
+fn anode_sum_and_charge_division() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let mut data = ChannelData::new(&map, &BuildParams::default());
+    let anodes = |front: f64, back: f64| {
+        let mut event = focal_plane_event(1000.0, [None, None, None, None]);
+        event[1].energy = front;
+        event[2].energy = back;
+        event
+    };
+    data.append_event(anodes(300.0, 100.0), &map, None);
+    data.append_event(anodes(0.0, 0.0), &map, None);
+    // No back anode
+    data.append_event(
+        vec![hit(ANODE_FRONT, ns_to_ps(1000.0), 1000.0, None, None)],
+        &map,
+        None,
+    );
+
+    assert_close(
+        column(&data, ChannelDataField::XavgCharge),
+        &[0.5, INVALID_VALUE, INVALID_VALUE],
+    );
+    assert_close(
+        column(&data, ChannelDataField::AnodeSumEnergy),
+        &[400.0, 0.0, INVALID_VALUE],
+    );
+    // Front anode 1 ns and back anode 2 ns after the start
+    assert_close(
+        column(&data, ChannelDataField::AnodeTimeDiff),
+        &[-1.0, -1.0, INVALID_VALUE],
+    );
+}
+
+#[test]
+fn cebra_rel_time_follows_the_configured_reference() {
+    let map = ChannelMap::new(&[Board::sps(0), Board::cebra(1)]).unwrap();
+    let cebra0 = on_board(1, hit(CEBRA0, ns_to_ps(1030.0), 1000.0, None, None));
+    // ScintLeft at 1000 ns and AnodeFront at 1001 ns, no AnodeBack
+    let event = || {
+        let mut event = vec![
+            hit(SCINT_LEFT, ns_to_ps(1000.0), 1000.0, None, None),
+            hit(ANODE_FRONT, ns_to_ps(1001.0), 1000.0, None, None),
+        ];
+        event.push(cebra0.clone());
+        event
+    };
+    let field = ChannelDataField::Cebra0RelTime;
+
+    // The default scintillator reference also needs AnodeBack
+    let mut data = ChannelData::new(&map, &BuildParams::default());
+    data.append_event(event(), &map, None);
+    assert_close(column(&data, field.clone()), &[INVALID_VALUE]);
+
+    let params = BuildParams {
+        cebra_reference: Some(ChannelType::AnodeFront),
+        ..Default::default()
+    };
+    let mut data = ChannelData::new(&map, &params);
+    data.append_event(event(), &map, None);
+    assert_close(column(&data, field.clone()), &[29.0]);
+
+    // Not written when the map has no such reference
+    let params = BuildParams {
+        cebra_reference: Some(ChannelType::PIPS1000),
+        ..Default::default()
+    };
+    let data = ChannelData::new(&map, &params);
+    assert!(!data.fields.contains_key(&field));
+}
+
+#[test]
+fn partial_focal_plane_leaves_derived_positions_invalid() {
+    let data = build(vec![
+        // Both planes, so a later event could only go wrong by reading stale positions
+        focal_plane_event(1000.0, [Some(52.0), Some(10.0), Some(10.0), Some(49.6)]),
+        // X1 only
+        focal_plane_event(2000.0, [Some(52.0), Some(10.0), None, Some(49.6)]),
+        // X2 only
+        focal_plane_event(3000.0, [Some(52.0), None, Some(10.0), Some(49.6)]),
+        // Neither
+        focal_plane_event(4000.0, [None, Some(10.0), Some(10.0), None]),
+    ]);
+
+    assert_close(
+        column(&data, ChannelDataField::X1),
+        &[10.0, 10.0, INVALID_VALUE, INVALID_VALUE],
+    );
+    assert_close(
+        column(&data, ChannelDataField::X2),
+        &[-10.0, INVALID_VALUE, -10.0, INVALID_VALUE],
+    );
+    for field in [ChannelDataField::Theta, ChannelDataField::Xavg] {
+        let values = column(&data, field.clone());
+        assert_ne!(values[0], INVALID_VALUE, "{}", field.as_ref());
+        assert_close(&values[1..], &[INVALID_VALUE; 3]);
+    }
+    for field in [ChannelDataField::X, ChannelDataField::Z] {
+        let values = &data.nested_fields[&field];
+        assert_eq!(values[0].len(), 400);
+        assert_eq!(
+            &values[1..],
+            &[
+                vec![INVALID_VALUE],
+                vec![INVALID_VALUE],
+                vec![INVALID_VALUE]
+            ]
+        );
+    }
+}
+
+#[test]
+fn vertical_scintillators_flow_through() {
+    // Spare channels 2 and 3 of the SPS board
+    let mut board = Board::sps(0);
+    board.channels[2] = ChannelType::ScintTop;
+    board.channels[3] = ChannelType::ScintBottom;
+    let map = ChannelMap::new(&[board]).unwrap();
+    let mut data = ChannelData::new(&map, &BuildParams::default());
+    data.append_event(
+        vec![
+            hit(2, ns_to_ps(1000.0), 1000.0, None, None),
+            hit(3, ns_to_ps(1003.5), 1000.0, None, None),
+        ],
+        &map,
+        None,
+    );
+    data.append_event(
+        vec![hit(2, ns_to_ps(2000.0), 1000.0, None, None)],
+        &map,
+        None,
+    );
+
+    assert_close(
+        column(&data, ChannelDataField::ScintTopEnergy),
+        &[1000.0; 2],
+    );
+    assert_eq!(
+        data.time_fields[&ChannelDataField::ScintBottomTime],
+        vec![ns_to_ps(1003.5), INVALID_TIME]
+    );
+    assert_close(
+        column(&data, ChannelDataField::ScintVerticalTimeDiff),
+        &[-3.5, INVALID_VALUE],
+    );
+
+    // Not written without the pair
+    let data = ChannelData::new(
+        &ChannelMap::new(&[Board::sps(0)]).unwrap(),
+        &BuildParams::default(),
+    );
+    assert!(!data.fields.contains_key(&ChannelDataField::ScintTopEnergy));
+    assert!(!data
+        .fields
+        .contains_key(&ChannelDataField::ScintVerticalTimeDiff));
+}
+
+#[test]
+fn position_dependent_xavg_weights() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let build_with_slopes = |slopes: Option<(f64, f64)>| {
+        let params = BuildParams {
+            xavg_weight_slopes: slopes,
+            ..Default::default()
+        };
+        let mut data = ChannelData::new(&map, &params);
+        // x1 = 10, x2 = -10 and x1 = -5, x2 = 10
+        for event in [
+            focal_plane_event(1000.0, [Some(52.0), Some(10.0), Some(10.0), Some(49.6)]),
+            focal_plane_event(3000.0, [Some(10.0), Some(31.0), Some(49.6), Some(10.0)]),
+        ] {
+            data.append_event(event, &map, Some((0.6, 0.4)));
+        }
+        data
+    };
+
+    let constant = build_with_slopes(None);
+    let flat = build_with_slopes(Some((0.0, 0.0)));
+    assert_eq!(
+        column(&flat, ChannelDataField::Xavg),
+        column(&constant, ChannelDataField::Xavg)
+    );
+
+    // Weights (0.7, 0.3) at x1 = 10 and (0.55, 0.45) at x1 = -5
+    let sloped = build_with_slopes(Some((0.01, -0.01)));
+    assert_close(column(&sloped, ChannelDataField::Xavg), &[4.0, 1.75]);
+}
+
+#[test]
+fn cebra_multiplicity_counts_detectors() {
+    let map = ChannelMap::new(&[Board::sps(0), Board::cebra(1)]).unwrap();
+    let cebra = |channel: u32, time_ns: f64| {
+        on_board(1, hit(channel, ns_to_ps(time_ns), 1000.0, None, None))
+    };
+    let mut data = ChannelData::new(&map, &BuildParams::default());
+    // Cebra0 fires twice and is only counted once
+    data.append_event(
+        vec![cebra(0, 1000.0), cebra(0, 1001.0), cebra(3, 1002.0)],
+        &map,
+        None,
+    );
+    data.append_event(
+        vec![hit(SCINT_LEFT, ns_to_ps(2000.0), 1000.0, None, None)],
+        &map,
+        None,
+    );
+    assert_close(
+        column(&data, ChannelDataField::CebraMultiplicity),
+        &[2.0, 0.0],
+    );
+
+    // Not written without any CeBrA in the map
+    let data = ChannelData::new(
+        &ChannelMap::new(&[Board::sps(0)]).unwrap(),
+        &BuildParams::default(),
+    );
+    assert!(!data
+        .fields
+        .contains_key(&ChannelDataField::CebraMultiplicity));
+}
+
+#[test]
+fn detector_multiplicities_count_hits() {
+    let map = ChannelMap::new(&[Board::sps(0), Board::cebra(1)]).unwrap();
+    let params = BuildParams {
+        multiplicity: true,
+        ..Default::default()
+    };
+    let cebra = |channel: u32, time_ns: f64| {
+        on_board(1, hit(channel, ns_to_ps(time_ns), 1000.0, None, None))
+    };
+    let mut data = ChannelData::new(&map, &params);
+    data.append_event(
+        vec![cebra(0, 1000.0), cebra(0, 1001.0), cebra(3, 1002.0)],
+        &map,
+        None,
+    );
+    assert_close(column(&data, ChannelDataField::Cebra0Multiplicity), &[2.0]);
+    assert_close(column(&data, ChannelDataField::Cebra3Multiplicity), &[1.0]);
+    assert_close(column(&data, ChannelDataField::Cebra1Multiplicity), &[0.0]);
+    assert_close(
+        column(&data, ChannelDataField::ScintLeftMultiplicity),
+        &[0.0],
+    );
+}
+
+#[test]
+fn get_value_reads_the_last_event() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let mut data = ChannelData::new(&map, &BuildParams::default());
+    assert_eq!(data.get_value(&ChannelDataField::ScintLeftEnergy), None);
+
+    data.append_event(
+        focal_plane_event(0.0, [Some(4.2), Some(0.0), None, None]),
+        &map,
+        None,
+    );
+    data.append_event(
+        vec![
+            hit(SCINT_LEFT, ns_to_ps(1000.0), 1000.0, None, None),
+            hit(ANODE_FRONT, ns_to_ps(1003.0), 1000.0, None, None),
+        ],
+        &map,
+        None,
+    );
+    assert_eq!(
+        data.get_value(&ChannelDataField::ScintLeftEnergy),
+        Some(1000.0)
+    );
+    assert_close(
+        &[data.get_value(&ChannelDataField::ScintLeftTime).unwrap()],
+        &[1000.0],
+    );
+    assert_close(
+        &[data
+            .get_value(&ChannelDataField::AnodeFrontRelTime)
+            .unwrap()],
+        &[3.0],
+    );
+    // Unset in this event, and not one of the fields
+    assert_eq!(data.get_value(&ChannelDataField::X1), None);
+    assert_eq!(data.get_value(&ChannelDataField::Cebra0Energy), None);
+}
+
+#[test]
+fn hit_pattern_selects_exact_patterns() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        hit_pattern: true,
+        ..Default::default()
+    };
+    let mut data = ChannelData::new(&map, &params);
+    // Both front delay lines, and ScintLeft and AnodeFront twice
+    data.append_event(
+        vec![
+            hit(SCINT_LEFT, ns_to_ps(0.0), 1000.0, None, None),
+            hit(ANODE_FRONT, ns_to_ps(1.0), 1000.0, None, None),
+            hit(ANODE_FRONT, ns_to_ps(2.0), 1000.0, None, None),
+            hit(DELAY_FRONT_LEFT, ns_to_ps(3.0), 1000.0, None, None),
+            hit(DELAY_FRONT_RIGHT, ns_to_ps(4.0), 1000.0, None, None),
+        ],
+        &map,
+        None,
+    );
+    data.append_event(focal_plane_event(1000.0, [Some(1.0); 4]), &map, None);
+
+    let pattern = ChannelType::bitmask(&[
+        ChannelType::ScintLeft,
+        ChannelType::AnodeFront,
+        ChannelType::DelayFrontLeft,
+        ChannelType::DelayFrontRight,
+    ]);
+    assert_eq!(pattern, 0b1100101);
+    let masks: Vec<u64> = column(&data, ChannelDataField::HitPattern)
+        .iter()
+        .map(|mask| *mask as u64)
+        .collect();
+    assert_eq!(masks[0], pattern);
+    assert_ne!(masks[1], pattern);
+    assert_eq!(masks[1] & pattern, pattern);
+}
+
+#[test]
+fn hit_pattern_keeps_the_detector_bitmask_names() {
+    let params: BuildParams = serde_yaml::from_str("detector_bitmask: true").unwrap();
+    assert!(params.hit_pattern);
+    assert_eq!(
+        ChannelDataField::from_name("DetectorBitmask"),
+        Some(ChannelDataField::HitPattern)
+    );
+    assert_eq!(
+        Expression::parse("DetectorBitmask * 2"),
+        Expression::parse("HitPattern * 2")
+    );
+    assert!(!BuildParams::default().hit_pattern);
+}
+
+#[test]
+fn delay_line_check_counts_negative_differences() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let check = Arc::new(DelayLineCheck::default());
+    let mut data = ChannelData::new(&map, &BuildParams::default());
+    data.set_delay_line_check(check.clone());
+    data.append_event(
+        focal_plane_event(0.0, [Some(1.0), Some(5.0), Some(5.0), Some(1.0)]),
+        &map,
+        None,
+    );
+    data.append_event(
+        focal_plane_event(1000.0, [Some(2.0), Some(6.0), None, None]),
+        &map,
+        None,
+    );
+
+    // Copies made for parallel chunks and streamed row groups count into the same check
+    let mut copy = data.empty_copy();
+    copy.append_event(
+        focal_plane_event(2000.0, [Some(7.0), Some(6.0), Some(1.0), None]),
+        &map,
+        None,
+    );
+
+    assert_eq!(check.counts(), [(3, 2), (1, 0)]);
+    let [x1, x2] = check.negative_fractions();
+    assert!((x1.unwrap() - 2.0 / 3.0).abs() < TOLERANCE);
+    assert_eq!(x2, Some(0.0));
+    assert_eq!(DelayLineCheck::default().negative_fractions(), [None, None]);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::{hit, on_board, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelData, ChannelDataField};
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use polars::prelude::*;
+use strum::IntoEnumIterator;
+
+// Board::sps on board 2
+fn build(params: &BuildParams) -> (ChannelData, DataFrame) {
+    let map = ChannelMap::new(&[Board::sps(2)]).unwrap();
+    let events = vec![vec![on_board(2, hit(SCINT_LEFT, 0, 1000.0, None, None))]];
+    let data = common::build_data(&map, params, events);
+    let df = data.clone().into_dataframe().unwrap();
+    (data, df)
+}
+
+#[test]
+fn hits_are_written_with_their_board_and_channel() {
+    let params = BuildParams {
+        provenance_columns: true,
+        ..Default::default()
+    };
+    let (_, df) = build(&params);
+    let board = df.column("ScintLeftBoard").unwrap();
+    assert_eq!(board.u64().unwrap().get(0), Some(2));
+    assert_eq!(
+        df.column("ScintLeftChannel").unwrap().u64().unwrap().get(0),
+        Some(SCINT_LEFT as u64)
+    );
+    // No hit of the anode, no board
+    assert_eq!(
+        df.column("AnodeFrontBoard").unwrap().u64().unwrap().get(0),
+        None
+    );
+}
+
+#[test]
+fn unfired_detectors_keep_the_sentinel() {
+    let params = BuildParams {
+        provenance_columns: true,
+        keep_invalid_sentinel: true,
+        ..Default::default()
+    };
+    let (data, df) = build(&params);
+    // Not board 0 channel 0, which is a real channel
+    let board = df.column("AnodeFrontBoard").unwrap();
+    assert_eq!(board.i64().unwrap().get(0), Some(-1_000_000));
+    let channel = df.column("AnodeFrontChannel").unwrap();
+    assert_eq!(channel.i64().unwrap().get(0), Some(-1_000_000));
+    let board = df.column("ScintLeftBoard").unwrap();
+    assert_eq!(board.i64().unwrap().get(0), Some(2));
+
+    let map = ChannelMap::new(&[Board::sps(2)]).unwrap();
+    let loaded = ChannelData::from_dataframe(&df, &map, &params).unwrap();
+    assert_eq!(loaded.fields, data.fields);
+}
+
+#[test]
+fn every_detector_energy_has_a_board_and_channel() {
+    for field in ChannelDataField::iter() {
+        if field.raw_energy_field().is_none() {
+            continue;
+        }
+        let (board, channel) = field.provenance_fields().unwrap();
+        assert_eq!(board.as_ref(), field.as_ref().replace("Energy", "Board"));
+        assert_eq!(
+            channel.as_ref(),
+            field.as_ref().replace("Energy", "Channel")
+        );
+        assert_eq!(board.provenance_energy_field(), Some(field.clone()));
+        assert_eq!(channel.provenance_energy_field(), Some(field.clone()));
+    }
+    assert_eq!(ChannelDataField::AnodeSumEnergy.provenance_fields(), None);
+}
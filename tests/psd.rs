@@ -0,0 +1,56 @@
+mod common;
+
+use common::{hit, on_board, values, CEBRA0, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use polars::prelude::*;
+
+// Board::sps on board 0 and Board::cebra on board 1
+fn build(params: &BuildParams) -> DataFrame {
+    let map = ChannelMap::new(&[Board::sps(0), Board::cebra(1)]).unwrap();
+    let events = vec![
+        vec![
+            hit(SCINT_LEFT, 0, 1000.0, Some(250.0), None),
+            on_board(1, hit(CEBRA0, 10, 800.0, Some(600.0), None)),
+        ],
+        // No short gate energy, then a raw energy of zero
+        vec![hit(SCINT_LEFT, 1_000_000, 1000.0, None, None)],
+        vec![on_board(1, hit(CEBRA0, 2_000_000, 0.0, Some(0.0), None))],
+    ];
+    common::build(&map, params, events)
+}
+
+#[test]
+fn psd_is_written_for_the_scintillators_and_cebra() {
+    let params = BuildParams {
+        psd_fields: true,
+        ..Default::default()
+    };
+    let df = build(&params);
+    assert_eq!(values(&df, "ScintLeftPSD"), vec![Some(0.75), None, None]);
+    assert_eq!(values(&df, "Cebra0PSD"), vec![Some(0.25), None, None]);
+    // Only for the mapped detectors
+    assert!(df.column("ScintTopPSD").is_err());
+    assert!(df.column("Cebra9PSD").is_err());
+}
+
+#[test]
+fn psd_columns_are_optional() {
+    let df = build(&BuildParams::default());
+    assert!(!df
+        .get_column_names()
+        .iter()
+        .any(|name| name.ends_with("PSD")));
+
+    // There is nothing to calculate them from without the short gate
+    let params = BuildParams {
+        psd_fields: true,
+        no_short_gate: true,
+        ..Default::default()
+    };
+    let df = build(&params);
+    assert!(!df
+        .get_column_names()
+        .iter()
+        .any(|name| name.ends_with("PSD")));
+}
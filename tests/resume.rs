@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+mod common;
+
+use common::{hit, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::ChannelData;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_data::CompassData;
+use eventbuilder::evb::stream_writer::StreamWriter;
+use polars::prelude::*;
+
+fn event(timestamp: i64) -> Vec<CompassData> {
+    vec![hit(SCINT_LEFT, timestamp, timestamp as f64, None, None)]
+}
+
+fn event_times(path: &Path) -> Vec<Option<i64>> {
+    let df = ParquetReader::new(File::open(path).unwrap())
+        .finish()
+        .unwrap();
+    df.column("EventTime")
+        .unwrap()
+        .i64()
+        .unwrap()
+        .into_iter()
+        .collect()
+}
+
+// Part and temporary files left next to the output file
+fn leftovers(path: &Path) -> Vec<PathBuf> {
+    [".part0", ".part1", ".part2", ".tmp"]
+        .iter()
+        .map(|suffix| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(suffix);
+            PathBuf::from(name)
+        })
+        .filter(|leftover| leftover.exists())
+        .collect()
+}
+
+#[test]
+fn resumed_files_keep_their_events() {
+    let path = std::env::temp_dir().join(format!("resume_{}.parquet", std::process::id()));
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        stream_output: true,
+        integer_timestamps: true,
+        ..Default::default()
+    };
+    let compression = params.parquet_compression().unwrap();
+
+    let mut data = ChannelData::new(&map, &params);
+    let mut writer = StreamWriter::new(&path, &data, 2, compression).unwrap();
+    assert_eq!(writer.resumed_after(), None);
+    for timestamp in [1_000, 2_000_000, 3_000_000] {
+        data.append_event(event(timestamp), &map, None);
+        writer.write_if_full(&mut data).unwrap();
+    }
+    writer.finish(&mut data, &[]).unwrap();
+
+    let mut data = ChannelData::new(&map, &params);
+    let writer = StreamWriter::resume(&path, &data, 2, compression).unwrap();
+    assert_eq!(writer.resumed_after(), Some(3_000_000));
+    assert_eq!(writer.rows_written(), 3);
+    data.append_event(event(4_000_000), &map, None);
+    writer.finish(&mut data, &[]).unwrap();
+
+    let df = ParquetReader::new(File::open(&path).unwrap())
+        .finish()
+        .unwrap();
+    let times: Vec<Option<i64>> = df
+        .column("EventTime")
+        .unwrap()
+        .i64()
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(
+        times,
+        vec![
+            Some(1_000),
+            Some(2_000_000),
+            Some(3_000_000),
+            Some(4_000_000)
+        ]
+    );
+    assert!(leftovers(&path).is_empty());
+
+    // Other columns are an error, and the file is left as it was
+    let other = BuildParams {
+        multiplicity: true,
+        ..params.clone()
+    };
+    let data = ChannelData::new(&map, &other);
+    assert!(matches!(
+        StreamWriter::resume(&path, &data, 2, compression),
+        Err(PolarsError::SchemaMismatch(_))
+    ));
+    let unchanged = ParquetReader::new(File::open(&path).unwrap())
+        .finish()
+        .unwrap();
+    assert!(unchanged.equals_missing(&df));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn interrupted_builds_resume_from_their_last_checkpoint() {
+    let path = std::env::temp_dir().join(format!("resume_crash_{}.parquet", std::process::id()));
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        stream_output: true,
+        integer_timestamps: true,
+        ..Default::default()
+    };
+    let compression = params.parquet_compression().unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    // A part is closed every row group of two events, the fifth event is still buffered at the crash
+    let mut data = ChannelData::new(&map, &params);
+    let mut writer = StreamWriter::new(&path, &data, 2, compression).unwrap();
+    writer.set_checkpoint_groups(1);
+    for timestamp in [1_000, 2_000, 3_000, 4_000, 5_000] {
+        data.append_event(event(timestamp), &map, None);
+        writer.write_if_full(&mut data).unwrap();
+    }
+    drop(writer);
+    assert!(!path.exists());
+    assert!(StreamWriter::can_resume(&path));
+
+    let mut data = ChannelData::new(&map, &params);
+    let writer = StreamWriter::resume(&path, &data, 2, compression).unwrap();
+    assert_eq!(writer.resumed_after(), Some(4_000));
+    assert_eq!(writer.rows_written(), 4);
+    data.append_event(event(5_000), &map, None);
+    writer.finish(&mut data, &[]).unwrap();
+    assert_eq!(
+        event_times(&path),
+        vec![
+            Some(1_000),
+            Some(2_000),
+            Some(3_000),
+            Some(4_000),
+            Some(5_000)
+        ]
+    );
+    assert!(leftovers(&path).is_empty());
+
+    // A resume stopped before finish leaves the finished file as it was, and can itself be resumed
+    let data = ChannelData::new(&map, &params);
+    drop(StreamWriter::resume(&path, &data, 2, compression).unwrap());
+    assert_eq!(event_times(&path).len(), 5);
+    let mut data = ChannelData::new(&map, &params);
+    let writer = StreamWriter::resume(&path, &data, 2, compression).unwrap();
+    assert_eq!(writer.resumed_after(), Some(5_000));
+    assert_eq!(writer.rows_written(), 5);
+    data.append_event(event(6_000), &map, None);
+    writer.finish(&mut data, &[]).unwrap();
+    assert_eq!(event_times(&path).len(), 6);
+    assert!(leftovers(&path).is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
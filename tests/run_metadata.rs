@@ -0,0 +1,38 @@
+use std::fs::File;
+
+use eventbuilder::evb::channel_map::Board;
+use eventbuilder::evb::metadata::{channel_map_hash, read_metadata, run_metadata};
+use polars::prelude::*;
+
+#[test]
+fn channel_map_hash_follows_the_map() {
+    let map = vec![Board::sps(0), Board::cebra(1)];
+    assert_eq!(channel_map_hash(&map), channel_map_hash(&map.clone()));
+
+    let swapped = vec![Board::cebra(0), Board::sps(1)];
+    assert_ne!(channel_map_hash(&map), channel_map_hash(&swapped));
+}
+
+#[test]
+fn run_metadata_is_read_back() {
+    let path = std::env::temp_dir().join(format!("run_metadata_{}.parquet", std::process::id()));
+    let hash = channel_map_hash(&[Board::sps(0)]);
+    let df = df!("X1" => [1.0, 2.0]).unwrap();
+    let mut file = File::create(&path).unwrap();
+    let mut writer = ParquetWriter::new(&mut file).batched(df.schema()).unwrap();
+    writer.write_batch(&df).unwrap();
+    writer
+        .get_writer()
+        .lock()
+        .unwrap()
+        .end(Some(run_metadata(42, &hash)))
+        .unwrap();
+    drop(file);
+
+    let metadata = read_metadata(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(metadata["RunNumber"], "42");
+    assert_eq!(metadata["ChannelMapHash"], hash);
+    assert_eq!(metadata["EventBuilderVersion"], env!("CARGO_PKG_VERSION"));
+    assert!(metadata["BuildTimestamp"].parse::<u64>().unwrap() > 0);
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use common::{hit, on_board};
+use eventbuilder::evb::scaler_list::ScalerCounts;
+
+#[test]
+fn counts_every_channel() {
+    let mut counts = ScalerCounts::default();
+    // One second of hits, board 5 is not in any channel map
+    for hit in [
+        hit(1, 0, 100.0, None, None),
+        hit(1, 500_000_000_000, 100.0, None, None),
+        on_board(5, hit(3, 1_000_000_000_000, 100.0, None, None)),
+    ] {
+        counts.count(&hit);
+    }
+
+    assert_eq!(counts.total(), 3);
+    assert_eq!(counts.channel_count(0, 1), 2);
+    assert_eq!(counts.channel_count(5, 3), 1);
+    assert_eq!(counts.channel_count(0, 2), 0);
+    assert_eq!(counts.channel_rate(0, 1), Some(2.0));
+    assert_eq!(counts.channels(), vec![(0, 1, 2), (5, 3, 1)]);
+}
+
+#[test]
+fn no_rate_without_duration() {
+    let mut counts = ScalerCounts::default();
+    counts.count(&hit(1, 10, 100.0, None, None));
+    assert_eq!(counts.channel_rate(0, 1), None);
+}
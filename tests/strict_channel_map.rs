@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::Board;
+use eventbuilder::evb::compass_run::{process_runs, ProcessParams};
+use eventbuilder::evb::error::EVBError;
+use eventbuilder::evb::kinematics::KineParameters;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use polars::prelude::*;
+
+// CoMPASS file with the energy only: board, channel, timestamp, energy, flags for each hit
+fn compass_file(hits: &[(u16, u16, u64)]) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend_from_slice(&0x0001u16.to_le_bytes());
+    for (board, channel, timestamp) in hits {
+        bytes.extend_from_slice(&board.to_le_bytes());
+        bytes.extend_from_slice(&channel.to_le_bytes());
+        bytes.extend_from_slice(&timestamp.to_le_bytes());
+        bytes.extend_from_slice(&1000u16.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+    }
+    bytes
+}
+
+// Directories of a run 1 archive holding these files, cleared first
+fn run_dir(name: &str, files: &[(&str, Vec<u8>)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("strict_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for sub in ["archive", "unpack", "output", "scalers"] {
+        std::fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    let archive = File::create(dir.join("archive").join("run_1.tar.gz")).unwrap();
+    let mut builder = tar::Builder::new(GzEncoder::new(archive, Compression::default()));
+    for (name, bytes) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, name, bytes.as_slice())
+            .unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+    dir
+}
+
+fn process(dir: &Path, build_params: BuildParams) -> Result<(), EVBError> {
+    let params = ProcessParams {
+        archive_dir: dir.join("archive"),
+        unpack_dir: dir.join("unpack"),
+        output_dir: dir.join("output"),
+        channel_map: vec![Board::sps(0)],
+        scaler_list: vec![],
+        shift_map: vec![],
+        pedestal_map: vec![],
+        calibration_map: vec![],
+        time_resolutions: vec![],
+        build_params,
+        coincidence_window: 3000.0,
+        max_events: None,
+        run_min: 1,
+        run_max: 2,
+        progress_callback: None,
+    };
+    process_runs(params, KineParameters::default(), Arc::new(Mutex::new(0.0)))
+}
+
+#[test]
+fn unmapped_channel_files_fail_before_any_output() {
+    // Channel 13 is the AnodeFront of the SPS board, channel 2 has no type
+    let dir = run_dir(
+        "files",
+        &[
+            ("Data_CH13@V1725_0.BIN", compass_file(&[(0, 13, 1_000)])),
+            ("Data_CH2@V1725_0.BIN", compass_file(&[(0, 2, 2_000)])),
+        ],
+    );
+    let output = dir.join("output").join("run_1.parquet");
+
+    process(&dir, BuildParams::default()).unwrap();
+    assert!(output.exists());
+    std::fs::remove_file(&output).unwrap();
+
+    for stream_output in [false, true] {
+        let params = BuildParams {
+            strict_channel_map: true,
+            stream_output,
+            ..Default::default()
+        };
+        assert!(matches!(
+            process(&dir, params),
+            Err(EVBError::UnmappedChannel {
+                board: 0,
+                channel: 2
+            })
+        ));
+        assert_eq!(std::fs::read_dir(dir.join("output")).unwrap().count(), 0);
+        assert_eq!(std::fs::read_dir(dir.join("unpack")).unwrap().count(), 0);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn later_unmapped_hits_leave_a_closed_file() {
+    // Both channels in one file, the unmapped hit after two events
+    let dir = run_dir(
+        "mixed",
+        &[(
+            "Data_V1725_0.BIN",
+            compass_file(&[(0, 13, 1_000), (0, 13, 10_000_000), (0, 2, 20_000_000)]),
+        )],
+    );
+    let params = BuildParams {
+        strict_channel_map: true,
+        stream_output: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        process(&dir, params),
+        Err(EVBError::UnmappedChannel {
+            board: 0,
+            channel: 2
+        })
+    ));
+
+    // Finished with its footer, holding the events completed before the unmapped hit. The last one is left
+    // out, the unmapped hit could have been part of it
+    let output = dir.join("output").join("run_1.parquet");
+    let df = ParquetReader::new(File::open(&output).unwrap())
+        .finish()
+        .unwrap();
+    assert_eq!(df.height(), 1);
+    assert_eq!(std::fs::read_dir(dir.join("unpack")).unwrap().count(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,155 @@
+use std::f64::consts::PI;
+
+use eventbuilder::evb::position::FocalPlaneConfig;
+
+const TOLERANCE: f64 = 1.0e-12;
+
+#[test]
+fn theta_positive_difference() {
+    let config = FocalPlaneConfig::default();
+    // x2 - x1 = 36, one separation
+    let theta = config.theta(-18.0, 18.0);
+    assert!((theta - PI * 0.25).abs() < TOLERANCE);
+}
+
+#[test]
+fn theta_negative_difference() {
+    let config = FocalPlaneConfig::default();
+    // x2 - x1 = -36
+    let theta = config.theta(18.0, -18.0);
+    assert!((theta + PI * 0.25).abs() < TOLERANCE);
+}
+
+#[test]
+fn theta_zero_difference() {
+    let config = FocalPlaneConfig::default();
+    // Between the small negative and positive differences, not the pi / 2 of a very large one
+    assert_eq!(config.theta(5.0, 5.0), 0.0);
+    assert_eq!(config.theta(0.0, -0.0), 0.0);
+}
+
+#[test]
+fn theta_is_continuous_across_zero() {
+    let config = FocalPlaneConfig::default();
+    let mut previous: Option<f64> = None;
+    for step in -1000..=1000 {
+        let diff = step as f64 * 1.0e-6;
+        let theta = config.theta(0.0, diff);
+        assert!((-PI * 0.5..PI * 0.5).contains(&theta), "{diff}: {theta}");
+        // Modulo pi, neighbouring differences give neighbouring angles, zero included
+        if let Some(previous) = previous {
+            let step = (theta - previous).rem_euclid(PI);
+            assert!(
+                step.min(PI - step) < 1.0e-7,
+                "{diff}: {previous} -> {theta}"
+            );
+            // Without wrapping around either
+            assert!(
+                (theta - previous).abs() < 1.0e-7,
+                "{diff}: {previous} -> {theta}"
+            );
+        }
+        previous = Some(theta);
+    }
+    assert!(config.theta(0.0, 1.0e-12).abs() < 1.0e-12);
+    assert!(config.theta(0.0, -1.0e-12).abs() < 1.0e-12);
+    // pi / 2 is only approached by large differences
+    assert!((config.theta(0.0, 1.0e9) - PI * 0.5).abs() < 1.0e-6);
+    assert!((config.theta(0.0, -1.0e9) + PI * 0.5).abs() < 1.0e-6);
+}
+
+#[test]
+fn theta_reversed_orientation() {
+    let config = FocalPlaneConfig {
+        reverse_theta: true,
+        ..Default::default()
+    };
+    assert!((config.theta(-18.0, 18.0) + PI * 0.25).abs() < TOLERANCE);
+    assert!((config.theta(18.0, -18.0) - PI * 0.25).abs() < TOLERANCE);
+    assert_eq!(config.theta(5.0, 5.0), 0.0);
+}
+
+#[test]
+fn theta_uses_separation() {
+    let config = FocalPlaneConfig {
+        theta_separation: 72.0,
+        ..Default::default()
+    };
+    let theta = config.theta(0.0, 72.0);
+    assert!((theta - PI * 0.25).abs() < TOLERANCE);
+}
+
+#[test]
+fn neutral_calibration_is_unchanged() {
+    let config = FocalPlaneConfig::default();
+    assert_eq!(config.theta_gain, 1.0);
+    assert_eq!(config.theta_offset, 0.0);
+    for (x1, x2) in [(-18.0, 18.0), (18.0, -18.0), (5.0, 5.0), (-3.7, 12.9)] {
+        let diff: f64 = x2 - x1;
+        assert_eq!(config.theta(x1, x2), (diff / 36.0).atan());
+    }
+}
+
+#[test]
+fn legacy_range_matches_the_old_output() {
+    let config = FocalPlaneConfig {
+        legacy_theta_range: true,
+        ..Default::default()
+    };
+    for (x1, x2) in [
+        (-18.0, 18.0),
+        (18.0, -18.0),
+        (5.0, 5.0),
+        (-3.7, 12.9),
+        (0.0, -0.0),
+    ] {
+        let diff: f64 = x2 - x1;
+        let expected = if diff < 0.0 {
+            PI + (diff / 36.0).atan()
+        } else {
+            (diff / 36.0).atan()
+        };
+        assert_eq!(config.theta(x1, x2), expected);
+    }
+    assert!((config.theta(18.0, -18.0) - PI * 0.75).abs() < TOLERANCE);
+
+    // Only the negative angles move, by pi, before the offset
+    let offset = FocalPlaneConfig {
+        theta_offset: 0.01,
+        ..config.clone()
+    };
+    assert!((offset.theta(18.0, -18.0) - (PI * 0.75 + 0.01)).abs() < TOLERANCE);
+    assert!((offset.theta(-18.0, 18.0) - (PI * 0.25 + 0.01)).abs() < TOLERANCE);
+    let current = FocalPlaneConfig::default();
+    assert_eq!(config.theta(18.0, -18.0), current.theta(18.0, -18.0) + PI);
+}
+
+#[test]
+fn theta_gain_and_offset() {
+    let config = FocalPlaneConfig {
+        theta_gain: 2.0,
+        theta_offset: 0.01,
+        ..Default::default()
+    };
+    // Ratio 0.5 doubled to one
+    assert!((config.theta(-9.0, 9.0) - (PI * 0.25 + 0.01)).abs() < TOLERANCE);
+    assert!((config.theta(9.0, -9.0) - (-PI * 0.25 + 0.01)).abs() < TOLERANCE);
+    assert!((config.theta(5.0, 5.0) - 0.01).abs() < TOLERANCE);
+}
+
+#[test]
+fn plane_separation_only_moves_the_track() {
+    let config = FocalPlaneConfig::default();
+    // The SE-SPS planes are 42.8625 mm apart
+    assert!((config.x_at(-10.0, 10.0, -42.8625 * 0.5) + 10.0).abs() < TOLERANCE);
+    assert!((config.x_at(-10.0, 10.0, 42.8625 * 0.5) - 10.0).abs() < TOLERANCE);
+    assert!(config.x_at(-10.0, 10.0, 0.0).abs() < TOLERANCE);
+
+    let wider = FocalPlaneConfig {
+        plane_separation: 50.0,
+        ..Default::default()
+    };
+    assert!((wider.x_at(-10.0, 10.0, 25.0) - 10.0).abs() < TOLERANCE);
+    assert!((wider.x_at(-10.0, 10.0, 12.5) - 5.0).abs() < TOLERANCE);
+    assert_eq!(wider.theta(-18.0, 18.0), config.theta(-18.0, 18.0));
+}
@@ -0,0 +1,18 @@
+use eventbuilder::evb::event_builder::TimeOrderCheck;
+
+#[test]
+fn counts_hits_earlier_than_the_one_before() {
+    let mut check = TimeOrderCheck::default();
+    for timestamp in [10, 20, 20, 15, 30, 25, 40] {
+        check.push(timestamp);
+    }
+    assert_eq!(check.out_of_order, 2);
+    assert_eq!(check.first_out_of_order, Some(15));
+
+    let mut sorted = TimeOrderCheck::default();
+    for timestamp in [1, 2, 2, 3] {
+        sorted.push(timestamp);
+    }
+    assert_eq!(sorted.out_of_order, 0);
+    assert_eq!(sorted.first_out_of_order, None);
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::{hit, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_data::CompassData;
+use eventbuilder::evb::event_builder::{build_events, build_events_in, TimeSlice};
+use eventbuilder::evb::validation::{validate_configuration, ConfigProblem};
+
+// ps
+const NS: i64 = 1_000;
+
+fn timestamps(events: impl Iterator<Item = Vec<CompassData>>) -> Vec<Vec<i64>> {
+    events
+        .map(|event| event.iter().map(|hit| hit.timestamp / NS).collect())
+        .collect()
+}
+
+#[test]
+fn events_are_kept_whole_by_their_first_hit() {
+    // 100 ns window, the event opened at 950 ns straddles the start and the one at 1950 ns the stop
+    let hits: Vec<CompassData> = [0, 950, 1010, 1200, 1950, 2020, 2500]
+        .into_iter()
+        .map(|t| hit(SCINT_LEFT, t * NS, 1000.0, None, None))
+        .collect();
+    let slice = TimeSlice {
+        start: Some(1000 * NS),
+        stop: Some(2000 * NS),
+    };
+    assert_eq!(
+        timestamps(build_events_in(hits.clone(), 100.0, slice)),
+        vec![vec![1200], vec![1950, 2020]]
+    );
+
+    // The same events as the whole run
+    let whole: Vec<Vec<i64>> = timestamps(build_events(hits.clone(), 100.0))
+        .into_iter()
+        .filter(|event| (1000..2000).contains(&event[0]))
+        .collect();
+    assert_eq!(whole, vec![vec![1200], vec![1950, 2020]]);
+
+    let open_ended = TimeSlice {
+        start: Some(1000 * NS),
+        stop: None,
+    };
+    assert_eq!(
+        timestamps(build_events_in(hits, 100.0, open_ended)),
+        vec![vec![1200], vec![1950, 2020], vec![2500]]
+    );
+}
+
+#[test]
+fn hits_past_the_stop_are_not_read() {
+    let slice = TimeSlice {
+        start: None,
+        stop: Some(30 * NS),
+    };
+    // Only the hit opening the first event past the stop is read
+    let mut read = 0;
+    let hits = (0..1_000).map(|t| {
+        read += 1;
+        hit(SCINT_LEFT, t * 10 * NS, 1000.0, None, None)
+    });
+    assert_eq!(build_events_in(hits, 5.0, slice).count(), 3);
+    assert_eq!(read, 4);
+}
+
+#[test]
+fn the_slice_is_given_in_seconds() {
+    let params = BuildParams {
+        slice_stop: Some(30.0),
+        ..Default::default()
+    };
+    assert_eq!(
+        params.time_slice(),
+        TimeSlice {
+            start: None,
+            stop: Some(30_000_000_000_000),
+        }
+    );
+    assert!(BuildParams::default().time_slice().is_whole_run());
+
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let backwards = BuildParams {
+        slice_start: Some(30.0),
+        slice_stop: Some(10.0),
+        ..Default::default()
+    };
+    let problems = validate_configuration(&map, &[], &[], &backwards);
+    assert!(problems
+        .iter()
+        .any(|problem| matches!(problem, ConfigProblem::TimeSlice { .. }) && problem.is_error()));
+}
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+mod common;
+
+use common::{hit, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelData, ChannelDataField};
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_data::ns_to_ps;
+use eventbuilder::evb::time_spread::{RunningStats, TimeSpread, FWHM_PER_SIGMA};
+
+const TOLERANCE: f64 = 1.0e-9;
+
+#[test]
+fn running_stats_match_the_two_pass_values() {
+    let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+    let mut stats = RunningStats::default();
+    assert_eq!(stats.mean(), None);
+    stats.push(values[0]);
+    assert_eq!(stats.variance(), None);
+    for value in &values[1..] {
+        stats.push(*value);
+    }
+    assert_eq!(stats.count(), 8);
+    assert!((stats.mean().unwrap() - 5.0).abs() < TOLERANCE);
+    // Sum of squared differences is 32
+    assert!((stats.variance().unwrap() - 32.0 / 7.0).abs() < TOLERANCE);
+    assert!((stats.fwhm().unwrap() - (32.0f64 / 7.0).sqrt() * FWHM_PER_SIGMA).abs() < TOLERANCE);
+}
+
+#[test]
+fn relative_times_are_accumulated_over_every_copy() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let spread = Arc::new(TimeSpread::default());
+    let mut data = ChannelData::new(&map, &BuildParams::default());
+    data.set_time_spread(spread.clone());
+    let mut copy = data.empty_copy();
+
+    // AnodeFrontRelTime of 9, 10, and 11 ns, then an event without the anode
+    for (start, rel_time) in [(0.0, 9.0), (10_000.0, 10.0)] {
+        data.append_event(
+            vec![
+                hit(SCINT_LEFT, ns_to_ps(start), 1000.0, None, None),
+                hit(ANODE_FRONT, ns_to_ps(start + rel_time), 1000.0, None, None),
+            ],
+            &map,
+            None,
+        );
+    }
+    copy.append_event(
+        vec![
+            hit(SCINT_LEFT, ns_to_ps(20_000.0), 1000.0, None, None),
+            hit(ANODE_FRONT, ns_to_ps(20_011.0), 1000.0, None, None),
+        ],
+        &map,
+        None,
+    );
+    copy.append_event(
+        vec![hit(SCINT_LEFT, ns_to_ps(30_000.0), 1000.0, None, None)],
+        &map,
+        None,
+    );
+
+    let stats = spread.stats();
+    assert_eq!(stats.len(), 1);
+    let (field, stats) = &stats[0];
+    assert_eq!(*field, ChannelDataField::AnodeFrontRelTime);
+    assert_eq!(stats.count(), 3);
+    assert!((stats.mean().unwrap() - 10.0).abs() < 1.0e-6);
+    assert!((stats.variance().unwrap() - 1.0).abs() < 1.0e-6);
+}
@@ -0,0 +1,191 @@
+use std::io::Write;
+
+mod common;
+
+use common::{build_data, hit, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::{ChannelData, ChannelDataField};
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::compass_file::{parse_compass_bytes, CompassFile, CompassVersion};
+use eventbuilder::evb::error::EVBError;
+use eventbuilder::evb::shift_map::{ShiftMap, ShiftMapEntry};
+use eventbuilder::evb::validation::{validate_configuration, ConfigProblem};
+
+// Beyond 2^53 ps (about 2.5 hours) an f64 can no longer hold every picosecond
+const LATE: i64 = (1 << 53) + 1;
+// A timestamp in ns which is beyond 2^56 ps (about 20 hours) once scaled, where an f64 would be 8 ps off
+const LATE_NS: u64 = (1 << 47) + 1;
+
+#[test]
+fn late_relative_times_stay_exact() {
+    // 1 ps and 3 ps after ScintLeft, which an f64 would round to 0 ps and 4 ps
+    let events = vec![
+        vec![
+            hit(SCINT_LEFT, LATE, 1000.0, None, None),
+            hit(ANODE_FRONT, LATE + 1, 1000.0, None, None),
+        ],
+        vec![
+            hit(SCINT_LEFT, LATE + 1000, 1000.0, None, None),
+            hit(ANODE_FRONT, LATE + 1003, 1000.0, None, None),
+        ],
+    ];
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let data = build_data(&map, &BuildParams::default(), events);
+
+    assert_eq!(
+        data.time_fields[&ChannelDataField::ScintLeftTime],
+        vec![LATE, LATE + 1000]
+    );
+    assert_eq!(
+        data.time_fields[&ChannelDataField::AnodeFrontTime],
+        vec![LATE + 1, LATE + 1003]
+    );
+    assert_eq!(
+        data.time_fields[&ChannelDataField::EventTime],
+        vec![LATE, LATE + 1000]
+    );
+    assert_eq!(
+        data.fields[&ChannelDataField::AnodeFrontRelTime],
+        vec![1.0e-3, 3.0e-3]
+    );
+}
+
+#[test]
+fn integer_timestamps_are_written_exactly() {
+    let params = BuildParams {
+        integer_timestamps: true,
+        ..Default::default()
+    };
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let data = build_data(
+        &map,
+        &params,
+        vec![vec![
+            hit(SCINT_LEFT, LATE, 1000.0, None, None),
+            hit(ANODE_FRONT, LATE + 1, 1000.0, None, None),
+        ]],
+    );
+    let df = data.into_dataframe().unwrap();
+
+    let anode_time = df.column("AnodeFrontTime").unwrap().i64().unwrap();
+    assert_eq!(anode_time.get(0), Some(LATE + 1));
+    let scint_time = df.column("ScintLeftTime").unwrap().i64().unwrap();
+    assert_eq!(scint_time.get(0), Some(LATE));
+}
+
+#[test]
+fn custom_sentinel_is_written_for_unset_values() {
+    let params = BuildParams {
+        keep_invalid_sentinel: true,
+        sentinel_value: Some(-9999.0),
+        provenance_columns: true,
+        ..Default::default()
+    };
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let data = build_data(
+        &map,
+        &params,
+        vec![vec![hit(SCINT_LEFT, 0, 1000.0, None, None)]],
+    );
+    // An unset value of -1e6 can not be told apart from a real one in memory, but the output can
+    let df = data.clone().into_dataframe().unwrap();
+
+    let anode_time = df.column("AnodeFrontTime").unwrap().f64().unwrap();
+    assert_eq!(anode_time.get(0), Some(-9999.0));
+    let x1 = df.column("X1").unwrap().f64().unwrap();
+    assert_eq!(x1.get(0), Some(-9999.0));
+    let anode_board = df.column("AnodeFrontBoard").unwrap().i64().unwrap();
+    assert_eq!(anode_board.get(0), Some(-9999));
+
+    let loaded = ChannelData::from_dataframe(&df, &map, &params).unwrap();
+    assert_eq!(loaded.fields, data.fields);
+    assert_eq!(loaded.time_fields, data.time_fields);
+    assert_eq!(loaded.nested_fields, data.nested_fields);
+
+    // An integer column can not hold a fractional sentinel
+    let params = BuildParams {
+        sentinel_value: Some(-0.5),
+        ..params
+    };
+    let data = build_data(
+        &map,
+        &params,
+        vec![vec![hit(SCINT_LEFT, 0, 1000.0, None, None)]],
+    );
+    let df = data.clone().into_dataframe().unwrap();
+    let anode_board = df.column("AnodeFrontBoard").unwrap().f64().unwrap();
+    assert_eq!(anode_board.get(0), Some(-0.5));
+    let scint_board = df.column("ScintLeftBoard").unwrap().f64().unwrap();
+    assert_eq!(scint_board.get(0), Some(0.0));
+    let loaded = ChannelData::from_dataframe(&df, &map, &params).unwrap();
+    assert_eq!(loaded.fields, data.fields);
+}
+
+#[test]
+fn nanosecond_timestamps_are_scaled_to_ps() {
+    let path = std::env::temp_dir().join(format!("time_unit_{}.bin", std::process::id()));
+    let mut file = std::fs::File::create(&path).unwrap();
+    // Header with the energy bit, then two hits of board 0 channel 1 at 100 and 250 ns
+    file.write_all(&0x0001u16.to_le_bytes()).unwrap();
+    for timestamp in [100u64, 250] {
+        file.write_all(&0u16.to_le_bytes()).unwrap(); // board
+        file.write_all(&1u16.to_le_bytes()).unwrap(); // channel
+        file.write_all(&timestamp.to_le_bytes()).unwrap();
+        file.write_all(&500u16.to_le_bytes()).unwrap(); // energy
+        file.write_all(&0u32.to_le_bytes()).unwrap(); // flags
+    }
+    drop(file);
+
+    let timestamps = |scale: Option<u64>, shifts: &Option<ShiftMap>| -> Vec<i64> {
+        let mut compass_file = CompassFile::new(&path, shifts).unwrap();
+        if let Some(scale) = scale {
+            compass_file.set_time_scale(scale);
+        }
+        compass_file.map(|hit| hit.unwrap().timestamp).collect()
+    };
+    assert_eq!(timestamps(None, &None), vec![100, 250]);
+    assert_eq!(timestamps(Some(1000), &None), vec![100_000, 250_000]);
+    // The shift is in ns whatever the unit of the file
+    let shifts = Some(ShiftMap::new(vec![ShiftMapEntry {
+        board_number: 0,
+        channel_number: 1,
+        time_shift: 2.0,
+    }]));
+    assert_eq!(timestamps(Some(1000), &shifts), vec![102_000, 252_000]);
+    std::fs::remove_file(&path).unwrap();
+
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    assert_eq!(BuildParams::default().time_scale(), 1);
+    let params = BuildParams {
+        time_unit: Some(0),
+        ..Default::default()
+    };
+    let problems = validate_configuration(&map, &[], &[], &params);
+    assert_eq!(problems, vec![ConfigProblem::TimeUnit(0)]);
+    assert!(problems[0].is_error());
+}
+
+#[test]
+fn late_scaled_timestamps_stay_exact() {
+    // Header with the energy bit, then one hit of board 0 channel 1
+    let file = |timestamp: u64| -> Vec<u8> {
+        let mut bytes = 0x0001u16.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&0u16.to_le_bytes()); // board
+        bytes.extend_from_slice(&1u16.to_le_bytes()); // channel
+        bytes.extend_from_slice(&timestamp.to_le_bytes());
+        bytes.extend_from_slice(&500u16.to_le_bytes()); // energy
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // flags
+        bytes
+    };
+
+    let hits = parse_compass_bytes(&file(LATE_NS), CompassVersion::V2, 1000, &None, &[]).unwrap();
+    assert!(hits[0].timestamp > 1 << 56);
+    assert_eq!(hits[0].timestamp, LATE_NS as i64 * 1000);
+
+    // A timestamp which does not fit in u64 ps once scaled is an error, not a wrapped or rounded value
+    let overflow = file(u64::MAX / 1000 + 1);
+    assert!(matches!(
+        parse_compass_bytes(&overflow, CompassVersion::V2, 1000, &None, &[]),
+        Err(EVBError::Parser)
+    ));
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::{build_data, on_board};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::ChannelData;
+use eventbuilder::evb::channel_map::{
+    Board, ChannelMap, ChannelMapError, ChannelType, UnmappedHits,
+};
+use eventbuilder::evb::compass_data::generate_board_channel_uuid;
+use eventbuilder::evb::error::EVBError;
+
+#[test]
+fn counts_hits_of_unmapped_channels() {
+    let mut board = Board::sps(0);
+    board.disabled[1] = true;
+    let map = ChannelMap::new(&[board]).unwrap();
+    let mut unmapped = UnmappedHits::default();
+    for (board, channel) in [(0, 0), (0, 1), (0, 2), (2, 5), (0, 2), (2, 5), (2, 5)] {
+        unmapped.count(&map, generate_board_channel_uuid(&board, &channel));
+    }
+
+    // Channel 2 of the SPS board has no type, board 2 is not in the map, and disabled channels are mapped
+    assert_eq!(unmapped.channels(), vec![(0, 2, 2), (2, 5, 3)]);
+    assert_eq!(
+        unmapped.to_string(),
+        "board 0 channel 2 (2 hits), board 2 channel 5 (3 hits, on no board of the map)"
+    );
+
+    let mut mapped = UnmappedHits::default();
+    mapped.count(&map, generate_board_channel_uuid(&0, &13));
+    assert!(mapped.is_empty());
+}
+
+#[test]
+fn lookup_tells_untyped_channels_from_unknown_ones() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let lookup =
+        |board: u32, channel: u32| map.lookup(&generate_board_channel_uuid(&board, &channel));
+
+    assert_eq!(
+        lookup(0, 13).unwrap().map(|data| data.channel_type),
+        Some(ChannelType::AnodeFront)
+    );
+    // Left without a type on purpose
+    assert!(lookup(0, 2).unwrap().is_none());
+    // Board 2 is not in the map
+    assert!(matches!(
+        lookup(2, 5),
+        Err(ChannelMapError::UnknownChannel {
+            board: 2,
+            channel: 5
+        })
+    ));
+}
+
+#[test]
+fn strict_events_with_unmapped_hits_are_errors() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let hit =
+        |board: u32, channel: u32| on_board(board, common::hit(channel, 0, 1000.0, None, None));
+
+    // Dropped as usual without the strict channel map
+    let mut data = ChannelData::new(&map, &BuildParams::default());
+    data.try_append_event(vec![hit(0, 13), hit(0, 2), hit(2, 5)], &map, None)
+        .unwrap();
+    assert_eq!(data.rows, 1);
+
+    let params = BuildParams {
+        strict_channel_map: true,
+        ..Default::default()
+    };
+    let mut data = ChannelData::new(&map, &params);
+    data.try_append_event(vec![hit(0, 13), hit(0, 13)], &map, None)
+        .unwrap();
+    // Left without a type on purpose
+    assert!(matches!(
+        data.try_append_event(vec![hit(0, 13), hit(0, 2)], &map, None),
+        Err(EVBError::UnmappedChannel {
+            board: 0,
+            channel: 2
+        })
+    ));
+    // Board 2 is missing in the map
+    assert!(matches!(
+        data.try_append_event(vec![hit(2, 5)], &map, None),
+        Err(EVBError::Channel(ChannelMapError::UnknownChannel {
+            board: 2,
+            channel: 5
+        }))
+    ));
+    assert_eq!(data.rows, 1);
+}
+
+#[test]
+fn events_without_mapped_hits_can_be_dropped() {
+    let mut board = Board::sps(0);
+    board.disabled[1] = true;
+    let map = ChannelMap::new(&[board]).unwrap();
+    let hit = |board: u32, channel: u32, timestamp: i64| {
+        on_board(board, common::hit(channel, timestamp, 1000.0, None, None))
+    };
+    // Untyped, unknown, and disabled channels only, then an event with the anode
+    let events = vec![
+        vec![hit(0, 2, 0), hit(2, 5, 10)],
+        vec![hit(0, 1, 1_000_000)],
+        vec![hit(0, 13, 2_000_000), hit(2, 5, 2_000_010)],
+    ];
+
+    // Every window is a row by default
+    let data = build_data(&map, &BuildParams::default(), events.clone());
+    assert_eq!(data.rows, 3);
+
+    let params = BuildParams {
+        drop_empty_events: true,
+        ..Default::default()
+    };
+    let data = build_data(&map, &params, events.clone());
+    assert_eq!(data.rows, 1);
+    let mut batch = ChannelData::new(&map, &params);
+    batch.append_events_batch(events, &map, None);
+    assert_eq!(batch.rows, 1);
+
+    let df = data.into_dataframe().unwrap();
+    assert_eq!(df.height(), 1);
+    assert_eq!(
+        df.column("AnodeFrontEnergy").unwrap().f64().unwrap().get(0),
+        Some(1000.0)
+    );
+}
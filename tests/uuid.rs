@@ -0,0 +1,60 @@
+use eventbuilder::evb::compass_data::{
+    compose_board_channel_to_uuid, decompose_uuid_to_board_channel, generate_board_channel_uuid,
+};
+
+// Boards and channels are read from the files as u16
+const MAX: u32 = u16::MAX as u32;
+
+fn round_trip(board: u32, channel: u32) {
+    let uuid = compose_board_channel_to_uuid(board, channel);
+    assert_eq!(uuid, generate_board_channel_uuid(&board, &channel));
+    assert_eq!(
+        decompose_uuid_to_board_channel(&uuid),
+        (board, channel),
+        "uuid {}",
+        uuid
+    );
+}
+
+#[test]
+fn small_boards_and_channels_fill_the_ids_in_order() {
+    // Every id below (n + 1)^2 is used by exactly one board and channel up to n
+    let n = 255;
+    let mut ids: Vec<u32> = (0..=n)
+        .flat_map(|board| (0..=n).map(move |channel| compose_board_channel_to_uuid(board, channel)))
+        .collect();
+    ids.sort_unstable();
+    assert!(ids.iter().copied().eq(0..(n + 1) * (n + 1)));
+    for board in 0..=n {
+        for channel in 0..=n {
+            round_trip(board, channel);
+        }
+    }
+}
+
+#[test]
+fn round_trips_across_the_full_range() {
+    let edges = [0, 1, 2, 15, 16, 255, 256, 4095, 32767, 32768, MAX - 1, MAX];
+    for board in edges {
+        for channel in edges {
+            round_trip(board, channel);
+        }
+    }
+    for board in (0..=MAX).step_by(97) {
+        for channel in (0..=MAX).step_by(1021) {
+            round_trip(board, channel);
+            round_trip(channel, board);
+        }
+    }
+    assert_eq!(compose_board_channel_to_uuid(MAX, MAX), u32::MAX);
+}
+
+#[test]
+fn every_id_near_the_top_decomposes() {
+    // The square root is exact in f64 for every u32, including the ids next to perfect squares
+    for uuid in (u32::MAX - 200_000..=u32::MAX).chain(0..200_000) {
+        let (board, channel) = decompose_uuid_to_board_channel(&uuid);
+        assert!(board <= MAX && channel <= MAX);
+        assert_eq!(compose_board_channel_to_uuid(board, channel), uuid);
+    }
+}
@@ -0,0 +1,104 @@
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::calibration_map::CalibrationMapEntry;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::expression::DerivedFieldEntry;
+use eventbuilder::evb::shift_map::ShiftMapEntry;
+use eventbuilder::evb::validation::{validate_configuration, ConfigProblem};
+
+fn calibration(board: u32, channel: u32) -> CalibrationMapEntry {
+    CalibrationMapEntry {
+        board_number: board,
+        channel_number: channel,
+        a: 0.0,
+        b: 1.0,
+        c: 0.0,
+    }
+}
+
+fn shift(board: u32, channel: u32) -> ShiftMapEntry {
+    ShiftMapEntry {
+        board_number: board,
+        channel_number: channel,
+        time_shift: 1.0,
+    }
+}
+
+#[test]
+fn finds_entries_for_unmapped_channels() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    // Channel 2 of the SPS board has no type and there is no board 3
+    let calibrations = [calibration(0, 1), calibration(0, 2), calibration(0, 1)];
+    let shifts = [shift(3, 0), shift(0, 13)];
+    let problems = validate_configuration(&map, &calibrations, &shifts, &BuildParams::default());
+
+    assert_eq!(
+        problems,
+        vec![
+            ConfigProblem::OrphanCalibration {
+                board: 0,
+                channel: 2
+            },
+            ConfigProblem::DuplicateCalibration {
+                board: 0,
+                channel: 1
+            },
+            ConfigProblem::OrphanShift {
+                board: 3,
+                channel: 0
+            },
+        ]
+    );
+    assert!(problems.iter().all(|problem| !problem.is_error()));
+}
+
+#[test]
+fn empty_field_set_and_bad_derived_fields_are_errors() {
+    let map = ChannelMap::new(&[Board::default()]).unwrap();
+    let params = BuildParams {
+        derived_fields: vec![DerivedFieldEntry {
+            name: "Ratio".to_string(),
+            expression: "AnodeFrontEnergy /".to_string(),
+        }],
+        ..Default::default()
+    };
+    let problems = validate_configuration(&map, &[], &[], &params);
+
+    assert_eq!(problems.len(), 2);
+    assert_eq!(problems[0], ConfigProblem::EmptyFieldSet);
+    assert!(matches!(&problems[1], ConfigProblem::DerivedField(name, _) if name == "Ratio"));
+    assert!(problems.iter().all(|problem| problem.is_error()));
+
+    // The hit pattern of an empty map has no bits to set
+    let params = BuildParams {
+        hit_pattern: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        validate_configuration(&map, &[], &[], &params),
+        vec![ConfigProblem::EmptyFieldSet]
+    );
+}
+
+#[test]
+fn duplicate_derived_field_names_are_errors() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let derived = |name: &str, expression: &str| DerivedFieldEntry {
+        name: name.to_string(),
+        expression: expression.to_string(),
+    };
+    let params = BuildParams {
+        derived_fields: vec![
+            derived("Ratio", "AnodeFrontEnergy / AnodeBackEnergy"),
+            derived("Sum", "AnodeFrontEnergy + AnodeBackEnergy"),
+            derived(" Ratio ", "AnodeBackEnergy / AnodeFrontEnergy"),
+        ],
+        ..Default::default()
+    };
+    let problems = validate_configuration(&map, &[], &[], &params);
+
+    assert_eq!(
+        problems,
+        vec![ConfigProblem::DuplicateDerivedField("Ratio".to_string())]
+    );
+    assert!(problems[0].is_error());
+}
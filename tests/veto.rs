@@ -0,0 +1,54 @@
+mod common;
+
+use common::{build, hit, values, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+use polars::prelude::*;
+
+// The unused channel 2 of Board::sps as the veto paddle
+const VETO: u32 = 2;
+
+#[test]
+fn events_with_a_veto_hit_are_flagged() {
+    let mut board = Board::sps(0);
+    board.channels[VETO as usize] = ChannelType::Veto;
+    let map = ChannelMap::new(&[board]).unwrap();
+    let df = build(
+        &map,
+        &BuildParams::default(),
+        vec![
+            vec![
+                hit(SCINT_LEFT, 0, 1000.0, None, None),
+                hit(ANODE_FRONT, 10, 1000.0, None, None),
+            ],
+            vec![
+                hit(VETO, 1_000_000, 1000.0, None, None),
+                hit(SCINT_LEFT, 1_000_005, 1000.0, None, None),
+            ],
+            vec![hit(VETO, 2_000_000, 1000.0, None, None)],
+        ],
+    );
+
+    let column = df.column("Vetoed").unwrap();
+    assert_eq!(column.dtype(), &DataType::Boolean);
+    let vetoed: Vec<Option<bool>> = column.bool().unwrap().into_iter().collect();
+    assert_eq!(vetoed, vec![Some(false), Some(true), Some(true)]);
+
+    // Flagged, not dropped, and the other detectors are filled as usual
+    assert_eq!(values(&df, "ScintLeftEnergy")[1], Some(1000.0));
+    assert!(!df
+        .get_column_names()
+        .iter()
+        .any(|name| name.starts_with("VetoEnergy") || name.starts_with("VetoTime")));
+}
+
+#[test]
+fn no_vetoed_column_without_a_veto_channel() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let df = build(
+        &map,
+        &BuildParams::default(),
+        vec![vec![hit(SCINT_LEFT, 0, 1000.0, None, None)]],
+    );
+    assert!(df.column("Vetoed").is_err());
+}
@@ -0,0 +1,83 @@
+use std::io::Write;
+
+mod common;
+
+use common::{build, hit, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_map::{Board, ChannelMap, ChannelType};
+use eventbuilder::evb::compass_data::{generate_board_channel_uuid, CompassData};
+use eventbuilder::evb::compass_file::CompassFile;
+use polars::prelude::*;
+
+#[test]
+fn waveforms_are_written_for_the_selected_types() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let events = vec![
+        vec![
+            hit(SCINT_LEFT, 0, 1000.0, None, Some(vec![1, 2, 3])),
+            hit(ANODE_FRONT, 10, 1000.0, None, Some(vec![4, 5])),
+        ],
+        vec![hit(ANODE_FRONT, 1_000_000, 1000.0, None, Some(vec![6]))],
+    ];
+
+    // Not part of the default schema
+    let df = build(&map, &BuildParams::default(), events.clone());
+    assert!(!df
+        .get_column_names()
+        .iter()
+        .any(|name| name.ends_with("Waveform")));
+
+    let params = BuildParams {
+        waveform_channels: vec![ChannelType::ScintLeft],
+        ..Default::default()
+    };
+    let df = build(&map, &params, events);
+    let column = df.column("ScintLeftWaveform").unwrap();
+    assert_eq!(column.dtype(), &DataType::List(Box::new(DataType::Int16)));
+    let rows: Vec<Option<Vec<i16>>> = column
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|row| row.map(|series| series.i16().unwrap().into_no_null_iter().collect()))
+        .collect();
+    assert_eq!(rows, vec![Some(vec![1, 2, 3]), None]);
+    assert!(df.column("AnodeFrontWaveform").is_err());
+}
+
+#[test]
+fn waveform_samples_are_read_from_the_file() {
+    let path = std::env::temp_dir().join(format!("waveforms_{}.bin", std::process::id()));
+    let mut file = std::fs::File::create(&path).unwrap();
+    // Header with the energy and waves bits, then two hits with three samples each
+    file.write_all(&0x0009u16.to_le_bytes()).unwrap();
+    for (timestamp, samples) in [(100u64, [10u16, 20, 30]), (200, [40, 50, 60])] {
+        file.write_all(&0u16.to_le_bytes()).unwrap(); // board
+        file.write_all(&1u16.to_le_bytes()).unwrap(); // channel
+        file.write_all(&timestamp.to_le_bytes()).unwrap();
+        file.write_all(&500u16.to_le_bytes()).unwrap(); // energy
+        file.write_all(&0u32.to_le_bytes()).unwrap(); // flags
+        file.write_all(&[1u8]).unwrap(); // waveform code
+        file.write_all(&3u32.to_le_bytes()).unwrap();
+        for sample in samples {
+            file.write_all(&sample.to_le_bytes()).unwrap();
+        }
+    }
+    drop(file);
+
+    let mut compass_file = CompassFile::new(&path, &None).unwrap();
+    assert_eq!(compass_file.get_number_of_hits(), 2);
+    compass_file.set_waveform_uuids(vec![generate_board_channel_uuid(&0, &1)]);
+    let hits: Vec<CompassData> = compass_file.map(|hit| hit.unwrap()).collect();
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[1].timestamp, 200);
+    assert_eq!(hits[0].waveform, Some(vec![10, 20, 30]));
+    assert_eq!(hits[1].waveform, Some(vec![40, 50, 60]));
+
+    // Skipped for the other channels
+    let mut compass_file = CompassFile::new(&path, &None).unwrap();
+    compass_file.set_waveform_uuids(vec![generate_board_channel_uuid(&0, &2)]);
+    let hits: Vec<CompassData> = compass_file.map(|hit| hit.unwrap()).collect();
+    assert_eq!(hits.len(), 2);
+    assert!(hits.iter().all(|hit| hit.waveform.is_none()));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use common::{build, hit, values, ANODE_BACK, ANODE_FRONT, SCINT_LEFT};
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::channel_data::ChannelDataField;
+use eventbuilder::evb::channel_map::{Board, ChannelMap};
+use eventbuilder::evb::expression::DerivedFieldEntry;
+use eventbuilder::evb::validation::{validate_configuration, ConfigProblem};
+
+#[test]
+fn patterns_match_field_names() {
+    let field = ChannelDataField::Cebra3RelTimeRight;
+    for pattern in [
+        "Cebra3RelTimeRight",
+        " Cebra3RelTimeRight ",
+        "*",
+        "Cebra*",
+        "*Right",
+        "Cebra*RelTime*",
+        "*3*",
+    ] {
+        assert!(field.matches_pattern(pattern), "{pattern}");
+    }
+    for pattern in [
+        "Cebra3RelTime",
+        "",
+        "*Short",
+        "Cebra*Left",
+        "Cebra3RelTimeRight*X",
+    ] {
+        assert!(!field.matches_pattern(pattern), "{pattern}");
+    }
+}
+
+#[test]
+fn excluded_fields_are_not_written() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        excluded_fields: vec!["*Short".to_string(), String::new()],
+        ..Default::default()
+    };
+    let df = build(
+        &map,
+        &params,
+        vec![vec![
+            hit(SCINT_LEFT, 0, 1000.0, Some(500.0), None),
+            hit(ANODE_FRONT, 2_000, 1000.0, Some(500.0), None),
+        ]],
+    );
+
+    let names: Vec<String> = df
+        .get_column_names()
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    assert!(!names.iter().any(|name| name.ends_with("Short")));
+    assert!(names.contains(&"ScintLeftEnergy".to_string()));
+    // Short gates are not an input of anything, the relative times are unaffected
+    let rel_time = df.column("AnodeFrontRelTime").unwrap().f64().unwrap();
+    assert_eq!(rel_time.get(0), Some(2.0));
+    assert!(validate_configuration(&map, &[], &[], &params).is_empty());
+}
+
+#[test]
+fn included_fields_narrow_the_map() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        included_fields: vec!["ScintLeft*".to_string(), "X1".to_string()],
+        excluded_fields: vec!["ScintLeftShort".to_string()],
+        ..Default::default()
+    };
+    // EventTime is always written
+    assert_eq!(
+        ChannelDataField::get_filtered_field_vec(&map, &params),
+        vec![
+            ChannelDataField::ScintLeftEnergy,
+            ChannelDataField::ScintLeftTime,
+            ChannelDataField::X1,
+            ChannelDataField::EventTime,
+        ]
+    );
+    // Still only what the map gives
+    let mapped = ChannelDataField::get_mapped_field_vec(&map, &params);
+    assert!(mapped.contains(&ChannelDataField::AnodeFrontEnergy));
+    assert!(!mapped.contains(&ChannelDataField::ScintTopEnergy));
+}
+
+#[test]
+fn fields_calculated_from_a_left_out_field_are_set() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        excluded_fields: vec!["ScintLeftTime".to_string(), "AnodeBackEnergy".to_string()],
+        derived_fields: vec![DerivedFieldEntry {
+            name: "BackRatio".to_string(),
+            expression: "AnodeBackEnergy / AnodeFrontEnergy".to_string(),
+        }],
+        ..Default::default()
+    };
+    assert!(validate_configuration(&map, &[], &[], &params).is_empty());
+    let df = build(
+        &map,
+        &params,
+        vec![vec![
+            hit(SCINT_LEFT, 0, 1000.0, None, None),
+            hit(ANODE_FRONT, 2_000, 200.0, None, None),
+            hit(ANODE_BACK, 3_000, 100.0, None, None),
+        ]],
+    );
+
+    assert!(df.column("ScintLeftTime").is_err());
+    assert!(df.column("AnodeBackEnergy").is_err());
+    assert_eq!(values(&df, "AnodeFrontRelTime"), vec![Some(2.0)]);
+    assert_eq!(values(&df, "AnodeSumEnergy"), vec![Some(300.0)]);
+    assert_eq!(values(&df, "BackRatio"), vec![Some(0.5)]);
+}
+
+#[test]
+fn unknown_patterns_are_errors() {
+    let map = ChannelMap::new(&[Board::sps(0)]).unwrap();
+    let params = BuildParams {
+        excluded_fields: vec!["*Shrot".to_string()],
+        ..Default::default()
+    };
+    let problems = validate_configuration(&map, &[], &[], &params);
+    assert_eq!(
+        problems,
+        vec![ConfigProblem::UnknownFieldPattern("*Shrot".to_string())]
+    );
+    assert!(problems[0].is_error());
+}
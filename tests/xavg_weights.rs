@@ -0,0 +1,77 @@
+use eventbuilder::evb::build_params::BuildParams;
+use eventbuilder::evb::column_alias::ColumnAlias;
+use eventbuilder::evb::kinematics::{fit_weights, fit_weights_from_dataframe};
+use polars::prelude::*;
+
+// Events in a single peak at target, spread along the focal plane tilt so that only
+// 0.3 * x1 + 0.7 * x2 lands every event back on the peak
+fn synthetic_peak(target: f64) -> (Vec<f64>, Vec<f64>) {
+    (0..200)
+        .map(|i| {
+            let spread = (i as f64 - 100.0) * 0.05;
+            (target + 0.7 * spread, target - 0.3 * spread)
+        })
+        .unzip()
+}
+
+#[test]
+fn fit_recovers_weights() {
+    let (x1, x2) = synthetic_peak(-45.0);
+    let (w1, w2) = fit_weights(&x1, &x2, -45.0).unwrap();
+    assert!((w1 - 0.3).abs() < 1.0e-9);
+    assert!((w2 - 0.7).abs() < 1.0e-9);
+}
+
+#[test]
+fn fit_without_spread_is_none() {
+    assert_eq!(fit_weights(&[1.0, 2.0], &[1.0, 2.0], 1.5), None);
+    assert_eq!(fit_weights(&[], &[], 0.0), None);
+}
+
+#[test]
+fn fit_from_dataframe_skips_missing() {
+    let (x1, x2) = synthetic_peak(12.0);
+    let mut x1: Vec<Option<f64>> = x1.into_iter().map(Some).collect();
+    let mut x2: Vec<Option<f64>> = x2.into_iter().map(Some).collect();
+    // An event missing X2, and one written with the -1e6 sentinel
+    x1.push(Some(500.0));
+    x2.push(None);
+    x1.push(Some(-1.0e6));
+    x2.push(Some(300.0));
+
+    let df = DataFrame::new(vec![
+        Column::new("X1".into(), x1),
+        Column::new("X2".into(), x2),
+    ])
+    .unwrap();
+    let (w1, w2) = fit_weights_from_dataframe(&df, &BuildParams::default(), 12.0)
+        .unwrap()
+        .unwrap();
+    assert!((w1 - 0.3).abs() < 1.0e-9);
+    assert!((w2 - 0.7).abs() < 1.0e-9);
+}
+
+#[test]
+fn fit_from_dataframe_uses_the_written_names() {
+    let (x1, x2) = synthetic_peak(12.0);
+    let df = DataFrame::new(vec![
+        Column::new("sps_X1".into(), x1),
+        Column::new("FrontPosition".into(), x2),
+    ])
+    .unwrap();
+    let params = BuildParams {
+        column_prefix: "sps_".to_string(),
+        column_aliases: vec![ColumnAlias {
+            field: "X2".to_string(),
+            alias: "FrontPosition".to_string(),
+        }],
+        ..Default::default()
+    };
+    let (w1, w2) = fit_weights_from_dataframe(&df, &params, 12.0)
+        .unwrap()
+        .unwrap();
+    assert!((w1 - 0.3).abs() < 1.0e-9);
+    assert!((w2 - 0.7).abs() < 1.0e-9);
+    // The plain names are not in this frame
+    assert!(fit_weights_from_dataframe(&df, &BuildParams::default(), 12.0).is_err());
+}